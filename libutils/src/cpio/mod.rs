@@ -0,0 +1,5 @@
+//! cpio archive utilities
+#![allow(dead_code)]
+
+mod newc;
+pub use newc::*;
@@ -0,0 +1,175 @@
+/// Magic number at the start of every newc header
+pub const NEWC_MAGIC: &[u8] = b"070701";
+
+/// Magic number at the start of a newc header which carries a checksum
+pub const NEWC_CRC_MAGIC: &[u8] = b"070702";
+
+/// Size of a newc header in bytes
+pub const NEWC_HEADER_SIZE: usize = 110;
+
+/// Name of the entry which marks the end of an archive
+pub const NEWC_TRAILER: &str = "TRAILER!!!";
+
+/// Errors which can occur while reading an archive, each carrying the offset of the bad header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpioError
+{
+    Truncated(usize),
+    BadMagic(usize),
+    BadHeader(usize),
+    BadName(usize)
+}
+
+/// A single entry in a cpio archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpioEntry<'a>
+{
+    pub inode: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub links: u32,
+    pub mtime: u32,
+    pub rdev_major: u32,
+    pub rdev_minor: u32,
+    pub name: &'a str,
+    pub data: &'a [u8]
+}
+
+/// Iterator over the entries of a newc format cpio archive, several archives
+/// may be concatenated with zero padding between them
+pub struct CpioArchive<'a>
+{
+    data: &'a [u8],
+    offset: usize,
+    done: bool
+}
+
+/// Round an offset up to the next multiple of four
+fn align4(offset: usize) -> usize
+{
+    (offset + 3) & !3
+}
+
+/// Parse one of the eight digit hexadecimal fields of a header
+fn parse_hex(field: &[u8]) -> Option<u32>
+{
+    let mut value = 0u32;
+
+    for c in field
+    {
+        value = (value << 4) | (*c as char).to_digit(16)?;
+    }
+
+    Some(value)
+}
+
+impl<'a> CpioArchive<'a>
+{
+    /// Create an iterator over the entries of an archive
+    pub fn new(data: &'a [u8]) -> Self
+    {
+        Self
+        {
+            data,
+            offset: 0,
+            done: false
+        }
+    }
+
+    /// Skip the zero padding after a trailer, returning true if another archive follows
+    fn skip_padding(&mut self) -> bool
+    {
+        while self.offset < self.data.len() && self.data[self.offset] == 0
+        {
+            self.offset += 1;
+        }
+
+        self.offset < self.data.len()
+    }
+
+    /// Read the entry at the current offset
+    fn read_entry(&mut self) -> Result<CpioEntry<'a>, CpioError>
+    {
+        let start = self.offset;
+
+        let header = self.data.get(start..start + NEWC_HEADER_SIZE).ok_or(CpioError::Truncated(start))?;
+
+        if &header[..6] != NEWC_MAGIC && &header[..6] != NEWC_CRC_MAGIC
+        {
+            return Err(CpioError::BadMagic(start));
+        }
+
+        let mut fields = [0u32; 13];
+
+        for (i, field) in fields.iter_mut().enumerate()
+        {
+            *field = parse_hex(&header[6 + i * 8..14 + i * 8]).ok_or(CpioError::BadHeader(start))?;
+        }
+
+        let file_size = fields[6] as usize;
+        let name_size = fields[11] as usize;
+
+        // The name includes its null terminator
+        if name_size == 0
+        {
+            return Err(CpioError::BadName(start));
+        }
+
+        let name_start = start + NEWC_HEADER_SIZE;
+        let name = self.data.get(name_start..name_start + name_size - 1).ok_or(CpioError::Truncated(start))?;
+        let name = core::str::from_utf8(name).map_err(|_| CpioError::BadName(start))?;
+
+        let data_start = align4(name_start + name_size);
+        let data = self.data.get(data_start..data_start + file_size).ok_or(CpioError::Truncated(start))?;
+
+        self.offset = align4(data_start + file_size);
+
+        Ok(CpioEntry
+        {
+            inode: fields[0],
+            mode: fields[1],
+            uid: fields[2],
+            gid: fields[3],
+            links: fields[4],
+            mtime: fields[5],
+            rdev_major: fields[9],
+            rdev_minor: fields[10],
+            name,
+            data
+        })
+    }
+}
+
+impl<'a> Iterator for CpioArchive<'a>
+{
+    type Item = Result<CpioEntry<'a>, CpioError>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        loop
+        {
+            if self.done || self.offset >= self.data.len()
+            {
+                return None;
+            }
+
+            match self.read_entry()
+            {
+                Ok(entry) if entry.name == NEWC_TRAILER =>
+                {
+                    if !self.skip_padding()
+                    {
+                        self.done = true;
+                    }
+                },
+                Ok(entry) => return Some(Ok(entry)),
+                Err(e) =>
+                {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
@@ -7,4 +7,6 @@ extern crate std;
 #[cfg(not(feature = "std"))]
 extern crate alloc;
 
+pub mod cpio;
+pub mod minix3;
 pub mod paths;
\ No newline at end of file
@@ -0,0 +1,600 @@
+//! Minix3 consistency checker
+//!
+//! Walks every directory from the root, recording which inodes are reachable,
+//! how many entries refer to each of them and which zones each one owns. The
+//! inode and zone bitmaps, the link counts and the directory entries are then
+//! compared against what was found. A repair rewrites whatever disagrees: bad
+//! zone pointers and bad directory entries are dropped, inodes which nothing
+//! refers to are freed and the bitmaps are rebuilt from the walk. Repairs are
+//! written data first, then inodes, then bitmaps, so an interrupted repair
+//! never leaves anything in use marked as free.
+
+use super::structures::*;
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+/// Byte level access to the device holding a Minix3 filesystem, offsets and lengths are multiples of 1024
+pub trait Minix3Device
+{
+    /// Read `buffer.len()` bytes starting at the given offset, returning false on failure
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> bool;
+
+    /// Write the data starting at the given offset, returning false on failure
+    fn write(&mut self, offset: usize, data: &[u8]) -> bool;
+}
+
+impl Minix3Device for [u8]
+{
+    /// Read `buffer.len()` bytes starting at the given offset, returning false on failure
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> bool
+    {
+        match self.get(offset..offset + buffer.len())
+        {
+            Some(data) => { buffer.copy_from_slice(data); true },
+            None => false
+        }
+    }
+
+    /// Write the data starting at the given offset, returning false on failure
+    fn write(&mut self, offset: usize, data: &[u8]) -> bool
+    {
+        match self.get_mut(offset..offset + data.len())
+        {
+            Some(region) => { region.copy_from_slice(data); true },
+            None => false
+        }
+    }
+}
+
+/// Errors which stop a check before it can say anything about the filesystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsckError
+{
+    /// Reading or writing the block at the given index failed
+    Io(usize),
+    BadMagic,
+    UnsupportedZoneSize,
+    BadLayout,
+    BadRoot
+}
+
+/// An inconsistency found by the checker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsckProblem
+{
+    /// A zone pointer of the inode lies outside the data zones
+    ZoneOutOfRange { inode: usize, zone: usize },
+    /// A zone is owned by more than one inode, or twice by the same inode
+    DuplicateZone { inode: usize, zone: usize },
+    /// The size of a directory is not a whole number of entries, or runs past its zones
+    BadDirectorySize { inode: usize },
+    /// A directory entry refers to an inode which is out of range or not in use
+    BadEntry { directory: usize, inode: usize },
+    /// A directory is linked from more than one directory
+    DirectoryHardLink { directory: usize, inode: usize },
+    /// An inode marked as in use is not reachable from the root
+    Unreachable { inode: usize },
+    /// The link count of an inode does not match the number of entries referring to it
+    LinkCount { inode: usize, recorded: u16, actual: u16 },
+    /// The inode bitmap does not match the inodes in use
+    InodeBitmap { inode: usize, used: bool },
+    /// The zone bitmap does not match the zones in use
+    ZoneBitmap { zone: usize, used: bool }
+}
+
+/// Result of checking a filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsckReport
+{
+    pub problems: Vec<FsckProblem>,
+    pub repaired: bool
+}
+
+impl FsckReport
+{
+    /// Check if the filesystem is consistent, either because nothing was found or because it was all repaired
+    pub fn is_clean(&self) -> bool
+    {
+        self.problems.is_empty() || self.repaired
+    }
+}
+
+/// Data zones or blocks of an inode, each with its index within the inode
+type ZoneList = Vec<(usize, usize)>;
+
+/// Check if a bit of a bitmap is set
+fn get_bit(map: &[u8], bit: usize) -> bool
+{
+    map[bit / 8] & (1 << (bit % 8)) != 0
+}
+
+/// Set or clear a bit of a bitmap
+fn set_bit(map: &mut [u8], bit: usize, value: bool)
+{
+    if value
+    {
+        map[bit / 8] |= 1 << (bit % 8);
+    }
+    else
+    {
+        map[bit / 8] &= !(1 << (bit % 8));
+    }
+}
+
+/// State of a check in progress
+struct Checker<'a, D: Minix3Device + ?Sized>
+{
+    device: &'a mut D,
+    superblock: Minix3Superblock,
+    imap: Vec<u8>,
+    zmap: Vec<u8>,
+    table: Vec<u8>,
+    inodes: Vec<Minix3DiskInode>,
+    blocks: BTreeMap<usize, Vec<u8>>,
+    reached: Vec<bool>,
+    refs: Vec<usize>,
+    zones_used: Vec<bool>,
+    problems: Vec<FsckProblem>
+}
+
+impl<'a, D: Minix3Device + ?Sized> Checker<'a, D>
+{
+    /// Read the superblock, bitmaps and inode table of a filesystem
+    fn new(device: &'a mut D) -> Result<Self, FsckError>
+    {
+        let mut buffer = [0; 1024];
+        if !device.read(MINIX3_SUPERBLOCK_OFFSET, &mut buffer)
+        {
+            return Err(FsckError::Io(1));
+        }
+
+        let superblock = Minix3Superblock::from_bytes(&buffer);
+
+        if superblock.magic != MINIX3_MAGIC
+        {
+            return Err(FsckError::BadMagic);
+        }
+
+        if superblock.log_zone_size > 8
+        {
+            return Err(FsckError::UnsupportedZoneSize);
+        }
+
+        let block_size = superblock.block_size();
+        let map_bits = |blocks: u16| blocks as usize * block_size * 8;
+
+        if block_size < 1024 || !block_size.is_power_of_two()
+            || superblock.ninodes == 0
+            || map_bits(superblock.imap_blocks) <= superblock.ninodes as usize
+            || superblock.inode_table_start() + superblock.inode_table_blocks() > superblock.zone_block(superblock.first_data_zone as usize)
+            || superblock.first_data_zone as usize >= superblock.zones as usize
+            || superblock.zone_bit(superblock.zones as usize - 1).is_none_or(|bit| map_bits(superblock.zmap_blocks) <= bit)
+        {
+            return Err(FsckError::BadLayout);
+        }
+
+        let mut checker = Self
+        {
+            device,
+            superblock,
+            imap: Vec::new(),
+            zmap: Vec::new(),
+            table: Vec::new(),
+            inodes: Vec::new(),
+            blocks: BTreeMap::new(),
+            reached: vec![false; superblock.ninodes as usize + 1],
+            refs: vec![0; superblock.ninodes as usize + 1],
+            zones_used: vec![false; (superblock.zones - superblock.first_data_zone as u32) as usize],
+            problems: Vec::new()
+        };
+
+        checker.imap = checker.read_blocks(superblock.imap_start(), superblock.imap_blocks as usize)?;
+        checker.zmap = checker.read_blocks(superblock.zmap_start(), superblock.zmap_blocks as usize)?;
+        checker.table = checker.read_blocks(superblock.inode_table_start(), superblock.inode_table_blocks())?;
+
+        // Inodes are numbered from one, so the list starts with a placeholder
+        checker.inodes.push(Minix3DiskInode::default());
+
+        for chunk in checker.table.chunks(MINIX3_INODE_SIZE).take(superblock.ninodes as usize)
+        {
+            checker.inodes.push(Minix3DiskInode::from_bytes(chunk));
+        }
+
+        Ok(checker)
+    }
+
+    /// Read a run of blocks from the device
+    fn read_blocks(&mut self, start: usize, count: usize) -> Result<Vec<u8>, FsckError>
+    {
+        let block_size = self.superblock.block_size();
+        let mut data = vec![0; count * block_size];
+
+        for (i, chunk) in data.chunks_mut(block_size).enumerate()
+        {
+            if !self.device.read((start + i) * block_size, chunk)
+            {
+                return Err(FsckError::Io(start + i));
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Read a block, including any changes made by the check
+    fn read_block(&mut self, block: usize) -> Result<Vec<u8>, FsckError>
+    {
+        match self.blocks.get(&block)
+        {
+            Some(data) => Ok(data.clone()),
+            None => self.read_blocks(block, 1)
+        }
+    }
+
+    /// Record a zone as owned by an inode, returning the zone if the pointer is valid or zero if it must be dropped
+    fn claim_zone(&mut self, inode: usize, zone: usize) -> usize
+    {
+        if zone == 0
+        {
+            return 0;
+        }
+
+        if self.superblock.zone_bit(zone).is_none()
+        {
+            self.problems.push(FsckProblem::ZoneOutOfRange { inode, zone });
+            return 0;
+        }
+
+        let index = zone - self.superblock.first_data_zone as usize;
+
+        if self.zones_used[index]
+        {
+            self.problems.push(FsckProblem::DuplicateZone { inode, zone });
+            return 0;
+        }
+
+        self.zones_used[index] = true;
+
+        zone
+    }
+
+    /// Walk the zones below a zone pointer at the given level of indirection, recording the data zones before
+    /// `needed` along with their index within the inode
+    fn walk_zone(&mut self, inode: usize, zone: usize, level: u32, first_index: usize, needed: usize, data: &mut ZoneList) -> Result<(), FsckError>
+    {
+        if zone == 0
+        {
+            return Ok(());
+        }
+
+        if level == 0
+        {
+            if first_index < needed
+            {
+                data.push((first_index, zone));
+            }
+
+            return Ok(());
+        }
+
+        let per_block = self.superblock.block_size() / 4;
+        let coverage = per_block.saturating_pow(level - 1);
+
+        let mut table = self.read_block(self.superblock.zone_block(zone))?;
+        let mut changed = false;
+
+        for slot in 0..per_block
+        {
+            let pointer = get_u32(&table, slot * 4) as usize;
+            let kept = self.claim_zone(inode, pointer);
+
+            if kept != pointer
+            {
+                put_u32(&mut table, slot * 4, kept as u32);
+                changed = true;
+            }
+
+            self.walk_zone(inode, kept, level - 1, first_index.saturating_add(slot.saturating_mul(coverage)), needed, data)?;
+        }
+
+        if changed
+        {
+            self.blocks.insert(self.superblock.zone_block(zone), table);
+        }
+
+        Ok(())
+    }
+
+    /// Walk all of the zones of an inode, returning the data zones which hold its contents
+    fn walk_inode(&mut self, number: usize) -> Result<ZoneList, FsckError>
+    {
+        let mut data = Vec::new();
+        let mut inode = self.inodes[number];
+
+        if !inode.has_zones()
+        {
+            return Ok(data);
+        }
+
+        let block_size = self.superblock.block_size();
+        let per_block = block_size / 4;
+        let needed = (inode.size as usize).div_ceil(self.superblock.zone_size());
+
+        let mut first_index = 0;
+
+        for i in 0..inode.zones.len()
+        {
+            let level = i.saturating_sub(MINIX3_DIRECT_ZONES - 1) as u32;
+
+            inode.zones[i] = self.claim_zone(number, inode.zones[i] as usize) as u32;
+            self.walk_zone(number, inode.zones[i] as usize, level, first_index, needed, &mut data)?;
+
+            first_index = first_index.saturating_add(per_block.saturating_pow(level));
+        }
+
+        self.inodes[number] = inode;
+
+        Ok(data)
+    }
+
+    /// Mark an inode as reachable from the root, walking its zones and returning the data zones of a directory
+    fn reach(&mut self, number: usize) -> Result<Option<ZoneList>, FsckError>
+    {
+        self.reached[number] = true;
+
+        let zones = self.walk_inode(number)?;
+
+        Ok(if self.inodes[number].is_directory() { Some(zones) } else { None })
+    }
+
+    /// Check the entries of a directory, returning the directories found in it
+    fn check_directory(&mut self, number: usize, zones: ZoneList) -> Result<Vec<(usize, ZoneList)>, FsckError>
+    {
+        let superblock = self.superblock;
+        let block_size = superblock.block_size();
+        let mut inode = self.inodes[number];
+
+        // Directories are read a block at a time, so each zone is split into its blocks
+        let zone_blocks = superblock.zone_size() / block_size;
+        let zones: ZoneList = zones.iter()
+            .flat_map(|(index, zone)| (0..zone_blocks).map(move |i| (index * zone_blocks + i, superblock.zone_block(*zone) + i)))
+            .collect();
+
+        // Nothing past the last zone can be read, and entries never straddle the end of the directory
+        let mapped = zones.last().map(|(block, _)| (block + 1) * block_size).unwrap_or(0);
+        let size = (inode.size as usize).min(mapped) / MINIX3_DIR_ENTRY_SIZE * MINIX3_DIR_ENTRY_SIZE;
+
+        if size != inode.size as usize
+        {
+            self.problems.push(FsckProblem::BadDirectorySize { inode: number });
+            inode.size = size as u32;
+        }
+
+        let mut data = vec![0; size];
+
+        for (block, disk_block) in &zones
+        {
+            let start = block * block_size;
+
+            if start < size
+            {
+                let length = (size - start).min(block_size);
+                data[start..start + length].copy_from_slice(&self.read_block(*disk_block)?[..length]);
+            }
+        }
+
+        let mut kept = Vec::new();
+        let mut found = Vec::new();
+
+        for entry in data.chunks(MINIX3_DIR_ENTRY_SIZE)
+        {
+            let target = get_u32(entry, 0) as usize;
+            let name = &entry[4..];
+            let dots = name.starts_with(b".\0") || name.starts_with(b"..\0");
+
+            // Other implementations remove entries by clearing the inode number
+            if target == 0
+            {
+                kept.extend_from_slice(entry);
+                continue;
+            }
+
+            if target >= self.inodes.len() || self.inodes[target].mode == 0
+            {
+                self.problems.push(FsckProblem::BadEntry { directory: number, inode: target });
+                continue;
+            }
+
+            if !dots && self.reached[target] && self.inodes[target].is_directory()
+            {
+                self.problems.push(FsckProblem::DirectoryHardLink { directory: number, inode: target });
+                continue;
+            }
+
+            self.refs[target] += 1;
+            kept.extend_from_slice(entry);
+
+            if !dots && !self.reached[target]
+            {
+                if let Some(zones) = self.reach(target)?
+                {
+                    found.push((target, zones));
+                }
+            }
+        }
+
+        // Removed entries are dropped by moving the later entries back, as the kernel does
+        if kept.len() != data.len()
+        {
+            inode.size = kept.len() as u32;
+
+            for (block, disk_block) in &zones
+            {
+                let start = block * block_size;
+
+                if start < kept.len()
+                {
+                    let mut contents = self.read_block(*disk_block)?;
+                    let length = (kept.len() - start).min(block_size);
+
+                    contents[..length].copy_from_slice(&kept[start..start + length]);
+                    self.blocks.insert(*disk_block, contents);
+                }
+            }
+        }
+
+        self.inodes[number] = inode;
+
+        Ok(found)
+    }
+
+    /// Walk every directory reachable from the root
+    fn check_tree(&mut self) -> Result<(), FsckError>
+    {
+        if !self.inodes[MINIX3_ROOT_INODE].is_directory()
+        {
+            return Err(FsckError::BadRoot);
+        }
+
+        let zones = self.reach(MINIX3_ROOT_INODE)?.unwrap_or_default();
+        let mut stack = vec![(MINIX3_ROOT_INODE, zones)];
+
+        while let Some((directory, zones)) = stack.pop()
+        {
+            let found = self.check_directory(directory, zones)?;
+            stack.extend(found);
+        }
+
+        Ok(())
+    }
+
+    /// Compare the inode bitmap and link counts against the walk, freeing anything which was not reached
+    fn check_inodes(&mut self)
+    {
+        if !get_bit(&self.imap, 0)
+        {
+            self.problems.push(FsckProblem::InodeBitmap { inode: 0, used: true });
+            set_bit(&mut self.imap, 0, true);
+        }
+
+        for number in 1..self.inodes.len()
+        {
+            let marked = get_bit(&self.imap, number);
+
+            if self.reached[number]
+            {
+                if !marked
+                {
+                    self.problems.push(FsckProblem::InodeBitmap { inode: number, used: true });
+                }
+
+                let inode = &mut self.inodes[number];
+
+                if inode.nlinks as usize != self.refs[number]
+                {
+                    let actual = self.refs[number].min(u16::MAX as usize) as u16;
+
+                    self.problems.push(FsckProblem::LinkCount { inode: number, recorded: inode.nlinks, actual });
+                    inode.nlinks = actual;
+                }
+            }
+            else if marked
+            {
+                // The zones of the inode were never claimed, so they are freed along with it
+                self.problems.push(FsckProblem::Unreachable { inode: number });
+                self.inodes[number] = Minix3DiskInode::default();
+            }
+
+            set_bit(&mut self.imap, number, self.reached[number]);
+        }
+    }
+
+    /// Compare the zone bitmap against the zones found by the walk
+    fn check_zones(&mut self)
+    {
+        if !get_bit(&self.zmap, 0)
+        {
+            self.problems.push(FsckProblem::ZoneBitmap { zone: 0, used: true });
+            set_bit(&mut self.zmap, 0, true);
+        }
+
+        for (index, used) in self.zones_used.iter().enumerate()
+        {
+            let zone = index + self.superblock.first_data_zone as usize;
+            let bit = index + 1;
+
+            if get_bit(&self.zmap, bit) != *used
+            {
+                self.problems.push(FsckProblem::ZoneBitmap { zone, used: *used });
+                set_bit(&mut self.zmap, bit, *used);
+            }
+        }
+    }
+
+    /// Write the blocks of a region which differ from their original contents
+    fn write_changed(&mut self, start: usize, original: &[u8], data: &[u8]) -> Result<(), FsckError>
+    {
+        let block_size = self.superblock.block_size();
+
+        for (i, (old, new)) in original.chunks(block_size).zip(data.chunks(block_size)).enumerate()
+        {
+            if old != new && !self.device.write((start + i) * block_size, new)
+            {
+                return Err(FsckError::Io(start + i));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write every repair to the device, data first, then inodes, then bitmaps
+    fn write_repairs(&mut self) -> Result<(), FsckError>
+    {
+        let block_size = self.superblock.block_size();
+
+        for (block, data) in core::mem::take(&mut self.blocks)
+        {
+            if !self.device.write(block * block_size, &data)
+            {
+                return Err(FsckError::Io(block));
+            }
+        }
+
+        let mut table = self.table.clone();
+
+        for (number, inode) in self.inodes.iter().enumerate().skip(1)
+        {
+            let offset = (number - 1) * MINIX3_INODE_SIZE;
+            inode.to_bytes(&mut table[offset..offset + MINIX3_INODE_SIZE]);
+        }
+
+        let original = core::mem::take(&mut self.table);
+        self.write_changed(self.superblock.inode_table_start(), &original, &table)?;
+
+        let original = self.read_blocks(self.superblock.imap_start(), self.superblock.imap_blocks as usize)?;
+        let imap = core::mem::take(&mut self.imap);
+        self.write_changed(self.superblock.imap_start(), &original, &imap)?;
+
+        let original = self.read_blocks(self.superblock.zmap_start(), self.superblock.zmap_blocks as usize)?;
+        let zmap = core::mem::take(&mut self.zmap);
+        self.write_changed(self.superblock.zmap_start(), &original, &zmap)
+    }
+}
+
+/// Check the consistency of a Minix3 filesystem, repairing any problems found if `repair` is set
+pub fn check_minix3<D: Minix3Device + ?Sized>(device: &mut D, repair: bool) -> Result<FsckReport, FsckError>
+{
+    let mut checker = Checker::new(device)?;
+
+    checker.check_tree()?;
+    checker.check_inodes();
+    checker.check_zones();
+
+    let repaired = repair && !checker.problems.is_empty();
+
+    if repaired
+    {
+        checker.write_repairs()?;
+    }
+
+    Ok(FsckReport { problems: checker.problems, repaired })
+}
@@ -0,0 +1,7 @@
+//! Minix3 filesystem utilities
+
+mod fsck;
+pub use fsck::*;
+
+mod structures;
+pub use structures::*;
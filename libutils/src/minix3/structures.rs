@@ -0,0 +1,226 @@
+/// Magic number of a Minix3 superblock
+pub const MINIX3_MAGIC: u16 = 0x4d5a;
+
+/// Byte offset of the superblock, which does not depend on the block size
+pub const MINIX3_SUPERBLOCK_OFFSET: usize = 1024;
+
+/// Size of an inode on the disk
+pub const MINIX3_INODE_SIZE: usize = 64;
+
+/// Size of a directory entry
+pub const MINIX3_DIR_ENTRY_SIZE: usize = 64;
+
+/// Bit of the superblock flags which is set while the filesystem is not mounted read write, a filesystem without
+/// it was not unmounted cleanly
+pub const MINIX3_FLAG_CLEAN: u16 = 0x0001;
+
+/// Byte offset of the flags within the superblock
+pub const MINIX3_FLAGS_OFFSET: usize = 14;
+
+/// Inode number of the root directory
+pub const MINIX3_ROOT_INODE: usize = 1;
+
+/// Number of zones an inode refers to directly, they are followed by a single, double and triple indirect zone
+pub const MINIX3_DIRECT_ZONES: usize = 7;
+
+/// Mask of the file type bits of a mode
+pub const S_IFMT: u16 = 0o170000;
+
+/// Directory file type
+pub const S_IFDIR: u16 = 0o040000;
+
+/// Regular file type
+pub const S_IFREG: u16 = 0o100000;
+
+/// Symbolic link file type
+pub const S_IFLNK: u16 = 0o120000;
+
+/// Read a little endian u16
+pub fn get_u16(data: &[u8], offset: usize) -> u16
+{
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Read a little endian u32
+pub fn get_u32(data: &[u8], offset: usize) -> u32
+{
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Write a little endian u16
+pub fn put_u16(data: &mut [u8], offset: usize, value: u16)
+{
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Write a little endian u32
+pub fn put_u32(data: &mut [u8], offset: usize, value: u32)
+{
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Fields of a Minix3 superblock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Minix3Superblock
+{
+    pub ninodes: u32,
+    pub imap_blocks: u16,
+    pub zmap_blocks: u16,
+    pub first_data_zone: u16,
+    pub log_zone_size: u16,
+    pub flags: u16,
+    pub max_size: u32,
+    pub zones: u32,
+    pub magic: u16,
+    pub block_size: u16
+}
+
+impl Minix3Superblock
+{
+    /// Parse a superblock from the bytes at `MINIX3_SUPERBLOCK_OFFSET`
+    pub fn from_bytes(data: &[u8]) -> Self
+    {
+        Self
+        {
+            ninodes: get_u32(data, 0),
+            imap_blocks: get_u16(data, 6),
+            zmap_blocks: get_u16(data, 8),
+            first_data_zone: get_u16(data, 10),
+            log_zone_size: get_u16(data, 12),
+            flags: get_u16(data, MINIX3_FLAGS_OFFSET),
+            max_size: get_u32(data, 16),
+            zones: get_u32(data, 20),
+            magic: get_u16(data, 24),
+            block_size: get_u16(data, 28)
+        }
+    }
+
+    /// Get the size of a block in bytes, older images leave it unset in which case it is 1024
+    pub fn block_size(&self) -> usize
+    {
+        if self.block_size == 0 { 1024 } else { self.block_size as usize }
+    }
+
+    /// Get the size of a zone in bytes
+    pub fn zone_size(&self) -> usize
+    {
+        self.block_size() << self.log_zone_size
+    }
+
+    /// Get the first block of a zone
+    pub fn zone_block(&self, zone: usize) -> usize
+    {
+        zone << self.log_zone_size
+    }
+
+    /// Get the first block of the inode bitmap
+    pub fn imap_start(&self) -> usize
+    {
+        2
+    }
+
+    /// Get the first block of the zone bitmap
+    pub fn zmap_start(&self) -> usize
+    {
+        self.imap_start() + self.imap_blocks as usize
+    }
+
+    /// Get the first block of the inode table
+    pub fn inode_table_start(&self) -> usize
+    {
+        self.zmap_start() + self.zmap_blocks as usize
+    }
+
+    /// Get the number of blocks in the inode table
+    pub fn inode_table_blocks(&self) -> usize
+    {
+        (self.ninodes as usize * MINIX3_INODE_SIZE).div_ceil(self.block_size())
+    }
+
+    /// Get the bit of the zone bitmap tracking a zone, bit zero is reserved and bit one is the first data zone,
+    /// returns None for zones outside the data zones
+    pub fn zone_bit(&self, zone: usize) -> Option<usize>
+    {
+        if zone < self.first_data_zone as usize || zone >= self.zones as usize
+        {
+            None
+        }
+        else
+        {
+            Some(zone + 1 - self.first_data_zone as usize)
+        }
+    }
+}
+
+/// A Minix3 inode as stored on the disk, the layout matches the inode table so it can be read in place
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Minix3DiskInode
+{
+    pub mode: u16,
+    pub nlinks: u16,
+    pub uid: u16,
+    pub gid: u16,
+    pub size: u32,
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+    pub zones: [u32; 10]
+}
+
+impl Minix3DiskInode
+{
+    /// Parse an inode from its 64 bytes in the inode table
+    pub fn from_bytes(data: &[u8]) -> Self
+    {
+        let mut zones = [0; 10];
+
+        for (i, zone) in zones.iter_mut().enumerate()
+        {
+            *zone = get_u32(data, 24 + 4 * i);
+        }
+
+        Self
+        {
+            mode: get_u16(data, 0),
+            nlinks: get_u16(data, 2),
+            uid: get_u16(data, 4),
+            gid: get_u16(data, 6),
+            size: get_u32(data, 8),
+            atime: get_u32(data, 12),
+            mtime: get_u32(data, 16),
+            ctime: get_u32(data, 20),
+            zones
+        }
+    }
+
+    /// Write the inode into its 64 bytes in the inode table
+    pub fn to_bytes(&self, data: &mut [u8])
+    {
+        put_u16(data, 0, self.mode);
+        put_u16(data, 2, self.nlinks);
+        put_u16(data, 4, self.uid);
+        put_u16(data, 6, self.gid);
+        put_u32(data, 8, self.size);
+        put_u32(data, 12, self.atime);
+        put_u32(data, 16, self.mtime);
+        put_u32(data, 20, self.ctime);
+
+        for (i, zone) in self.zones.iter().enumerate()
+        {
+            put_u32(data, 24 + 4 * i, *zone);
+        }
+    }
+
+    /// Check if the inode is a directory
+    pub fn is_directory(&self) -> bool
+    {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    /// Check if the zones of the inode refer to data, device files keep their device number there instead
+    pub fn has_zones(&self) -> bool
+    {
+        matches!(self.mode & S_IFMT, S_IFDIR | S_IFREG | S_IFLNK)
+    }
+}
@@ -0,0 +1,47 @@
+extern crate libutils;
+
+/// Build a newc entry for the tests
+fn entry(archive: &mut Vec<u8>, inode: u32, mode: u32, name: &str, data: &[u8])
+{
+    let fields = [inode, mode, 0, 0, 1, 0, data.len() as u32, 0, 0, 0, 0, name.len() as u32 + 1, 0];
+
+    archive.extend_from_slice(b"070701");
+    for field in fields.iter()
+    {
+        archive.extend_from_slice(format!("{:08X}", field).as_bytes());
+    }
+
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    archive.resize((archive.len() + 3) & !3, 0);
+
+    archive.extend_from_slice(data);
+    archive.resize((archive.len() + 3) & !3, 0);
+}
+
+/// Test reading a newc archive
+#[test]
+pub fn test_cpio_newc()
+{
+    use libutils::cpio::{CpioArchive, CpioError};
+
+    let mut archive = Vec::new();
+    entry(&mut archive, 1, 0o40755, "bin", &[]);
+    entry(&mut archive, 2, 0o100755, "bin/init", b"hello");
+    entry(&mut archive, 0, 0, "TRAILER!!!", &[]);
+    archive.extend_from_slice(&[0; 512]);
+    entry(&mut archive, 3, 0o100644, "etc", b"abcd");
+    entry(&mut archive, 0, 0, "TRAILER!!!", &[]);
+
+    let entries = CpioArchive::new(&archive).collect::<Result<Vec<_>, _>>().unwrap();
+
+    assert_eq!(entries.iter().map(|e| e.name).collect::<Vec<_>>(), vec!["bin", "bin/init", "etc"]);
+    assert_eq!(entries[1].mode, 0o100755);
+    assert_eq!(entries[1].data, b"hello");
+    assert_eq!(entries[2].inode, 3);
+    assert_eq!(entries[2].data, b"abcd");
+
+    let truncated = &archive[..archive.len() / 4];
+    assert!(CpioArchive::new(truncated).any(|e| e.is_err()));
+    assert_eq!(CpioArchive::new(&[b'x'; 128]).next(), Some(Err(CpioError::BadMagic(0))));
+}
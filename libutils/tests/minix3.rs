@@ -0,0 +1,189 @@
+extern crate libutils;
+
+use libutils::minix3::*;
+
+/// Layout of a test image with 32 inodes and 64 zones
+struct Layout
+{
+    block: usize,
+    shift: u16,
+    first_zone: usize
+}
+
+impl Layout
+{
+    /// Lay out an image with the given block size and zone shift
+    fn new(block: usize, shift: u16) -> Self
+    {
+        let first_block = 4 + (32 * MINIX3_INODE_SIZE).div_ceil(block);
+
+        Self { block, shift, first_zone: first_block.div_ceil(1 << shift) }
+    }
+
+    /// Get the byte offset of a zone
+    fn zone(&self, zone: usize) -> usize
+    {
+        (zone << self.shift) * self.block
+    }
+
+    /// Write an inode into a test image
+    fn put_inode(&self, image: &mut [u8], number: usize, mode: u16, nlinks: u16, size: u32, zones: &[usize])
+    {
+        let mut inode = Minix3DiskInode { mode, nlinks, size, ..Default::default() };
+
+        for (slot, zone) in inode.zones.iter_mut().zip(zones)
+        {
+            *slot = *zone as u32;
+        }
+
+        let offset = 4 * self.block + (number - 1) * MINIX3_INODE_SIZE;
+        inode.to_bytes(&mut image[offset..offset + MINIX3_INODE_SIZE]);
+    }
+
+    /// Mark a zone as used or free in the zone bitmap of a test image
+    fn mark_zone(&self, image: &mut [u8], zone: usize, used: bool)
+    {
+        let bit = zone + 1 - self.first_zone;
+        let byte = 3 * self.block + bit / 8;
+
+        if used
+        {
+            image[byte] |= 1 << (bit % 8);
+        }
+        else
+        {
+            image[byte] &= !(1 << (bit % 8));
+        }
+    }
+
+    /// Write a directory entry into a test image
+    fn put_entry(&self, image: &mut [u8], zone: usize, slot: usize, inode: u32, name: &str)
+    {
+        let offset = self.zone(zone) + slot * MINIX3_DIR_ENTRY_SIZE;
+
+        put_u32(image, offset, inode);
+        image[offset + 4..offset + 4 + name.len()].copy_from_slice(name.as_bytes());
+    }
+
+    /// Build an image holding `/file` and the directory `/sub`
+    fn build(&self) -> Vec<u8>
+    {
+        let mut image = vec![0; self.zone(64)];
+        let zone = self.first_zone;
+
+        let superblock = MINIX3_SUPERBLOCK_OFFSET;
+        put_u32(&mut image, superblock, 32);
+        put_u16(&mut image, superblock + 6, 1);
+        put_u16(&mut image, superblock + 8, 1);
+        put_u16(&mut image, superblock + 10, zone as u16);
+        put_u16(&mut image, superblock + 12, self.shift);
+        put_u32(&mut image, superblock + 20, 64);
+        put_u16(&mut image, superblock + 24, MINIX3_MAGIC);
+        put_u16(&mut image, superblock + 28, self.block as u16);
+
+        // Inodes 0 to 3 and the first three data zones are in use, bit zero of each bitmap is reserved
+        image[2 * self.block] = 0b1111;
+        image[3 * self.block] = 0b1;
+
+        for used in zone..zone + 3
+        {
+            self.mark_zone(&mut image, used, true);
+        }
+
+        self.put_inode(&mut image, 1, S_IFDIR | 0o755, 3, 4 * 64, &[zone]);
+        self.put_inode(&mut image, 2, S_IFREG | 0o644, 1, 10, &[zone + 1]);
+        self.put_inode(&mut image, 3, S_IFDIR | 0o755, 2, 2 * 64, &[zone + 2]);
+
+        self.put_entry(&mut image, zone, 0, 1, ".");
+        self.put_entry(&mut image, zone, 1, 1, "..");
+        self.put_entry(&mut image, zone, 2, 2, "file");
+        self.put_entry(&mut image, zone, 3, 3, "sub");
+
+        self.put_entry(&mut image, zone + 2, 0, 3, ".");
+        self.put_entry(&mut image, zone + 2, 1, 1, "..");
+
+        image
+    }
+}
+
+/// Test checking and repairing a Minix3 filesystem
+#[test]
+pub fn test_minix3_fsck()
+{
+    let layout = Layout::new(1024, 0);
+    let zone = layout.first_zone;
+    let block = layout.block;
+
+    let mut image = layout.build();
+    let report = check_minix3(image.as_mut_slice(), false).unwrap();
+    assert_eq!(report.problems, vec![]);
+    assert!(report.is_clean());
+
+    // An entry for a free inode, a wrong link count, a leaked inode and a zone missing from the bitmap
+    layout.put_entry(&mut image, zone + 2, 2, 20, "ghost");
+    layout.put_inode(&mut image, 3, S_IFDIR | 0o755, 2, 3 * 64, &[zone + 2]);
+    layout.put_inode(&mut image, 2, S_IFREG | 0o644, 5, 10, &[zone + 1]);
+    layout.put_inode(&mut image, 4, S_IFREG | 0o644, 1, 10, &[zone + 3]);
+    image[2 * block] |= 0b10000;
+    layout.mark_zone(&mut image, zone + 2, false);
+    layout.mark_zone(&mut image, zone + 3, true);
+
+    let damaged = image.clone();
+    let report = check_minix3(image.as_mut_slice(), false).unwrap();
+    assert!(!report.is_clean());
+    assert_eq!(image, damaged);
+
+    for problem in &[
+        FsckProblem::BadEntry { directory: 3, inode: 20 },
+        FsckProblem::LinkCount { inode: 2, recorded: 5, actual: 1 },
+        FsckProblem::Unreachable { inode: 4 },
+        FsckProblem::ZoneBitmap { zone: zone + 2, used: true },
+        FsckProblem::ZoneBitmap { zone: zone + 3, used: false }]
+    {
+        assert!(report.problems.contains(problem), "missing {:?}", problem);
+    }
+
+    let report = check_minix3(image.as_mut_slice(), true).unwrap();
+    assert!(report.repaired && report.is_clean());
+    assert_eq!(check_minix3(image.as_mut_slice(), false).unwrap().problems, vec![]);
+
+    assert_eq!(image[2 * block], 0b1111);
+    assert_eq!(image[3 * block..4 * block], layout.build()[3 * block..4 * block]);
+    assert_eq!(get_u32(&image, 4 * block + 2 * MINIX3_INODE_SIZE + 8), 2 * 64);
+
+    // Zones outside the data zones and zones owned twice are dropped
+    layout.put_inode(&mut image, 2, S_IFREG | 0o644, 1, 3 * block as u32, &[zone + 1, 100, zone]);
+    let report = check_minix3(image.as_mut_slice(), true).unwrap();
+    assert!(report.problems.contains(&FsckProblem::ZoneOutOfRange { inode: 2, zone: 100 }));
+    assert!(report.problems.contains(&FsckProblem::DuplicateZone { inode: 2, zone }) || report.problems.contains(&FsckProblem::DuplicateZone { inode: 1, zone }));
+    assert_eq!(check_minix3(image.as_mut_slice(), false).unwrap().problems, vec![]);
+
+    // Zones below the first data zone must not wrap around into the bitmap
+    layout.put_inode(&mut image, 2, S_IFREG | 0o644, 1, block as u32, &[1]);
+    let report = check_minix3(image.as_mut_slice(), true).unwrap();
+    assert!(report.problems.contains(&FsckProblem::ZoneOutOfRange { inode: 2, zone: 1 }));
+
+    put_u16(&mut image, MINIX3_SUPERBLOCK_OFFSET + 24, 0x137f);
+    assert_eq!(check_minix3(image.as_mut_slice(), false), Err(FsckError::BadMagic));
+}
+
+/// Test checking a Minix3 filesystem with 4096 byte blocks and two blocks per zone
+#[test]
+pub fn test_minix3_fsck_large_zones()
+{
+    let layout = Layout::new(4096, 1);
+    let zone = layout.first_zone;
+
+    let mut image = layout.build();
+    assert_eq!(check_minix3(image.as_mut_slice(), false).unwrap().problems, vec![]);
+
+    // An entry in the second block of the root zone is found, and its inode is reachable
+    layout.put_inode(&mut image, 1, S_IFDIR | 0o755, 3, (4096 + 64) as u32, &[zone]);
+    layout.put_inode(&mut image, 4, S_IFREG | 0o644, 1, 0, &[]);
+    put_u32(&mut image, layout.zone(zone) + 4096, 4);
+    image[2 * 4096] |= 0b10000;
+
+    // Zero inode numbers between the entries are free slots
+    let report = check_minix3(image.as_mut_slice(), false).unwrap();
+    assert_eq!(report.problems, vec![]);
+}
@@ -12,6 +12,9 @@ _start:
     la gp, _global_pointer
     .option pop
 
+    # Save the device tree pointer passed by the bootloader before a1 is reused
+    mv s1, a1
+
     # Make sure we are in machine mode
     csrw satp, zero
 
@@ -59,6 +62,9 @@ _start_init:
     # Set up the return address for when kinit returns
     la ra, _start_kinit_return
 
+    # Pass the device tree pointer to kinit
+    mv a0, s1
+
     # Call kinit
     mret
 
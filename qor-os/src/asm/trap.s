@@ -132,4 +132,11 @@ asm_wait_for_int:
     la ra, _start_wfi_loop
     
     # Jump to _start_wfi_loop
-    mret
\ No newline at end of file
+    mret
+
+.globl asm_request_writeback
+asm_request_writeback:
+    # Make the sync system call, the kernel only runs a due background writeback for the init process
+    li a7, 162
+    ecall
+    ret
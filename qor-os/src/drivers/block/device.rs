@@ -0,0 +1,278 @@
+use crate::*;
+
+use crate::drivers::virtio::drivers::block::*;
+
+use super::{BlockOperation, BlockQueue};
+
+/// Generic block device, addressed in sectors of `BLOCK_SECTOR_SIZE` bytes
+pub trait BlockDevice
+{
+    /// Get the size of the device in sectors
+    fn sector_count(&self) -> u64;
+
+    /// Read `count` sectors starting at `sector` into the buffer
+    fn read_sectors(&mut self, sector: u64, buffer: *mut u8, count: usize) -> Result<(), BlockRequestError>;
+
+    /// Write `count` sectors starting at `sector` from the buffer
+    fn write_sectors(&mut self, sector: u64, buffer: *const u8, count: usize) -> Result<(), BlockRequestError>;
+
+    /// Flush any writes held by the device to stable storage
+    fn flush(&mut self) -> Result<(), BlockRequestError>;
+
+    /// Get the request queue for the device if it can perform requests in the background
+    fn request_queue(&mut self) -> Option<DeviceQueue<'_>>
+    {
+        None
+    }
+
+    /// Check a range of sectors lies within the device
+    fn check_range(&self, sector: u64, count: usize) -> Result<(), BlockRequestError>
+    {
+        if sector + count as u64 > self.sector_count()
+        {
+            Err(BlockRequestError::OutOfRange)
+        }
+        else
+        {
+            Ok(())
+        }
+    }
+}
+
+impl BlockDevice for BlockQueue
+{
+    /// Get the size of the device in sectors
+    fn sector_count(&self) -> u64
+    {
+        self.get_capacity()
+    }
+
+    /// Read `count` sectors starting at `sector` into the buffer
+    fn read_sectors(&mut self, sector: u64, buffer: *mut u8, count: usize) -> Result<(), BlockRequestError>
+    {
+        self.check_range(sector, count)?;
+
+        if self.sync_read(buffer, (count as u64 * BLOCK_SECTOR_SIZE) as u32, sector * BLOCK_SECTOR_SIZE)
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(BlockRequestError::DeviceError)
+        }
+    }
+
+    /// Write `count` sectors starting at `sector` from the buffer
+    fn write_sectors(&mut self, sector: u64, buffer: *const u8, count: usize) -> Result<(), BlockRequestError>
+    {
+        self.check_range(sector, count)?;
+
+        if self.sync_write(buffer as *mut u8, (count as u64 * BLOCK_SECTOR_SIZE) as u32, sector * BLOCK_SECTOR_SIZE)
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(BlockRequestError::DeviceError)
+        }
+    }
+
+    /// Flush any writes held by the device to stable storage
+    fn flush(&mut self) -> Result<(), BlockRequestError>
+    {
+        if BlockQueue::flush(self)
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(BlockRequestError::DeviceError)
+        }
+    }
+
+    /// Get the request queue for the device if it can perform requests in the background
+    fn request_queue(&mut self) -> Option<DeviceQueue<'_>>
+    {
+        let length = self.get_capacity() * BLOCK_SECTOR_SIZE;
+        Some(DeviceQueue { queue: self, offset: 0, length })
+    }
+}
+
+/// View of a request queue covering a range of bytes on its device, offsets are relative to the start of the range
+pub struct DeviceQueue<'a>
+{
+    queue: &'a mut BlockQueue,
+    offset: u64,
+    length: u64
+}
+
+impl<'a> DeviceQueue<'a>
+{
+    /// Narrow the view to `length` bytes starting at `offset` within it
+    pub fn window(self, offset: u64, length: u64) -> Self
+    {
+        Self
+        {
+            queue: self.queue,
+            offset: self.offset + offset,
+            length: length.min(self.length.saturating_sub(offset))
+        }
+    }
+
+    /// Add an operation to the queue, returning its position in the next dispatch
+    pub fn queue(&mut self, operation: BlockOperation, offset: u64, buffer: *mut u8, length: u32) -> Result<usize, BlockRequestError>
+    {
+        if offset + length as u64 > self.length
+        {
+            return Err(BlockRequestError::OutOfRange);
+        }
+
+        self.queue.queue(operation, self.offset + offset, buffer, length)
+    }
+
+    /// Send every queued operation to the device
+    pub fn dispatch(&mut self) -> Vec<Result<BlockRequestToken, BlockRequestError>>
+    {
+        self.queue.dispatch()
+    }
+
+    /// Check if the request with the given token has completed
+    pub fn is_complete(&mut self, token: BlockRequestToken) -> bool
+    {
+        self.queue.is_complete(token)
+    }
+
+    /// Wait for the request with the given token to complete, returning whether it succeeded
+    pub fn finish(&mut self, token: BlockRequestToken) -> bool
+    {
+        self.queue.finish(token)
+    }
+}
+
+/// Block device backed by kernel memory
+pub struct RamBlockDevice
+{
+    data: Vec<u8>
+}
+
+impl RamBlockDevice
+{
+    /// Create a new zeroed RAM block device with the given number of sectors
+    pub fn new(sectors: u64) -> Self
+    {
+        Self
+        {
+            data: alloc::vec![0; (sectors * BLOCK_SECTOR_SIZE) as usize]
+        }
+    }
+
+    /// Create a RAM block device holding a copy of an image, padded to a whole number of sectors
+    pub fn from_image(image: &[u8]) -> Self
+    {
+        let mut data = image.to_vec();
+        let sectors = (data.len() as u64 + BLOCK_SECTOR_SIZE - 1) / BLOCK_SECTOR_SIZE;
+        data.resize((sectors * BLOCK_SECTOR_SIZE) as usize, 0);
+
+        Self { data }
+    }
+}
+
+impl BlockDevice for RamBlockDevice
+{
+    /// Get the size of the device in sectors
+    fn sector_count(&self) -> u64
+    {
+        self.data.len() as u64 / BLOCK_SECTOR_SIZE
+    }
+
+    /// Read `count` sectors starting at `sector` into the buffer
+    fn read_sectors(&mut self, sector: u64, buffer: *mut u8, count: usize) -> Result<(), BlockRequestError>
+    {
+        self.check_range(sector, count)?;
+
+        let start = (sector * BLOCK_SECTOR_SIZE) as usize;
+        let length = count * BLOCK_SECTOR_SIZE as usize;
+
+        unsafe { core::ptr::copy(self.data[start..].as_ptr(), buffer, length) };
+
+        Ok(())
+    }
+
+    /// Write `count` sectors starting at `sector` from the buffer
+    fn write_sectors(&mut self, sector: u64, buffer: *const u8, count: usize) -> Result<(), BlockRequestError>
+    {
+        self.check_range(sector, count)?;
+
+        let start = (sector * BLOCK_SECTOR_SIZE) as usize;
+        let length = count * BLOCK_SECTOR_SIZE as usize;
+
+        unsafe { core::ptr::copy(buffer, self.data[start..].as_mut_ptr(), length) };
+
+        Ok(())
+    }
+
+    /// Flush any writes held by the device to stable storage
+    fn flush(&mut self) -> Result<(), BlockRequestError>
+    {
+        Ok(())
+    }
+}
+
+/// A range of sectors on another registered block device
+pub struct Partition
+{
+    parent: usize,
+    start: u64,
+    sectors: u64
+}
+
+impl Partition
+{
+    /// Create a new partition covering `sectors` sectors of the device at index `parent`, starting at `start`
+    pub fn new(parent: usize, start: u64, sectors: u64) -> Self
+    {
+        Self { parent, start, sectors }
+    }
+
+    /// Get the device the partition lives on
+    fn parent(&self) -> Result<&'static mut dyn BlockDevice, BlockRequestError>
+    {
+        super::get_block_device(self.parent).ok_or(BlockRequestError::OutOfRange)
+    }
+}
+
+impl BlockDevice for Partition
+{
+    /// Get the size of the device in sectors
+    fn sector_count(&self) -> u64
+    {
+        self.sectors
+    }
+
+    /// Read `count` sectors starting at `sector` into the buffer
+    fn read_sectors(&mut self, sector: u64, buffer: *mut u8, count: usize) -> Result<(), BlockRequestError>
+    {
+        self.check_range(sector, count)?;
+        self.parent()?.read_sectors(self.start + sector, buffer, count)
+    }
+
+    /// Write `count` sectors starting at `sector` from the buffer
+    fn write_sectors(&mut self, sector: u64, buffer: *const u8, count: usize) -> Result<(), BlockRequestError>
+    {
+        self.check_range(sector, count)?;
+        self.parent()?.write_sectors(self.start + sector, buffer, count)
+    }
+
+    /// Flush any writes held by the device to stable storage
+    fn flush(&mut self) -> Result<(), BlockRequestError>
+    {
+        self.parent()?.flush()
+    }
+
+    /// Get the request queue for the device if it can perform requests in the background
+    fn request_queue(&mut self) -> Option<DeviceQueue<'_>>
+    {
+        let queue = self.parent().ok()?.request_queue()?;
+        Some(queue.window(self.start * BLOCK_SECTOR_SIZE, self.sectors * BLOCK_SECTOR_SIZE))
+    }
+}
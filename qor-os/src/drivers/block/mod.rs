@@ -0,0 +1,147 @@
+//! Block layer between the filesystems and the block device drivers
+
+use crate::*;
+
+mod device;
+pub use device::*;
+
+mod queue;
+pub use queue::*;
+
+use crate::drivers::virtio::drivers::block::BLOCK_SECTOR_SIZE;
+
+/// Offset of the partition table in a master boot record
+const MBR_PARTITION_TABLE: usize = 446;
+
+/// Number of entries in a master boot record partition table
+const MBR_PARTITION_COUNT: usize = 4;
+
+/// Size of the RAM disk registered as /dev/ram0 in sectors
+const RAM_DISK_SECTORS: u64 = 2048;
+
+// Request queues for each of the block devices
+static mut BLOCK_QUEUES: Option<Vec<BlockQueue>> = None;
+
+// Every registered block device along with its name under /dev
+static mut BLOCK_DEVICES: Option<Vec<(String, &'static mut dyn BlockDevice)>> = None;
+
+/// Get the request queue for the block device with the given index
+pub fn get_block_queue(index: usize) -> Option<&'static mut BlockQueue>
+{
+    let queues = unsafe { &mut BLOCK_QUEUES };
+
+    // The queues are built the first time they are needed, after every device has been discovered
+    if queues.is_none()
+    {
+        let mut new_queues = Vec::new();
+
+        while let Some(driver) = drivers::virtio::get_block_driver(new_queues.len())
+        {
+            new_queues.push(BlockQueue::new(driver));
+        }
+
+        *queues = Some(new_queues);
+    }
+
+    queues.as_mut().unwrap().get_mut(index)
+}
+
+/// Get the list of registered block devices
+fn get_block_devices() -> &'static mut Vec<(String, &'static mut dyn BlockDevice)>
+{
+    let devices = unsafe { &mut BLOCK_DEVICES };
+
+    if devices.is_none()
+    {
+        *devices = Some(Vec::new());
+    }
+
+    devices.as_mut().unwrap()
+}
+
+/// Register a block device under the given name, returning its index
+pub fn register_block_device(name: String, device: &'static mut dyn BlockDevice) -> usize
+{
+    let devices = get_block_devices();
+
+    kdebugln!(BlockDevice, "Registered block device {} with {} sectors", name, device.sector_count());
+
+    devices.push((name, device));
+    devices.len() - 1
+}
+
+/// Get the block device with the given index
+pub fn get_block_device(index: usize) -> Option<&'static mut dyn BlockDevice>
+{
+    get_block_devices().get_mut(index).map(|(_, device)| &mut **device)
+}
+
+/// Get the name of the block device with the given index
+pub fn get_block_device_name(index: usize) -> Option<String>
+{
+    get_block_devices().get(index).map(|(name, _)| name.clone())
+}
+
+/// Get the number of registered block devices
+pub fn block_device_count() -> usize
+{
+    get_block_devices().len()
+}
+
+/// Register a partition for every entry in the master boot record of a device
+fn scan_partitions(index: usize)
+{
+    let name = get_block_device_name(index).unwrap();
+    let device = get_block_device(index).unwrap();
+
+    let mut sector = [0u8; BLOCK_SECTOR_SIZE as usize];
+
+    if device.read_sectors(0, sector.as_mut_ptr(), 1).is_err() || sector[510] != 0x55 || sector[511] != 0xAA
+    {
+        return;
+    }
+
+    for i in 0..MBR_PARTITION_COUNT
+    {
+        let entry = &sector[MBR_PARTITION_TABLE + 16 * i..MBR_PARTITION_TABLE + 16 * (i + 1)];
+
+        let kind = entry[4];
+        let start = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64;
+        let count = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as u64;
+
+        if kind == 0 || count == 0
+        {
+            continue;
+        }
+
+        if start + count > device.sector_count()
+        {
+            kwarnln!("Partition {} of {} extends past the end of the device", i + 1, name);
+            continue;
+        }
+
+        let partition = Box::leak(Box::new(Partition::new(index, start, count)));
+        register_block_device(format!("{}{}", name, i + 1), partition);
+    }
+}
+
+/// Register every virtio block device as /dev/vda, /dev/vdb, ... along with any partitions on them, and a RAM disk
+/// as /dev/ram0
+pub fn init_block_devices()
+{
+    let mut disks = Vec::new();
+
+    while let Some(queue) = get_block_queue(disks.len())
+    {
+        let name = format!("vd{}", (b'a' + disks.len() as u8) as char);
+        disks.push(register_block_device(name, queue));
+    }
+
+    for disk in disks
+    {
+        scan_partitions(disk);
+    }
+
+    let ram_disk = Box::leak(Box::new(RamBlockDevice::new(RAM_DISK_SECTORS)));
+    register_block_device(String::from("ram0"), ram_disk);
+}
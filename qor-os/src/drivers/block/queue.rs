@@ -0,0 +1,278 @@
+use crate::*;
+
+use crate::drivers::virtio::drivers::block::*;
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+
+/// Kind of operation to perform on a block device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockOperation
+{
+    Read,
+    Write
+}
+
+/// An operation which has been queued but not yet sent to the device
+struct QueuedOperation
+{
+    operation: BlockOperation,
+    sector: u64,
+    buffer: *mut u8,
+    length: u32
+}
+
+/// Request queue sitting between the filesystems and a block device driver
+///
+/// Operations are collected with `queue` and sent to the device with `dispatch`, at which point operations on
+/// adjacent sectors are merged into a single scatter-gather request. Every operation merged into a request
+/// shares its token, the result of the request is held until every one of them has been finished.
+pub struct BlockQueue
+{
+    driver: &'static mut BlockDriver,
+    queued: Vec<QueuedOperation>,
+    outstanding: BTreeMap<BlockRequestToken, (usize, Option<bool>)>
+}
+
+impl BlockQueue
+{
+    /// Create a new request queue for the given driver
+    pub fn new(driver: &'static mut BlockDriver) -> Self
+    {
+        Self
+        {
+            driver,
+            queued: Vec::new(),
+            outstanding: BTreeMap::new()
+        }
+    }
+
+    /// Get the index of the underlying device
+    pub fn get_index(&self) -> usize
+    {
+        self.driver.get_index()
+    }
+
+    /// Get the capacity of the device in sectors
+    pub fn get_capacity(&self) -> u64
+    {
+        self.driver.get_capacity()
+    }
+
+    /// Add an operation to the queue, returning its position in the next dispatch
+    pub fn queue(&mut self, operation: BlockOperation, offset: u64, buffer: *mut u8, length: u32) -> Result<usize, BlockRequestError>
+    {
+        if offset % BLOCK_SECTOR_SIZE != 0 || length as u64 % BLOCK_SECTOR_SIZE != 0
+        {
+            return Err(BlockRequestError::Unsupported);
+        }
+
+        let sector = offset / BLOCK_SECTOR_SIZE;
+
+        if sector + length as u64 / BLOCK_SECTOR_SIZE > self.get_capacity()
+        {
+            return Err(BlockRequestError::OutOfRange);
+        }
+
+        self.queued.push(QueuedOperation { operation, sector, buffer, length });
+
+        Ok(self.queued.len() - 1)
+    }
+
+    /// Submit a request to the driver, waiting for an outstanding request to finish if the driver has no room
+    fn submit(&mut self, blktype: u32, sector: u64, segments: &[(*mut u8, u32)]) -> Result<BlockRequestToken, BlockRequestError>
+    {
+        loop
+        {
+            match self.driver.submit(blktype, sector, segments)
+            {
+                Err(BlockRequestError::PoolExhausted) =>
+                {
+                    // Retire the oldest request whose result has not been collected yet to free up its slot
+                    let token = self.outstanding.iter().find(|(_, (_, result))| result.is_none()).map(|(token, _)| *token);
+
+                    if let Some(token) = token
+                    {
+                        let result = self.driver.sync(token);
+                        self.outstanding.get_mut(&token).unwrap().1 = Some(result);
+                    }
+                    else
+                    {
+                        return Err(BlockRequestError::PoolExhausted);
+                    }
+                },
+                result => return result
+            }
+        }
+    }
+
+    /// Send every queued operation to the device, merging operations on adjacent sectors, returns the token for
+    /// each operation in the order they were queued
+    pub fn dispatch(&mut self) -> Vec<Result<BlockRequestToken, BlockRequestError>>
+    {
+        let queued = core::mem::take(&mut self.queued);
+
+        let mut order: Vec<usize> = (0..queued.len()).collect();
+        order.sort_by_key(|i| queued[*i].sector);
+
+        let mut results = vec![Err(BlockRequestError::Unsupported); queued.len()];
+
+        let mut i = 0;
+
+        while i < order.len()
+        {
+            let first = &queued[order[i]];
+
+            let mut members = vec![order[i]];
+            let mut segments = vec![(first.buffer, first.length)];
+            let mut next_sector = first.sector + first.length as u64 / BLOCK_SECTOR_SIZE;
+
+            // Extend the request for as long as the following operations continue where it leaves off
+            while i + members.len() < order.len() && segments.len() < self.driver.max_segments()
+            {
+                let next = &queued[order[i + members.len()]];
+
+                if next.operation != first.operation || next.sector != next_sector
+                {
+                    break;
+                }
+
+                members.push(order[i + members.len()]);
+                segments.push((next.buffer, next.length));
+                next_sector += next.length as u64 / BLOCK_SECTOR_SIZE;
+            }
+
+            if members.len() > 1
+            {
+                kdebugln!(BlockDevice, "Merged {} operations starting at sector {}", members.len(), first.sector);
+            }
+
+            let blktype = match first.operation
+            {
+                BlockOperation::Read => VIRTIO_BLK_T_IN,
+                BlockOperation::Write => VIRTIO_BLK_T_OUT
+            };
+
+            let result = self.submit(blktype, first.sector, &segments);
+
+            if let Ok(token) = result
+            {
+                self.outstanding.insert(token, (members.len(), None));
+            }
+
+            for member in &members
+            {
+                results[*member] = result;
+            }
+
+            i += members.len();
+        }
+
+        results
+    }
+
+    /// Check if the request for the given token has completed
+    pub fn is_complete(&mut self, token: BlockRequestToken) -> bool
+    {
+        if let Some((_, Some(_))) = self.outstanding.get(&token)
+        {
+            return true;
+        }
+
+        self.driver.is_complete(token)
+    }
+
+    /// Finish an operation, waiting for it to complete if needed, returns true if the operation succeeded
+    pub fn finish(&mut self, token: BlockRequestToken) -> bool
+    {
+        if let Some((remaining, result)) = self.outstanding.get_mut(&token)
+        {
+            let value = match result
+            {
+                Some(value) => *value,
+                None =>
+                {
+                    let value = self.driver.sync(token);
+                    *result = Some(value);
+                    value
+                }
+            };
+
+            *remaining -= 1;
+
+            if *remaining == 0
+            {
+                self.outstanding.remove(&token);
+            }
+
+            value
+        }
+        else
+        {
+            false
+        }
+    }
+
+    /// Queue and dispatch a single operation, waiting for it to complete
+    fn sync_operation(&mut self, operation: BlockOperation, offset: u64, buffer: *mut u8, length: u32) -> bool
+    {
+        let result = self.queue(operation, offset, buffer, length).and_then(|_| self.dispatch().pop().unwrap());
+
+        match result
+        {
+            Ok(token) => self.finish(token),
+            Err(e) =>
+            {
+                kwarnln!("Block operation on device {} at offset 0x{:x} failed: {:?}", self.get_index(), offset, e);
+                false
+            }
+        }
+    }
+
+    /// Read from the device, waiting for the read to complete
+    pub fn sync_read(&mut self, buffer: *mut u8, length: u32, offset: u64) -> bool
+    {
+        self.sync_operation(BlockOperation::Read, offset, buffer, length)
+    }
+
+    /// Write to the device, waiting for the write to complete
+    pub fn sync_write(&mut self, buffer: *mut u8, length: u32, offset: u64) -> bool
+    {
+        self.sync_operation(BlockOperation::Write, offset, buffer, length)
+    }
+
+    /// Flush the device's write cache, devices without a write cache succeed immediately
+    pub fn flush(&mut self) -> bool
+    {
+        if !self.driver.supports_flush()
+        {
+            return true;
+        }
+
+        match self.submit(VIRTIO_BLK_T_FLUSH, 0, &[])
+        {
+            Ok(token) => self.driver.sync(token),
+            Err(_) => false
+        }
+    }
+
+    /// Tell the device a range of sectors is no longer in use, returns false if the device does not support it
+    pub fn discard(&mut self, offset: u64, length: u32) -> bool
+    {
+        if !self.driver.supports_discard() || offset % BLOCK_SECTOR_SIZE != 0
+        {
+            return false;
+        }
+
+        if offset / BLOCK_SECTOR_SIZE + length as u64 / BLOCK_SECTOR_SIZE > self.get_capacity()
+        {
+            return false;
+        }
+
+        match self.submit(VIRTIO_BLK_T_DISCARD, offset / BLOCK_SECTOR_SIZE, &[(core::ptr::null_mut(), length)])
+        {
+            Ok(token) => self.driver.sync(token),
+            Err(_) => false
+        }
+    }
+}
@@ -3,6 +3,7 @@
 use crate::*;
 
 // Modules for each driver
+pub mod block;
 pub mod generic;
 pub mod gpu;
 pub mod mmio;
@@ -113,6 +113,8 @@ impl UARTDriver
         if let Some(byte) = unsafe { read_byte(self.base) }
         {
             self.tty_push_byte(byte);
+
+            crate::process::readiness::notify_readiness();
         }
     }
 }
@@ -165,7 +165,7 @@ pub fn initialize_virtio_devices()
                         },
                         Ok(features) =>
                         {
-                            let mut block_driver = super::drivers::block::BlockDriver::new(driver);
+                            let mut block_driver = super::drivers::block::BlockDriver::new(driver, devices.block_devices.len());
 
                             if let Err(e) = block_driver.device_specific(features)
                             {
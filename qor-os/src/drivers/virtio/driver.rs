@@ -128,14 +128,57 @@ impl VirtIODeviceDriver
     {
         let queue_ref = unsafe { self.queues[queue].as_mut().unwrap() };
 
-        // Insert the descriptor ptr into the queue
-        queue_ref.avail.ring[queue_ref.avail.idx as usize] = index as u16;
-        queue_ref.avail.idx = (queue_ref.avail.idx + 1) % VIRTIO_QUEUE_SIZE as u16;
+        // Insert the descriptor ptr into the queue, the index itself is free running and only wrapped when indexing
+        // into the ring
+        queue_ref.avail.ring[queue_ref.avail.idx as usize % VIRTIO_QUEUE_SIZE as usize] = index as u16;
+        queue_ref.avail.idx = queue_ref.avail.idx.wrapping_add(1);
 
         // Notify the device
         self.device.write_field(Field::QueueNotify, queue as u32);
     }
 
+    /// Pop the next entry the device has placed in the used ring of the given queue, returning the index of the head
+    /// descriptor of the completed chain
+    pub fn pop_used(&mut self, queue: usize) -> Option<u16>
+    {
+        let queue_ref = unsafe { self.queues[queue].as_mut().unwrap() };
+
+        // The device writes the used index, so it must be read volatile
+        let used_idx = unsafe { (&queue_ref.used.idx as *const u16).read_volatile() };
+
+        if self.queue_aux_data[queue].ack_index as u16 == used_idx
+        {
+            return None;
+        }
+
+        let ring_index = self.queue_aux_data[queue].ack_index % VIRTIO_QUEUE_SIZE as usize;
+        let id = unsafe { (&queue_ref.used.ring[ring_index].id as *const u32).read_volatile() };
+
+        self.queue_aux_data[queue].ack_index = (self.queue_aux_data[queue].ack_index + 1) % (u16::MAX as usize + 1);
+
+        Some(id as u16)
+    }
+
+    /// Acknowledge any pending interrupts from the device
+    pub fn acknowledge_interrupt(&mut self)
+    {
+        let status = self.device.read_field(Field::InterruptStatus);
+        self.device.write_field(Field::InterruptAck, status);
+    }
+
+    /// Read a 32 bit value from the device specific configuration space
+    pub fn read_config(&self, offset: usize) -> u32
+    {
+        // Safety: The configuration space follows the fixed mmio fields of the device
+        unsafe { crate::drivers::mmio::read_offset(self.device.base, Field::Config as usize + offset) }
+    }
+
+    /// Get the base address of the device
+    pub fn get_base(&self) -> usize
+    {
+        self.device.base
+    }
+
     /// Add a VirtIODescriptor to one of the loaded queues
     pub fn add_descriptor_to_queue(&mut self, queue: usize, descriptor: VirtIODescriptor) -> usize
     {
@@ -160,6 +203,21 @@ impl VirtIODeviceDriver
         self.queue_aux_data[queue].index
     }
 
+    /// Write a VirtIODescriptor at a fixed index of one of the loaded queues, for drivers which divide the ring
+    /// between their own requests rather than taking descriptors in turn
+    pub fn write_descriptor(&mut self, queue: usize, index: usize, descriptor: VirtIODescriptor)
+    {
+        let idx = index % VIRTIO_QUEUE_SIZE as usize;
+
+        unsafe { &mut *self.queues[queue] }.desc[idx] = descriptor;
+
+        // If another descriptor is required, link to the next descriptor entry
+        if descriptor.flags & VIRTIO_DESC_F_NEXT > 0
+        {
+            unsafe { &mut *self.queues[queue] }.desc[idx].next = ((idx + 1) % VIRTIO_QUEUE_SIZE as usize) as u16;
+        }
+    }
+
     /// Internal VirtIO device driver initialization, should be called wrapped
     /// in an error handler which will set the failed bit to notify the device
     /// of the failure
@@ -3,3 +3,28 @@ pub const VIRTIO_BLK_T_OUT: u32 = 1;
 pub const VIRTIO_BLK_T_FLUSH: u32 = 4;
 pub const VIRTIO_BLK_T_DISCARD: u32 = 11;
 pub const VIRTIO_BLK_T_WRITE_ZEROES: u32 = 13;
+
+
+// Status values written by the device
+pub const VIRTIO_BLK_S_OK: u8 = 0;
+pub const VIRTIO_BLK_S_IOERR: u8 = 1;
+pub const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+// Feature bits
+pub const VIRTIO_BLK_F_RO: u32 = 1 << 5;
+pub const VIRTIO_BLK_F_FLUSH: u32 = 1 << 9;
+pub const VIRTIO_BLK_F_DISCARD: u32 = 1 << 13;
+pub const VIRTIO_RING_F_INDIRECT_DESC: u32 = 1 << 28;
+
+// Number of requests which can be outstanding on a single device
+pub const BLOCK_REQUEST_POOL_SIZE: usize = 32;
+
+// Maximum number of data segments in a single request
+pub const BLOCK_MAX_SEGMENTS: usize = 32;
+
+// Number of ring descriptors owned by each request slot, a request which is not placed in an indirect table must
+// fit its whole chain in its slot's descriptors
+pub const BLOCK_SLOT_DESCRIPTORS: usize = crate::drivers::virtio::VIRTIO_QUEUE_SIZE as usize / BLOCK_REQUEST_POOL_SIZE;
+
+// Size of a sector on a block device
+pub const BLOCK_SECTOR_SIZE: u64 = 512;
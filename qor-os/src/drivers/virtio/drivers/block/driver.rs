@@ -5,31 +5,107 @@ use crate::drivers::virtio::*;
 use super::structs::*;
 use super::consts::*;
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Value placed in the status of a request before it is sent to the device
+const STATUS_PENDING: u8 = 111;
+
 /// VirtIO Block Driver
 pub struct BlockDriver
 {
-    pub device: VirtIODeviceDriver
+    pub device: VirtIODeviceDriver,
+    index: usize,
+    features: u32,
+    capacity: u64,
+    requests: Vec<Request>,
+    indirect_tables: Vec<Box<[VirtIODescriptor; BLOCK_MAX_SEGMENTS + 2]>>,
+    next_id: usize,
+    polling: AtomicBool
 }
 
 impl BlockDriver
 {
     /// Create a new block driver from a device driver
-    pub fn new(device: VirtIODeviceDriver) -> Self
+    pub fn new(device: VirtIODeviceDriver, index: usize) -> Self
     {
         if device.get_device_type() != VirtIODeviceType::BlockDevice
         {
             panic!("Cannot create block device from {:?}", device.get_device_type());
         }
 
+        // The request pool is allocated once and never grows, so the device can safely hold pointers into it
+        let mut requests = Vec::with_capacity(BLOCK_REQUEST_POOL_SIZE);
+        let mut indirect_tables = Vec::with_capacity(BLOCK_REQUEST_POOL_SIZE);
+
+        let empty = VirtIODescriptor { addr: 0, len: 0, flags: 0, next: 0 };
+
+        for _ in 0..BLOCK_REQUEST_POOL_SIZE
+        {
+            requests.push(Request::new());
+            indirect_tables.push(Box::new([empty; BLOCK_MAX_SEGMENTS + 2]));
+        }
+
         Self
         {
-            device
+            device,
+            index,
+            features: 0,
+            capacity: 0,
+            requests,
+            indirect_tables,
+            next_id: 0,
+            polling: AtomicBool::new(false)
+        }
+    }
+
+    /// Get the index of the block device
+    pub fn get_index(&self) -> usize
+    {
+        self.index
+    }
+
+    /// Get the capacity of the device in sectors
+    pub fn get_capacity(&self) -> u64
+    {
+        self.capacity
+    }
+
+    /// Get the largest number of data segments a single request may carry, without indirect descriptors the
+    /// header, segments and status must all fit in the descriptors owned by the request's slot
+    pub fn max_segments(&self) -> usize
+    {
+        if self.features & VIRTIO_RING_F_INDIRECT_DESC > 0
+        {
+            BLOCK_MAX_SEGMENTS
         }
+        else
+        {
+            BLOCK_MAX_SEGMENTS.min(BLOCK_SLOT_DESCRIPTORS - 2)
+        }
+    }
+
+    /// Check if the device accepts flush requests
+    pub fn supports_flush(&self) -> bool
+    {
+        self.features & VIRTIO_BLK_F_FLUSH > 0
+    }
+
+    /// Check if the device accepts discard requests
+    pub fn supports_discard(&self) -> bool
+    {
+        self.features & VIRTIO_BLK_F_DISCARD > 0
     }
 
     /// Perform the device specific initialization
-    pub fn device_specific(&mut self, _features: u32) -> Result<(), String>
+    pub fn device_specific(&mut self, features: u32) -> Result<(), String>
     {
+        self.features = features;
+
+        // The capacity is the first field of the configuration space, as a count of 512 byte sectors
+        self.capacity = self.device.read_config(0) as u64 | ((self.device.read_config(4) as u64) << 32);
+
+        kdebugln!(BlockDevice, "Block device {} has {} sectors, features 0x{:x}", self.index, self.capacity, features);
+
         self.device.verify_queue_size()?;
 
         self.device.init_queues(1)?;
@@ -39,92 +115,261 @@ impl BlockDriver
         Ok(())
     }
 
-    /// Internal generic block driver
-    fn block_operation(&mut self, buffer: *mut u8, size: u32, offset: u64, write: bool) -> Option<*mut Request>
+    /// Claim a free request from the request pool
+    fn allocate_request(&mut self) -> Option<usize>
+    {
+        let slot = self.requests.iter().position(|request| !request.in_use)?;
+
+        self.next_id += 1;
+
+        let request = &mut self.requests[slot];
+
+        request.in_use = true;
+        request.complete = false;
+        request.id = self.next_id;
+
+        Some(slot)
+    }
+
+    /// Submit a request to the device, the data is given as a list of buffers which are read or written in order
+    /// starting at the given sector, for a discard only the lengths of the segments are used to give the size of
+    /// the range
+    pub fn submit(&mut self, blktype: u32, sector: u64, segments: &[(*mut u8, u32)]) -> Result<BlockRequestToken, BlockRequestError>
     {
-        kdebugln!(BlockDevice, "Block Operation: Buffer: 0x{:x} {} bytes at offset 0x{:x} | {}", buffer as usize, size, offset, if write {"WRITE"} else {"READ"});
-    
-        let sector = offset / 512;
-        // TODO: Before we get here, we are NOT allowed to schedule a read or
-        // write OUTSIDE of the disk's size. So, we can read capacity from
-        // the configuration space to ensure we stay within bounds.
-        let blk_request = Box::leak(Box::new(Request::new()));
-        
-        let desc = VirtIODescriptor { addr:  &(*blk_request).header as *const Header as u64,
-                                len:   core::mem::size_of::<Header>() as u32,
-                                flags: VIRTIO_DESC_F_NEXT,
-                                next:  0, };
+        kdebugln!(BlockDevice, "Block Operation: type {} at sector {} with {} segments", blktype, sector, segments.len());
+
+        if segments.len() > self.max_segments()
+        {
+            return Err(BlockRequestError::TooManySegments);
+        }
+
+        match blktype
+        {
+            VIRTIO_BLK_T_IN | VIRTIO_BLK_T_OUT =>
+            {
+                let length: u64 = segments.iter().map(|(_, len)| *len as u64).sum();
+
+                if sector + (length + BLOCK_SECTOR_SIZE - 1) / BLOCK_SECTOR_SIZE > self.capacity
+                {
+                    return Err(BlockRequestError::OutOfRange);
+                }
+            },
+            VIRTIO_BLK_T_FLUSH if self.supports_flush() => {},
+            VIRTIO_BLK_T_DISCARD if self.supports_discard() => {},
+            _ => return Err(BlockRequestError::Unsupported)
+        }
+
+        let slot = self.allocate_request().ok_or(BlockRequestError::PoolExhausted)?;
+        let blk_request = &mut self.requests[slot] as *mut Request;
+
+        // Build the full descriptor chain: the header, the data segments then the status
+        let mut chain = Vec::with_capacity(segments.len() + 2);
+
+        unsafe
+        {
+            (*blk_request).header.blktype = blktype;
+            (*blk_request).header.reserved = 0;
+            (*blk_request).header.sector = if blktype == VIRTIO_BLK_T_DISCARD { 0 } else { sector };
+            (*blk_request).data.data = segments.first().map(|(ptr, _)| *ptr).unwrap_or(core::ptr::null_mut());
+            // We put 111 in the status. Whenever the device finishes, it will write into
+            // status. If we read status and it is 111, we know that it wasn't written to by
+            // the device.
+            (*blk_request).status.status = STATUS_PENDING;
 
-        let head_idx = self.device.add_descriptor_to_queue(0, desc);
-        (*blk_request).header.sector = sector;
+            chain.push(VirtIODescriptor { addr: &(*blk_request).header as *const Header as u64,
+                                          len: core::mem::size_of::<Header>() as u32,
+                                          flags: VIRTIO_DESC_F_NEXT,
+                                          next: 0 });
 
-        // A write is an "out" direction, whereas a read is an "in" direction.
-        (*blk_request).header.blktype = if true == write {
-            VIRTIO_BLK_T_OUT
+            if blktype == VIRTIO_BLK_T_DISCARD
+            {
+                // A discard describes the range to drop in its data segment
+                let length: u64 = segments.iter().map(|(_, len)| *len as u64).sum();
+
+                (*blk_request).discard.sector = sector;
+                (*blk_request).discard.num_sectors = (length / BLOCK_SECTOR_SIZE) as u32;
+                (*blk_request).discard.flags = 0;
+
+                chain.push(VirtIODescriptor { addr: &(*blk_request).discard as *const DiscardSegment as u64,
+                                              len: core::mem::size_of::<DiscardSegment>() as u32,
+                                              flags: VIRTIO_DESC_F_NEXT,
+                                              next: 0 });
+            }
+            else
+            {
+                // A read is an "in" direction, so the device writes to the buffers
+                for (ptr, len) in segments
+                {
+                    chain.push(VirtIODescriptor { addr: *ptr as u64,
+                                                  len: *len,
+                                                  flags: VIRTIO_DESC_F_NEXT | if blktype == VIRTIO_BLK_T_IN { VIRTIO_DESC_F_WRITE } else { 0 },
+                                                  next: 0 });
+                }
+            }
+
+            chain.push(VirtIODescriptor { addr: &(*blk_request).status as *const Status as u64,
+                                          len: core::mem::size_of::<Status>() as u32,
+                                          flags: VIRTIO_DESC_F_WRITE,
+                                          next: 0 });
+        }
+
+        let head_idx = if self.features & VIRTIO_RING_F_INDIRECT_DESC > 0 && chain.len() > 3
+        {
+            // Scatter-gather requests are placed in the request's indirect table so they only take a single
+            // descriptor in the queue
+            let table = &mut self.indirect_tables[slot];
+
+            for (i, desc) in chain.iter().enumerate()
+            {
+                table[i] = *desc;
+                table[i].next = (i + 1) as u16;
+            }
+
+            let desc = VirtIODescriptor { addr: table.as_ptr() as u64,
+                                          len: (core::mem::size_of::<VirtIODescriptor>() * chain.len()) as u32,
+                                          flags: VIRTIO_DESC_F_INDIRECT,
+                                          next: 0 };
+
+            let head = slot * BLOCK_SLOT_DESCRIPTORS;
+            self.device.write_descriptor(0, head, desc);
+
+            head
         }
-        else {
-            VIRTIO_BLK_T_IN
+        else
+        {
+            // Each slot owns its own run of the ring, so a chain can never overwrite descriptors the device still
+            // holds for another outstanding request
+            let head = slot * BLOCK_SLOT_DESCRIPTORS;
+
+            for (i, desc) in chain.into_iter().enumerate()
+            {
+                self.device.write_descriptor(0, head + i, desc);
+            }
+
+            head
         };
-        // We put 111 in the status. Whenever the device finishes, it will write into
-        // status. If we read status and it is 111, we know that it wasn't written to by
-        // the device.
-        (*blk_request).data.data = buffer;
-        (*blk_request).header.reserved = 0;
-        (*blk_request).status.status = 111;
-        let desc = VirtIODescriptor { addr:  buffer as u64,
-                                len:   size,
-                                flags: VIRTIO_DESC_F_NEXT
-                                    | if false == write {
-                                        VIRTIO_DESC_F_WRITE
-                                    }
-                                    else {
-                                        0
-                                    },
-                                next:  0, };
-
-        let _data_idx = self.device.add_descriptor_to_queue(0, desc);
-
-        let desc = VirtIODescriptor { addr:  &(*blk_request).status as *const Status as u64,
-                                len:   core::mem::size_of::<Status>() as u32,
-                                flags: VIRTIO_DESC_F_WRITE,
-                                next:  0, };
-
-        let _status_idx = self.device.add_descriptor_to_queue(0, desc);
+
+        unsafe { (*blk_request).head = head_idx as u16 };
 
         self.device.send_on_queue(0, head_idx);
 
-        Some(blk_request as *mut Request)
+        Ok(BlockRequestToken { device: self.index, slot, id: unsafe { (*blk_request).id } })
     }
 
     /// Send a read request to the block device
-    pub fn read(&mut self, buffer: *mut u8, size: u32, offset: u64) -> Option<*mut Request>
+    pub fn read(&mut self, buffer: *mut u8, size: u32, offset: u64) -> Result<BlockRequestToken, BlockRequestError>
     {
-        self.block_operation(buffer, size, offset, false)
+        self.submit(VIRTIO_BLK_T_IN, offset / BLOCK_SECTOR_SIZE, &[(buffer, size)])
     }
 
     /// Send a write request to the block device
-    pub fn write(&mut self, buffer: *mut u8, size: u32, offset: u64) -> Option<*mut Request>
+    pub fn write(&mut self, buffer: *mut u8, size: u32, offset: u64) -> Result<BlockRequestToken, BlockRequestError>
     {
-        self.block_operation(buffer, size, offset, true)
+        self.submit(VIRTIO_BLK_T_OUT, offset / BLOCK_SECTOR_SIZE, &[(buffer, size)])
     }
 
-    // Generic function to sync with a request finishing
-    unsafe fn sync(request: *mut Request)
+    /// Walk the used ring, marking every request the device has finished as complete
+    pub fn poll(&mut self)
     {
-        while request.read_volatile().status.status == 111
+        // The used ring may be walked from both the interrupt handler and a synchronous wait, so only one of them is
+        // allowed in at once, whichever loses simply picks up the completions on its next check
+        if self.polling.swap(true, Ordering::Acquire)
+        {
+            return;
+        }
+
+        while let Some(head) = self.device.pop_used(0)
         {
+            if let Some(request) = self.requests.iter_mut().find(|request| request.in_use && !request.complete && request.head == head)
+            {
+                request.complete = true;
+            }
+            else
+            {
+                kwarnln!("Block device {} completed unknown descriptor chain {}", self.index, head);
+            }
         }
 
-        Box::from_raw(request);
+        self.polling.store(false, Ordering::Release);
+    }
+
+    /// Handle an interrupt from the device
+    pub fn handle_interrupt(&mut self)
+    {
+        self.device.acknowledge_interrupt();
+        self.poll();
     }
 
-    pub fn sync_read(&mut self, buffer: *mut u8, size: u32, offset: u64)
+    /// Check if the request for the given token has completed, a token whose request has already been finished
+    /// is treated as complete
+    pub fn is_complete(&mut self, token: BlockRequestToken) -> bool
     {
-        unsafe { Self::sync(self.read(buffer, size, offset).unwrap()) };
+        self.poll();
+
+        let request = &self.requests[token.slot];
+
+        !request.in_use || request.id != token.id || request.complete
     }
 
-    pub fn sync_write(&mut self, buffer: *mut u8, size: u32, offset: u64)
+    /// Release a completed request back to the pool, returning true if the operation succeeded
+    pub fn finish(&mut self, token: BlockRequestToken) -> bool
     {
-        unsafe { Self::sync(self.write(buffer, size, offset).unwrap()) };
+        let request = &mut self.requests[token.slot];
+
+        if !request.in_use || request.id != token.id
+        {
+            return false;
+        }
+
+        let status = unsafe { (&request.status.status as *const u8).read_volatile() };
+
+        request.in_use = false;
+        request.complete = false;
+
+        if status != VIRTIO_BLK_S_OK
+        {
+            kwarnln!("Block device {} request failed with status {}", self.index, status);
+        }
+
+        status == VIRTIO_BLK_S_OK
+    }
+
+    /// Wait for a request to complete and release it, returning true if the operation succeeded
+    pub fn sync(&mut self, token: BlockRequestToken) -> bool
+    {
+        while !self.is_complete(token)
+        {
+            core::hint::spin_loop();
+        }
+
+        self.finish(token)
+    }
+
+    /// Read from the device, waiting for the read to complete
+    pub fn sync_read(&mut self, buffer: *mut u8, size: u32, offset: u64) -> bool
+    {
+        match self.read(buffer, size, offset)
+        {
+            Ok(token) => self.sync(token),
+            Err(e) =>
+            {
+                kwarnln!("Unable to read from block device {}: {:?}", self.index, e);
+                false
+            }
+        }
+    }
+
+    /// Write to the device, waiting for the write to complete
+    pub fn sync_write(&mut self, buffer: *mut u8, size: u32, offset: u64) -> bool
+    {
+        match self.write(buffer, size, offset)
+        {
+            Ok(token) => self.sync(token),
+            Err(e) =>
+            {
+                kwarnln!("Unable to write to block device {}: {:?}", self.index, e);
+                false
+            }
+        }
     }
 }
\ No newline at end of file
@@ -53,13 +53,38 @@ impl Status
     }
 }
 
+#[repr(C)]
+pub struct DiscardSegment
+{
+    pub sector: u64,
+    pub num_sectors: u32,
+    pub flags: u32,
+}
+
+impl DiscardSegment
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            sector: 0,
+            num_sectors: 0,
+            flags: 0,
+        }
+    }
+}
+
 #[repr(C)]
 pub struct Request
 {
     pub header: Header,
     pub data:   Data,
+    pub discard: DiscardSegment,
     pub status: Status,
     pub head:   u16,
+    pub id:     usize,
+    pub in_use: bool,
+    pub complete: bool,
 }
 
 impl Request
@@ -70,8 +95,32 @@ impl Request
         {
             header: Header::new(),
             data: Data::new(),
+            discard: DiscardSegment::new(),
             status: Status::new(),
             head: 0,
+            id: 0,
+            in_use: false,
+            complete: false,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Handle to an outstanding block device request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BlockRequestToken
+{
+    pub device: usize,
+    pub slot: usize,
+    pub id: usize
+}
+
+/// Errors which can occur when submitting a block request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockRequestError
+{
+    OutOfRange,
+    PoolExhausted,
+    TooManySegments,
+    Unsupported,
+    DeviceError
+}
@@ -56,8 +56,21 @@ pub fn get_gpu_driver(index: usize) -> Option<&'static mut drivers::gpu::GPUDriv
 }
 
 /// Interrupt handler for all VirtIO interrupts
-pub fn handle_interrupt(_interrupt: u32)
+pub fn handle_interrupt(interrupt: u32)
 {
+    // Interrupt numbers start at 1 for the first VirtIO slot
+    let base = virtio_index_to_address(interrupt as usize - 1);
+
+    if let Some(collection) = unsafe { &mut VIRTIO_DEVICE_COLLECTION }
+    {
+        for driver in &mut collection.block_devices
+        {
+            if driver.device.get_base() == base
+            {
+                driver.handle_interrupt();
+            }
+        }
+    }
 }
 
 /// Initialize the VirtIO interrupts
@@ -0,0 +1,291 @@
+//! POSIX access control lists
+//!
+//! ACLs are stored in the `system.posix_acl_access` and
+//! `system.posix_acl_default` extended attributes, using the layout Linux
+//! uses for them: a version number followed by one entry per user or group.
+//! The owner, group and other entries mirror the permission bits of the file
+//! mode, and the two are kept in sync whenever either changes.
+
+use crate::*;
+
+use super::structures::*;
+
+/// Extended attribute holding the ACL checked on access to an inode
+pub const XATTR_POSIX_ACL_ACCESS: &str = "system.posix_acl_access";
+
+/// Extended attribute holding the ACL new entries of a directory start with
+pub const XATTR_POSIX_ACL_DEFAULT: &str = "system.posix_acl_default";
+
+/// Version number at the start of every ACL
+const ACL_VERSION: u32 = 2;
+
+/// Size of the version number at the start of an ACL
+const ACL_HEADER_SIZE: usize = 4;
+
+/// Size of a single ACL entry
+const ACL_ENTRY_SIZE: usize = 8;
+
+/// Entry for the owner of the inode
+pub const ACL_USER_OBJ: u16 = 0x01;
+
+/// Entry for a named user
+pub const ACL_USER: u16 = 0x02;
+
+/// Entry for the group of the inode
+pub const ACL_GROUP_OBJ: u16 = 0x04;
+
+/// Entry for a named group
+pub const ACL_GROUP: u16 = 0x08;
+
+/// Upper bound on the permissions granted by every entry except the owner and other entries
+pub const ACL_MASK: u16 = 0x10;
+
+/// Entry for everyone else
+pub const ACL_OTHER: u16 = 0x20;
+
+/// Permission to read
+pub const ACL_READ: u16 = 0x04;
+
+/// Permission to write
+pub const ACL_WRITE: u16 = 0x02;
+
+/// Permission to execute, or to search a directory
+pub const ACL_EXECUTE: u16 = 0x01;
+
+/// Id of the entries which do not name a user or group
+pub const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+/// Single ACL entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclEntry
+{
+    pub tag: u16,
+    pub perm: u16,
+    pub id: u32
+}
+
+/// Access Control List
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Acl
+{
+    pub entries: Vec<AclEntry>
+}
+
+impl Acl
+{
+    /// Parse and validate an ACL stored in an extended attribute
+    pub fn from_bytes(data: &[u8]) -> FilesystemResult<Self>
+    {
+        if data.len() < ACL_HEADER_SIZE || (data.len() - ACL_HEADER_SIZE) % ACL_ENTRY_SIZE != 0
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        if u32::from_le_bytes([data[0], data[1], data[2], data[3]]) != ACL_VERSION
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        let entries = data[ACL_HEADER_SIZE..].chunks(ACL_ENTRY_SIZE).map(|entry| AclEntry
+            {
+                tag: u16::from_le_bytes([entry[0], entry[1]]),
+                perm: u16::from_le_bytes([entry[2], entry[3]]),
+                id: u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]])
+            }).collect();
+
+        let acl = Self { entries };
+        acl.validate()?;
+
+        Ok(acl)
+    }
+
+    /// Convert the ACL to the layout stored in an extended attribute
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        let mut data = Vec::with_capacity(ACL_HEADER_SIZE + self.entries.len() * ACL_ENTRY_SIZE);
+        data.extend_from_slice(&ACL_VERSION.to_le_bytes());
+
+        for entry in &self.entries
+        {
+            data.extend_from_slice(&entry.tag.to_le_bytes());
+            data.extend_from_slice(&entry.perm.to_le_bytes());
+            data.extend_from_slice(&entry.id.to_le_bytes());
+        }
+
+        data
+    }
+
+    /// Check the entries are in order, with exactly one owner, group and other entry, and a mask if there are any
+    /// named entries
+    fn validate(&self) -> FilesystemResult<()>
+    {
+        let mut last: Option<(u16, u32)> = None;
+        let mut named = false;
+        let mut required = 0;
+
+        for entry in &self.entries
+        {
+            if entry.perm & !0o7 != 0
+            {
+                return Err(FilesystemError::InvalidArgument);
+            }
+
+            let key = match entry.tag
+            {
+                ACL_USER | ACL_GROUP =>
+                {
+                    named = true;
+                    (entry.tag, entry.id)
+                },
+                ACL_USER_OBJ | ACL_GROUP_OBJ | ACL_OTHER =>
+                {
+                    required += 1;
+                    (entry.tag, 0)
+                },
+                ACL_MASK => (entry.tag, 0),
+                _ => return Err(FilesystemError::InvalidArgument)
+            };
+
+            // Entries are sorted by tag, and named entries by id, so a repeat shows up as an entry out of order
+            if last.map(|last| last >= key).unwrap_or(false)
+            {
+                return Err(FilesystemError::InvalidArgument);
+            }
+
+            last = Some(key);
+        }
+
+        if required != 3 || (named && self.perm(ACL_MASK).is_none())
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        Ok(())
+    }
+
+    /// Get the permissions of the first entry with the given tag
+    fn perm(&self, tag: u16) -> Option<u16>
+    {
+        self.entries.iter().find(|entry| entry.tag == tag).map(|entry| entry.perm)
+    }
+
+    /// Set the permissions of the first entry with the given tag
+    fn set_perm(&mut self, tag: u16, perm: u16)
+    {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.tag == tag)
+        {
+            entry.perm = perm;
+        }
+    }
+
+    /// Check if the ACL only holds the entries the permission bits of the mode can represent
+    pub fn is_minimal(&self) -> bool
+    {
+        self.entries.len() == 3
+    }
+
+    /// Get the permission bits of the mode the ACL corresponds to, the group bits show the mask if there is one
+    pub fn mode(&self) -> u16
+    {
+        let group = self.perm(ACL_MASK).or(self.perm(ACL_GROUP_OBJ)).unwrap_or(0);
+
+        self.perm(ACL_USER_OBJ).unwrap_or(0) << 6 | group << 3 | self.perm(ACL_OTHER).unwrap_or(0)
+    }
+
+    /// Update the owner, group (or mask) and other entries from the permission bits of a new mode
+    pub fn set_mode(&mut self, mode: u16)
+    {
+        let group_tag = if self.perm(ACL_MASK).is_some() { ACL_MASK } else { ACL_GROUP_OBJ };
+
+        self.set_perm(ACL_USER_OBJ, (mode >> 6) & 0o7);
+        self.set_perm(group_tag, (mode >> 3) & 0o7);
+        self.set_perm(ACL_OTHER, mode & 0o7);
+    }
+
+    /// Check if a user and group may access an inode owned by `owner` and `group` with all of the permissions in
+    /// `want`
+    pub fn permits(&self, owner: u16, group: u16, uid: u16, gid: u16, want: u16) -> bool
+    {
+        let mask = self.perm(ACL_MASK).unwrap_or(0o7);
+        let mut group_matched = false;
+
+        for entry in &self.entries
+        {
+            match entry.tag
+            {
+                // The owner entry is not limited by the mask
+                ACL_USER_OBJ if uid == owner => return entry.perm & want == want,
+                ACL_USER if uid as u32 == entry.id => return entry.perm & mask & want == want,
+                ACL_GROUP_OBJ | ACL_GROUP =>
+                {
+                    let matches = if entry.tag == ACL_GROUP_OBJ { gid == group } else { gid as u32 == entry.id };
+
+                    // Any matching group entry which grants everything is enough
+                    if matches && entry.perm & want == want
+                    {
+                        return mask & want == want;
+                    }
+
+                    group_matched |= matches;
+                },
+                _ => {}
+            }
+        }
+
+        // Matching a group hides the other entry
+        !group_matched && self.perm(ACL_OTHER).unwrap_or(0) & want == want
+    }
+}
+
+/// Build an ACL for a mode with no named entries
+pub fn acl_from_mode(mode: u16) -> Acl
+{
+    let mut acl = Acl
+    {
+        entries: vec![
+            AclEntry { tag: ACL_USER_OBJ, perm: 0, id: ACL_UNDEFINED_ID },
+            AclEntry { tag: ACL_GROUP_OBJ, perm: 0, id: ACL_UNDEFINED_ID },
+            AclEntry { tag: ACL_OTHER, perm: 0, id: ACL_UNDEFINED_ID }]
+    };
+
+    acl.set_mode(mode);
+
+    acl
+}
+
+/// ACL Evaluation Test
+#[test_case]
+fn acl_evaluation()
+{
+    let mut acl = acl_from_mode(0o640);
+    assert!(acl.is_minimal());
+
+    acl.entries.insert(1, AclEntry { tag: ACL_USER, perm: 0o6, id: 1000 });
+    acl.entries.insert(3, AclEntry { tag: ACL_GROUP, perm: 0o2, id: 50 });
+    acl.entries.insert(4, AclEntry { tag: ACL_MASK, perm: 0o4, id: ACL_UNDEFINED_ID });
+
+    let parsed = Acl::from_bytes(&acl.to_bytes()).unwrap();
+    assert_eq!(parsed, acl);
+    assert_eq!(parsed.mode(), 0o640);
+
+    // The owner ignores the mask, named entries are limited by it
+    assert!(acl.permits(0, 0, 0, 0, 0o6));
+    assert!(acl.permits(0, 0, 1000, 7, 0o4));
+    assert!(!acl.permits(0, 0, 1000, 7, 0o2));
+
+    // A matching group hides the other entry
+    assert!(!acl.permits(0, 0, 5, 50, 0o4));
+    assert!(acl.permits(0, 0, 5, 7, 0o0));
+    assert!(!acl.permits(0, 0, 5, 7, 0o4));
+
+    // Changing the mode changes the mask rather than the group entry
+    acl.set_mode(0o770);
+    assert_eq!(acl.perm(ACL_MASK), Some(0o7));
+    assert_eq!(acl.perm(ACL_GROUP_OBJ), Some(0o4));
+
+    // Named entries need a mask, and entries must be in order
+    acl.entries.remove(4);
+    assert!(Acl::from_bytes(&acl.to_bytes()).is_err());
+    acl.entries.swap(0, 1);
+    assert!(Acl::from_bytes(&acl.to_bytes()).is_err());
+}
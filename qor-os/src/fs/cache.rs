@@ -0,0 +1,620 @@
+//! Kernel wide page cache for file data
+//!
+//! Pages are keyed by the inode they belong to (which carries the mount id) and
+//! the index of the page within the file. Descriptor reads and writes as well
+//! as shared file mappings all go through the same pages, so every user of a
+//! file sees the same data. Pages are evicted in least recently used order and
+//! dirty pages are written back to the owning filesystem before eviction, on
+//! sync, or when a file descriptor is closed.
+//!
+//! Pages dropped by a truncate or an invalidation while a process still maps
+//! them are orphaned: they stay with the mapping but are no longer seen by
+//! reads or writes, and are freed once the last mapping is released.
+//!
+//! Filesystems which keep their data in memory lend their pages to the cache
+//! rather than having them copied, so the data is only held once.
+
+use crate::*;
+
+use super::fstrait::Filesystem;
+use super::structures::*;
+use super::vfs::FilesystemInterface;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use crate::drivers::virtio::drivers::block::BlockRequestToken;
+
+use mem::PAGE_SIZE;
+
+/// Maximum number of pages held by the page cache
+pub const PAGE_CACHE_CAPACITY: usize = 256;
+
+/// Number of pages a read waits for the disk to load, kept small enough that the blocks they cover fit in a
+/// filesystem's block cache so the pages of one read cannot evict each other before the read restarts
+const PREFETCH_WINDOW: usize = 8;
+
+/// Key for a page in the page cache
+pub type PageKey = (FilesystemIndex, usize);
+
+/// A single cached page of file data
+pub struct CachedPage
+{
+    pub data: *mut u8,
+    pub dirty: bool,
+    pub last_access: usize,
+    pub pins: usize,
+    pub shared_writers: usize,
+    pub borrowed: bool
+}
+
+impl CachedPage
+{
+    /// Check if the page must be written back before it can be dropped
+    fn needs_writeback(&self) -> bool
+    {
+        self.dirty || self.shared_writers > 0
+    }
+}
+
+/// Page Cache
+pub struct PageCache
+{
+    pages: BTreeMap<PageKey, CachedPage>,
+    orphans: BTreeMap<usize, CachedPage>,
+    sizes: BTreeMap<FilesystemIndex, usize>,
+    resized: BTreeSet<FilesystemIndex>,
+    clock: usize,
+    capacity: usize
+}
+
+// Global Page Cache
+static mut GLOBAL_PAGE_CACHE: Option<PageCache> = None;
+
+/// Get a reference to the global page cache, initializing it if needed
+pub fn get_page_cache() -> &'static mut PageCache
+{
+    let cache = unsafe { &mut GLOBAL_PAGE_CACHE };
+
+    if cache.is_none()
+    {
+        *cache = Some(PageCache::new(PAGE_CACHE_CAPACITY));
+    }
+
+    cache.as_mut().unwrap()
+}
+
+impl PageCache
+{
+    /// Create a new, empty page cache which holds at most `capacity` pages
+    pub fn new(capacity: usize) -> Self
+    {
+        Self
+        {
+            pages: BTreeMap::new(),
+            orphans: BTreeMap::new(),
+            sizes: BTreeMap::new(),
+            resized: BTreeSet::new(),
+            clock: 0,
+            capacity
+        }
+    }
+
+    /// Get the number of pages currently held in the cache
+    pub fn page_count(&self) -> usize
+    {
+        self.pages.len()
+    }
+
+    /// Advance the access clock
+    fn tick(&mut self) -> usize
+    {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Get the size of the file at the given inode
+    pub fn get_size(&mut self, vfs: &mut FilesystemInterface, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if let Some(size) = self.sizes.get(&inode)
+        {
+            return Ok(*size);
+        }
+
+        let size = vfs.get_stat(inode)?.size;
+        self.sizes.insert(inode, size);
+
+        Ok(size)
+    }
+
+    /// Get the size of the file at the given inode if the cache is tracking it
+    pub fn cached_size(&self, inode: FilesystemIndex) -> Option<usize>
+    {
+        self.sizes.get(&inode).copied()
+    }
+
+    /// Set the size of the file at the given inode, dropping any pages past
+    /// the new end of the file
+    pub fn truncate(&mut self, vfs: &mut FilesystemInterface, inode: FilesystemIndex, size: usize) -> FilesystemResult<()>
+    {
+        let old_size = self.get_size(vfs, inode)?;
+
+        if size > old_size
+        {
+            vfs.reserve_size(inode, size)?;
+        }
+
+        if size < old_size
+        {
+            let first_dropped = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+            let last_page = (old_size + PAGE_SIZE - 1) / PAGE_SIZE;
+
+            for page in first_dropped..last_page
+            {
+                self.drop_page(vfs, (inode, page));
+            }
+
+            // Zero the tail of the last partial page so it does not reappear if
+            // the file is extended again
+            if size % PAGE_SIZE != 0
+            {
+                if let Some(page) = self.pages.get_mut(&(inode, size / PAGE_SIZE))
+                {
+                    for i in size % PAGE_SIZE..PAGE_SIZE
+                    {
+                        unsafe { page.data.add(i).write(0) };
+                    }
+
+                    page.dirty = true;
+                }
+            }
+        }
+
+        self.sizes.insert(inode, size);
+        self.resized.insert(inode);
+
+        // A smaller size reaches the filesystem at once, so a filesystem which lends its pages to the cache cannot
+        // hand out the data past the new end again
+        if size < old_size
+        {
+            self.flush_inode(vfs, inode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Zero the cached bytes of an inode in `start..end`, used once the filesystem has punched a hole there, the
+    /// pages are left clean as they now match the disk
+    pub fn zero_range(&mut self, inode: FilesystemIndex, start: usize, end: usize)
+    {
+        if start >= end
+        {
+            return;
+        }
+
+        for ((_, index), page) in self.pages.range_mut((inode, start / PAGE_SIZE)..=(inode, (end - 1) / PAGE_SIZE))
+        {
+            let page_start = index * PAGE_SIZE;
+            let from = start.max(page_start) - page_start;
+            let to = end.min(page_start + PAGE_SIZE) - page_start;
+
+            unsafe { core::ptr::write_bytes(page.data.add(from), 0, to - from) };
+        }
+    }
+
+    /// Forget the size of an inode so it is read from the filesystem again, used when the filesystem changes the
+    /// size itself
+    pub fn forget_size(&mut self, inode: FilesystemIndex)
+    {
+        // A size which has not been written back yet is still the correct one
+        if !self.resized.contains(&inode)
+        {
+            self.sizes.remove(&inode);
+        }
+    }
+
+    /// Remove a page from the cache without writing it back
+    fn drop_page(&mut self, vfs: &mut FilesystemInterface, key: PageKey)
+    {
+        if let Some(mut page) = self.pages.remove(&key)
+        {
+            if page.pins > 0
+            {
+                // The page is still mapped by a process, it cannot be freed
+                // out from under it, so it is kept until the last unpin, and
+                // a page lent by the filesystem becomes the cache's to free
+                kdebugln!(Filesystem, "Orphaning pinned page {} of inode {:?}", key.1, key.0);
+
+                if page.borrowed
+                {
+                    vfs.disown_inode_page(key.0, key.1);
+                    page.borrowed = false;
+                }
+
+                self.orphans.insert(page.data as usize, page);
+            }
+            else if !page.borrowed
+            {
+                mem::kpfree(page.data as usize, 1).unwrap();
+            }
+        }
+    }
+
+    /// Find a pinned page by its key and data, which may have been orphaned
+    fn find_pinned(&mut self, key: PageKey, data: *mut u8) -> Option<&mut CachedPage>
+    {
+        match self.pages.get_mut(&key)
+        {
+            Some(page) if page.data == data => Some(page),
+            _ => self.orphans.get_mut(&(data as usize))
+        }
+    }
+
+    /// Evict the least recently used unpinned page
+    fn evict_one(&mut self, vfs: &mut FilesystemInterface) -> FilesystemResult<()>
+    {
+        let mut victim: Option<(PageKey, usize)> = None;
+
+        for (key, page) in &self.pages
+        {
+            if page.pins > 0
+            {
+                continue;
+            }
+
+            if victim.map(|(_, access)| page.last_access < access).unwrap_or(true)
+            {
+                victim = Some((*key, page.last_access));
+            }
+        }
+
+        if let Some((key, _)) = victim
+        {
+            kdebugln!(Filesystem, "Evicting page {} of inode {:?} from the page cache", key.1, key.0);
+
+            if self.pages.get(&key).unwrap().needs_writeback()
+            {
+                self.flush_inode(vfs, key.0)?;
+            }
+
+            self.drop_page(vfs, key);
+        }
+
+        Ok(())
+    }
+
+    /// Get a pointer to a cached page, reading it from the filesystem if it is
+    /// not already present
+    pub fn get_page(&mut self, vfs: &mut FilesystemInterface, key: PageKey) -> FilesystemResult<*mut u8>
+    {
+        let time = self.tick();
+
+        if let Some(page) = self.pages.get_mut(&key)
+        {
+            page.last_access = time;
+            return Ok(page.data);
+        }
+
+        while self.pages.len() >= self.capacity
+        {
+            let before = self.pages.len();
+            self.evict_one(vfs)?;
+
+            // Every page is pinned, allow the cache to grow past its capacity
+            if self.pages.len() == before
+            {
+                break;
+            }
+        }
+
+        let size = self.get_size(vfs, key.0)?;
+
+        // Pages entirely past the end of the file have no backing data
+        if key.1 * PAGE_SIZE < size
+        {
+            if let Some(data) = vfs.inode_page_memory(key.0, key.1)?
+            {
+                self.pages.insert(key, CachedPage { data, dirty: false, last_access: time, pins: 0, shared_writers: 0, borrowed: true });
+
+                return Ok(data);
+            }
+        }
+
+        let data = mem::kpzalloc(1, "Page Cache").map_err(|_| FilesystemError::OutOfSpace)? as *mut u8;
+
+        if key.1 * PAGE_SIZE < size
+        {
+            if let Err(e) = vfs.read_inode_page(key.0, key.1, data)
+            {
+                mem::kpfree(data as usize, 1).unwrap();
+                return Err(e);
+            }
+        }
+
+        self.pages.insert(key, CachedPage { data, dirty: false, last_access: time, pins: 0, shared_writers: 0, borrowed: false });
+
+        Ok(data)
+    }
+
+    /// Start loading any pages of a file needed for a read which are not yet cached, returning a token to wait on
+    /// if the read would have to block on the disk. Only the first `PREFETCH_WINDOW` pages of the read are waited
+    /// for, the read itself loads any pages past them synchronously
+    pub fn prefetch(&mut self, vfs: &mut FilesystemInterface, inode: FilesystemIndex, offset: usize, count: usize) -> FilesystemResult<Option<BlockRequestToken>>
+    {
+        let size = self.get_size(vfs, inode)?;
+        let end = (offset + count).min(size).min((offset / PAGE_SIZE + PREFETCH_WINDOW) * PAGE_SIZE);
+
+        let mut wait = None;
+
+        if offset < end
+        {
+            for page in offset / PAGE_SIZE..(end + PAGE_SIZE - 1) / PAGE_SIZE
+            {
+                if !self.pages.contains_key(&(inode, page))
+                {
+                    if let Some(token) = vfs.prefetch_inode_page(inode, page)?
+                    {
+                        wait.get_or_insert(token);
+                    }
+                }
+            }
+        }
+
+        Ok(wait)
+    }
+
+    /// Read from a file through the cache, returning the number of bytes read
+    pub fn read(&mut self, vfs: &mut FilesystemInterface, inode: FilesystemIndex, offset: usize, buffer: *mut u8, count: usize) -> FilesystemResult<usize>
+    {
+        let size = self.get_size(vfs, inode)?;
+
+        if offset >= size
+        {
+            return Ok(0);
+        }
+
+        let count = count.min(size - offset);
+        let mut done = 0;
+
+        while done < count
+        {
+            let position = offset + done;
+            let page_offset = position % PAGE_SIZE;
+            let length = (PAGE_SIZE - page_offset).min(count - done);
+
+            let page = self.get_page(vfs, (inode, position / PAGE_SIZE))?;
+
+            unsafe { core::ptr::copy(page.add(page_offset), buffer.add(done), length) };
+
+            done += length;
+        }
+
+        Ok(done)
+    }
+
+    /// Write to a file through the cache, returning the number of bytes written
+    pub fn write(&mut self, vfs: &mut FilesystemInterface, inode: FilesystemIndex, offset: usize, buffer: *const u8, count: usize) -> FilesystemResult<usize>
+    {
+        // The space for the data is set aside before any page is dirtied, so a full filesystem fails the write
+        if offset + count > self.get_size(vfs, inode)?
+        {
+            vfs.reserve_size(inode, offset + count)?;
+        }
+
+        let mut done = 0;
+
+        while done < count
+        {
+            let position = offset + done;
+            let page_offset = position % PAGE_SIZE;
+            let length = (PAGE_SIZE - page_offset).min(count - done);
+
+            let key = (inode, position / PAGE_SIZE);
+            let page = self.get_page(vfs, key)?;
+
+            unsafe { core::ptr::copy(buffer.add(done), page.add(page_offset), length) };
+
+            self.pages.get_mut(&key).unwrap().dirty = true;
+
+            done += length;
+        }
+
+        let size = self.get_size(vfs, inode)?;
+
+        if offset + count > size
+        {
+            self.sizes.insert(inode, offset + count);
+            self.resized.insert(inode);
+        }
+
+        Ok(done)
+    }
+
+    /// Pin a page in the cache so it can be mapped into a process
+    pub fn pin_page(&mut self, vfs: &mut FilesystemInterface, key: PageKey, writable: bool) -> FilesystemResult<*mut u8>
+    {
+        let data = self.get_page(vfs, key)?;
+        let page = self.pages.get_mut(&key).unwrap();
+
+        page.pins += 1;
+
+        if writable
+        {
+            page.shared_writers += 1;
+        }
+
+        Ok(data)
+    }
+
+    /// Take another pin on a page which is already pinned, such as when a
+    /// mapping is shared with a forked process
+    pub fn share_page(&mut self, key: PageKey, data: *mut u8, writable: bool)
+    {
+        if let Some(page) = self.find_pinned(key, data)
+        {
+            page.pins += 1;
+
+            if writable
+            {
+                page.shared_writers += 1;
+            }
+        }
+    }
+
+    /// Release a pin on the page holding `data`, a writable mapping leaves the
+    /// page dirty, and an orphaned page is freed with its last pin
+    pub fn unpin_page(&mut self, key: PageKey, data: *mut u8, writable: bool)
+    {
+        if let Some(page) = self.find_pinned(key, data)
+        {
+            page.pins = page.pins.saturating_sub(1);
+
+            if writable
+            {
+                page.shared_writers = page.shared_writers.saturating_sub(1);
+                page.dirty = true;
+            }
+        }
+
+        if self.orphans.get(&(data as usize)).map(|page| page.pins == 0).unwrap_or(false)
+        {
+            self.orphans.remove(&(data as usize));
+            mem::kpfree(data as usize, 1).unwrap();
+        }
+    }
+
+    /// Get the byte ranges of an inode covered by pages which have not been written back, adjacent pages are merged
+    /// into a single range
+    pub fn dirty_ranges(&self, inode: FilesystemIndex) -> Vec<(usize, usize)>
+    {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+        for ((_, index), page) in self.pages.range((inode, 0)..=(inode, usize::MAX))
+        {
+            if !page.needs_writeback()
+            {
+                continue;
+            }
+
+            let start = index * PAGE_SIZE;
+
+            match ranges.last_mut()
+            {
+                Some((_, end)) if *end == start => *end = start + PAGE_SIZE,
+                _ => ranges.push((start, start + PAGE_SIZE))
+            }
+        }
+
+        ranges
+    }
+
+    /// Write any dirty pages for an inode back to its filesystem
+    pub fn flush_inode(&mut self, vfs: &mut FilesystemInterface, inode: FilesystemIndex) -> FilesystemResult<()>
+    {
+        let mut dirty = Vec::new();
+
+        for ((page_inode, index), page) in self.pages.range((inode, 0)..=(inode, usize::MAX))
+        {
+            if *page_inode == inode && page.needs_writeback()
+            {
+                dirty.push((*index, page.data as *const u8));
+            }
+        }
+
+        if dirty.len() == 0 && !self.resized.contains(&inode)
+        {
+            return Ok(());
+        }
+
+        let size = self.get_size(vfs, inode)?;
+
+        kdebugln!(Filesystem, "Writing back {} pages of inode {:?}", dirty.len(), inode);
+
+        // Pages are only clean once they have reached the filesystem, a failed write back leaves them to be tried
+        // again rather than evicted
+        vfs.write_inode_pages(inode, size, &dirty)?;
+
+        for (index, _) in &dirty
+        {
+            if let Some(page) = self.pages.get_mut(&(inode, *index))
+            {
+                page.dirty = false;
+            }
+        }
+
+        self.resized.remove(&inode);
+
+        Ok(())
+    }
+
+    /// Write back every dirty page in the cache
+    pub fn flush_all(&mut self, vfs: &mut FilesystemInterface) -> FilesystemResult<()>
+    {
+        let mut inodes = BTreeSet::new();
+
+        for ((inode, _), page) in &self.pages
+        {
+            if page.needs_writeback()
+            {
+                inodes.insert(*inode);
+            }
+        }
+
+        for inode in &self.resized
+        {
+            inodes.insert(*inode);
+        }
+
+        for inode in inodes
+        {
+            self.flush_inode(vfs, inode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write back every dirty page belonging to a filesystem
+    pub fn flush_mount(&mut self, vfs: &mut FilesystemInterface, mount_id: usize) -> FilesystemResult<()>
+    {
+        for inode in self.mount_inodes(mount_id)
+        {
+            self.flush_inode(vfs, inode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drop every cached page belonging to a filesystem without writing it back
+    pub fn invalidate_mount(&mut self, vfs: &mut FilesystemInterface, mount_id: usize)
+    {
+        for inode in self.mount_inodes(mount_id)
+        {
+            self.invalidate_inode(vfs, inode);
+        }
+    }
+
+    /// Get every inode of a filesystem the cache holds anything for
+    fn mount_inodes(&self, mount_id: usize) -> BTreeSet<FilesystemIndex>
+    {
+        let mut inodes = BTreeSet::new();
+
+        inodes.extend(self.pages.keys().map(|(inode, _)| *inode).filter(|inode| inode.mount_id == mount_id));
+        inodes.extend(self.sizes.keys().copied().filter(|inode| inode.mount_id == mount_id));
+
+        inodes
+    }
+
+    /// Drop every cached page of an inode without writing it back, used when
+    /// the inode is removed or rewritten outside of the cache
+    pub fn invalidate_inode(&mut self, vfs: &mut FilesystemInterface, inode: FilesystemIndex)
+    {
+        let keys: Vec<PageKey> = self.pages.range((inode, 0)..=(inode, usize::MAX)).map(|(key, _)| *key).collect();
+
+        for key in keys
+        {
+            self.drop_page(vfs, key);
+        }
+
+        self.sizes.remove(&inode);
+        self.resized.remove(&inode);
+    }
+}
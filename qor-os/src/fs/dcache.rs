@@ -0,0 +1,247 @@
+//! Directory entry cache
+//!
+//! Caches the result of looking up a name in a directory, keyed by the inode
+//! of the directory and the name. Failed lookups are cached as negative
+//! entries so repeated searches for missing files (such as walking `PATH`) do
+//! not have to list the directory again. Every positive entry also records the
+//! directory it was found in, which lets an inode be mapped back to a path,
+//! and whether the inode is a symbolic link once that is known so path walks
+//! do not have to ask the filesystem again. Entries are evicted in least
+//! recently used order.
+
+use crate::*;
+
+use super::structures::*;
+
+use alloc::collections::BTreeMap;
+
+/// Maximum number of entries held by the directory entry cache
+pub const DENTRY_CACHE_CAPACITY: usize = 1024;
+
+/// Key for an entry in the directory entry cache
+type DentryKey = (FilesystemIndex, String);
+
+/// A single cached lookup, `inode` is `None` for a negative entry and `symlink` is `None` until the type is known
+struct CachedDentry
+{
+    inode: Option<FilesystemIndex>,
+    symlink: Option<bool>,
+    last_access: usize
+}
+
+/// Directory Entry Cache
+pub struct DirectoryCache
+{
+    entries: BTreeMap<DentryKey, CachedDentry>,
+    parents: BTreeMap<FilesystemIndex, DentryKey>,
+    lru: BTreeMap<usize, DentryKey>,
+    clock: usize,
+    capacity: usize
+}
+
+impl DirectoryCache
+{
+    /// Create a new, empty directory entry cache which holds at most `capacity` entries
+    pub fn new(capacity: usize) -> Self
+    {
+        Self
+        {
+            entries: BTreeMap::new(),
+            parents: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            capacity
+        }
+    }
+
+    /// Get the number of entries currently held in the cache
+    pub fn entry_count(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    /// Look up a name in a directory, returns `Some(None)` for a cached negative entry and `None` on a miss
+    pub fn get(&mut self, directory: FilesystemIndex, name: &str) -> Option<Option<FilesystemIndex>>
+    {
+        self.clock += 1;
+        let time = self.clock;
+
+        let key = (directory, String::from(name));
+        let entry = self.entries.get_mut(&key)?;
+
+        self.lru.remove(&entry.last_access);
+        self.lru.insert(time, key);
+        entry.last_access = time;
+
+        Some(entry.inode)
+    }
+
+    /// Check if the entry for a name in a directory is a symbolic link, `None` if it is not cached as `inode`
+    /// or its type is not yet known
+    pub fn is_symlink(&self, directory: FilesystemIndex, name: &str, inode: FilesystemIndex) -> Option<bool>
+    {
+        let entry = self.entries.get(&(directory, String::from(name)))?;
+
+        if entry.inode == Some(inode)
+        {
+            entry.symlink
+        }
+        else
+        {
+            None
+        }
+    }
+
+    /// Record whether the entry for a name in a directory is a symbolic link, if it is cached as `inode`
+    pub fn set_symlink(&mut self, directory: FilesystemIndex, name: &str, inode: FilesystemIndex, symlink: bool)
+    {
+        if let Some(entry) = self.entries.get_mut(&(directory, String::from(name)))
+        {
+            if entry.inode == Some(inode)
+            {
+                entry.symlink = Some(symlink);
+            }
+        }
+    }
+
+    /// Get the directory and name an inode was last found under
+    pub fn parent_of(&self, inode: FilesystemIndex) -> Option<(FilesystemIndex, String)>
+    {
+        self.parents.get(&inode).cloned()
+    }
+
+    /// Record the result of a lookup, `None` records that the name does not exist
+    pub fn insert(&mut self, directory: FilesystemIndex, name: &str, inode: Option<FilesystemIndex>)
+    {
+        self.remove(directory, name);
+
+        while self.entries.len() >= self.capacity && self.evict_one() {}
+
+        self.clock += 1;
+
+        let key = (directory, String::from(name));
+
+        // The loopback and parent entries are not names the inode can be found under
+        if let Some(inode) = inode
+        {
+            if name != "." && name != ".."
+            {
+                self.parents.insert(inode, key.clone());
+            }
+        }
+
+        self.lru.insert(self.clock, key.clone());
+        self.entries.insert(key, CachedDentry { inode, symlink: None, last_access: self.clock });
+    }
+
+    /// Remove the entry for a name in a directory
+    pub fn remove(&mut self, directory: FilesystemIndex, name: &str)
+    {
+        let key = (directory, String::from(name));
+
+        if let Some(entry) = self.entries.remove(&key)
+        {
+            self.lru.remove(&entry.last_access);
+
+            if let Some(inode) = entry.inode
+            {
+                if self.parents.get(&inode) == Some(&key)
+                {
+                    self.parents.remove(&inode);
+                }
+            }
+        }
+    }
+
+    /// Drop every entry in a directory, along with any entry which refers to the inode itself
+    pub fn invalidate_inode(&mut self, inode: FilesystemIndex)
+    {
+        let names: Vec<String> = self.entries.keys().filter(|(directory, _)| *directory == inode).map(|(_, name)| name.clone()).collect();
+
+        for name in names
+        {
+            self.remove(inode, &name);
+        }
+
+        let referring: Vec<DentryKey> = self.entries.iter().filter(|(_, entry)| entry.inode == Some(inode)).map(|(key, _)| key.clone()).collect();
+
+        for (directory, name) in referring
+        {
+            self.remove(directory, &name);
+        }
+    }
+
+    /// Drop every negative entry in a directory, used when names may have appeared without going through the cache
+    pub fn invalidate_negative(&mut self, directory: FilesystemIndex)
+    {
+        self.entries.retain(|(dir, _), entry| *dir != directory || entry.inode.is_some());
+
+        let entries = &self.entries;
+        self.lru.retain(|_, key| entries.contains_key(key));
+    }
+
+    /// Drop every entry in or referring to a filesystem, used when it is unmounted
+    pub fn invalidate_mount(&mut self, mount_id: usize)
+    {
+        self.entries.retain(|(directory, _), entry| directory.mount_id != mount_id && entry.inode.map(|inode| inode.mount_id != mount_id).unwrap_or(true));
+        self.parents.retain(|inode, (directory, _)| inode.mount_id != mount_id && directory.mount_id != mount_id);
+
+        let entries = &self.entries;
+        self.lru.retain(|_, key| entries.contains_key(key));
+    }
+
+    /// Evict the least recently used entry, returns false if the cache is empty
+    fn evict_one(&mut self) -> bool
+    {
+        let victim = self.lru.values().next().cloned();
+
+        if let Some((directory, name)) = victim
+        {
+            self.remove(directory, &name);
+            true
+        }
+        else
+        {
+            false
+        }
+    }
+}
+
+/// Directory Entry Cache Test
+#[test_case]
+fn directory_cache()
+{
+    let dir = FilesystemIndex { mount_id: 0, inode: 1 };
+    let file = FilesystemIndex { mount_id: 0, inode: 2 };
+
+    let mut cache = DirectoryCache::new(2);
+
+    // Misses, negative entries and positive entries are distinguished
+    assert!(cache.get(dir, "a").is_none());
+    cache.insert(dir, "a", None);
+    assert_eq!(cache.get(dir, "a"), Some(None));
+    cache.insert(dir, "a", Some(file));
+    assert_eq!(cache.get(dir, "a"), Some(Some(file)));
+    assert_eq!(cache.parent_of(file), Some((dir, String::from("a"))));
+
+    // The least recently used entry is evicted first
+    cache.insert(dir, "b", None);
+    cache.get(dir, "a");
+    cache.insert(dir, "c", None);
+    assert_eq!(cache.entry_count(), 2);
+    assert!(cache.get(dir, "b").is_none());
+
+    // The type of an entry is only reported for the inode it was recorded against
+    let link = FilesystemIndex { mount_id: 0, inode: 3 };
+    cache.insert(dir, "d", Some(link));
+    assert_eq!(cache.is_symlink(dir, "d", link), None);
+    cache.set_symlink(dir, "d", link, true);
+    assert_eq!(cache.is_symlink(dir, "d", link), Some(true));
+    assert_eq!(cache.is_symlink(dir, "d", file), None);
+    cache.insert(dir, "a", Some(file));
+
+    // Invalidating an inode drops the entries referring to it
+    cache.invalidate_inode(file);
+    assert!(cache.get(dir, "a").is_none());
+    assert!(cache.parent_of(file).is_none());
+}
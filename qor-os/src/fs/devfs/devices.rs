@@ -4,7 +4,7 @@ use fs::ioctl::IOControlCommand;
 
 use process::descriptor::*;
 
-use fs::structures::FilesystemIndex;
+use fs::structures::{DirectoryEntryType, FilesystemIndex};
 
 use super::tty::TeletypeDevice;
 
@@ -33,8 +33,9 @@ impl core::fmt::Display for DeviceDirectories
 /// DeviceFile object
 pub struct DeviceFile
 {
-    pub name: &'static str,
+    pub name: String,
     pub directory: DeviceDirectories,
+    pub block_device: Option<usize>,
     desc_const: Box<dyn Fn(FilesystemIndex) -> Box<dyn FileDescriptor>>,
     io_ctl: Box<dyn Fn(IOControlCommand) -> usize>
 }
@@ -42,22 +43,51 @@ pub struct DeviceFile
 impl DeviceFile
 {
     /// Create a new device file
-    pub fn new(name: &'static str, desc_const: Box<dyn Fn(FilesystemIndex) -> Box<dyn FileDescriptor>>,
+    pub fn new(name: &str, desc_const: Box<dyn Fn(FilesystemIndex) -> Box<dyn FileDescriptor>>,
                io_ctl: Box<dyn Fn(IOControlCommand) -> usize>) -> Self
     {
         Self
         {
-            name, desc_const, io_ctl, directory: DeviceDirectories::Root
+            name: String::from(name), desc_const, io_ctl, directory: DeviceDirectories::Root, block_device: None
         }
     }
 
     /// Create a new device file in a sub directory
-    pub fn new_in_dir(name: &'static str, desc_const: Box<dyn Fn(FilesystemIndex) -> Box<dyn FileDescriptor>>,
+    pub fn new_in_dir(name: &str, desc_const: Box<dyn Fn(FilesystemIndex) -> Box<dyn FileDescriptor>>,
                io_ctl: Box<dyn Fn(IOControlCommand) -> usize>, directory: DeviceDirectories) -> Self
     {
         Self
         {
-            name, desc_const, io_ctl, directory
+            name: String::from(name), desc_const, io_ctl, directory, block_device: None
+        }
+    }
+
+    /// Create a new device file for the registered block device with the given index
+    pub fn new_block_device(index: usize) -> Self
+    {
+        Self
+        {
+            name: drivers::block::get_block_device_name(index).unwrap(),
+            desc_const: Box::new(
+                move |inode| Box::new(
+                    BlockDeviceDescriptor::new(drivers::block::get_block_device(index).unwrap(), inode)
+                )),
+            io_ctl: Box::new( |_| usize::MAX),
+            directory: DeviceDirectories::Root,
+            block_device: Some(index)
+        }
+    }
+
+    /// Get the type of directory entry for the device
+    pub fn entry_type(&self) -> DirectoryEntryType
+    {
+        if self.block_device.is_some()
+        {
+            DirectoryEntryType::BlockDevice
+        }
+        else
+        {
+            DirectoryEntryType::CharDevice
         }
     }
 
@@ -161,5 +191,11 @@ pub fn get_device_files() -> Vec<DeviceFile>
             Box::new( |cmd| drivers::rtc::RealTimeClockDriver::get_driver().exec_ioctl(cmd))
         ));
 
+    // /dev/vda, /dev/vda1, ... : Raw access to the block devices
+    for index in 0..drivers::block::block_device_count()
+    {
+        result.push(DeviceFile::new_block_device(index));
+    }
+
     result
 }
\ No newline at end of file
@@ -3,7 +3,7 @@ use crate::*;
 use super::super::fstrait::*;
 use super::super::structures::*;
 
-use libutils::paths::PathBuffer;
+use libutils::paths::{OwnedPath, PathBuffer};
 
 use crate::process::descriptor::*;
 
@@ -62,8 +62,8 @@ impl DevFilesystem
             let dir_ent = DirectoryEntry
                 {
                     index: FilesystemIndex { mount_id, inode: i + 2 + self.directories.len()},
-                    name: String::from(dev.name),
-                    entry_type: DirectoryEntryType::CharDevice,
+                    name: dev.name.clone(),
+                    entry_type: dev.entry_type(),
                 };
 
             result.push(dir_ent);
@@ -144,7 +144,7 @@ impl Filesystem for DevFilesystem
     }
 
     /// Convert an inode to a path
-    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<PathBuffer>
+    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<OwnedPath>
     {
         if let Some(vfs) = &mut self.vfs
         {
@@ -209,9 +209,66 @@ impl Filesystem for DevFilesystem
     }
 
     /// Get the directory entry for the given inode
-    fn get_stat(&mut self, _inode: FilesystemIndex) -> FilesystemResult<FileStat>
+    fn get_stat(&mut self, inode: FilesystemIndex) -> FilesystemResult<FileStat>
     {
-        todo!()
+        if Some(inode.mount_id) == self.mount_id
+        {
+            let (mode, special_dev_id, size) = if inode.inode < 2 + self.directories.len()
+            {
+                (0o040755, 0, 0)
+            }
+            else if inode.inode < 2 + self.directories.len() + self.devices.len()
+            {
+                let device = &self.devices[inode.inode - 2 - self.directories.len()];
+
+                if let Some(index) = device.block_device
+                {
+                    let sectors = crate::drivers::block::get_block_device(index).ok_or(FilesystemError::BadINode)?.sector_count();
+
+                    (0o060660, index, sectors as usize * 512)
+                }
+                else
+                {
+                    (0o020666, 0, 0)
+                }
+            }
+            else if inode.inode & PSUEDO_TERMINAL_FLAG > 0
+            {
+                (0o020620, inode.inode & ((1 << 16) - 1), 0)
+            }
+            else
+            {
+                return Err(FilesystemError::BadINode);
+            };
+
+            Ok(FileStat
+            {
+                dev_id: inode.mount_id,
+                inode: inode.inode,
+                mode,
+                links: 1,
+                uid: 0,
+                gid: 0,
+                special_dev_id,
+                size,
+                blk_size: 512,
+                blocks_alloced: (size + 511) / 512,
+                atime: 0,
+                mtime: 0,
+                ctime: 0,
+            })
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.get_stat(inode)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
     }
 
     fn create_file(&mut self, _inode: FilesystemIndex, _name: alloc::string::String) -> FilesystemResult<FilesystemIndex>
@@ -297,11 +354,6 @@ impl Filesystem for DevFilesystem
         }
     }
 
-    fn mount_fs_at(&mut self, _inode: FilesystemIndex, _root: FilesystemIndex, _name: alloc::string::String) -> FilesystemResult<()>
-    {
-        todo!()
-    }
-
     /// Open a filedescriptor for the given inode
     fn open_fd(&mut self, inode: FilesystemIndex, mode: usize) -> FilesystemResult<Box<dyn crate::process::descriptor::FileDescriptor>>
     {
@@ -370,4 +422,11 @@ impl Filesystem for DevFilesystem
             Err(FilesystemError::FilesystemNotMounted)
         }
     }
+
+    /// Check if the results of lookups can be cached, filesystems whose entries change on their own return false
+    fn cacheable_lookups(&mut self) -> bool
+    {
+        // Pseudo terminal entries come and go as terminals are opened and closed
+        false
+    }
 }
\ No newline at end of file
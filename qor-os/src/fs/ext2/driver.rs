@@ -0,0 +1,2111 @@
+use crate::*;
+
+use crate::fs::fstrait::*;
+use crate::fs::structures::*;
+
+use super::structures::*;
+use super::xattr::*;
+
+use alloc::vec;
+use alloc::collections::{BTreeMap, VecDeque};
+
+use crate::process::descriptor::*;
+
+use crate::drivers::block::BlockOperation;
+
+use libutils::paths::{OwnedPath, PathBuffer};
+
+use super::super::ioctl::*;
+
+/// Number of clean blocks held by the block cache, file data is cached by the page cache so this only needs to
+/// cover metadata
+const BLOCK_CACHE_SIZE: usize = 128;
+
+/// Size of the sectors of the block device
+const SECTOR_SIZE: usize = 512;
+
+enum UpdateTimes
+{
+    Access,
+    Modify,
+    Create
+}
+
+/// Get the current time as stored in an inode
+fn current_time() -> u32
+{
+    (crate::drivers::rtc::driver::RealTimeClockDriver::get_driver().get_unix_timestamp_nano() / 1_000_000_000) as u32
+}
+
+fn update_time(inode: &mut Ext2Inode, time: UpdateTimes)
+{
+    let this_time = current_time();
+
+    match time
+    {
+        UpdateTimes::Access => inode.atime = this_time,
+        UpdateTimes::Create => inode.ctime = this_time,
+        UpdateTimes::Modify => inode.mtime = this_time,
+    }
+}
+
+/// Read the little endian word at the given index of a block
+fn get_u32(buffer: &[u8], index: usize) -> u32
+{
+    u32::from_le_bytes([buffer[index * 4], buffer[index * 4 + 1], buffer[index * 4 + 2], buffer[index * 4 + 3]])
+}
+
+/// Write a little endian word at the given index of a block
+fn set_u32(buffer: &mut [u8], index: usize, value: u32)
+{
+    buffer[index * 4..index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Read the header of the directory entry at an offset in a block
+fn read_dir_header(buffer: &[u8], offset: usize) -> Ext2DirEntryHeader
+{
+    unsafe { (buffer[offset..].as_ptr() as *const Ext2DirEntryHeader).read_unaligned() }
+}
+
+/// Write the header of the directory entry at an offset in a block
+fn write_dir_header(buffer: &mut [u8], offset: usize, header: Ext2DirEntryHeader)
+{
+    unsafe { (buffer[offset..].as_mut_ptr() as *mut Ext2DirEntryHeader).write_unaligned(header) }
+}
+
+/// A directory entry along with where it is stored
+struct DirectorySlot
+{
+    block: usize,
+    offset: usize,
+    inode: usize,
+    file_type: u8,
+    name: String
+}
+
+/// Ext2 Filesystem Driver
+pub struct Ext2Filesystem
+{
+    device: &'static mut dyn crate::drivers::block::BlockDevice,
+    mount_id: Option<usize>,
+    vfs: Option<&'static mut crate::fs::vfs::FilesystemInterface>,
+    superblock: Option<Ext2SuperBlock>,
+    raw_superblock: Vec<u8>,
+    groups: Vec<Ext2GroupDescriptor>,
+    block_size: usize,
+    inode_size: usize,
+    first_inode: usize,
+    cache: BTreeMap<usize, Vec<u8>>,
+    cache_order: VecDeque<usize>,
+    dirty: BTreeMap<usize, Vec<u8>>,
+    metadata_dirty: bool
+}
+
+impl Ext2Filesystem
+{
+    /// Initialize a new Ext2 Filesystem Interface on the block device with the given index
+    pub fn new(device_id: usize) -> Self
+    {
+        Self
+        {
+            device: crate::drivers::block::get_block_device(device_id).unwrap(),
+            mount_id: None,
+            vfs: None,
+            superblock: None,
+            raw_superblock: Vec::new(),
+            groups: Vec::new(),
+            block_size: 1024,
+            inode_size: EXT2_GOOD_OLD_INODE_SIZE,
+            first_inode: EXT2_GOOD_OLD_FIRST_INODE,
+            cache: BTreeMap::new(),
+            cache_order: VecDeque::new(),
+            dirty: BTreeMap::new(),
+            metadata_dirty: false
+        }
+    }
+
+    /// Get the vfs this filesystem is mounted in
+    fn vfs(&mut self) -> FilesystemResult<&mut crate::fs::vfs::FilesystemInterface>
+    {
+        match &mut self.vfs
+        {
+            Some(vfs) => Ok(vfs),
+            None => Err(FilesystemError::FilesystemNotMounted)
+        }
+    }
+
+    /// Check if an index refers to an inode on this filesystem
+    fn is_local(&self, index: FilesystemIndex) -> bool
+    {
+        Some(index.mount_id) == self.mount_id
+    }
+
+    /// Build the index of an inode on this filesystem
+    fn index(&self, inode: usize) -> FilesystemIndex
+    {
+        FilesystemIndex { mount_id: self.mount_id.unwrap_or(0), inode }
+    }
+
+    /// Get a copy of the superblock
+    fn superblock(&self) -> FilesystemResult<Ext2SuperBlock>
+    {
+        self.superblock.ok_or(FilesystemError::FilesystemUninitialized)
+    }
+
+    /// Check if directory entries record the type of the file they refer to
+    fn has_file_types(&self) -> bool
+    {
+        self.superblock.map(|superblock| superblock.feature_incompat & EXT2_FEATURE_INCOMPAT_FILETYPE > 0).unwrap_or(false)
+    }
+
+    /// Number of block numbers held in an indirect block
+    fn pointers_per_block(&self) -> usize
+    {
+        self.block_size / 4
+    }
+
+    /// Add a clean block to the block cache, evicting the oldest block if the cache is full
+    fn cache_block(&mut self, index: usize, data: Vec<u8>)
+    {
+        if self.cache.insert(index, data).is_none()
+        {
+            self.cache_order.push_back(index);
+        }
+
+        while self.cache_order.len() > BLOCK_CACHE_SIZE
+        {
+            if let Some(old) = self.cache_order.pop_front()
+            {
+                self.cache.remove(&old);
+            }
+        }
+    }
+
+    /// Read a block as a buffer
+    fn read_block(&mut self, index: usize) -> FilesystemResult<Vec<u8>>
+    {
+        if let Some(data) = self.dirty.get(&index)
+        {
+            return Ok(data.clone());
+        }
+
+        if let Some(data) = self.cache.get(&index)
+        {
+            return Ok(data.clone());
+        }
+
+        // A block number past the end of the disk means the metadata is damaged
+        if index == 0 || index >= self.superblock()?.blocks_count as usize
+        {
+            kerrorln!("Ext2 block number {} is out of range", index);
+            return Err(FilesystemError::BadFilesystemFormat);
+        }
+
+        let mut buffer = vec![0u8; self.block_size];
+        let sectors = self.block_size / SECTOR_SIZE;
+
+        if let Err(e) = self.device.read_sectors((index * sectors) as u64, buffer.as_mut_ptr(), sectors)
+        {
+            kerrorln!("Unable to read block {}: {:?}", index, e);
+            return Err(FilesystemError::BadFilesystemFormat);
+        }
+
+        self.cache_block(index, buffer.clone());
+
+        Ok(buffer)
+    }
+
+    /// Get a mutable reference to a block, which is written back at the next sync
+    fn block_mut(&mut self, index: usize) -> FilesystemResult<&mut Vec<u8>>
+    {
+        if !self.dirty.contains_key(&index)
+        {
+            let data = self.read_block(index)?;
+            self.dirty.insert(index, data);
+        }
+
+        Ok(self.dirty.get_mut(&index).unwrap())
+    }
+
+    /// Replace the contents of a block with zeros without reading it
+    fn zero_block(&mut self, index: usize)
+    {
+        self.dirty.insert(index, vec![0u8; self.block_size]);
+    }
+
+    /// Get the block and byte offset in that block holding an inode
+    fn inode_location(&self, inode_number: usize) -> FilesystemResult<(usize, usize)>
+    {
+        let superblock = self.superblock()?;
+
+        if inode_number == 0 || inode_number > superblock.inodes_count as usize
+        {
+            return Err(FilesystemError::BadINode);
+        }
+
+        let group = (inode_number - 1) / superblock.inodes_per_group as usize;
+        let index = (inode_number - 1) % superblock.inodes_per_group as usize;
+
+        let table = self.groups.get(group).ok_or(FilesystemError::BadINode)?.inode_table as usize;
+        let offset = index * self.inode_size;
+
+        Ok((table + offset / self.block_size, offset % self.block_size))
+    }
+
+    /// Get the block group an inode belongs to
+    fn group_of_inode(&self, inode_number: usize) -> usize
+    {
+        self.superblock.map(|superblock| (inode_number - 1) / superblock.inodes_per_group as usize).unwrap_or(0)
+    }
+
+    /// Read an inode
+    fn get_inode(&mut self, inode_number: usize) -> FilesystemResult<Ext2Inode>
+    {
+        kdebugln!(Filesystem, "Opening inode {} on fs {:?}", inode_number, self.mount_id);
+
+        let (block, offset) = self.inode_location(inode_number)?;
+        let buffer = self.read_block(block)?;
+
+        Ok(unsafe { (buffer[offset..].as_ptr() as *const Ext2Inode).read_unaligned() })
+    }
+
+    /// Write an inode back to the inode table
+    fn put_inode(&mut self, inode_number: usize, inode: &Ext2Inode) -> FilesystemResult<()>
+    {
+        let (block, offset) = self.inode_location(inode_number)?;
+        let buffer = self.block_mut(block)?;
+
+        unsafe { (buffer[offset..].as_mut_ptr() as *mut Ext2Inode).write_unaligned(*inode) };
+
+        Ok(())
+    }
+
+    /// Find the first clear bit from `start` up to `count` in a bitmap block and set it
+    fn claim_bit(&mut self, bitmap: usize, start: usize, count: usize) -> FilesystemResult<Option<usize>>
+    {
+        let buffer = self.read_block(bitmap)?;
+        let count = count.min(buffer.len() * 8);
+
+        for index in start..count
+        {
+            if buffer[index / 8] == 0xFF
+            {
+                continue;
+            }
+
+            if buffer[index / 8] & (1 << (index % 8)) == 0
+            {
+                self.block_mut(bitmap)?[index / 8] |= 1 << (index % 8);
+
+                return Ok(Some(index));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Clear a bit in a bitmap block, returning false if it was already clear
+    fn release_bit(&mut self, bitmap: usize, index: usize) -> FilesystemResult<bool>
+    {
+        let buffer = self.block_mut(bitmap)?;
+
+        let was_set = buffer[index / 8] & (1 << (index % 8)) != 0;
+        buffer[index / 8] &= !(1 << (index % 8));
+
+        Ok(was_set)
+    }
+
+    /// Claim a free block, searching from the given block group
+    fn allocate_block(&mut self, goal: usize) -> FilesystemResult<usize>
+    {
+        let superblock = self.superblock()?;
+        let group_count = self.groups.len();
+
+        for i in 0..group_count
+        {
+            let group = (goal + i) % group_count;
+
+            if self.groups[group].free_blocks_count == 0
+            {
+                continue;
+            }
+
+            let first = superblock.first_data_block as usize + group * superblock.blocks_per_group as usize;
+            let count = (superblock.blocks_count as usize - first).min(superblock.blocks_per_group as usize);
+
+            if let Some(bit) = self.claim_bit(self.groups[group].block_bitmap as usize, 0, count)?
+            {
+                self.groups[group].free_blocks_count -= 1;
+
+                let superblock = self.superblock.as_mut().unwrap();
+                superblock.free_blocks_count = superblock.free_blocks_count.saturating_sub(1);
+
+                self.metadata_dirty = true;
+
+                return Ok(first + bit);
+            }
+        }
+
+        Err(FilesystemError::OutOfSpace)
+    }
+
+    /// Return a block to the free pool
+    fn free_block(&mut self, block: usize) -> FilesystemResult<()>
+    {
+        let superblock = self.superblock()?;
+
+        let relative = block.checked_sub(superblock.first_data_block as usize).ok_or(FilesystemError::BadFilesystemFormat)?;
+        let group = relative / superblock.blocks_per_group as usize;
+        let bit = relative % superblock.blocks_per_group as usize;
+
+        let bitmap = self.groups.get(group).ok_or(FilesystemError::BadFilesystemFormat)?.block_bitmap as usize;
+
+        if self.release_bit(bitmap, bit)?
+        {
+            self.groups[group].free_blocks_count += 1;
+            self.superblock.as_mut().unwrap().free_blocks_count += 1;
+            self.metadata_dirty = true;
+        }
+
+        Ok(())
+    }
+
+    /// Claim a free inode, searching from the given block group
+    fn allocate_inode(&mut self, goal: usize, directory: bool) -> FilesystemResult<usize>
+    {
+        let superblock = self.superblock()?;
+        let group_count = self.groups.len();
+        let per_group = superblock.inodes_per_group as usize;
+
+        for i in 0..group_count
+        {
+            let group = (goal + i) % group_count;
+
+            if self.groups[group].free_inodes_count == 0
+            {
+                continue;
+            }
+
+            // The inodes before the first non-reserved inode are never handed out
+            let start = (self.first_inode - 1).saturating_sub(group * per_group);
+            let count = (superblock.inodes_count as usize - group * per_group).min(per_group);
+
+            if let Some(bit) = self.claim_bit(self.groups[group].inode_bitmap as usize, start, count)?
+            {
+                self.groups[group].free_inodes_count -= 1;
+
+                if directory
+                {
+                    self.groups[group].used_dirs_count += 1;
+                }
+
+                let superblock = self.superblock.as_mut().unwrap();
+                superblock.free_inodes_count = superblock.free_inodes_count.saturating_sub(1);
+
+                self.metadata_dirty = true;
+
+                return Ok(group * per_group + bit + 1);
+            }
+        }
+
+        Err(FilesystemError::OutOfSpace)
+    }
+
+    /// Return an inode to the free pool
+    fn free_inode(&mut self, inode_number: usize, directory: bool) -> FilesystemResult<()>
+    {
+        let superblock = self.superblock()?;
+
+        let group = (inode_number - 1) / superblock.inodes_per_group as usize;
+        let bit = (inode_number - 1) % superblock.inodes_per_group as usize;
+
+        let bitmap = self.groups.get(group).ok_or(FilesystemError::BadINode)?.inode_bitmap as usize;
+
+        if self.release_bit(bitmap, bit)?
+        {
+            self.groups[group].free_inodes_count += 1;
+
+            if directory
+            {
+                self.groups[group].used_dirs_count = self.groups[group].used_dirs_count.saturating_sub(1);
+            }
+
+            self.superblock.as_mut().unwrap().free_inodes_count += 1;
+            self.metadata_dirty = true;
+        }
+
+        Ok(())
+    }
+
+    /// Allocate and initialize a new inode with the given mode, placing it near the inode `near`
+    fn new_inode(&mut self, near: usize, mode: u16) -> FilesystemResult<usize>
+    {
+        let inode_number = self.allocate_inode(self.group_of_inode(near), mode & S_IFMT == S_IFDIR)?;
+
+        // Clear the whole on disk inode, including any space past the fields this driver knows about
+        let (block, offset) = self.inode_location(inode_number)?;
+        let inode_size = self.inode_size;
+        let buffer = self.block_mut(block)?;
+
+        for byte in &mut buffer[offset..offset + inode_size]
+        {
+            *byte = 0;
+        }
+
+        // Large inodes record how much of the extra space is in use
+        if inode_size > EXT2_GOOD_OLD_INODE_SIZE
+        {
+            let extra = (inode_size - EXT2_GOOD_OLD_INODE_SIZE).min(32) as u16;
+            buffer[offset + EXT2_GOOD_OLD_INODE_SIZE..offset + EXT2_GOOD_OLD_INODE_SIZE + 2].copy_from_slice(&extra.to_le_bytes());
+        }
+
+        let time = current_time();
+
+        let inode = Ext2Inode
+        {
+            mode,
+            links_count: 1,
+            atime: time,
+            ctime: time,
+            mtime: time,
+            ..Default::default()
+        };
+
+        self.put_inode(inode_number, &inode)?;
+
+        Ok(inode_number)
+    }
+
+    /// Get the block holding the given block of an inode, returns zero if the block is a hole
+    fn block_for(&mut self, inode: &Ext2Inode, block: usize) -> FilesystemResult<usize>
+    {
+        if block < EXT2_DIRECT_BLOCKS
+        {
+            return Ok(inode.block[block] as usize);
+        }
+
+        let per_block = self.pointers_per_block();
+
+        let mut block = block - EXT2_DIRECT_BLOCKS;
+        let mut span = per_block;
+
+        for level in 1..=3
+        {
+            if block < span
+            {
+                let mut current = inode.block[EXT2_DIRECT_BLOCKS - 1 + level] as usize;
+
+                // Walk down the indirect blocks
+                for depth in (0..level).rev()
+                {
+                    if current == 0
+                    {
+                        return Ok(0);
+                    }
+
+                    let table = self.read_block(current)?;
+                    current = get_u32(&table, (block / per_block.pow(depth as u32)) % per_block) as usize;
+                }
+
+                return Ok(current);
+            }
+
+            block -= span;
+            span *= per_block;
+        }
+
+        Err(FilesystemError::OutOfSpace)
+    }
+
+    /// Claim a zeroed block for an inode, counting it in the inode's block count
+    fn allocate_data_block(&mut self, inode_number: usize, inode: &mut Ext2Inode) -> FilesystemResult<usize>
+    {
+        let block = self.allocate_block(self.group_of_inode(inode_number))?;
+        self.zero_block(block);
+
+        inode.blocks += (self.block_size / SECTOR_SIZE) as u32;
+
+        Ok(block)
+    }
+
+    /// Free a block belonging to an inode, removing it from the inode's block count
+    fn free_data_block(&mut self, inode: &mut Ext2Inode, block: usize) -> FilesystemResult<()>
+    {
+        self.free_block(block)?;
+
+        inode.blocks = inode.blocks.saturating_sub((self.block_size / SECTOR_SIZE) as u32);
+
+        Ok(())
+    }
+
+    /// Get the block holding the given block of an inode, allocating it and any indirect blocks above it if needed
+    fn allocate_block_for(&mut self, inode_number: usize, inode: &mut Ext2Inode, block: usize) -> FilesystemResult<usize>
+    {
+        if block < EXT2_DIRECT_BLOCKS
+        {
+            if inode.block[block] == 0
+            {
+                inode.block[block] = self.allocate_data_block(inode_number, inode)? as u32;
+            }
+
+            return Ok(inode.block[block] as usize);
+        }
+
+        let per_block = self.pointers_per_block();
+
+        let mut block = block - EXT2_DIRECT_BLOCKS;
+        let mut span = per_block;
+
+        for level in 1..=3
+        {
+            if block < span
+            {
+                let slot = EXT2_DIRECT_BLOCKS - 1 + level;
+
+                if inode.block[slot] == 0
+                {
+                    inode.block[slot] = self.allocate_data_block(inode_number, inode)? as u32;
+                }
+
+                let mut current = inode.block[slot] as usize;
+
+                // Walk down the indirect blocks, filling in any which are missing
+                for depth in (0..level).rev()
+                {
+                    let index = (block / per_block.pow(depth as u32)) % per_block;
+                    let mut next = get_u32(&self.read_block(current)?, index) as usize;
+
+                    if next == 0
+                    {
+                        next = self.allocate_data_block(inode_number, inode)?;
+                        set_u32(self.block_mut(current)?, index, next as u32);
+                    }
+
+                    current = next;
+                }
+
+                return Ok(current);
+            }
+
+            block -= span;
+            span *= per_block;
+        }
+
+        Err(FilesystemError::OutOfSpace)
+    }
+
+    /// Free an indirect block and every block it refers to
+    fn free_tree(&mut self, inode: &mut Ext2Inode, block: usize, level: usize) -> FilesystemResult<()>
+    {
+        if level > 0
+        {
+            let table = self.read_block(block)?;
+
+            for i in 0..self.pointers_per_block()
+            {
+                let entry = get_u32(&table, i) as usize;
+
+                if entry != 0
+                {
+                    self.free_tree(inode, entry, level - 1)?;
+                }
+            }
+        }
+
+        self.free_data_block(inode, block)
+    }
+
+    /// Free the blocks an indirect block refers to from block `keep` onwards, returns true if it no longer refers
+    /// to any blocks
+    fn release_indirect(&mut self, inode: &mut Ext2Inode, block: usize, level: usize, keep: usize) -> FilesystemResult<bool>
+    {
+        let per_block = self.pointers_per_block();
+        let sub_span = per_block.pow(level as u32 - 1);
+
+        let table = self.read_block(block)?;
+        let mut empty = true;
+
+        for i in 0..per_block
+        {
+            let entry = get_u32(&table, i) as usize;
+
+            if entry == 0
+            {
+                continue;
+            }
+
+            let start = i * sub_span;
+
+            let freed = if start >= keep
+            {
+                self.free_tree(inode, entry, level - 1)?;
+                true
+            }
+            else if level > 1 && keep < start + sub_span && self.release_indirect(inode, entry, level - 1, keep - start)?
+            {
+                self.free_data_block(inode, entry)?;
+                true
+            }
+            else
+            {
+                false
+            };
+
+            if freed
+            {
+                set_u32(self.block_mut(block)?, i, 0);
+            }
+            else
+            {
+                empty = false;
+            }
+        }
+
+        Ok(empty)
+    }
+
+    /// Free every block of an inode from block `keep` onwards, along with any indirect blocks no longer needed
+    fn release_blocks(&mut self, inode: &mut Ext2Inode, keep: usize) -> FilesystemResult<()>
+    {
+        for i in keep.min(EXT2_DIRECT_BLOCKS)..EXT2_DIRECT_BLOCKS
+        {
+            if inode.block[i] != 0
+            {
+                self.free_data_block(inode, inode.block[i] as usize)?;
+                inode.block[i] = 0;
+            }
+        }
+
+        let per_block = self.pointers_per_block();
+
+        let mut base = EXT2_DIRECT_BLOCKS;
+        let mut span = per_block;
+
+        for level in 1..=3
+        {
+            let slot = EXT2_DIRECT_BLOCKS - 1 + level;
+            let root = inode.block[slot] as usize;
+
+            if root != 0
+            {
+                if keep <= base
+                {
+                    self.free_tree(inode, root, level)?;
+                    inode.block[slot] = 0;
+                }
+                else if keep < base + span && self.release_indirect(inode, root, level, keep - base)?
+                {
+                    self.free_data_block(inode, root)?;
+                    inode.block[slot] = 0;
+                }
+            }
+
+            base += span;
+            span *= per_block;
+        }
+
+        Ok(())
+    }
+
+    /// Record that the filesystem holds a file too large for the original format
+    fn note_file_size(&mut self, size: usize)
+    {
+        if size >= 1 << 31
+        {
+            if let Some(superblock) = &mut self.superblock
+            {
+                if superblock.feature_ro_compat & EXT2_FEATURE_RO_COMPAT_LARGE_FILE == 0
+                {
+                    superblock.feature_ro_compat |= EXT2_FEATURE_RO_COMPAT_LARGE_FILE;
+                    self.metadata_dirty = true;
+                }
+            }
+        }
+    }
+
+    /// Read the data from an inode
+    fn read_from_inode(&mut self, inode: &Ext2Inode) -> FilesystemResult<Vec<u8>>
+    {
+        let size = inode.file_size();
+
+        // Short symbolic links keep their target in place of the block numbers
+        if inode.is_fast_symlink()
+        {
+            let bytes = unsafe { core::slice::from_raw_parts(inode.block.as_ptr() as *const u8, 60) };
+
+            return Ok(bytes[..size].to_vec());
+        }
+
+        let block_size = self.block_size;
+        let mut buffer = vec![0u8; size];
+
+        for (i, chunk) in buffer.chunks_mut(block_size).enumerate()
+        {
+            // Holes read as zeros
+            let block = self.block_for(inode, i)?;
+
+            if block != 0
+            {
+                let data = self.read_block(block)?;
+                chunk.copy_from_slice(&data[..chunk.len()]);
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    /// Write data to the blocks of an inode starting at the given block, allocating blocks as needed
+    fn write_blocks(&mut self, inode_number: usize, inode: &mut Ext2Inode, first_block: usize, data: &[u8]) -> FilesystemResult<()>
+    {
+        for (i, chunk) in data.chunks(self.block_size).enumerate()
+        {
+            let block = self.allocate_block_for(inode_number, inode, first_block + i)?;
+            let buffer = self.block_mut(block)?;
+
+            buffer[..chunk.len()].copy_from_slice(chunk);
+
+            for byte in &mut buffer[chunk.len()..]
+            {
+                *byte = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Change the size of an inode, freeing any blocks past the new end
+    fn resize_inode(&mut self, inode: &mut Ext2Inode, size: usize) -> FilesystemResult<()>
+    {
+        let block_size = self.block_size;
+
+        if size < inode.file_size()
+        {
+            self.release_blocks(inode, (size + block_size - 1) / block_size)?;
+
+            // The tail of the last block must read as zeros if the file grows again
+            if size % block_size != 0
+            {
+                let block = self.block_for(inode, size / block_size)?;
+
+                if block != 0
+                {
+                    for byte in &mut self.block_mut(block)?[size % block_size..]
+                    {
+                        *byte = 0;
+                    }
+                }
+            }
+        }
+
+        inode.set_file_size(size);
+        self.note_file_size(size);
+
+        Ok(())
+    }
+
+    /// Write data to a file
+    fn write_to_file(&mut self, inode_number: usize, data: &[u8]) -> FilesystemResult<()>
+    {
+        let mut inode = self.get_inode(inode_number)?;
+
+        if inode.is_fast_symlink()
+        {
+            return Err(FilesystemError::PermissionDenied);
+        }
+
+        self.write_blocks(inode_number, &mut inode, 0, data)?;
+        self.release_blocks(&mut inode, (data.len() + self.block_size - 1) / self.block_size)?;
+
+        inode.set_file_size(data.len());
+        self.note_file_size(data.len());
+
+        update_time(&mut inode, UpdateTimes::Modify);
+        update_time(&mut inode, UpdateTimes::Create);
+
+        self.put_inode(inode_number, &inode)
+    }
+
+    /// Remove an inode and the blocks associated with it
+    fn delete_inode(&mut self, inode_number: usize) -> FilesystemResult<()>
+    {
+        let mut inode = self.get_inode(inode_number)?;
+
+        if !inode.is_fast_symlink()
+        {
+            self.release_blocks(&mut inode, 0)?;
+        }
+
+        if inode.file_acl != 0
+        {
+            self.release_xattr_block(&mut inode)?;
+        }
+
+        inode.links_count = 0;
+        inode.dtime = current_time();
+
+        self.put_inode(inode_number, &inode)?;
+        self.free_inode(inode_number, inode.mode & S_IFMT == S_IFDIR)
+    }
+
+    /// Read the extended attributes of an inode
+    fn read_xattrs(&mut self, inode: &Ext2Inode) -> FilesystemResult<Vec<Ext2Xattr>>
+    {
+        if inode.file_acl == 0
+        {
+            return Ok(Vec::new());
+        }
+
+        decode_xattr_block(&self.read_block(inode.file_acl as usize)?)
+    }
+
+    /// Store the extended attributes of an inode, giving the inode a block of its own if its block is shared
+    fn write_xattrs(&mut self, inode_number: usize, inode: &mut Ext2Inode, mut attributes: Vec<Ext2Xattr>) -> FilesystemResult<()>
+    {
+        // Build the block first so attributes which do not fit leave the old block in place
+        let data = if attributes.is_empty() { None } else { Some(encode_xattr_block(&mut attributes, self.block_size)?) };
+
+        if inode.file_acl != 0 && (data.is_none() || xattr_block_refcount(&self.read_block(inode.file_acl as usize)?) > 1)
+        {
+            self.release_xattr_block(inode)?;
+        }
+
+        if let Some(data) = data
+        {
+            if inode.file_acl == 0
+            {
+                inode.file_acl = self.allocate_data_block(inode_number, inode)? as u32;
+            }
+
+            *self.block_mut(inode.file_acl as usize)? = data;
+
+            if let Some(superblock) = &mut self.superblock
+            {
+                if superblock.feature_compat & EXT2_FEATURE_COMPAT_EXT_ATTR == 0
+                {
+                    superblock.feature_compat |= EXT2_FEATURE_COMPAT_EXT_ATTR;
+                    self.metadata_dirty = true;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drop an inode's reference to its extended attribute block, freeing the block once nothing refers to it
+    fn release_xattr_block(&mut self, inode: &mut Ext2Inode) -> FilesystemResult<()>
+    {
+        let block = inode.file_acl as usize;
+        let refcount = xattr_block_refcount(&self.read_block(block)?);
+
+        if refcount > 1
+        {
+            set_xattr_block_refcount(self.block_mut(block)?, refcount - 1);
+            inode.blocks = inode.blocks.saturating_sub((self.block_size / SECTOR_SIZE) as u32);
+        }
+        else
+        {
+            self.free_data_block(inode, block)?;
+        }
+
+        inode.file_acl = 0;
+
+        Ok(())
+    }
+
+    /// Read every entry in a directory
+    fn read_directory(&mut self, inode_number: usize) -> FilesystemResult<Vec<DirectorySlot>>
+    {
+        let inode = self.get_inode(inode_number)?;
+
+        if inode.mode & S_IFMT != S_IFDIR
+        {
+            return Err(FilesystemError::INodeIsNotADirectory);
+        }
+
+        let block_size = self.block_size;
+        let file_types = self.has_file_types();
+
+        let mut result = Vec::new();
+
+        for i in 0..inode.file_size() / block_size
+        {
+            let block = self.block_for(&inode, i)?;
+
+            if block == 0
+            {
+                continue;
+            }
+
+            let data = self.read_block(block)?;
+            let mut offset = 0;
+
+            while offset + EXT2_DIR_ENTRY_HEADER_SIZE <= block_size
+            {
+                let header = read_dir_header(&data, offset);
+                let rec_len = header.rec_len as usize;
+
+                // Without file types the name length takes up both bytes
+                let (name_len, file_type) = if file_types { (header.name_len as usize, header.file_type) } else { (header.name_len as usize | (header.file_type as usize) << 8, 0) };
+
+                if rec_len < EXT2_DIR_ENTRY_HEADER_SIZE || offset + rec_len > block_size || EXT2_DIR_ENTRY_HEADER_SIZE + name_len > rec_len
+                {
+                    kerrorln!("Corrupt ext2 directory entry in inode {} at block {} offset {}", inode_number, i, offset);
+                    return Err(FilesystemError::BadFilesystemFormat);
+                }
+
+                if header.inode != 0
+                {
+                    let name_start = offset + EXT2_DIR_ENTRY_HEADER_SIZE;
+
+                    result.push(DirectorySlot
+                    {
+                        block,
+                        offset,
+                        inode: header.inode as usize,
+                        file_type,
+                        name: String::from_utf8_lossy(&data[name_start..name_start + name_len]).to_string()
+                    });
+                }
+
+                offset += rec_len;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Find the inode a name refers to in the directory at the given inode
+    fn find_directory_entry(&mut self, inode: usize, name: &str) -> FilesystemResult<Option<usize>>
+    {
+        Ok(self.read_directory(inode)?.into_iter().find(|slot| slot.name == name).map(|slot| slot.inode))
+    }
+
+    /// Check if a directory holds anything other than its `.` and `..` entries
+    fn is_empty_directory(&mut self, inode: usize) -> FilesystemResult<bool>
+    {
+        Ok(self.read_directory(inode)?.iter().all(|slot| slot.name == "." || slot.name == ".."))
+    }
+
+    /// Mark a directory as changed, dropping any hashed index since this driver does not keep it up to date
+    fn touch_directory(&mut self, inode_number: usize, inode: &mut Ext2Inode) -> FilesystemResult<()>
+    {
+        inode.flags &= !EXT2_INDEX_FL;
+
+        update_time(inode, UpdateTimes::Modify);
+        update_time(inode, UpdateTimes::Create);
+
+        self.put_inode(inode_number, inode)
+    }
+
+    /// Write a directory entry into a block
+    fn write_dir_entry(&self, buffer: &mut [u8], offset: usize, inode: usize, rec_len: usize, name: &str, file_type: u8)
+    {
+        write_dir_header(buffer, offset, Ext2DirEntryHeader
+        {
+            inode: inode as u32,
+            rec_len: rec_len as u16,
+            name_len: name.len() as u8,
+            file_type: if self.has_file_types() { file_type } else { 0 }
+        });
+
+        let name_start = offset + EXT2_DIR_ENTRY_HEADER_SIZE;
+        buffer[name_start..name_start + name.len()].copy_from_slice(name.as_bytes());
+    }
+
+    /// Add a directory entry from the inode and name to the given inode
+    fn add_directory_entry(&mut self, dest: usize, inode: usize, name: &str) -> FilesystemResult<()>
+    {
+        if name.len() == 0 || name.len() > 255
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        let file_type = file_type_from_mode(self.get_inode(inode)?.mode);
+        let file_types = self.has_file_types();
+
+        let mut directory = self.get_inode(dest)?;
+        let block_size = self.block_size;
+        let needed = dir_entry_size(name.len());
+        let block_count = directory.file_size() / block_size;
+
+        // Look for an unused entry or an entry with enough space left over after its name
+        for i in 0..block_count
+        {
+            let block = self.block_for(&directory, i)?;
+
+            if block == 0
+            {
+                continue;
+            }
+
+            let data = self.read_block(block)?;
+            let mut offset = 0;
+
+            while offset + EXT2_DIR_ENTRY_HEADER_SIZE <= block_size
+            {
+                let header = read_dir_header(&data, offset);
+                let rec_len = header.rec_len as usize;
+
+                if rec_len < EXT2_DIR_ENTRY_HEADER_SIZE || offset + rec_len > block_size
+                {
+                    return Err(FilesystemError::BadFilesystemFormat);
+                }
+
+                let name_len = if file_types { header.name_len as usize } else { header.name_len as usize | (header.file_type as usize) << 8 };
+                let used = if header.inode == 0 { 0 } else { dir_entry_size(name_len) };
+
+                if rec_len >= used + needed
+                {
+                    let mut buffer = self.block_mut(block)?.clone();
+
+                    if used == 0
+                    {
+                        self.write_dir_entry(&mut buffer, offset, inode, rec_len, name, file_type);
+                    }
+                    else
+                    {
+                        write_dir_header(&mut buffer, offset, Ext2DirEntryHeader { rec_len: used as u16, ..header });
+                        self.write_dir_entry(&mut buffer, offset + used, inode, rec_len - used, name, file_type);
+                    }
+
+                    *self.block_mut(block)? = buffer;
+
+                    return self.touch_directory(dest, &mut directory);
+                }
+
+                offset += rec_len;
+            }
+        }
+
+        // Otherwise the directory grows by a block holding just the new entry
+        let block = self.allocate_block_for(dest, &mut directory, block_count)?;
+
+        let mut buffer = vec![0u8; block_size];
+        self.write_dir_entry(&mut buffer, 0, inode, block_size, name, file_type);
+        *self.block_mut(block)? = buffer;
+
+        directory.set_file_size((block_count + 1) * block_size);
+
+        self.touch_directory(dest, &mut directory)
+    }
+
+    /// Remove a directory entry from the given inode
+    fn remove_directory_entry(&mut self, inode: usize, name: String) -> FilesystemResult<()>
+    {
+        let mut directory = self.get_inode(inode)?;
+
+        let slot = self.read_directory(inode)?.into_iter().find(|slot| slot.name == name).ok_or_else(|| FilesystemError::FileNotFound(name.clone()))?;
+
+        let buffer = self.block_mut(slot.block)?;
+        let header = read_dir_header(buffer, slot.offset);
+
+        // Find the entry before this one in the same block, if there is one it absorbs the space
+        let mut previous = None;
+        let mut offset = 0;
+
+        while offset < slot.offset
+        {
+            previous = Some(offset);
+            offset += read_dir_header(buffer, offset).rec_len as usize;
+        }
+
+        match previous
+        {
+            Some(previous) =>
+            {
+                let previous_header = read_dir_header(buffer, previous);
+                write_dir_header(buffer, previous, Ext2DirEntryHeader { rec_len: previous_header.rec_len + header.rec_len, ..previous_header });
+            },
+            None => write_dir_header(buffer, slot.offset, Ext2DirEntryHeader { inode: 0, ..header })
+        }
+
+        self.touch_directory(inode, &mut directory)
+    }
+
+    /// Point an existing entry in the directory at the given inode to a different inode
+    fn set_directory_entry(&mut self, inode: usize, name: &str, target: usize) -> FilesystemResult<()>
+    {
+        let file_type = if self.has_file_types() { file_type_from_mode(self.get_inode(target)?.mode) } else { 0 };
+        let mut directory = self.get_inode(inode)?;
+
+        let slot = self.read_directory(inode)?.into_iter().find(|slot| slot.name == name).ok_or_else(|| FilesystemError::FileNotFound(name.to_string()))?;
+
+        let buffer = self.block_mut(slot.block)?;
+        let header = read_dir_header(buffer, slot.offset);
+        write_dir_header(buffer, slot.offset, Ext2DirEntryHeader { inode: target as u32, file_type, ..header });
+
+        self.touch_directory(inode, &mut directory)
+    }
+
+    /// Check if the given inode is a directory
+    fn is_directory(&mut self, inode: usize) -> FilesystemResult<bool>
+    {
+        Ok(self.get_inode(inode)?.mode & S_IFMT == S_IFDIR)
+    }
+
+    /// Add `delta` to the link count of an inode, returning the new count
+    fn adjust_links(&mut self, inode: usize, delta: isize) -> FilesystemResult<usize>
+    {
+        let mut inode_data = self.get_inode(inode)?;
+
+        inode_data.links_count = (inode_data.links_count as isize + delta).max(0) as u16;
+        update_time(&mut inode_data, UpdateTimes::Create);
+
+        self.put_inode(inode, &inode_data)?;
+
+        Ok(inode_data.links_count as usize)
+    }
+
+    /// Update the change time of an inode
+    fn touch_inode(&mut self, inode: usize) -> FilesystemResult<()>
+    {
+        let mut inode_data = self.get_inode(inode)?;
+        update_time(&mut inode_data, UpdateTimes::Create);
+
+        self.put_inode(inode, &inode_data)
+    }
+
+    /// Move a directory entry to a new name, possibly in another directory, replacing or exchanging with the target
+    fn rename_entry(&mut self, old_dir: usize, old_name: &str, new_dir: usize, new_name: &str, flags: usize) -> FilesystemResult<()>
+    {
+        let source = self.find_directory_entry(old_dir, old_name)?.ok_or_else(|| FilesystemError::FileNotFound(old_name.to_string()))?;
+        let target = self.find_directory_entry(new_dir, new_name)?;
+
+        if target == Some(source)
+        {
+            return Ok(());
+        }
+
+        let source_is_dir = self.is_directory(source)?;
+
+        if flags & RENAME_EXCHANGE > 0
+        {
+            let target = target.ok_or_else(|| FilesystemError::FileNotFound(new_name.to_string()))?;
+            let target_is_dir = self.is_directory(target)?;
+
+            self.set_directory_entry(old_dir, old_name, target)?;
+            self.set_directory_entry(new_dir, new_name, source)?;
+
+            // Each directory's parent entry and the link it adds to its parent follow it
+            if old_dir != new_dir
+            {
+                if source_is_dir
+                {
+                    self.set_directory_entry(source, "..", new_dir)?;
+                    self.adjust_links(old_dir, -1)?;
+                    self.adjust_links(new_dir, 1)?;
+                }
+
+                if target_is_dir
+                {
+                    self.set_directory_entry(target, "..", old_dir)?;
+                    self.adjust_links(new_dir, -1)?;
+                    self.adjust_links(old_dir, 1)?;
+                }
+            }
+
+            self.touch_inode(target)?;
+            self.touch_inode(source)?;
+
+            return Ok(());
+        }
+
+        if let Some(target) = target
+        {
+            if flags & RENAME_NOREPLACE > 0
+            {
+                return Err(FilesystemError::FileExists);
+            }
+
+            let target_is_dir = self.is_directory(target)?;
+
+            if source_is_dir && !target_is_dir
+            {
+                return Err(FilesystemError::INodeIsNotADirectory);
+            }
+            else if !source_is_dir && target_is_dir
+            {
+                return Err(FilesystemError::INodeIsDirectory);
+            }
+            else if target_is_dir && !self.is_empty_directory(target)?
+            {
+                return Err(FilesystemError::DirectoryNotEmpty);
+            }
+
+            self.set_directory_entry(new_dir, new_name, source)?;
+
+            // A replaced directory also takes its parent entry's link away from the new directory
+            if target_is_dir
+            {
+                self.adjust_links(new_dir, -1)?;
+                self.delete_inode(target)?;
+            }
+            else if self.adjust_links(target, -1)? == 0
+            {
+                self.delete_inode(target)?;
+            }
+        }
+        else
+        {
+            self.add_directory_entry(new_dir, source, new_name)?;
+        }
+
+        self.remove_directory_entry(old_dir, old_name.to_string())?;
+
+        if source_is_dir && old_dir != new_dir
+        {
+            self.set_directory_entry(source, "..", new_dir)?;
+            self.adjust_links(old_dir, -1)?;
+            self.adjust_links(new_dir, 1)?;
+        }
+
+        self.touch_inode(source)
+    }
+
+    /// Allocate a new directory
+    fn new_directory(&mut self, dest: usize, name: String) -> FilesystemResult<usize>
+    {
+        let inode_number = self.new_inode(dest, S_IFDIR | 0o755)?;
+        let mut inode = self.get_inode(inode_number)?;
+
+        // The first block holds the `.` and `..` entries, with `..` taking up the rest of the block
+        let block_size = self.block_size;
+        let block = self.allocate_block_for(inode_number, &mut inode, 0)?;
+
+        let mut buffer = vec![0u8; block_size];
+        self.write_dir_entry(&mut buffer, 0, inode_number, dir_entry_size(1), ".", file_type_from_mode(S_IFDIR));
+        self.write_dir_entry(&mut buffer, dir_entry_size(1), dest, block_size - dir_entry_size(1), "..", file_type_from_mode(S_IFDIR));
+        *self.block_mut(block)? = buffer;
+
+        // The new directory is linked from its own `.` entry, and its `..` entry links to the parent
+        inode.links_count = 2;
+        inode.set_file_size(block_size);
+        self.put_inode(inode_number, &inode)?;
+
+        self.add_directory_entry(dest, inode_number, &name)?;
+        self.adjust_links(dest, 1)?;
+
+        Ok(inode_number)
+    }
+
+    /// Write the group descriptors and superblock back to the disk
+    fn write_metadata(&mut self) -> FilesystemResult<()>
+    {
+        let mut superblock = self.superblock()?;
+        superblock.wtime = current_time();
+        self.superblock = Some(superblock);
+
+        // The group descriptor table starts in the block after the superblock
+        let descriptor_size = core::mem::size_of::<Ext2GroupDescriptor>();
+        let per_block = self.block_size / descriptor_size;
+        let first = superblock.first_data_block as usize + 1;
+
+        for (i, group) in self.groups.clone().iter().enumerate()
+        {
+            let buffer = self.block_mut(first + i / per_block)?;
+            let offset = (i % per_block) * descriptor_size;
+
+            unsafe { (buffer[offset..].as_mut_ptr() as *mut Ext2GroupDescriptor).write_unaligned(*group) };
+        }
+
+        self.write_superblock()?;
+
+        self.metadata_dirty = false;
+
+        Ok(())
+    }
+
+    /// Write the superblock straight to the disk
+    fn write_superblock(&mut self) -> FilesystemResult<()>
+    {
+        let superblock = self.superblock()?;
+
+        unsafe { (self.raw_superblock.as_mut_ptr() as *mut Ext2SuperBlock).write_unaligned(superblock) };
+
+        if let Err(e) = self.device.write_sectors((EXT2_SUPERBLOCK_OFFSET / SECTOR_SIZE) as u64, self.raw_superblock.as_ptr(), EXT2_SUPERBLOCK_SIZE / SECTOR_SIZE)
+        {
+            kerrorln!("Unable to write ext2 superblock: {:?}", e);
+            return Err(FilesystemError::IOError);
+        }
+
+        Ok(())
+    }
+
+    /// Wait for everything written to reach the disk
+    fn flush_device(&mut self) -> FilesystemResult<()>
+    {
+        if let Err(e) = self.device.flush()
+        {
+            kerrorln!("Unable to flush block device: {:?}", e);
+            return Err(FilesystemError::IOError);
+        }
+
+        Ok(())
+    }
+}
+
+impl Filesystem for Ext2Filesystem
+{
+    /// Initialize the filesystem on the current disk
+    fn init(&mut self) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Initializing Ext2 Filesystem");
+
+        // Read the super block
+        let mut raw = vec![0u8; EXT2_SUPERBLOCK_SIZE];
+
+        if self.device.read_sectors((EXT2_SUPERBLOCK_OFFSET / SECTOR_SIZE) as u64, raw.as_mut_ptr(), EXT2_SUPERBLOCK_SIZE / SECTOR_SIZE).is_err()
+        {
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        let superblock = unsafe { (raw.as_ptr() as *const Ext2SuperBlock).read_unaligned() };
+
+        // Verify the filesystem is an ext2 filesystem
+        if superblock.magic != EXT2_MAGIC
+        {
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        let block_size = 1024usize.checked_shl(superblock.log_block_size).unwrap_or(0);
+
+        if block_size == 0 || block_size > mem::PAGE_SIZE || superblock.blocks_per_group == 0 || superblock.inodes_per_group == 0
+        {
+            kerrorln!("Unsupported ext2 geometry with {} byte blocks", block_size);
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        // Revision 0 filesystems have fixed inodes and no feature flags
+        if superblock.rev_level > 0
+        {
+            if superblock.feature_incompat & !EXT2_SUPPORTED_INCOMPAT != 0 || superblock.feature_ro_compat & !EXT2_SUPPORTED_RO_COMPAT != 0
+            {
+                kerrorln!("Ext2 filesystem uses unsupported features: incompat {:#x}, ro_compat {:#x}", superblock.feature_incompat, superblock.feature_ro_compat);
+                return Err(FilesystemError::BadFilesystemFormat)
+            }
+
+            self.inode_size = superblock.inode_size as usize;
+            self.first_inode = superblock.first_ino as usize;
+        }
+
+        if self.inode_size < EXT2_GOOD_OLD_INODE_SIZE || self.inode_size > block_size || !self.inode_size.is_power_of_two()
+        {
+            kerrorln!("Unsupported ext2 inode size {}", self.inode_size);
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        self.block_size = block_size;
+        self.superblock = Some(superblock);
+        self.raw_superblock = raw;
+
+        // Read the group descriptor table, which starts in the block after the superblock
+        let group_count = (superblock.blocks_count - superblock.first_data_block + superblock.blocks_per_group - 1) as usize / superblock.blocks_per_group as usize;
+
+        let descriptor_size = core::mem::size_of::<Ext2GroupDescriptor>();
+        let per_block = block_size / descriptor_size;
+
+        self.groups.clear();
+
+        for i in 0..group_count
+        {
+            let buffer = self.read_block(superblock.first_data_block as usize + 1 + i / per_block)?;
+            let offset = (i % per_block) * descriptor_size;
+
+            self.groups.push(unsafe { (buffer[offset..].as_ptr() as *const Ext2GroupDescriptor).read_unaligned() });
+        }
+
+        kdebugln!(Filesystem, "Ext2 filesystem with {} blocks of {} bytes in {} groups", superblock.blocks_count, block_size, group_count);
+
+        if superblock.state & EXT2_VALID_FS == 0 || superblock.state & EXT2_ERROR_FS > 0
+        {
+            kwarnln!("Mounting an ext2 filesystem which was not unmounted cleanly, checking it is recommended");
+        }
+        else if superblock.max_mnt_count as i16 > 0 && superblock.mnt_count >= superblock.max_mnt_count
+        {
+            kwarnln!("Ext2 filesystem has reached its maximal mount count, checking it is recommended");
+        }
+
+        Ok(())
+    }
+
+    /// Mark the filesystem as not cleanly unmounted while it is mounted read write, counting the mount, and as
+    /// clean again once everything has been synced
+    fn set_writable(&mut self, writable: bool) -> FilesystemResult<()>
+    {
+        let mut superblock = self.superblock()?;
+
+        if writable
+        {
+            superblock.state &= !EXT2_VALID_FS;
+            superblock.mnt_count = superblock.mnt_count.wrapping_add(1);
+            superblock.mtime = current_time();
+        }
+        else
+        {
+            // A filesystem which could not be synced keeps its unclean state
+            if self.metadata_dirty || !self.dirty.is_empty()
+            {
+                return Ok(());
+            }
+
+            superblock.state |= EXT2_VALID_FS;
+            superblock.wtime = current_time();
+        }
+
+        self.superblock = Some(superblock);
+
+        self.write_superblock()?;
+        self.flush_device()
+    }
+
+    /// Sync the filesystem with the current disk
+    fn sync(&mut self) -> FilesystemResult<()>
+    {
+        if self.metadata_dirty
+        {
+            self.write_metadata()?;
+        }
+
+        kdebugln!(Filesystem, "{} Blocks Rewritten", self.dirty.len());
+
+        let block_size = self.block_size;
+        let mut failed = false;
+
+        if let Some(mut queue) = self.device.request_queue()
+        {
+            for (block, data) in &self.dirty
+            {
+                kdebugln!(Filesystem, "Writing to Block {}", block);
+
+                let ptr = data.as_ptr() as *mut u8;
+
+                if let Err(e) = queue.queue(BlockOperation::Write, (block_size * *block) as u64, ptr, block_size as u32)
+                {
+                    kerrorln!("Unable to write block {}: {:?}", block, e);
+                    failed = true;
+                }
+            }
+
+            // Adjacent blocks are merged into a single request by the block layer, every request is waited on so
+            // none of them still refers to the dirty blocks once this returns
+            for result in queue.dispatch()
+            {
+                match result
+                {
+                    Ok(token) => if !queue.finish(token)
+                    {
+                        kerrorln!("Ext2 block write failed");
+                        failed = true;
+                    },
+                    Err(e) =>
+                    {
+                        kerrorln!("Unable to dispatch ext2 block write: {:?}", e);
+                        failed = true;
+                    }
+                }
+            }
+        }
+        else
+        {
+            let sectors = block_size / SECTOR_SIZE;
+
+            for (block, data) in &self.dirty
+            {
+                kdebugln!(Filesystem, "Writing to Block {}", block);
+
+                if let Err(e) = self.device.write_sectors((*block * sectors) as u64, data.as_ptr(), sectors)
+                {
+                    kerrorln!("Unable to write block {}: {:?}", block, e);
+                    failed = true;
+                    break;
+                }
+            }
+        }
+
+        // Blocks which may not have reached the disk stay dirty, so the next sync writes them again
+        if failed
+        {
+            return Err(FilesystemError::IOError);
+        }
+
+        self.flush_device()?;
+
+        // The written blocks are now clean, so move them into the block cache
+        for (block, data) in core::mem::take(&mut self.dirty)
+        {
+            self.cache_block(block, data);
+        }
+
+        Ok(())
+    }
+
+    /// Set the mount_id of the filesystem
+    fn set_mount_id(&mut self, mount_id: usize, vfs: &'static mut crate::fs::vfs::FilesystemInterface)
+    {
+        self.mount_id = Some(mount_id);
+        self.vfs = Some(vfs);
+    }
+
+    /// Get the index of the root directory of the filesystem
+    fn get_root_index(&mut self) -> FilesystemResult<FilesystemIndex>
+    {
+        if self.mount_id.is_none()
+        {
+            return Err(FilesystemError::FilesystemNotMounted);
+        }
+
+        Ok(self.index(EXT2_ROOT_INODE))
+    }
+
+    /// Convert a path to an inode
+    fn path_to_inode(&mut self, path: PathBuffer) -> FilesystemResult<FilesystemIndex>
+    {
+        self.vfs()?.path_to_inode(path)
+    }
+
+    /// Convert an inode to a path
+    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<OwnedPath>
+    {
+        self.vfs()?.inode_to_path(inode)
+    }
+
+    /// Get the directory entries for the given inode
+    fn get_dir_entries(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<DirectoryEntry>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.get_dir_entries(inode);
+        }
+
+        Ok(self.read_directory(inode.inode)?.into_iter().map(|slot| DirectoryEntry
+            {
+                index: self.index(slot.inode),
+                name: slot.name,
+                entry_type: entry_type_from_file_type(slot.file_type)
+            }).collect())
+    }
+
+    /// Find the inode for a name in the directory at the given inode
+    fn lookup(&mut self, directory: FilesystemIndex, name: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.lookup(directory, name);
+        }
+
+        match self.find_directory_entry(directory.inode, name)?
+        {
+            Some(inode) => Ok(self.index(inode)),
+            None => Err(FilesystemError::FileNotFound(name.to_string()))
+        }
+    }
+
+    /// Get the directory entry for the given inode
+    fn get_stat(&mut self, inode: FilesystemIndex) -> FilesystemResult<FileStat>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.get_stat(inode);
+        }
+
+        let read = self.get_inode(inode.inode)?;
+
+        // Device files keep their device number in the first block pointer
+        let special_dev_id = match read.mode & S_IFMT
+        {
+            S_IFCHR | S_IFBLK => read.block[0] as usize,
+            _ => 0
+        };
+
+        Ok(FileStat
+        {
+            dev_id: inode.mount_id,
+            inode: inode.inode,
+            mode: read.mode,
+            links: read.links_count,
+            uid: read.uid,
+            gid: read.gid,
+            special_dev_id,
+            size: read.file_size(),
+            blk_size: self.block_size,
+            blocks_alloced: read.blocks as usize,
+            atime: read.atime as usize,
+            mtime: read.mtime as usize,
+            ctime: read.ctime as usize,
+        })
+    }
+
+    /// Create a file in the directory at the given inode
+    fn create_file(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.create_file(inode, name);
+        }
+
+        let file_inode = self.new_inode(inode.inode, S_IFREG | 0o644)?;
+
+        if let Err(e) = self.add_directory_entry(inode.inode, file_inode, &name)
+        {
+            self.delete_inode(file_inode)?;
+            return Err(e);
+        }
+
+        Ok(self.index(file_inode))
+    }
+
+    /// Create a directory in the directory at the given inode
+    fn create_directory(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.create_directory(inode, name);
+        }
+
+        let dir_inode = self.new_directory(inode.inode, name)?;
+
+        Ok(self.index(dir_inode))
+    }
+
+    /// Create a symbolic link in the directory at the given inode which points to `target`
+    fn create_symlink(&mut self, directory: FilesystemIndex, name: String, target: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.create_symlink(directory, name, target);
+        }
+
+        let link_inode = self.new_inode(directory.inode, S_IFLNK | 0o777)?;
+
+        // Short targets are stored in place of the block numbers, longer ones in a data block
+        if target.len() < 60
+        {
+            let mut inode = self.get_inode(link_inode)?;
+
+            let bytes = unsafe { core::slice::from_raw_parts_mut(inode.block.as_mut_ptr() as *mut u8, 60) };
+            bytes[..target.len()].copy_from_slice(target.as_bytes());
+
+            inode.set_file_size(target.len());
+            self.put_inode(link_inode, &inode)?;
+        }
+        else
+        {
+            self.write_to_file(link_inode, target.as_bytes())?;
+        }
+
+        if let Err(e) = self.add_directory_entry(directory.inode, link_inode, &name)
+        {
+            self.delete_inode(link_inode)?;
+            return Err(e);
+        }
+
+        Ok(self.index(link_inode))
+    }
+
+    /// Create a named pipe in the directory at the given inode
+    fn create_fifo(&mut self, directory: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.create_fifo(directory, name);
+        }
+
+        let fifo_inode = self.new_inode(directory.inode, S_IFIFO | 0o644)?;
+
+        if let Err(e) = self.add_directory_entry(directory.inode, fifo_inode, &name)
+        {
+            self.delete_inode(fifo_inode)?;
+            return Err(e);
+        }
+
+        Ok(self.index(fifo_inode))
+    }
+
+    /// Create a socket in the directory at the given inode
+    fn create_socket(&mut self, directory: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.create_socket(directory, name);
+        }
+
+        let socket_inode = self.new_inode(directory.inode, S_IFSOCK | 0o755)?;
+
+        if let Err(e) = self.add_directory_entry(directory.inode, socket_inode, &name)
+        {
+            self.delete_inode(socket_inode)?;
+            return Err(e);
+        }
+
+        Ok(self.index(socket_inode))
+    }
+
+    /// Read the target of the symbolic link at the given inode
+    fn read_link(&mut self, inode: FilesystemIndex) -> FilesystemResult<String>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.read_link(inode);
+        }
+
+        let inode_data = self.get_inode(inode.inode)?;
+
+        if inode_data.mode & S_IFMT != S_IFLNK
+        {
+            return Err(FilesystemError::NotASymbolicLink);
+        }
+
+        let data = self.read_from_inode(&inode_data)?;
+
+        Ok(String::from_utf8_lossy(&data).to_string())
+    }
+
+    /// Add an entry for an existing inode to the directory at the given inode
+    fn create_link(&mut self, inode: FilesystemIndex, directory: FilesystemIndex, name: String) -> FilesystemResult<()>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.create_link(inode, directory, name);
+        }
+
+        // Hard links to directories would allow loops in the tree
+        if self.is_directory(inode.inode)?
+        {
+            return Err(FilesystemError::PermissionDenied);
+        }
+
+        self.add_directory_entry(directory.inode, inode.inode, &name)?;
+        self.adjust_links(inode.inode, 1)?;
+
+        Ok(())
+    }
+
+    /// Change the metadata of an inode, only the permission bits of a new mode are used
+    fn set_attr(&mut self, inode: FilesystemIndex, attributes: SetAttributes) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.set_attr(inode, attributes);
+        }
+
+        let mut inode_data = self.get_inode(inode.inode)?;
+
+        if let Some(size) = attributes.size
+        {
+            if inode_data.mode & S_IFMT == S_IFDIR
+            {
+                return Err(FilesystemError::INodeIsDirectory);
+            }
+
+            // Growing a file leaves a hole, which reads as zeros
+            self.resize_inode(&mut inode_data, size)?;
+            update_time(&mut inode_data, UpdateTimes::Modify);
+        }
+
+        if let Some(mode) = attributes.mode
+        {
+            inode_data.mode = (inode_data.mode & S_IFMT) | (mode & 0o7777);
+        }
+
+        if let Some(uid) = attributes.uid
+        {
+            inode_data.uid = uid;
+        }
+
+        if let Some(gid) = attributes.gid
+        {
+            inode_data.gid = gid;
+        }
+
+        if let Some(atime) = attributes.atime
+        {
+            inode_data.atime = atime as u32;
+        }
+
+        if let Some(mtime) = attributes.mtime
+        {
+            inode_data.mtime = mtime as u32;
+        }
+
+        update_time(&mut inode_data, UpdateTimes::Create);
+
+        self.put_inode(inode.inode, &inode_data)
+    }
+
+    /// Move the entry `old_name` in the directory at `old_directory` to `new_name` in `new_directory`, replacing or
+    /// exchanging with any existing entry depending on the `RENAME_*` flags
+    fn rename(&mut self, old_directory: FilesystemIndex, old_name: String, new_directory: FilesystemIndex, new_name: String, flags: usize) -> FilesystemResult<()>
+    {
+        if self.is_local(old_directory) && self.is_local(new_directory)
+        {
+            self.rename_entry(old_directory.inode, &old_name, new_directory.inode, &new_name, flags)
+        }
+        else if old_directory.mount_id != new_directory.mount_id
+        {
+            Err(FilesystemError::CrossDeviceLink)
+        }
+        else
+        {
+            self.vfs()?.rename(old_directory, old_name, new_directory, new_name, flags)
+        }
+    }
+
+    /// Remove an inode at the given index from the given directory
+    fn remove_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.remove_inode(inode);
+        }
+
+        self.delete_inode(inode.inode)
+    }
+
+    /// Remove a directory entry from the directory at the given inode
+    fn remove_dir_entry(&mut self, directory_index: FilesystemIndex, name: String) -> FilesystemResult<()>
+    {
+        if !self.is_local(directory_index)
+        {
+            return self.vfs()?.remove_dir_entry(directory_index, name);
+        }
+
+        let removed = self.find_directory_entry(directory_index.inode, &name)?;
+
+        self.remove_directory_entry(directory_index.inode, name)?;
+
+        // Removing a directory also removes the link its `..` entry held on the parent
+        if let Some(removed) = removed
+        {
+            if removed != directory_index.inode && self.is_directory(removed)?
+            {
+                self.adjust_links(directory_index.inode, -1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Increment the number of links to an inode
+    fn increment_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.increment_links(inode);
+        }
+
+        self.adjust_links(inode.inode, 1)
+    }
+
+    /// Decrement the number of links to an inode
+    fn decrement_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.decrement_links(inode);
+        }
+
+        self.adjust_links(inode.inode, -1)
+    }
+
+    /// Read the data stored in an inode
+    fn read_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<u8>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.read_inode(inode);
+        }
+
+        let inode = self.get_inode(inode.inode)?;
+
+        self.read_from_inode(&inode)
+    }
+
+    /// Read a single page of the data stored in an inode into the buffer, returning the number of bytes read
+    fn read_inode_page(&mut self, inode: FilesystemIndex, page: usize, buffer: *mut u8) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.read_inode_page(inode, page, buffer);
+        }
+
+        let inode = self.get_inode(inode.inode)?;
+        let size = inode.file_size();
+        let block_size = self.block_size;
+
+        let mut read = 0;
+
+        for i in 0..mem::PAGE_SIZE / block_size
+        {
+            let offset = page * mem::PAGE_SIZE + i * block_size;
+
+            if offset >= size
+            {
+                break;
+            }
+
+            let length = (size - offset).min(block_size);
+            let block = self.block_for(&inode, offset / block_size)?;
+
+            // Holes read as zeros, and the buffer is expected to be zeroed
+            if block != 0
+            {
+                let data = self.read_block(block)?;
+                unsafe { core::ptr::copy(data.as_ptr(), buffer.add(i * block_size), length) };
+            }
+
+            read += length;
+        }
+
+        Ok(read)
+    }
+
+    /// Write back pages of the data stored in an inode, resizing the inode to `size` bytes
+    fn write_inode_pages(&mut self, inode: FilesystemIndex, size: usize, pages: &[(usize, *const u8)]) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.write_inode_pages(inode, size, pages);
+        }
+
+        let inode_number = inode.inode;
+        let mut inode = self.get_inode(inode_number)?;
+        let block_size = self.block_size;
+
+        // Only the given pages are written, anything else keeps the blocks it already has
+        for (page, ptr) in pages
+        {
+            for i in 0..mem::PAGE_SIZE / block_size
+            {
+                let offset = page * mem::PAGE_SIZE + i * block_size;
+
+                if offset >= size
+                {
+                    break;
+                }
+
+                let length = (size - offset).min(block_size);
+                let data = unsafe { core::slice::from_raw_parts(ptr.add(i * block_size), length) };
+
+                self.write_blocks(inode_number, &mut inode, offset / block_size, data)?;
+            }
+        }
+
+        self.resize_inode(&mut inode, size)?;
+
+        update_time(&mut inode, UpdateTimes::Modify);
+        update_time(&mut inode, UpdateTimes::Create);
+
+        self.put_inode(inode_number, &inode)
+    }
+
+    /// Write data to an inode
+    fn write_inode(&mut self, inode: FilesystemIndex, data: &[u8]) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.write_inode(inode, data);
+        }
+
+        self.write_to_file(inode.inode, data)
+    }
+
+    /// Get the value of an extended attribute of an inode
+    fn get_xattr(&mut self, inode: FilesystemIndex, name: &str) -> FilesystemResult<Vec<u8>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.get_xattr(inode, name);
+        }
+
+        let inode_data = self.get_inode(inode.inode)?;
+
+        self.read_xattrs(&inode_data)?.iter()
+            .find(|attribute| attribute.full_name().as_deref() == Some(name))
+            .ok_or(FilesystemError::AttributeNotFound)?
+            .vfs_value()
+    }
+
+    /// Set the value of an extended attribute of an inode, creating the attribute if it does not exist
+    fn set_xattr(&mut self, inode: FilesystemIndex, name: &str, value: &[u8]) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.set_xattr(inode, name, value);
+        }
+
+        let attribute = Ext2Xattr::new(name, value)?;
+
+        let mut inode_data = self.get_inode(inode.inode)?;
+        let mut attributes = self.read_xattrs(&inode_data)?;
+
+        attributes.retain(|existing| !existing.same_name(&attribute));
+        attributes.push(attribute);
+
+        self.write_xattrs(inode.inode, &mut inode_data, attributes)?;
+        update_time(&mut inode_data, UpdateTimes::Create);
+
+        self.put_inode(inode.inode, &inode_data)
+    }
+
+    /// Get the names of every extended attribute of an inode
+    fn list_xattr(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<String>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.list_xattr(inode);
+        }
+
+        let inode_data = self.get_inode(inode.inode)?;
+
+        // Attributes under a prefix the driver does not know are kept, but not shown
+        Ok(self.read_xattrs(&inode_data)?.iter().filter_map(|attribute| attribute.full_name()).collect())
+    }
+
+    /// Remove an extended attribute from an inode
+    fn remove_xattr(&mut self, inode: FilesystemIndex, name: &str) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.remove_xattr(inode, name);
+        }
+
+        let mut inode_data = self.get_inode(inode.inode)?;
+        let mut attributes = self.read_xattrs(&inode_data)?;
+        let count = attributes.len();
+
+        attributes.retain(|attribute| attribute.full_name().as_deref() != Some(name));
+
+        if attributes.len() == count
+        {
+            return Err(FilesystemError::AttributeNotFound);
+        }
+
+        self.write_xattrs(inode.inode, &mut inode_data, attributes)?;
+        update_time(&mut inode_data, UpdateTimes::Create);
+
+        self.put_inode(inode.inode, &inode_data)
+    }
+
+    /// Open a filedescriptor for the given inode
+    fn open_fd(&mut self, inode: FilesystemIndex, mode: usize) -> FilesystemResult<Box<dyn crate::process::descriptor::FileDescriptor>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.open_fd(inode, mode);
+        }
+
+        Ok(Box::new(PageCacheDescriptor::new(self.vfs()?, inode, mode)?))
+    }
+
+    /// Execute an ioctl command on an inode
+    fn exec_ioctl(&mut self, inode: FilesystemIndex, cmd: IOControlCommand) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.exec_ioctl(inode, cmd);
+        }
+
+        // Nothing to do here (yet)
+        Ok(usize::MAX)
+    }
+}
@@ -0,0 +1,6 @@
+pub mod driver;
+pub use driver::*;
+
+pub mod structures;
+
+pub mod xattr;
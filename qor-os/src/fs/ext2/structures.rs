@@ -0,0 +1,243 @@
+use crate::fs::structures::*;
+
+/// Magic number in the superblock of every ext2 filesystem
+pub const EXT2_MAGIC: u16 = 0xef53;
+
+/// Byte offset of the superblock on the disk
+pub const EXT2_SUPERBLOCK_OFFSET: usize = 1024;
+
+/// Size of the superblock in bytes
+pub const EXT2_SUPERBLOCK_SIZE: usize = 1024;
+
+/// Inode of the root directory
+pub const EXT2_ROOT_INODE: usize = 2;
+
+/// Size of the inodes of a revision 0 filesystem
+pub const EXT2_GOOD_OLD_INODE_SIZE: usize = 128;
+
+/// First inode not reserved on a revision 0 filesystem
+pub const EXT2_GOOD_OLD_FIRST_INODE: usize = 11;
+
+/// Number of block numbers held directly in an inode
+pub const EXT2_DIRECT_BLOCKS: usize = 12;
+
+/// Inodes may have a block of extended attributes
+pub const EXT2_FEATURE_COMPAT_EXT_ATTR: u32 = 0x0008;
+
+/// Directory entries record their file type
+pub const EXT2_FEATURE_INCOMPAT_FILETYPE: u32 = 0x0002;
+
+/// Only some block groups hold backups of the superblock
+pub const EXT2_FEATURE_RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+
+/// Files can be larger than 2 GiB
+pub const EXT2_FEATURE_RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+
+/// Incompatible features the driver understands
+pub const EXT2_SUPPORTED_INCOMPAT: u32 = EXT2_FEATURE_INCOMPAT_FILETYPE;
+
+/// Read only compatible features the driver understands
+pub const EXT2_SUPPORTED_RO_COMPAT: u32 = EXT2_FEATURE_RO_COMPAT_SPARSE_SUPER | EXT2_FEATURE_RO_COMPAT_LARGE_FILE;
+
+/// Superblock state bit set while the filesystem is not mounted read write, cleared while it may hold unwritten
+/// changes
+pub const EXT2_VALID_FS: u16 = 0x0001;
+
+/// Superblock state bit set once errors have been found in the filesystem
+pub const EXT2_ERROR_FS: u16 = 0x0002;
+
+/// Directory is indexed with a hashed tree, which this driver does not maintain
+pub const EXT2_INDEX_FL: u32 = 0x1000;
+
+/// Ext2 Superblock, only the fields up to the end of the revision 1 extensions are described
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Ext2SuperBlock
+{
+    pub inodes_count:        u32,
+    pub blocks_count:        u32,
+    pub r_blocks_count:      u32,
+    pub free_blocks_count:   u32,
+    pub free_inodes_count:   u32,
+    pub first_data_block:    u32,
+    pub log_block_size:      u32,
+    pub log_frag_size:       u32,
+    pub blocks_per_group:    u32,
+    pub frags_per_group:     u32,
+    pub inodes_per_group:    u32,
+    pub mtime:               u32,
+    pub wtime:               u32,
+    pub mnt_count:           u16,
+    pub max_mnt_count:       u16,
+    pub magic:               u16,
+    pub state:               u16,
+    pub errors:              u16,
+    pub minor_rev_level:     u16,
+    pub lastcheck:           u32,
+    pub checkinterval:       u32,
+    pub creator_os:          u32,
+    pub rev_level:           u32,
+    pub def_resuid:          u16,
+    pub def_resgid:          u16,
+    pub first_ino:           u32,
+    pub inode_size:          u16,
+    pub block_group_nr:      u16,
+    pub feature_compat:      u32,
+    pub feature_incompat:    u32,
+    pub feature_ro_compat:   u32,
+    pub uuid:                [u8; 16],
+    pub volume_name:         [u8; 16],
+    pub last_mounted:        [u8; 64],
+    pub algo_bitmap:         u32,
+    pub prealloc_blocks:     u8,
+    pub prealloc_dir_blocks: u8,
+    pub reserved_gdt_blocks: u16,
+    pub journal_uuid:        [u8; 16],
+    pub journal_inum:        u32,
+    pub journal_dev:         u32,
+    pub last_orphan:         u32,
+    pub hash_seed:           [u32; 4],
+    pub def_hash_version:    u8,
+    pub jnl_backup_type:     u8,
+    pub desc_size:           u16,
+    pub default_mount_opts:  u32,
+    pub first_meta_bg:       u32
+}
+
+static_assertions::const_assert_eq!(core::mem::size_of::<Ext2SuperBlock>(), 264);
+
+/// Ext2 Block Group Descriptor
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ext2GroupDescriptor
+{
+    pub block_bitmap:      u32,
+    pub inode_bitmap:      u32,
+    pub inode_table:       u32,
+    pub free_blocks_count: u16,
+    pub free_inodes_count: u16,
+    pub used_dirs_count:   u16,
+    pub pad:               u16,
+    pub reserved:          [u32; 3]
+}
+
+static_assertions::const_assert_eq!(core::mem::size_of::<Ext2GroupDescriptor>(), 32);
+
+/// Ext2 Inode, larger inodes keep this layout for their first 128 bytes
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ext2Inode
+{
+    pub mode:        u16,
+    pub uid:         u16,
+    pub size:        u32,
+    pub atime:       u32,
+    pub ctime:       u32,
+    pub mtime:       u32,
+    pub dtime:       u32,
+    pub gid:         u16,
+    pub links_count: u16,
+    pub blocks:      u32,
+    pub flags:       u32,
+    pub osd1:        u32,
+    pub block:       [u32; 15],
+    pub generation:  u32,
+    pub file_acl:    u32,
+    pub size_high:   u32,
+    pub faddr:       u32,
+    pub frag:        u8,
+    pub fsize:       u8,
+    pub pad1:        u16,
+    pub uid_high:    u16,
+    pub gid_high:    u16,
+    pub reserved2:   u32
+}
+
+static_assertions::const_assert_eq!(core::mem::size_of::<Ext2Inode>(), EXT2_GOOD_OLD_INODE_SIZE);
+
+impl Ext2Inode
+{
+    /// Get the size of the file, regular files keep the upper half of the size in `size_high`
+    pub fn file_size(&self) -> usize
+    {
+        if self.mode & S_IFMT == S_IFREG
+        {
+            self.size as usize | (self.size_high as usize) << 32
+        }
+        else
+        {
+            self.size as usize
+        }
+    }
+
+    /// Set the size of the file
+    pub fn set_file_size(&mut self, size: usize)
+    {
+        self.size = size as u32;
+
+        if self.mode & S_IFMT == S_IFREG
+        {
+            self.size_high = (size >> 32) as u32;
+        }
+    }
+
+    /// Check if the inode is a symbolic link whose target is stored in the block pointers
+    pub fn is_fast_symlink(&self) -> bool
+    {
+        let acl_blocks = if self.file_acl != 0 { 1 } else { 0 };
+
+        self.mode & S_IFMT == S_IFLNK && self.blocks as usize <= acl_blocks * 8 && (self.file_size() < 60)
+    }
+}
+
+/// Header of an ext2 directory entry, followed by `name_len` bytes of name
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Ext2DirEntryHeader
+{
+    pub inode:     u32,
+    pub rec_len:   u16,
+    pub name_len:  u8,
+    pub file_type: u8
+}
+
+/// Size of the header of a directory entry
+pub const EXT2_DIR_ENTRY_HEADER_SIZE: usize = 8;
+
+/// Size a directory entry with a name of the given length takes up
+pub fn dir_entry_size(name_len: usize) -> usize
+{
+    (EXT2_DIR_ENTRY_HEADER_SIZE + name_len + 3) & !3
+}
+
+/// Get the directory entry file type for a mode
+pub fn file_type_from_mode(mode: u16) -> u8
+{
+    match mode & S_IFMT
+    {
+        S_IFREG => 1,
+        S_IFDIR => 2,
+        S_IFCHR => 3,
+        S_IFBLK => 4,
+        S_IFIFO => 5,
+        S_IFSOCK => 6,
+        S_IFLNK => 7,
+        _ => 0
+    }
+}
+
+/// Convert a directory entry file type to the generic entry type
+pub fn entry_type_from_file_type(file_type: u8) -> DirectoryEntryType
+{
+    match file_type
+    {
+        1 => DirectoryEntryType::RegularFile,
+        2 => DirectoryEntryType::Directory,
+        3 => DirectoryEntryType::CharDevice,
+        4 => DirectoryEntryType::BlockDevice,
+        5 => DirectoryEntryType::FirstInFirstOut,
+        6 => DirectoryEntryType::Socket,
+        7 => DirectoryEntryType::SymbolicLink,
+        _ => DirectoryEntryType::Unknown
+    }
+}
@@ -0,0 +1,334 @@
+//! Ext2 extended attribute blocks
+//!
+//! The extended attributes of an inode are kept in a single block named by
+//! the inode's `file_acl` field. The block starts with a header, followed by
+//! a list of entries holding the attribute names, while the values are packed
+//! downwards from the end of the block. Identical blocks may be shared by
+//! several inodes, which the reference count in the header tracks.
+
+use crate::*;
+
+use crate::fs::acl::*;
+use crate::fs::structures::*;
+
+use alloc::vec;
+
+/// Magic number at the start of an extended attribute block
+pub const EXT2_XATTR_MAGIC: u32 = 0xEA020000;
+
+/// Size of the header of an extended attribute block
+const EXT2_XATTR_HEADER_SIZE: usize = 32;
+
+/// Size of an entry, not including its name
+const EXT2_XATTR_ENTRY_SIZE: usize = 16;
+
+/// Name index of the access ACL
+const EXT2_XATTR_INDEX_POSIX_ACL_ACCESS: u8 = 2;
+
+/// Name index of the default ACL
+const EXT2_XATTR_INDEX_POSIX_ACL_DEFAULT: u8 = 3;
+
+/// Prefixes stored as a name index in place of the start of an attribute name
+const EXT2_XATTR_PREFIXES: [(u8, &str); 5] = [
+    (1, "user."),
+    (EXT2_XATTR_INDEX_POSIX_ACL_ACCESS, XATTR_POSIX_ACL_ACCESS),
+    (EXT2_XATTR_INDEX_POSIX_ACL_DEFAULT, XATTR_POSIX_ACL_DEFAULT),
+    (4, "trusted."),
+    (6, "security.")];
+
+/// Version number at the start of an ACL on disk
+const EXT2_ACL_VERSION: u32 = 1;
+
+/// Read a little endian half word at a byte offset
+fn get_u16(buffer: &[u8], offset: usize) -> u16
+{
+    u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+/// Read a little endian word at a byte offset
+fn get_u32(buffer: &[u8], offset: usize) -> u32
+{
+    u32::from_le_bytes([buffer[offset], buffer[offset + 1], buffer[offset + 2], buffer[offset + 3]])
+}
+
+/// Write a little endian word at a byte offset
+fn set_u32(buffer: &mut [u8], offset: usize, value: u32)
+{
+    buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Round a length up to a multiple of four bytes
+fn pad(length: usize) -> usize
+{
+    (length + 3) & !3
+}
+
+/// Single extended attribute as stored in a block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ext2Xattr
+{
+    pub index: u8,
+    pub name: Vec<u8>,
+    pub value: Vec<u8>
+}
+
+impl Ext2Xattr
+{
+    /// Build the attribute stored for a full attribute name and the value the vfs uses for it
+    pub fn new(name: &str, value: &[u8]) -> FilesystemResult<Self>
+    {
+        let (index, prefix) = EXT2_XATTR_PREFIXES.iter()
+            .find(|(index, prefix)| if is_acl_index(*index) { name == *prefix } else { name.starts_with(prefix) })
+            .ok_or(FilesystemError::OperationNotSupported)?;
+
+        // ACLs are converted to the more compact layout ext2 uses
+        let value = if is_acl_index(*index) { acl_to_disk(&Acl::from_bytes(value)?) } else { value.to_vec() };
+
+        Ok(Self { index: *index, name: name[prefix.len()..].as_bytes().to_vec(), value })
+    }
+
+    /// Get the full name of the attribute, attributes with an unknown name index have none
+    pub fn full_name(&self) -> Option<String>
+    {
+        let (_, prefix) = EXT2_XATTR_PREFIXES.iter().find(|(index, _)| *index == self.index)?;
+
+        Some(format!("{}{}", prefix, String::from_utf8_lossy(&self.name)))
+    }
+
+    /// Get the value of the attribute in the form the vfs uses
+    pub fn vfs_value(&self) -> FilesystemResult<Vec<u8>>
+    {
+        if is_acl_index(self.index)
+        {
+            acl_from_disk(&self.value)
+        }
+        else
+        {
+            Ok(self.value.clone())
+        }
+    }
+
+    /// Check if the attribute has the same name as another
+    pub fn same_name(&self, other: &Self) -> bool
+    {
+        self.index == other.index && self.name == other.name
+    }
+}
+
+/// Check if a name index is one of the ACL indexes
+fn is_acl_index(index: u8) -> bool
+{
+    index == EXT2_XATTR_INDEX_POSIX_ACL_ACCESS || index == EXT2_XATTR_INDEX_POSIX_ACL_DEFAULT
+}
+
+/// Convert an ACL to the ext2 layout, which leaves out the id of the entries which do not name a user or group
+fn acl_to_disk(acl: &Acl) -> Vec<u8>
+{
+    let mut data = EXT2_ACL_VERSION.to_le_bytes().to_vec();
+
+    for entry in &acl.entries
+    {
+        data.extend_from_slice(&entry.tag.to_le_bytes());
+        data.extend_from_slice(&entry.perm.to_le_bytes());
+
+        if entry.tag == ACL_USER || entry.tag == ACL_GROUP
+        {
+            data.extend_from_slice(&entry.id.to_le_bytes());
+        }
+    }
+
+    data
+}
+
+/// Convert an ACL in the ext2 layout to the layout the vfs uses
+fn acl_from_disk(data: &[u8]) -> FilesystemResult<Vec<u8>>
+{
+    if data.len() < 4 || get_u32(data, 0) != EXT2_ACL_VERSION
+    {
+        return Err(FilesystemError::BadFilesystemFormat);
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = 4;
+
+    while offset < data.len()
+    {
+        if offset + 4 > data.len()
+        {
+            return Err(FilesystemError::BadFilesystemFormat);
+        }
+
+        let tag = get_u16(data, offset);
+        let perm = get_u16(data, offset + 2);
+        offset += 4;
+
+        let id = if tag == ACL_USER || tag == ACL_GROUP
+        {
+            if offset + 4 > data.len()
+            {
+                return Err(FilesystemError::BadFilesystemFormat);
+            }
+
+            offset += 4;
+            get_u32(data, offset - 4)
+        }
+        else
+        {
+            ACL_UNDEFINED_ID
+        };
+
+        entries.push(AclEntry { tag, perm, id });
+    }
+
+    Ok(Acl { entries }.to_bytes())
+}
+
+/// Hash an entry from its name and padded value
+fn entry_hash(name: &[u8], value: &[u8]) -> u32
+{
+    let mut hash = 0u32;
+
+    for c in name
+    {
+        hash = (hash << 5) ^ (hash >> 27) ^ *c as u32;
+    }
+
+    for word in value.chunks(4)
+    {
+        hash = (hash << 16) ^ (hash >> 16) ^ get_u32(word, 0);
+    }
+
+    hash
+}
+
+/// Get the reference count of an extended attribute block
+pub fn xattr_block_refcount(block: &[u8]) -> u32
+{
+    get_u32(block, 4)
+}
+
+/// Set the reference count of an extended attribute block
+pub fn set_xattr_block_refcount(block: &mut [u8], refcount: u32)
+{
+    set_u32(block, 4, refcount);
+}
+
+/// Parse the attributes stored in an extended attribute block
+pub fn decode_xattr_block(block: &[u8]) -> FilesystemResult<Vec<Ext2Xattr>>
+{
+    if block.len() < EXT2_XATTR_HEADER_SIZE || get_u32(block, 0) != EXT2_XATTR_MAGIC || get_u32(block, 8) != 1
+    {
+        return Err(FilesystemError::BadFilesystemFormat);
+    }
+
+    let mut attributes = Vec::new();
+    let mut offset = EXT2_XATTR_HEADER_SIZE;
+
+    // The list of entries ends with four zero bytes
+    while offset + 4 <= block.len() && get_u32(block, offset) != 0
+    {
+        if offset + EXT2_XATTR_ENTRY_SIZE > block.len()
+        {
+            return Err(FilesystemError::BadFilesystemFormat);
+        }
+
+        let name_length = block[offset] as usize;
+        let index = block[offset + 1];
+        let value_offset = get_u16(block, offset + 2) as usize;
+        let value_inode = get_u32(block, offset + 4);
+        let value_size = get_u32(block, offset + 8) as usize;
+
+        let name_start = offset + EXT2_XATTR_ENTRY_SIZE;
+
+        // Values stored in inodes of their own are a later extension
+        if value_inode != 0 || name_start + name_length > block.len() || value_offset + value_size > block.len()
+        {
+            return Err(FilesystemError::BadFilesystemFormat);
+        }
+
+        attributes.push(Ext2Xattr
+        {
+            index,
+            name: block[name_start..name_start + name_length].to_vec(),
+            value: block[value_offset..value_offset + value_size].to_vec()
+        });
+
+        offset = name_start + pad(name_length);
+    }
+
+    Ok(attributes)
+}
+
+/// Build an extended attribute block holding the given attributes with a reference count of one
+pub fn encode_xattr_block(attributes: &mut [Ext2Xattr], block_size: usize) -> FilesystemResult<Vec<u8>>
+{
+    attributes.sort_by(|a, b| (a.index, a.name.len(), &a.name).cmp(&(b.index, b.name.len(), &b.name)));
+
+    let mut block = vec![0u8; block_size];
+    let mut entry = EXT2_XATTR_HEADER_SIZE;
+    let mut value_end = block_size;
+    let mut block_hash = Some(0u32);
+
+    for attribute in attributes.iter()
+    {
+        let entry_size = EXT2_XATTR_ENTRY_SIZE + pad(attribute.name.len());
+        let value_size = pad(attribute.value.len());
+
+        // Leave room for the four zero bytes ending the list of entries
+        if attribute.name.len() > u8::MAX as usize || entry + entry_size + 4 + value_size > value_end
+        {
+            return Err(FilesystemError::OutOfSpace);
+        }
+
+        value_end -= value_size;
+        block[value_end..value_end + attribute.value.len()].copy_from_slice(&attribute.value);
+
+        let hash = entry_hash(&attribute.name, &block[value_end..value_end + value_size]);
+        let value_offset = if attribute.value.is_empty() { 0 } else { value_end };
+
+        block[entry] = attribute.name.len() as u8;
+        block[entry + 1] = attribute.index;
+        block[entry + 2..entry + 4].copy_from_slice(&(value_offset as u16).to_le_bytes());
+        set_u32(&mut block, entry + 8, attribute.value.len() as u32);
+        set_u32(&mut block, entry + 12, hash);
+        block[entry + EXT2_XATTR_ENTRY_SIZE..entry + EXT2_XATTR_ENTRY_SIZE + attribute.name.len()].copy_from_slice(&attribute.name);
+
+        // An entry without a hash leaves the whole block without one
+        block_hash = block_hash.filter(|_| hash != 0).map(|block_hash| (block_hash << 16) ^ (block_hash >> 16) ^ hash);
+
+        entry += entry_size;
+    }
+
+    set_u32(&mut block, 0, EXT2_XATTR_MAGIC);
+    set_xattr_block_refcount(&mut block, 1);
+    set_u32(&mut block, 8, 1);
+    set_u32(&mut block, 12, block_hash.unwrap_or(0));
+
+    Ok(block)
+}
+
+/// Ext2 Extended Attribute Block Test
+#[test_case]
+fn ext2_xattr_blocks()
+{
+    let acl = acl_from_mode(0o640).to_bytes();
+
+    let mut attributes = vec![
+        Ext2Xattr::new("user.comment", b"hello").unwrap(),
+        Ext2Xattr::new(XATTR_POSIX_ACL_ACCESS, &acl).unwrap(),
+        Ext2Xattr { index: 7, name: b"unknown".to_vec(), value: Vec::new() }];
+
+    // Short ACL entries leave out the id
+    assert_eq!(attributes[1].value.len(), 4 + 3 * 4);
+    assert_eq!(attributes[1].full_name().unwrap(), XATTR_POSIX_ACL_ACCESS);
+    assert_eq!(attributes[1].vfs_value().unwrap(), acl);
+
+    let block = encode_xattr_block(&mut attributes, 1024).unwrap();
+    assert_eq!(xattr_block_refcount(&block), 1);
+    assert_eq!(decode_xattr_block(&block).unwrap(), attributes);
+
+    // Unknown prefixes cannot be stored, and values must fit in the block
+    assert!(Ext2Xattr::new("other.name", b"").is_err());
+    attributes.push(Ext2Xattr::new("user.large", &[1; 1024]).unwrap());
+    assert!(encode_xattr_block(&mut attributes, 1024).is_err());
+}
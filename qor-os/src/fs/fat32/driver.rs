@@ -0,0 +1,1602 @@
+use crate::*;
+
+use crate::fs::fstrait::*;
+use crate::fs::structures::*;
+
+use super::structures::*;
+
+use alloc::vec;
+use alloc::collections::{BTreeMap, VecDeque};
+
+use crate::process::descriptor::*;
+
+use crate::drivers::block::BlockOperation;
+
+use libutils::paths::{OwnedPath, PathBuffer};
+
+use super::super::ioctl::*;
+
+/// Number of clean sectors held by the sector cache
+const SECTOR_CACHE_SIZE: usize = 256;
+
+/// Size of the sectors of the block device
+const DEVICE_SECTOR_SIZE: usize = 512;
+
+/// Get the current time as stored in a directory entry
+fn current_time() -> u32
+{
+    (crate::drivers::rtc::driver::RealTimeClockDriver::get_driver().get_unix_timestamp_nano() / 1_000_000_000) as u32
+}
+
+/// A file or directory which has been given an inode number
+///
+/// FAT has no inodes, so inode numbers are handed out as entries are found and
+/// tracked here along with where the entry for each is stored.
+struct FatNode
+{
+    parent: usize,
+    slots: Vec<u64>,
+    first_cluster: u32,
+    directory: bool,
+    chain: Option<Vec<u32>>
+}
+
+/// A directory entry along with the long filename entries in front of it
+struct DirectorySlot
+{
+    slots: Vec<u64>,
+    entry: FatDirEntry,
+    name: String
+}
+
+impl DirectorySlot
+{
+    /// Location of the short entry on the disk
+    fn location(&self) -> u64
+    {
+        *self.slots.last().unwrap()
+    }
+
+    /// Check if the slot matches the name, FAT names are not case sensitive and the short name can be used too
+    fn matches(&self, name: &str) -> bool
+    {
+        self.name.to_lowercase() == name.to_lowercase() || self.entry.short_name().eq_ignore_ascii_case(name)
+    }
+}
+
+/// FAT32 Filesystem Driver with long filename support
+pub struct Fat32Filesystem
+{
+    device: &'static mut dyn crate::drivers::block::BlockDevice,
+    mount_id: Option<usize>,
+    vfs: Option<&'static mut crate::fs::vfs::FilesystemInterface>,
+    boot: Option<Fat32BootSector>,
+    sector_size: usize,
+    cluster_size: usize,
+    total_sectors: usize,
+    fat_start: u64,
+    fat_bytes: u64,
+    active_fat: usize,
+    written_fats: Vec<usize>,
+    data_start: u64,
+    cluster_count: u32,
+    fs_info: Option<usize>,
+    free_count: u32,
+    next_free: u32,
+    fs_info_dirty: bool,
+    nodes: BTreeMap<usize, FatNode>,
+    locations: BTreeMap<u64, usize>,
+    next_inode: usize,
+    cache: BTreeMap<usize, Vec<u8>>,
+    cache_order: VecDeque<usize>,
+    dirty: BTreeMap<usize, Vec<u8>>
+}
+
+impl Fat32Filesystem
+{
+    /// Initialize a new FAT32 Filesystem Interface on the block device with the given index
+    pub fn new(device_id: usize) -> Self
+    {
+        Self
+        {
+            device: crate::drivers::block::get_block_device(device_id).unwrap(),
+            mount_id: None,
+            vfs: None,
+            boot: None,
+            sector_size: DEVICE_SECTOR_SIZE,
+            cluster_size: DEVICE_SECTOR_SIZE,
+            total_sectors: 0,
+            fat_start: 0,
+            fat_bytes: 0,
+            active_fat: 0,
+            written_fats: Vec::new(),
+            data_start: 0,
+            cluster_count: 0,
+            fs_info: None,
+            free_count: FSINFO_UNKNOWN,
+            next_free: FSINFO_UNKNOWN,
+            fs_info_dirty: false,
+            nodes: BTreeMap::new(),
+            locations: BTreeMap::new(),
+            next_inode: FAT32_ROOT_INODE + 1,
+            cache: BTreeMap::new(),
+            cache_order: VecDeque::new(),
+            dirty: BTreeMap::new()
+        }
+    }
+
+    /// Get the vfs this filesystem is mounted in
+    fn vfs(&mut self) -> FilesystemResult<&mut crate::fs::vfs::FilesystemInterface>
+    {
+        match &mut self.vfs
+        {
+            Some(vfs) => Ok(vfs),
+            None => Err(FilesystemError::FilesystemNotMounted)
+        }
+    }
+
+    /// Check if an index refers to an inode on this filesystem
+    fn is_local(&self, index: FilesystemIndex) -> bool
+    {
+        Some(index.mount_id) == self.mount_id
+    }
+
+    /// Build the index of an inode on this filesystem
+    fn index(&self, inode: usize) -> FilesystemIndex
+    {
+        FilesystemIndex { mount_id: self.mount_id.unwrap_or(0), inode }
+    }
+
+    /// Get the node for an inode
+    fn node(&self, inode: usize) -> FilesystemResult<&FatNode>
+    {
+        self.nodes.get(&inode).ok_or(FilesystemError::BadINode)
+    }
+
+    /// Get the node for an inode mutably
+    fn node_mut(&mut self, inode: usize) -> FilesystemResult<&mut FatNode>
+    {
+        self.nodes.get_mut(&inode).ok_or(FilesystemError::BadINode)
+    }
+
+    /// Add a clean sector to the sector cache, evicting the oldest sector if the cache is full
+    fn cache_sector(&mut self, index: usize, data: Vec<u8>)
+    {
+        if self.cache.insert(index, data).is_none()
+        {
+            self.cache_order.push_back(index);
+        }
+
+        while self.cache_order.len() > SECTOR_CACHE_SIZE
+        {
+            if let Some(old) = self.cache_order.pop_front()
+            {
+                self.cache.remove(&old);
+            }
+        }
+    }
+
+    /// Get a sector of the filesystem
+    fn sector(&mut self, index: usize) -> FilesystemResult<&Vec<u8>>
+    {
+        if !self.dirty.contains_key(&index) && !self.cache.contains_key(&index)
+        {
+            if index >= self.total_sectors
+            {
+                kerrorln!("FAT sector {} is out of range", index);
+                return Err(FilesystemError::BadFilesystemFormat);
+            }
+
+            let mut buffer = vec![0u8; self.sector_size];
+            let count = self.sector_size / DEVICE_SECTOR_SIZE;
+
+            if let Err(e) = self.device.read_sectors((index * count) as u64, buffer.as_mut_ptr(), count)
+            {
+                kerrorln!("Unable to read sector {}: {:?}", index, e);
+                return Err(FilesystemError::BadFilesystemFormat);
+            }
+
+            self.cache_sector(index, buffer);
+        }
+
+        match self.dirty.get(&index)
+        {
+            Some(data) => Ok(data),
+            None => Ok(self.cache.get(&index).unwrap())
+        }
+    }
+
+    /// Read bytes from the filesystem starting at the given byte offset
+    fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> FilesystemResult<()>
+    {
+        let sector_size = self.sector_size;
+        let mut done = 0;
+
+        while done < buffer.len()
+        {
+            let position = offset as usize + done;
+            let start = position % sector_size;
+            let length = (sector_size - start).min(buffer.len() - done);
+
+            let sector = self.sector(position / sector_size)?;
+            buffer[done..done + length].copy_from_slice(&sector[start..start + length]);
+
+            done += length;
+        }
+
+        Ok(())
+    }
+
+    /// Write bytes to the filesystem starting at the given byte offset, which reach the disk at the next sync
+    fn write_bytes(&mut self, offset: u64, data: &[u8]) -> FilesystemResult<()>
+    {
+        let sector_size = self.sector_size;
+        let mut done = 0;
+
+        while done < data.len()
+        {
+            let position = offset as usize + done;
+            let index = position / sector_size;
+            let start = position % sector_size;
+            let length = (sector_size - start).min(data.len() - done);
+
+            // Whole sectors are replaced without reading them first
+            if length == sector_size
+            {
+                self.dirty.insert(index, data[done..done + length].to_vec());
+            }
+            else
+            {
+                if !self.dirty.contains_key(&index)
+                {
+                    let sector = self.sector(index)?.clone();
+                    self.dirty.insert(index, sector);
+                }
+
+                self.dirty.get_mut(&index).unwrap()[start..start + length].copy_from_slice(&data[done..done + length]);
+            }
+
+            done += length;
+        }
+
+        Ok(())
+    }
+
+    /// Check if a cluster number refers to a cluster in the data region
+    fn is_valid_cluster(&self, cluster: u32) -> bool
+    {
+        cluster >= FAT32_FIRST_CLUSTER && cluster < FAT32_FIRST_CLUSTER + self.cluster_count
+    }
+
+    /// Get the byte offset of a cluster
+    fn cluster_offset(&self, cluster: u32) -> u64
+    {
+        self.data_start + (cluster - FAT32_FIRST_CLUSTER) as u64 * self.cluster_size as u64
+    }
+
+    /// Read the FAT entry for a cluster
+    fn fat_entry(&mut self, cluster: u32) -> FilesystemResult<u32>
+    {
+        let mut buffer = [0u8; 4];
+        self.read_bytes(self.fat_start + self.active_fat as u64 * self.fat_bytes + cluster as u64 * 4, &mut buffer)?;
+
+        Ok(u32::from_le_bytes(buffer) & FAT32_ENTRY_MASK)
+    }
+
+    /// Set the FAT entry for a cluster in every copy of the FAT, keeping the reserved upper bits
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> FilesystemResult<()>
+    {
+        for fat in self.written_fats.clone()
+        {
+            let offset = self.fat_start + fat as u64 * self.fat_bytes + cluster as u64 * 4;
+
+            let mut buffer = [0u8; 4];
+            self.read_bytes(offset, &mut buffer)?;
+
+            let entry = (u32::from_le_bytes(buffer) & !FAT32_ENTRY_MASK) | (value & FAT32_ENTRY_MASK);
+            self.write_bytes(offset, &entry.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Follow a cluster chain from its first cluster
+    fn chain(&mut self, first: u32) -> FilesystemResult<Vec<u32>>
+    {
+        let mut result = Vec::new();
+        let mut cluster = first;
+
+        while cluster != 0 && cluster < FAT32_END_OF_CHAIN
+        {
+            // A chain which leaves the data region or is longer than the disk is damaged
+            if !self.is_valid_cluster(cluster) || result.len() >= self.cluster_count as usize
+            {
+                kerrorln!("Corrupt FAT cluster chain starting at {}", first);
+                return Err(FilesystemError::BadFilesystemFormat);
+            }
+
+            result.push(cluster);
+            cluster = self.fat_entry(cluster)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Get the cluster chain of an inode
+    fn node_chain(&mut self, inode: usize) -> FilesystemResult<Vec<u32>>
+    {
+        if let Some(chain) = &self.node(inode)?.chain
+        {
+            return Ok(chain.clone());
+        }
+
+        let chain = self.chain(self.node(inode)?.first_cluster)?;
+        self.node_mut(inode)?.chain = Some(chain.clone());
+
+        Ok(chain)
+    }
+
+    /// Claim a free cluster, filling it with zeros and adding it to the end of the chain ending in `previous`
+    fn allocate_cluster(&mut self, previous: Option<u32>) -> FilesystemResult<u32>
+    {
+        let start = if self.is_valid_cluster(self.next_free) { self.next_free } else { FAT32_FIRST_CLUSTER };
+
+        for i in 0..self.cluster_count
+        {
+            let cluster = FAT32_FIRST_CLUSTER + (start - FAT32_FIRST_CLUSTER + i) % self.cluster_count;
+
+            if self.fat_entry(cluster)? == 0
+            {
+                self.set_fat_entry(cluster, FAT32_ENTRY_MASK)?;
+
+                if let Some(previous) = previous
+                {
+                    self.set_fat_entry(previous, cluster)?;
+                }
+
+                self.write_bytes(self.cluster_offset(cluster), &vec![0u8; self.cluster_size])?;
+
+                if self.free_count != FSINFO_UNKNOWN
+                {
+                    self.free_count = self.free_count.saturating_sub(1);
+                }
+
+                self.next_free = cluster + 1;
+                self.fs_info_dirty = true;
+
+                return Ok(cluster);
+            }
+        }
+
+        Err(FilesystemError::OutOfSpace)
+    }
+
+    /// Return the clusters of a chain to the free pool
+    fn free_clusters(&mut self, clusters: &[u32]) -> FilesystemResult<()>
+    {
+        for cluster in clusters
+        {
+            self.set_fat_entry(*cluster, 0)?;
+
+            if self.free_count != FSINFO_UNKNOWN
+            {
+                self.free_count += 1;
+            }
+        }
+
+        self.fs_info_dirty = true;
+
+        Ok(())
+    }
+
+    /// Change the number of clusters in the chain of an inode, new clusters are filled with zeros
+    fn resize_chain(&mut self, inode: usize, clusters: usize) -> FilesystemResult<()>
+    {
+        let mut chain = self.node_chain(inode)?;
+        let mut result = Ok(());
+
+        if chain.len() > clusters
+        {
+            if clusters > 0
+            {
+                self.set_fat_entry(chain[clusters - 1], FAT32_ENTRY_MASK)?;
+            }
+
+            self.free_clusters(&chain[clusters..])?;
+            chain.truncate(clusters);
+        }
+
+        while chain.len() < clusters
+        {
+            match self.allocate_cluster(chain.last().copied())
+            {
+                Ok(cluster) => chain.push(cluster),
+                Err(e) =>
+                {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        // Whatever was allocated stays attached to the inode, even if the disk filled up part way
+        let first = chain.first().copied().unwrap_or(0);
+
+        let node = self.node_mut(inode)?;
+        node.first_cluster = first;
+        node.chain = Some(chain);
+
+        self.update_entry(inode, |entry| entry.set_first_cluster(first))?;
+
+        result
+    }
+
+    /// Read the short directory entry of an inode, the root directory has no entry
+    fn get_entry(&mut self, inode: usize) -> FilesystemResult<Option<FatDirEntry>>
+    {
+        match self.node(inode)?.slots.last().copied()
+        {
+            Some(location) => Ok(Some(self.read_entry(location)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Change the short directory entry of an inode, if it has one
+    fn update_entry(&mut self, inode: usize, f: impl FnOnce(&mut FatDirEntry)) -> FilesystemResult<()>
+    {
+        if let Some(location) = self.node(inode)?.slots.last().copied()
+        {
+            let mut entry = self.read_entry(location)?;
+            f(&mut entry);
+            self.write_bytes(location, &entry.to_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the directory entry at the given location
+    fn read_entry(&mut self, location: u64) -> FilesystemResult<FatDirEntry>
+    {
+        let mut buffer = [0u8; FAT_DIR_ENTRY_SIZE];
+        self.read_bytes(location, &mut buffer)?;
+
+        Ok(FatDirEntry::from_bytes(&buffer))
+    }
+
+    /// Get the size of the data of an inode, directories have no size in their entry
+    fn data_size(&mut self, inode: usize) -> FilesystemResult<usize>
+    {
+        if self.node(inode)?.directory
+        {
+            Ok(self.node_chain(inode)?.len() * self.cluster_size)
+        }
+        else
+        {
+            Ok(self.get_entry(inode)?.map(|entry| entry.size as usize).unwrap_or(0))
+        }
+    }
+
+    /// Read from the data of an inode at the given offset
+    fn read_range(&mut self, inode: usize, offset: usize, buffer: &mut [u8]) -> FilesystemResult<()>
+    {
+        let chain = self.node_chain(inode)?;
+        let mut done = 0;
+
+        while done < buffer.len()
+        {
+            let position = offset + done;
+            let start = position % self.cluster_size;
+            let length = (self.cluster_size - start).min(buffer.len() - done);
+
+            let cluster = *chain.get(position / self.cluster_size).ok_or(FilesystemError::BadFilesystemFormat)?;
+            self.read_bytes(self.cluster_offset(cluster) + start as u64, &mut buffer[done..done + length])?;
+
+            done += length;
+        }
+
+        Ok(())
+    }
+
+    /// Write to the data of an inode at the given offset, the clusters must already be allocated
+    fn write_range(&mut self, inode: usize, offset: usize, data: &[u8]) -> FilesystemResult<()>
+    {
+        let chain = self.node_chain(inode)?;
+        let mut done = 0;
+
+        while done < data.len()
+        {
+            let position = offset + done;
+            let start = position % self.cluster_size;
+            let length = (self.cluster_size - start).min(data.len() - done);
+
+            let cluster = *chain.get(position / self.cluster_size).ok_or(FilesystemError::BadFilesystemFormat)?;
+            self.write_bytes(self.cluster_offset(cluster) + start as u64, &data[done..done + length])?;
+
+            done += length;
+        }
+
+        Ok(())
+    }
+
+    /// Change the size of a file, growing it with zeros
+    fn resize_file(&mut self, inode: usize, size: usize) -> FilesystemResult<()>
+    {
+        if self.node(inode)?.directory
+        {
+            return Err(FilesystemError::INodeIsDirectory);
+        }
+
+        // The size of a file is stored in 32 bits
+        if size > u32::MAX as usize
+        {
+            return Err(FilesystemError::OutOfSpace);
+        }
+
+        let old_size = self.data_size(inode)?;
+        let old_capacity = self.node_chain(inode)?.len() * self.cluster_size;
+
+        self.resize_chain(inode, (size + self.cluster_size - 1) / self.cluster_size)?;
+
+        // New clusters start out zeroed, but the end of the old last cluster may hold stale data
+        if size > old_size && old_capacity > old_size
+        {
+            let length = size.min(old_capacity) - old_size;
+            self.write_range(inode, old_size, &vec![0u8; length])?;
+        }
+
+        let time = current_time();
+
+        self.update_entry(inode, |entry|
+        {
+            entry.size = size as u32;
+            entry.set_write_time(time);
+        })
+    }
+
+    /// Read every entry in a directory, except for `.` and `..`
+    fn read_directory(&mut self, inode: usize) -> FilesystemResult<Vec<DirectorySlot>>
+    {
+        if !self.node(inode)?.directory
+        {
+            return Err(FilesystemError::INodeIsNotADirectory);
+        }
+
+        let chain = self.node_chain(inode)?;
+        let per_cluster = self.cluster_size / FAT_DIR_ENTRY_SIZE;
+
+        let mut result = Vec::new();
+
+        let mut long_name: Vec<u16> = Vec::new();
+        let mut long_slots: Vec<u64> = Vec::new();
+        let mut expected = 0;
+        let mut checksum = 0;
+
+        'clusters: for cluster in chain
+        {
+            let mut data = vec![0u8; self.cluster_size];
+            self.read_bytes(self.cluster_offset(cluster), &mut data)?;
+
+            for i in 0..per_cluster
+            {
+                let raw = &data[i * FAT_DIR_ENTRY_SIZE..(i + 1) * FAT_DIR_ENTRY_SIZE];
+                let location = self.cluster_offset(cluster) + (i * FAT_DIR_ENTRY_SIZE) as u64;
+
+                if raw[0] == DIR_ENTRY_END
+                {
+                    break 'clusters;
+                }
+
+                if raw[0] == DIR_ENTRY_FREE
+                {
+                    expected = 0;
+                    continue;
+                }
+
+                // Long filename entries come before the short entry, holding the name in reverse order
+                if raw[11] & 0x3F == ATTR_LONG_NAME
+                {
+                    let sequence = (raw[0] & 0x1F) as usize;
+
+                    if raw[0] & LFN_LAST_ENTRY > 0 && sequence > 0
+                    {
+                        long_name = vec![0xFFFF; sequence * LFN_CHARS_PER_ENTRY];
+                        long_slots.clear();
+                        checksum = raw[13];
+                    }
+                    else if sequence == 0 || sequence + 1 != expected || raw[13] != checksum
+                    {
+                        expected = 0;
+                        continue;
+                    }
+
+                    for (j, offset) in LFN_CHAR_OFFSETS.iter().enumerate()
+                    {
+                        long_name[(sequence - 1) * LFN_CHARS_PER_ENTRY + j] = get_u16(raw, *offset);
+                    }
+
+                    long_slots.push(location);
+                    expected = sequence;
+
+                    continue;
+                }
+
+                let entry = FatDirEntry::from_bytes(raw);
+
+                // The volume label is not a file
+                if entry.attributes & ATTR_VOLUME_ID > 0 || entry.name[0] == b'.'
+                {
+                    expected = 0;
+                    continue;
+                }
+
+                // The long filename is only used if it was complete and belongs to this entry
+                let (name, mut slots) = if expected == 1 && checksum == short_name_checksum(&entry.name)
+                {
+                    let units = long_name.iter().copied().take_while(|unit| *unit != 0 && *unit != 0xFFFF);
+                    let name: String = core::char::decode_utf16(units).map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER)).collect();
+
+                    (name, core::mem::take(&mut long_slots))
+                }
+                else
+                {
+                    (entry.short_name(), Vec::new())
+                };
+
+                slots.push(location);
+                expected = 0;
+
+                result.push(DirectorySlot { slots, entry, name });
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Find the entry for a name in a directory
+    fn find_slot(&mut self, directory: usize, name: &str) -> FilesystemResult<Option<DirectorySlot>>
+    {
+        Ok(self.read_directory(directory)?.into_iter().find(|slot| slot.matches(name)))
+    }
+
+    /// Get the inode for an entry in a directory, giving it an inode number if it does not have one yet
+    fn node_for_slot(&mut self, parent: usize, slot: &DirectorySlot) -> usize
+    {
+        if let Some(inode) = self.locations.get(&slot.location())
+        {
+            return *inode;
+        }
+
+        let inode = self.next_inode;
+        self.next_inode += 1;
+
+        self.nodes.insert(inode, FatNode
+        {
+            parent,
+            slots: slot.slots.clone(),
+            first_cluster: slot.entry.first_cluster(),
+            directory: slot.entry.is_directory(),
+            chain: None
+        });
+
+        self.locations.insert(slot.location(), inode);
+
+        inode
+    }
+
+    /// Find a run of free entries in a directory, growing the directory if there is not one
+    fn find_free_slots(&mut self, directory: usize, count: usize) -> FilesystemResult<Vec<u64>>
+    {
+        let per_cluster = self.cluster_size / FAT_DIR_ENTRY_SIZE;
+        let mut run = Vec::new();
+        let mut index = 0;
+
+        loop
+        {
+            let chain = self.node_chain(directory)?;
+
+            while index < chain.len() * per_cluster
+            {
+                let location = self.cluster_offset(chain[index / per_cluster]) + ((index % per_cluster) * FAT_DIR_ENTRY_SIZE) as u64;
+
+                let mut first = [0u8; 1];
+                self.read_bytes(location, &mut first)?;
+
+                if first[0] == DIR_ENTRY_FREE || first[0] == DIR_ENTRY_END
+                {
+                    run.push(location);
+
+                    if run.len() == count
+                    {
+                        return Ok(run);
+                    }
+                }
+                else
+                {
+                    run.clear();
+                }
+
+                index += 1;
+            }
+
+            if (chain.len() + 1) * per_cluster > FAT_MAX_DIR_ENTRIES
+            {
+                return Err(FilesystemError::OutOfSpace);
+            }
+
+            // The new cluster is zeroed, so every entry in it is free
+            self.resize_chain(directory, chain.len() + 1)?;
+        }
+    }
+
+    /// Update the modification time of a directory
+    fn touch_directory(&mut self, directory: usize) -> FilesystemResult<()>
+    {
+        let time = current_time();
+
+        self.update_entry(directory, |entry| entry.set_write_time(time))
+    }
+
+    /// Add an entry to a directory under the given name, generating a short name and long filename entries as
+    /// needed, returns the locations of the entries written
+    fn add_entry(&mut self, directory: usize, name: &str, mut entry: FatDirEntry) -> FilesystemResult<Vec<u64>>
+    {
+        if !is_valid_long_name(name)
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        let existing = self.read_directory(directory)?;
+
+        if existing.iter().any(|slot| slot.matches(name))
+        {
+            return Err(FilesystemError::FileExists);
+        }
+
+        let used = |short: &[u8; 11]| existing.iter().any(|slot| &slot.entry.name == short);
+
+        // Names which fit in 8.3 only need the short entry, anything else gets a unique short alias
+        let (short, case_flags, long_name) = match exact_short_name(name)
+        {
+            Some((short, case_flags)) if !used(&short) => (short, case_flags, Vec::new()),
+            _ =>
+            {
+                let basis = short_name_basis(name);
+                let short = (1..1_000_000).map(|n| with_numeric_tail(&basis, n)).find(|short| !used(short)).ok_or(FilesystemError::OutOfSpace)?;
+
+                (short, 0, name.encode_utf16().collect::<Vec<u16>>())
+            }
+        };
+
+        entry.name = short;
+        entry.case_flags = case_flags;
+
+        let long_count = (long_name.len() + LFN_CHARS_PER_ENTRY - 1) / LFN_CHARS_PER_ENTRY;
+        let slots = self.find_free_slots(directory, long_count + 1)?;
+
+        let checksum = short_name_checksum(&short);
+
+        // The part of the name at the end comes first
+        for (i, location) in slots[..long_count].iter().enumerate()
+        {
+            let sequence = long_count - i;
+
+            let mut raw = [0u8; FAT_DIR_ENTRY_SIZE];
+            raw[0] = sequence as u8 | if i == 0 { LFN_LAST_ENTRY } else { 0 };
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+
+            // The name is terminated with a zero if there is room, then padded with 0xFFFF
+            for (j, offset) in LFN_CHAR_OFFSETS.iter().enumerate()
+            {
+                let position = (sequence - 1) * LFN_CHARS_PER_ENTRY + j;
+
+                let unit = match position.cmp(&long_name.len())
+                {
+                    core::cmp::Ordering::Less => long_name[position],
+                    core::cmp::Ordering::Equal => 0,
+                    core::cmp::Ordering::Greater => 0xFFFF
+                };
+
+                raw[*offset..*offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+
+            self.write_bytes(*location, &raw)?;
+        }
+
+        self.write_bytes(slots[long_count], &entry.to_bytes())?;
+        self.touch_directory(directory)?;
+
+        Ok(slots)
+    }
+
+    /// Mark the entries at the given locations as free
+    fn remove_slots(&mut self, slots: &[u64]) -> FilesystemResult<()>
+    {
+        for location in slots
+        {
+            self.write_bytes(*location, &[DIR_ENTRY_FREE])?;
+        }
+
+        Ok(())
+    }
+
+    /// Build a new directory entry with the current time
+    fn new_entry(&self, attributes: u8, first_cluster: u32) -> FatDirEntry
+    {
+        let (date, time) = unix_to_fat(current_time());
+
+        let mut entry = FatDirEntry
+        {
+            attributes,
+            create_time: time,
+            create_date: date,
+            access_date: date,
+            write_time: time,
+            write_date: date,
+            ..Default::default()
+        };
+
+        entry.set_first_cluster(first_cluster);
+
+        entry
+    }
+
+    /// Add a node for a newly created entry
+    fn add_node(&mut self, parent: usize, slots: Vec<u64>, first_cluster: u32, directory: bool) -> usize
+    {
+        let inode = self.next_inode;
+        self.next_inode += 1;
+
+        self.locations.insert(*slots.last().unwrap(), inode);
+        self.nodes.insert(inode, FatNode { parent, slots, first_cluster, directory, chain: None });
+
+        inode
+    }
+
+    /// Get the cluster a `..` entry uses to refer to a directory, the root directory is always cluster 0
+    fn parent_cluster(&self, directory: usize) -> FilesystemResult<u32>
+    {
+        if directory == FAT32_ROOT_INODE
+        {
+            Ok(0)
+        }
+        else
+        {
+            Ok(self.node(directory)?.first_cluster)
+        }
+    }
+
+    /// Point the `..` entry of a directory at a new parent
+    fn set_parent_entry(&mut self, directory: usize, parent: usize) -> FilesystemResult<()>
+    {
+        let cluster = self.parent_cluster(parent)?;
+        let location = self.cluster_offset(self.node(directory)?.first_cluster) + FAT_DIR_ENTRY_SIZE as u64;
+
+        let mut entry = self.read_entry(location)?;
+        entry.set_first_cluster(cluster);
+        self.write_bytes(location, &entry.to_bytes())?;
+
+        self.node_mut(directory)?.parent = parent;
+
+        Ok(())
+    }
+
+    /// Copy the contents of a directory entry, keeping the name of another
+    fn with_name(data: &FatDirEntry, named: &FatDirEntry) -> FatDirEntry
+    {
+        FatDirEntry { name: named.name, case_flags: named.case_flags, ..*data }
+    }
+
+    /// Move a directory entry to a new name, possibly in another directory, replacing or exchanging with the target
+    fn rename_entry(&mut self, old_dir: usize, old_name: &str, new_dir: usize, new_name: &str, flags: usize) -> FilesystemResult<()>
+    {
+        let source_slot = self.find_slot(old_dir, old_name)?.ok_or_else(|| FilesystemError::FileNotFound(old_name.to_string()))?;
+        let source = self.node_for_slot(old_dir, &source_slot);
+
+        let target_slot = self.find_slot(new_dir, new_name)?;
+        let target = target_slot.as_ref().map(|slot| self.node_for_slot(new_dir, slot));
+
+        if target == Some(source)
+        {
+            return Ok(());
+        }
+
+        let source_is_dir = source_slot.entry.is_directory();
+
+        if flags & RENAME_EXCHANGE > 0
+        {
+            let target_slot = target_slot.ok_or_else(|| FilesystemError::FileNotFound(new_name.to_string()))?;
+            let target = target.unwrap();
+
+            // The entries swap contents, keeping their names where they are
+            self.write_bytes(source_slot.location(), &Self::with_name(&target_slot.entry, &source_slot.entry).to_bytes())?;
+            self.write_bytes(target_slot.location(), &Self::with_name(&source_slot.entry, &target_slot.entry).to_bytes())?;
+
+            self.node_mut(source)?.slots = target_slot.slots.clone();
+            self.node_mut(target)?.slots = source_slot.slots.clone();
+
+            self.locations.insert(target_slot.location(), source);
+            self.locations.insert(source_slot.location(), target);
+
+            self.node_mut(source)?.parent = new_dir;
+            self.node_mut(target)?.parent = old_dir;
+
+            if old_dir != new_dir
+            {
+                if source_is_dir
+                {
+                    self.set_parent_entry(source, new_dir)?;
+                }
+
+                if target_slot.entry.is_directory()
+                {
+                    self.set_parent_entry(target, old_dir)?;
+                }
+            }
+        }
+        else
+        {
+            let slots = if let (Some(target_slot), Some(target)) = (target_slot, target)
+            {
+                if flags & RENAME_NOREPLACE > 0
+                {
+                    return Err(FilesystemError::FileExists);
+                }
+
+                let target_is_dir = target_slot.entry.is_directory();
+
+                if source_is_dir && !target_is_dir
+                {
+                    return Err(FilesystemError::INodeIsNotADirectory);
+                }
+                else if !source_is_dir && target_is_dir
+                {
+                    return Err(FilesystemError::INodeIsDirectory);
+                }
+                else if target_is_dir && self.read_directory(target)?.len() > 0
+                {
+                    return Err(FilesystemError::DirectoryNotEmpty);
+                }
+
+                // The source takes over the entries of the target, whose data is freed
+                self.write_bytes(target_slot.location(), &Self::with_name(&source_slot.entry, &target_slot.entry).to_bytes())?;
+
+                let chain = self.node_chain(target)?;
+                self.free_clusters(&chain)?;
+                self.nodes.remove(&target);
+
+                target_slot.slots
+            }
+            else
+            {
+                self.add_entry(new_dir, new_name, source_slot.entry)?
+            };
+
+            self.remove_slots(&source_slot.slots)?;
+            self.locations.remove(&source_slot.location());
+            self.locations.insert(*slots.last().unwrap(), source);
+
+            self.node_mut(source)?.slots = slots;
+            self.node_mut(source)?.parent = new_dir;
+
+            if source_is_dir && old_dir != new_dir
+            {
+                self.set_parent_entry(source, new_dir)?;
+            }
+        }
+
+        self.touch_directory(old_dir)?;
+        self.touch_directory(new_dir)
+    }
+
+    /// Write the free cluster count and hint back to the FSInfo sector
+    fn write_fs_info(&mut self) -> FilesystemResult<()>
+    {
+        if let Some(sector) = self.fs_info
+        {
+            let offset = (sector * self.sector_size) as u64;
+
+            self.write_bytes(offset + 488, &self.free_count.to_le_bytes())?;
+            self.write_bytes(offset + 492, &self.next_free.to_le_bytes())?;
+        }
+
+        self.fs_info_dirty = false;
+
+        Ok(())
+    }
+}
+
+impl Filesystem for Fat32Filesystem
+{
+    /// Initialize the filesystem on the current disk
+    fn init(&mut self) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Initializing FAT32 Filesystem");
+
+        // Read the boot sector
+        let mut buffer = vec![0u8; DEVICE_SECTOR_SIZE];
+
+        if self.device.read_sectors(0, buffer.as_mut_ptr(), 1).is_err()
+        {
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        let boot = Fat32BootSector::from_bytes(&buffer);
+
+        if boot.signature != 0xAA55
+        {
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        let sector_size = boot.bytes_per_sector as usize;
+
+        if !sector_size.is_power_of_two() || sector_size < DEVICE_SECTOR_SIZE || sector_size > mem::PAGE_SIZE || !boot.sectors_per_cluster.is_power_of_two() || boot.fat_count == 0
+        {
+            kerrorln!("Unsupported FAT geometry with {} byte sectors", sector_size);
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        // Only FAT32 has a zero sized FAT16 table and no fixed root directory
+        if boot.fat_size_16 != 0 || boot.fat_size_32 == 0 || boot.root_entry_count != 0 || boot.fs_version != 0
+        {
+            kerrorln!("Not a FAT32 filesystem");
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        let fat_sectors = boot.fat_count as usize * boot.fat_size_32 as usize;
+        let data_sector = boot.reserved_sectors as usize + fat_sectors;
+
+        if data_sector >= boot.total_sectors()
+        {
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        self.sector_size = sector_size;
+        self.cluster_size = sector_size * boot.sectors_per_cluster as usize;
+        self.total_sectors = boot.total_sectors();
+        self.fat_start = (boot.reserved_sectors as usize * sector_size) as u64;
+        self.fat_bytes = (boot.fat_size_32 as usize * sector_size) as u64;
+        self.data_start = (data_sector * sector_size) as u64;
+
+        // The cluster count is limited by both the data region and the size of the FAT
+        let clusters = (boot.total_sectors() - data_sector) / boot.sectors_per_cluster as usize;
+        self.cluster_count = clusters.min(self.fat_bytes as usize / 4 - FAT32_FIRST_CLUSTER as usize) as u32;
+
+        // Unless mirroring is disabled every copy of the FAT is kept up to date
+        if boot.ext_flags & 0x80 > 0
+        {
+            self.active_fat = (boot.ext_flags & 0xF) as usize;
+            self.written_fats = vec![self.active_fat];
+        }
+        else
+        {
+            self.active_fat = 0;
+            self.written_fats = (0..boot.fat_count as usize).collect();
+        }
+
+        if !self.is_valid_cluster(boot.root_cluster) || self.active_fat >= boot.fat_count as usize
+        {
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        self.boot = Some(boot);
+
+        // The FSInfo sector holds hints about free space, which are only used if it is valid
+        let fs_info = boot.fs_info as usize;
+
+        if fs_info > 0 && fs_info < boot.reserved_sectors as usize
+        {
+            let sector = self.sector(fs_info)?.clone();
+
+            if get_u32(&sector, 0) == FSINFO_LEAD_SIGNATURE && get_u32(&sector, 484) == FSINFO_STRUCT_SIGNATURE
+            {
+                self.fs_info = Some(fs_info);
+                self.free_count = get_u32(&sector, 488);
+                self.next_free = get_u32(&sector, 492);
+
+                if self.free_count > self.cluster_count
+                {
+                    self.free_count = FSINFO_UNKNOWN;
+                }
+            }
+        }
+
+        self.nodes.clear();
+        self.locations.clear();
+
+        self.nodes.insert(FAT32_ROOT_INODE, FatNode
+        {
+            parent: FAT32_ROOT_INODE,
+            slots: Vec::new(),
+            first_cluster: boot.root_cluster,
+            directory: true,
+            chain: None
+        });
+
+        kdebugln!(Filesystem, "FAT32 filesystem with {} clusters of {} bytes", self.cluster_count, self.cluster_size);
+
+        Ok(())
+    }
+
+    /// Sync the filesystem with the current disk
+    fn sync(&mut self) -> FilesystemResult<()>
+    {
+        if self.fs_info_dirty
+        {
+            self.write_fs_info()?;
+        }
+
+        kdebugln!(Filesystem, "{} Sectors Rewritten", self.dirty.len());
+
+        let sector_size = self.sector_size;
+
+        if let Some(mut queue) = self.device.request_queue()
+        {
+            for (sector, data) in &self.dirty
+            {
+                let ptr = data.as_ptr() as *mut u8;
+
+                if let Err(e) = queue.queue(BlockOperation::Write, (sector_size * *sector) as u64, ptr, sector_size as u32)
+                {
+                    kerrorln!("Unable to write sector {}: {:?}", sector, e);
+                }
+            }
+
+            // Adjacent sectors are merged into a single request by the block layer
+            for token in queue.dispatch().into_iter().flatten()
+            {
+                if !queue.finish(token)
+                {
+                    kerrorln!("FAT sector write failed");
+                }
+            }
+        }
+        else
+        {
+            let count = sector_size / DEVICE_SECTOR_SIZE;
+
+            for (sector, data) in &self.dirty
+            {
+                if let Err(e) = self.device.write_sectors((*sector * count) as u64, data.as_ptr(), count)
+                {
+                    kerrorln!("Unable to write sector {}: {:?}", sector, e);
+                }
+            }
+        }
+
+        if let Err(e) = self.device.flush()
+        {
+            kerrorln!("Unable to flush block device: {:?}", e);
+        }
+
+        // The written sectors are now clean, so move them into the sector cache
+        for (sector, data) in core::mem::take(&mut self.dirty)
+        {
+            self.cache_sector(sector, data);
+        }
+
+        Ok(())
+    }
+
+    /// Set the mount_id of the filesystem
+    fn set_mount_id(&mut self, mount_id: usize, vfs: &'static mut crate::fs::vfs::FilesystemInterface)
+    {
+        self.mount_id = Some(mount_id);
+        self.vfs = Some(vfs);
+    }
+
+    /// Get the index of the root directory of the filesystem
+    fn get_root_index(&mut self) -> FilesystemResult<FilesystemIndex>
+    {
+        if self.mount_id.is_none()
+        {
+            return Err(FilesystemError::FilesystemNotMounted);
+        }
+
+        Ok(self.index(FAT32_ROOT_INODE))
+    }
+
+    /// Convert a path to an inode
+    fn path_to_inode(&mut self, path: PathBuffer) -> FilesystemResult<FilesystemIndex>
+    {
+        self.vfs()?.path_to_inode(path)
+    }
+
+    /// Convert an inode to a path
+    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<OwnedPath>
+    {
+        self.vfs()?.inode_to_path(inode)
+    }
+
+    /// Get the directory entries for the given inode
+    fn get_dir_entries(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<DirectoryEntry>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.get_dir_entries(inode);
+        }
+
+        let parent = self.node(inode.inode)?.parent;
+
+        let mut result = Vec::new();
+
+        result.push(DirectoryEntry { index: inode, name: String::from("."), entry_type: DirectoryEntryType::Directory });
+        result.push(DirectoryEntry { index: self.index(parent), name: String::from(".."), entry_type: DirectoryEntryType::Directory });
+
+        for slot in self.read_directory(inode.inode)?
+        {
+            let child = self.node_for_slot(inode.inode, &slot);
+            let entry_type = if slot.entry.is_directory() { DirectoryEntryType::Directory } else { DirectoryEntryType::RegularFile };
+
+            result.push(DirectoryEntry { index: self.index(child), name: slot.name, entry_type });
+        }
+
+        Ok(result)
+    }
+
+    /// Find the inode for a name in the directory at the given inode
+    fn lookup(&mut self, directory: FilesystemIndex, name: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.lookup(directory, name);
+        }
+
+        match name
+        {
+            "." => Ok(directory),
+            ".." => Ok(self.index(self.node(directory.inode)?.parent)),
+            _ =>
+            {
+                let slot = self.find_slot(directory.inode, name)?.ok_or_else(|| FilesystemError::FileNotFound(name.to_string()))?;
+                let inode = self.node_for_slot(directory.inode, &slot);
+
+                Ok(self.index(inode))
+            }
+        }
+    }
+
+    /// Names are not case sensitive, so a cached lookup of one spelling goes stale when another spelling changes
+    fn cacheable_lookups(&mut self) -> bool
+    {
+        false
+    }
+
+    /// Get the directory entry for the given inode
+    fn get_stat(&mut self, inode: FilesystemIndex) -> FilesystemResult<FileStat>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.get_stat(inode);
+        }
+
+        let directory = self.node(inode.inode)?.directory;
+        let entry = self.get_entry(inode.inode)?.unwrap_or_default();
+        let allocated = self.node_chain(inode.inode)?.len() * self.cluster_size;
+
+        // FAT has no permissions, only a read only flag
+        let permissions = if entry.attributes & ATTR_READ_ONLY > 0 { 0o555 } else { 0o755 };
+        let mtime = if entry.write_date != 0 { fat_to_unix(entry.write_date, entry.write_time) as usize } else { 0 };
+        let atime = if entry.access_date != 0 { fat_to_unix(entry.access_date, 0) as usize } else { mtime };
+
+        Ok(FileStat
+        {
+            dev_id: inode.mount_id,
+            inode: inode.inode,
+            mode: (if directory { S_IFDIR } else { S_IFREG }) | permissions,
+            // Subdirectories are not counted, a link count of one tells tools like find not to rely on it
+            links: 1,
+            uid: 0,
+            gid: 0,
+            special_dev_id: 0,
+            size: if directory { allocated } else { entry.size as usize },
+            blk_size: self.cluster_size,
+            blocks_alloced: allocated / DEVICE_SECTOR_SIZE,
+            atime,
+            mtime,
+            ctime: mtime,
+        })
+    }
+
+    /// Create a file in the directory at the given inode
+    fn create_file(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.create_file(inode, name);
+        }
+
+        let entry = self.new_entry(ATTR_ARCHIVE, 0);
+        let slots = self.add_entry(inode.inode, &name, entry)?;
+
+        let file = self.add_node(inode.inode, slots, 0, false);
+
+        Ok(self.index(file))
+    }
+
+    /// Create a directory in the directory at the given inode
+    fn create_directory(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.create_directory(inode, name);
+        }
+
+        if !is_valid_long_name(&name)
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        // The first cluster of the directory holds its `.` and `..` entries
+        let cluster = self.allocate_cluster(None)?;
+
+        let mut dot = self.new_entry(ATTR_DIRECTORY, cluster);
+        dot.name = *b".          ";
+
+        let mut dot_dot = self.new_entry(ATTR_DIRECTORY, self.parent_cluster(inode.inode)?);
+        dot_dot.name = *b"..         ";
+
+        let offset = self.cluster_offset(cluster);
+        self.write_bytes(offset, &dot.to_bytes())?;
+        self.write_bytes(offset + FAT_DIR_ENTRY_SIZE as u64, &dot_dot.to_bytes())?;
+
+        let entry = self.new_entry(ATTR_DIRECTORY, cluster);
+
+        let slots = match self.add_entry(inode.inode, &name, entry)
+        {
+            Ok(slots) => slots,
+            Err(e) =>
+            {
+                self.free_clusters(&[cluster])?;
+                return Err(e);
+            }
+        };
+
+        let directory = self.add_node(inode.inode, slots, cluster, true);
+
+        Ok(self.index(directory))
+    }
+
+    /// Change the metadata of an inode, only the permission bits of a new mode are used
+    fn set_attr(&mut self, inode: FilesystemIndex, attributes: SetAttributes) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.set_attr(inode, attributes);
+        }
+
+        // Every file is owned by root, so ownership cannot be changed
+        if attributes.uid.unwrap_or(0) != 0 || attributes.gid.unwrap_or(0) != 0
+        {
+            return Err(FilesystemError::PermissionDenied);
+        }
+
+        if let Some(size) = attributes.size
+        {
+            self.resize_file(inode.inode, size)?;
+        }
+
+        self.update_entry(inode.inode, |entry|
+        {
+            // Only whether the file can be written is stored
+            if let Some(mode) = attributes.mode
+            {
+                if mode & 0o222 == 0
+                {
+                    entry.attributes |= ATTR_READ_ONLY;
+                }
+                else
+                {
+                    entry.attributes &= !ATTR_READ_ONLY;
+                }
+            }
+
+            if let Some(atime) = attributes.atime
+            {
+                entry.access_date = unix_to_fat(atime as u32).0;
+            }
+
+            if let Some(mtime) = attributes.mtime
+            {
+                entry.set_write_time(mtime as u32);
+            }
+        })
+    }
+
+    /// Move the entry `old_name` in the directory at `old_directory` to `new_name` in `new_directory`, replacing or
+    /// exchanging with any existing entry depending on the `RENAME_*` flags
+    fn rename(&mut self, old_directory: FilesystemIndex, old_name: String, new_directory: FilesystemIndex, new_name: String, flags: usize) -> FilesystemResult<()>
+    {
+        if self.is_local(old_directory) && self.is_local(new_directory)
+        {
+            self.rename_entry(old_directory.inode, &old_name, new_directory.inode, &new_name, flags)
+        }
+        else if old_directory.mount_id != new_directory.mount_id
+        {
+            Err(FilesystemError::CrossDeviceLink)
+        }
+        else
+        {
+            self.vfs()?.rename(old_directory, old_name, new_directory, new_name, flags)
+        }
+    }
+
+    /// Remove an inode at the given index from the given directory
+    fn remove_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.remove_inode(inode);
+        }
+
+        if inode.inode == FAT32_ROOT_INODE
+        {
+            return Err(FilesystemError::PermissionDenied);
+        }
+
+        let chain = self.node_chain(inode.inode)?;
+        self.free_clusters(&chain)?;
+
+        if let Some(node) = self.nodes.remove(&inode.inode)
+        {
+            if let Some(location) = node.slots.last()
+            {
+                self.locations.remove(location);
+                self.remove_slots(&node.slots)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a directory entry from the directory at the given inode
+    fn remove_dir_entry(&mut self, directory_index: FilesystemIndex, name: String) -> FilesystemResult<()>
+    {
+        if !self.is_local(directory_index)
+        {
+            return self.vfs()?.remove_dir_entry(directory_index, name);
+        }
+
+        let slot = self.find_slot(directory_index.inode, &name)?.ok_or_else(|| FilesystemError::FileNotFound(name.clone()))?;
+        let inode = self.node_for_slot(directory_index.inode, &slot);
+
+        // The clusters stay attached to the node until the inode itself is removed
+        self.remove_slots(&slot.slots)?;
+        self.locations.remove(&slot.location());
+        self.node_mut(inode)?.slots.clear();
+
+        self.touch_directory(directory_index.inode)
+    }
+
+    /// Increment the number of links to an inode
+    fn increment_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.increment_links(inode);
+        }
+
+        // Each file has exactly one directory entry
+        Err(FilesystemError::PermissionDenied)
+    }
+
+    /// Decrement the number of links to an inode
+    fn decrement_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.decrement_links(inode);
+        }
+
+        // A file is linked for as long as its directory entry exists
+        Ok(if self.node(inode.inode)?.slots.is_empty() { 0 } else { 1 })
+    }
+
+    /// Read the data stored in an inode
+    fn read_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<u8>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.read_inode(inode);
+        }
+
+        let mut buffer = vec![0u8; self.data_size(inode.inode)?];
+        self.read_range(inode.inode, 0, &mut buffer)?;
+
+        Ok(buffer)
+    }
+
+    /// Read a single page of the data stored in an inode into the buffer, returning the number of bytes read
+    fn read_inode_page(&mut self, inode: FilesystemIndex, page: usize, buffer: *mut u8) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.read_inode_page(inode, page, buffer);
+        }
+
+        let size = self.data_size(inode.inode)?;
+        let start = (page * mem::PAGE_SIZE).min(size);
+        let length = (size - start).min(mem::PAGE_SIZE);
+
+        let buffer = unsafe { core::slice::from_raw_parts_mut(buffer, length) };
+        self.read_range(inode.inode, start, buffer)?;
+
+        Ok(length)
+    }
+
+    /// Write back pages of the data stored in an inode, resizing the inode to `size` bytes
+    fn write_inode_pages(&mut self, inode: FilesystemIndex, size: usize, pages: &[(usize, *const u8)]) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.write_inode_pages(inode, size, pages);
+        }
+
+        self.resize_file(inode.inode, size)?;
+
+        for (page, ptr) in pages
+        {
+            let start = (page * mem::PAGE_SIZE).min(size);
+            let length = (size - start).min(mem::PAGE_SIZE);
+
+            let data = unsafe { core::slice::from_raw_parts(*ptr, length) };
+            self.write_range(inode.inode, start, data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write data to an inode
+    fn write_inode(&mut self, inode: FilesystemIndex, data: &[u8]) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.write_inode(inode, data);
+        }
+
+        if self.node(inode.inode)?.directory
+        {
+            return Err(FilesystemError::INodeIsDirectory);
+        }
+
+        if data.len() > u32::MAX as usize
+        {
+            return Err(FilesystemError::OutOfSpace);
+        }
+
+        // The old data past the new end is beyond the size, so it is zeroed if the file grows again
+        self.resize_chain(inode.inode, (data.len() + self.cluster_size - 1) / self.cluster_size)?;
+        self.write_range(inode.inode, 0, data)?;
+
+        let time = current_time();
+
+        self.update_entry(inode.inode, |entry|
+        {
+            entry.size = data.len() as u32;
+            entry.set_write_time(time);
+        })
+    }
+
+    /// Open a filedescriptor for the given inode
+    fn open_fd(&mut self, inode: FilesystemIndex, mode: usize) -> FilesystemResult<Box<dyn crate::process::descriptor::FileDescriptor>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.open_fd(inode, mode);
+        }
+
+        Ok(Box::new(PageCacheDescriptor::new(self.vfs()?, inode, mode)?))
+    }
+
+    /// Execute an ioctl command on an inode
+    fn exec_ioctl(&mut self, inode: FilesystemIndex, cmd: IOControlCommand) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.exec_ioctl(inode, cmd);
+        }
+
+        // Nothing to do here (yet)
+        Ok(usize::MAX)
+    }
+}
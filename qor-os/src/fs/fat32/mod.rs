@@ -0,0 +1,4 @@
+pub mod driver;
+pub use driver::*;
+
+pub mod structures;
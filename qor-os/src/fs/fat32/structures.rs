@@ -0,0 +1,424 @@
+use crate::*;
+
+/// Inode number given to the root directory, which has no directory entry of its own
+pub const FAT32_ROOT_INODE: usize = 1;
+
+/// Size of a directory entry in bytes
+pub const FAT_DIR_ENTRY_SIZE: usize = 32;
+
+/// Mask of the bits of a FAT32 entry which hold the cluster number
+pub const FAT32_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+
+/// FAT entries at or above this value mark the end of a chain
+pub const FAT32_END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+
+/// FAT entry of a cluster which cannot be used
+pub const FAT32_BAD_CLUSTER: u32 = 0x0FFF_FFF7;
+
+/// First cluster number which refers to the data region
+pub const FAT32_FIRST_CLUSTER: u32 = 2;
+
+/// Signatures of the FSInfo sector
+pub const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+pub const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+
+/// Value of an FSInfo count which has not been computed
+pub const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+/// Directory entry attributes
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_HIDDEN: u8 = 0x02;
+pub const ATTR_SYSTEM: u8 = 0x04;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+
+/// Attributes of a long filename entry
+pub const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// First byte of a deleted directory entry
+pub const DIR_ENTRY_FREE: u8 = 0xE5;
+
+/// First byte of the entry after the last used entry in a directory
+pub const DIR_ENTRY_END: u8 = 0x00;
+
+/// Stored in place of a first byte of 0xE5 in a short name
+pub const DIR_ENTRY_KANJI: u8 = 0x05;
+
+/// Set in the sequence number of the long filename entry holding the end of the name
+pub const LFN_LAST_ENTRY: u8 = 0x40;
+
+/// Number of UTF-16 units held in each long filename entry
+pub const LFN_CHARS_PER_ENTRY: usize = 13;
+
+/// Byte offsets of the UTF-16 units held in a long filename entry
+pub const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// Maximum length of a long filename in UTF-16 units
+pub const LFN_MAX_LENGTH: usize = 255;
+
+/// Case flags for short names which are displayed in lower case
+pub const CASE_LOWER_BASE: u8 = 0x08;
+pub const CASE_LOWER_EXT: u8 = 0x10;
+
+/// Largest number of entries a directory can hold
+pub const FAT_MAX_DIR_ENTRIES: usize = 65536;
+
+/// Unix timestamp of the FAT epoch, 1980-01-01
+pub const FAT_EPOCH: u32 = 315_532_800;
+
+/// Read a little endian half word from a buffer
+pub fn get_u16(buffer: &[u8], offset: usize) -> u16
+{
+    u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+/// Read a little endian word from a buffer
+pub fn get_u32(buffer: &[u8], offset: usize) -> u32
+{
+    u32::from_le_bytes([buffer[offset], buffer[offset + 1], buffer[offset + 2], buffer[offset + 3]])
+}
+
+/// FAT32 Boot Sector, holding the BIOS Parameter Block
+#[derive(Debug, Clone, Copy)]
+pub struct Fat32BootSector
+{
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub fat_count: u8,
+    pub root_entry_count: u16,
+    pub total_sectors_16: u16,
+    pub fat_size_16: u16,
+    pub total_sectors_32: u32,
+    pub fat_size_32: u32,
+    pub ext_flags: u16,
+    pub fs_version: u16,
+    pub root_cluster: u32,
+    pub fs_info: u16,
+    pub signature: u16
+}
+
+impl Fat32BootSector
+{
+    /// Parse the boot sector from the first sector of the disk, the fields are not naturally aligned
+    pub fn from_bytes(buffer: &[u8]) -> Self
+    {
+        Self
+        {
+            bytes_per_sector: get_u16(buffer, 11),
+            sectors_per_cluster: buffer[13],
+            reserved_sectors: get_u16(buffer, 14),
+            fat_count: buffer[16],
+            root_entry_count: get_u16(buffer, 17),
+            total_sectors_16: get_u16(buffer, 19),
+            fat_size_16: get_u16(buffer, 22),
+            total_sectors_32: get_u32(buffer, 32),
+            fat_size_32: get_u32(buffer, 36),
+            ext_flags: get_u16(buffer, 40),
+            fs_version: get_u16(buffer, 42),
+            root_cluster: get_u32(buffer, 44),
+            fs_info: get_u16(buffer, 48),
+            signature: get_u16(buffer, 510)
+        }
+    }
+
+    /// Get the number of sectors in the filesystem
+    pub fn total_sectors(&self) -> usize
+    {
+        if self.total_sectors_16 != 0
+        {
+            self.total_sectors_16 as usize
+        }
+        else
+        {
+            self.total_sectors_32 as usize
+        }
+    }
+}
+
+/// Short FAT directory entry
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FatDirEntry
+{
+    pub name:               [u8; 11],
+    pub attributes:         u8,
+    pub case_flags:         u8,
+    pub create_time_tenths: u8,
+    pub create_time:        u16,
+    pub create_date:        u16,
+    pub access_date:        u16,
+    pub cluster_high:       u16,
+    pub write_time:         u16,
+    pub write_date:         u16,
+    pub cluster_low:        u16,
+    pub size:               u32
+}
+
+static_assertions::const_assert_eq!(core::mem::size_of::<FatDirEntry>(), FAT_DIR_ENTRY_SIZE);
+
+impl FatDirEntry
+{
+    /// Read a directory entry from a buffer
+    pub fn from_bytes(buffer: &[u8]) -> Self
+    {
+        unsafe { (buffer.as_ptr() as *const FatDirEntry).read_unaligned() }
+    }
+
+    /// Convert the directory entry to the bytes stored on the disk
+    pub fn to_bytes(&self) -> [u8; FAT_DIR_ENTRY_SIZE]
+    {
+        unsafe { core::mem::transmute(*self) }
+    }
+
+    /// Get the first cluster of the file
+    pub fn first_cluster(&self) -> u32
+    {
+        (self.cluster_high as u32) << 16 | self.cluster_low as u32
+    }
+
+    /// Set the first cluster of the file
+    pub fn set_first_cluster(&mut self, cluster: u32)
+    {
+        self.cluster_high = (cluster >> 16) as u16;
+        self.cluster_low = cluster as u16;
+    }
+
+    /// Check if the entry is a directory
+    pub fn is_directory(&self) -> bool
+    {
+        self.attributes & ATTR_DIRECTORY > 0
+    }
+
+    /// Get the name of the entry as displayed when there is no long filename
+    pub fn short_name(&self) -> String
+    {
+        let mut name = self.name;
+
+        if name[0] == DIR_ENTRY_KANJI
+        {
+            name[0] = DIR_ENTRY_FREE;
+        }
+
+        let convert = |bytes: &[u8], lower: bool| -> String
+        {
+            bytes.iter()
+                .take_while(|byte| **byte != b' ')
+                .map(|byte| if lower { byte.to_ascii_lowercase() as char } else { *byte as char })
+                .collect()
+        };
+
+        let base = convert(&name[..8], self.case_flags & CASE_LOWER_BASE > 0);
+        let ext = convert(&name[8..], self.case_flags & CASE_LOWER_EXT > 0);
+
+        if ext.len() > 0
+        {
+            format!("{}.{}", base, ext)
+        }
+        else
+        {
+            base
+        }
+    }
+
+    /// Set the write date and time of the entry
+    pub fn set_write_time(&mut self, unix: u32)
+    {
+        let (date, time) = unix_to_fat(unix);
+
+        self.write_date = date;
+        self.write_time = time;
+    }
+}
+
+/// Checksum of a short name, stored in each of the long filename entries which go with it
+pub fn short_name_checksum(name: &[u8; 11]) -> u8
+{
+    name.iter().fold(0u8, |sum, byte| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*byte))
+}
+
+/// Check if a character can appear in a short name
+fn is_short_name_char(c: char) -> bool
+{
+    c.is_ascii_alphanumeric() || "$%'-_@~`!(){}^#&".contains(c)
+}
+
+/// Check if a name can be stored as a long filename
+pub fn is_valid_long_name(name: &str) -> bool
+{
+    name.len() > 0 && name.encode_utf16().count() <= LFN_MAX_LENGTH && !name.chars().any(|c| (c as u32) < 0x20 || "\"*/:<>?\\|".contains(c))
+}
+
+/// Get the short name which represents the given name exactly, along with the case flags needed to display it,
+/// returns `None` if the name needs a long filename
+pub fn exact_short_name(name: &str) -> Option<([u8; 11], u8)>
+{
+    let (base, ext) = match name.rfind('.')
+    {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => (name, "")
+    };
+
+    if base.len() == 0 || base.len() > 8 || ext.len() > 3 || (name.ends_with('.'))
+    {
+        return None;
+    }
+
+    let mut result = [b' '; 11];
+    let mut flags = 0;
+
+    for (part, start, lower_flag) in [(base, 0, CASE_LOWER_BASE), (ext, 8, CASE_LOWER_EXT)]
+    {
+        if !part.chars().all(is_short_name_char)
+        {
+            return None;
+        }
+
+        // Each part can be all upper case or all lower case, but not a mix of both
+        let has_lower = part.chars().any(|c| c.is_ascii_lowercase());
+        let has_upper = part.chars().any(|c| c.is_ascii_uppercase());
+
+        if has_lower && has_upper
+        {
+            return None;
+        }
+
+        if has_lower
+        {
+            flags |= lower_flag;
+        }
+
+        result[start..start + part.len()].copy_from_slice(part.to_ascii_uppercase().as_bytes());
+    }
+
+    Some((result, flags))
+}
+
+/// Generate the short name a long name is based on, before a numeric tail is added to make it unique
+pub fn short_name_basis(name: &str) -> [u8; 11]
+{
+    let convert = |c: char| -> u8
+    {
+        if is_short_name_char(c) { c.to_ascii_uppercase() as u8 } else { b'_' }
+    };
+
+    // Spaces and leading dots are dropped entirely
+    let stripped: String = name.trim_start_matches('.').chars().filter(|c| *c != ' ').collect();
+
+    let (base, ext) = match stripped.rfind('.')
+    {
+        Some(index) => (&stripped[..index], &stripped[index + 1..]),
+        None => (&stripped[..], "")
+    };
+
+    let mut result = [b' '; 11];
+
+    for (i, byte) in base.chars().filter(|c| *c != '.').map(convert).take(8).enumerate()
+    {
+        result[i] = byte;
+    }
+
+    for (i, byte) in ext.chars().map(convert).take(3).enumerate()
+    {
+        result[8 + i] = byte;
+    }
+
+    if result[0] == b' '
+    {
+        result[0] = b'_';
+    }
+
+    result
+}
+
+/// Add the numeric tail `~n` to the base of a short name
+pub fn with_numeric_tail(basis: &[u8; 11], n: usize) -> [u8; 11]
+{
+    let tail = format!("~{}", n);
+
+    let base_length = basis[..8].iter().position(|byte| *byte == b' ').unwrap_or(8).min(8 - tail.len());
+
+    let mut result = *basis;
+    result[base_length..base_length + tail.len()].copy_from_slice(tail.as_bytes());
+
+    for byte in &mut result[base_length + tail.len()..8]
+    {
+        *byte = b' ';
+    }
+
+    result
+}
+
+/// Get the number of days between 1970-01-01 and the given date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64
+{
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Get the date which is the given number of days after 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64)
+{
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+
+    (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+/// Convert a FAT date and time to a Unix timestamp, FAT times have no time zone and are treated as UTC
+pub fn fat_to_unix(date: u16, time: u16) -> u32
+{
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0xF).clamp(1, 12) as i64;
+    let day = (date & 0x1F).max(1) as i64;
+
+    let seconds = (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3F) as i64 * 60 + (time & 0x1F) as i64 * 2;
+
+    (days_from_civil(year, month, day) * 86400 + seconds) as u32
+}
+
+/// Convert a Unix timestamp to a FAT date and time, times outside of the range FAT can store are clamped
+pub fn unix_to_fat(unix: u32) -> (u16, u16)
+{
+    let unix = unix.max(FAT_EPOCH) as i64;
+
+    let (year, month, day) = civil_from_days(unix / 86400);
+    let seconds = unix % 86400;
+
+    let date = ((year - 1980) as u16) << 9 | (month as u16) << 5 | day as u16;
+    let time = ((seconds / 3600) as u16) << 11 | (((seconds / 60) % 60) as u16) << 5 | ((seconds % 60) / 2) as u16;
+
+    (date, time)
+}
+
+#[test_case]
+fn fat_names_and_times()
+{
+    // Names which fit in 8.3 are stored without a long filename, keeping their case in the flags
+    assert_eq!(exact_short_name("README.TXT"), Some((*b"README  TXT", 0)));
+    assert_eq!(exact_short_name("notes.md"), Some((*b"NOTES   MD ", CASE_LOWER_BASE | CASE_LOWER_EXT)));
+    assert_eq!(exact_short_name("Notes.md"), None);
+    assert_eq!(exact_short_name("a long name.txt"), None);
+
+    // Other names get a mangled short name with a numeric tail
+    assert_eq!(&short_name_basis("a long name.text"), b"ALONGNAMTEX");
+    assert_eq!(&with_numeric_tail(&short_name_basis("a long name.text"), 1), b"ALONGN~1TEX");
+    assert_eq!(&with_numeric_tail(&short_name_basis(".profile"), 12), b"PROFI~12   ");
+
+    // Dates round trip at two second resolution
+    let (date, time) = unix_to_fat(1_600_000_000);
+    assert_eq!(fat_to_unix(date, time), 1_600_000_000);
+    assert_eq!(fat_to_unix(0x21, 0), FAT_EPOCH);
+}
@@ -4,7 +4,7 @@ use crate::*;
 
 use super::structures::*;
 
-use libutils::paths::PathBuffer;
+use libutils::paths::{OwnedPath, PathBuffer};
 
 use super::ioctl::*;
 
@@ -27,11 +27,44 @@ pub trait Filesystem
     fn path_to_inode(&mut self, path: PathBuffer) -> FilesystemResult<FilesystemIndex>;
 
     /// Convert an inode to a path
-    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<PathBuffer>;
+    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<OwnedPath>;
 
     /// Get the directory entries in the directory at the given inode
     fn get_dir_entries(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<DirectoryEntry>>;
 
+    /// Find the inode for a name in the directory at the given inode
+    fn lookup(&mut self, directory: FilesystemIndex, name: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        for entry in self.get_dir_entries(directory)?
+        {
+            if entry.name == name
+            {
+                return Ok(entry.index);
+            }
+        }
+
+        Err(FilesystemError::FileNotFound(name.to_string()))
+    }
+
+    /// Check if the results of lookups can be cached, filesystems whose entries change on their own return false
+    fn cacheable_lookups(&mut self) -> bool
+    {
+        true
+    }
+
+    /// Check if the filesystem is consistent enough to be mounted read write
+    fn is_clean(&mut self) -> bool
+    {
+        true
+    }
+
+    /// Record whether the filesystem is mounted read write, it is only marked as no longer writable once everything
+    /// has been synced, so a filesystem can note on the disk whether it was unmounted cleanly
+    fn set_writable(&mut self, _writable: bool) -> FilesystemResult<()>
+    {
+        Ok(())
+    }
+
     /// Get the directory entry for the given inode
     fn get_stat(&mut self, inode: FilesystemIndex) -> FilesystemResult<FileStat>;
 
@@ -41,6 +74,49 @@ pub trait Filesystem
     /// Create a directory in the directory at the given inode
     fn create_directory(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>;
 
+    /// Create a symbolic link in the directory at the given inode which points to `target`
+    fn create_symlink(&mut self, _directory: FilesystemIndex, _name: String, _target: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        Err(FilesystemError::PermissionDenied)
+    }
+
+    /// Create a named pipe in the directory at the given inode
+    fn create_fifo(&mut self, _directory: FilesystemIndex, _name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        Err(FilesystemError::PermissionDenied)
+    }
+
+    /// Create a socket in the directory at the given inode
+    fn create_socket(&mut self, _directory: FilesystemIndex, _name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        Err(FilesystemError::PermissionDenied)
+    }
+
+    /// Read the target of the symbolic link at the given inode
+    fn read_link(&mut self, _inode: FilesystemIndex) -> FilesystemResult<String>
+    {
+        Err(FilesystemError::NotASymbolicLink)
+    }
+
+    /// Add an entry for an existing inode to the directory at the given inode
+    fn create_link(&mut self, _inode: FilesystemIndex, _directory: FilesystemIndex, _name: String) -> FilesystemResult<()>
+    {
+        Err(FilesystemError::PermissionDenied)
+    }
+
+    /// Change the metadata of an inode, only the permission bits of a new mode are used
+    fn set_attr(&mut self, _inode: FilesystemIndex, _attributes: SetAttributes) -> FilesystemResult<()>
+    {
+        Err(FilesystemError::PermissionDenied)
+    }
+
+    /// Move the entry `old_name` in the directory at `old_directory` to `new_name` in `new_directory`, replacing or
+    /// exchanging with any existing entry depending on the `RENAME_*` flags
+    fn rename(&mut self, _old_directory: FilesystemIndex, _old_name: String, _new_directory: FilesystemIndex, _new_name: String, _flags: usize) -> FilesystemResult<()>
+    {
+        Err(FilesystemError::PermissionDenied)
+    }
+
     /// Remove an inode at the given index from the given directory
     fn remove_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<()>;
 
@@ -59,19 +135,124 @@ pub trait Filesystem
     /// Write data to an inode
     fn write_inode(&mut self, inode: FilesystemIndex, data: &[u8]) -> FilesystemResult<()>;
 
-    /// Mount a filesystem at the given inode
-    fn mount_fs_at(&mut self, inode: FilesystemIndex, root: FilesystemIndex, name: String) -> FilesystemResult<()>;
-
     /// Open a filedescriptor for the given inode
     fn open_fd(&mut self, inode: FilesystemIndex, mode: usize) -> FilesystemResult<Box<dyn crate::process::descriptor::FileDescriptor>>;
 
     /// Execute an ioctl command on an inode
     fn exec_ioctl(&mut self, inode: FilesystemIndex, cmd: IOControlCommand) -> FilesystemResult<usize>;
 
+    /// Read a single page of the data stored in an inode into the buffer, returning the number of bytes read
+    fn read_inode_page(&mut self, inode: FilesystemIndex, page: usize, buffer: *mut u8) -> FilesystemResult<usize>
+    {
+        let data = self.read_inode(inode)?;
+        let start = (page * mem::PAGE_SIZE).min(data.len());
+        let end = (start + mem::PAGE_SIZE).min(data.len());
+
+        unsafe { core::ptr::copy(data[start..end].as_ptr(), buffer, end - start) };
+
+        Ok(end - start)
+    }
+
+    /// Start reading a page of an inode in the background, returning a token to wait on if the data is not ready
+    fn prefetch_inode_page(&mut self, _inode: FilesystemIndex, _page: usize) -> FilesystemResult<Option<crate::drivers::virtio::drivers::block::BlockRequestToken>>
+    {
+        Ok(None)
+    }
+
+    /// Get the memory holding a page of an inode, for filesystems which keep their data in memory. The page cache
+    /// uses the page in place of a copy, so writes through the cache land directly in the filesystem
+    fn inode_page_memory(&mut self, _inode: FilesystemIndex, _page: usize) -> FilesystemResult<Option<*mut u8>>
+    {
+        Ok(None)
+    }
+
+    /// Hand a page returned by `inode_page_memory` over to the page cache, which frees it once no process maps it,
+    /// the filesystem has to allocate a new page if it needs one again
+    fn disown_inode_page(&mut self, _inode: FilesystemIndex, _page: usize)
+    {
+        // Filesystems which do not lend out pages have nothing to give up
+    }
+
+    /// Make sure an inode can grow to `size` bytes, called before a write through the page cache extends a file so
+    /// running out of space fails the write rather than the later write back
+    fn reserve_size(&mut self, _inode: FilesystemIndex, _size: usize) -> FilesystemResult<()>
+    {
+        Ok(())
+    }
+
+    /// Write back pages of the data stored in an inode, resizing the inode to `size` bytes
+    fn write_inode_pages(&mut self, inode: FilesystemIndex, size: usize, pages: &[(usize, *const u8)]) -> FilesystemResult<()>
+    {
+        let mut data = self.read_inode(inode)?;
+        data.resize(size, 0);
+
+        for (page, ptr) in pages
+        {
+            let start = (page * mem::PAGE_SIZE).min(size);
+            let end = (start + mem::PAGE_SIZE).min(size);
+
+            unsafe { core::ptr::copy(*ptr, data[start..end].as_mut_ptr(), end - start) };
+        }
+
+        self.write_inode(inode, &data)
+    }
+
+    /// Find the first offset at or after `offset` which holds data, or which is in a hole if `hole` is set, the end
+    /// of the file counts as a hole
+    fn seek_data(&mut self, inode: FilesystemIndex, offset: usize, hole: bool) -> FilesystemResult<usize>
+    {
+        let size = self.get_stat(inode)?.size;
+
+        // Without holes, all of the file is data
+        if offset >= size
+        {
+            Err(FilesystemError::OffsetPastEnd)
+        }
+        else if hole
+        {
+            Ok(size)
+        }
+        else
+        {
+            Ok(offset)
+        }
+    }
+
+    /// Allocate or deallocate the storage for `length` bytes of an inode starting at `offset` according to the
+    /// `FALLOC_FL_*` flags in `mode`
+    fn allocate_range(&mut self, _inode: FilesystemIndex, _mode: usize, _offset: usize, _length: usize) -> FilesystemResult<()>
+    {
+        Err(FilesystemError::OperationNotSupported)
+    }
+
+    /// Get the value of an extended attribute of an inode
+    fn get_xattr(&mut self, _inode: FilesystemIndex, _name: &str) -> FilesystemResult<Vec<u8>>
+    {
+        Err(FilesystemError::AttributeNotFound)
+    }
+
+    /// Set the value of an extended attribute of an inode, creating the attribute if it does not exist
+    fn set_xattr(&mut self, _inode: FilesystemIndex, _name: &str, _value: &[u8]) -> FilesystemResult<()>
+    {
+        Err(FilesystemError::OperationNotSupported)
+    }
+
+    /// Get the names of every extended attribute of an inode
+    fn list_xattr(&mut self, _inode: FilesystemIndex) -> FilesystemResult<Vec<String>>
+    {
+        Ok(Vec::new())
+    }
+
+    /// Remove an extended attribute from an inode
+    fn remove_xattr(&mut self, _inode: FilesystemIndex, _name: &str) -> FilesystemResult<()>
+    {
+        Err(FilesystemError::OperationNotSupported)
+    }
+
     /// Assert is not a directory
     fn assert_not_directory(&mut self, inode: FilesystemIndex) -> FilesystemResult<()>
     {
-        if self.get_stat(inode)?.mode & 0x4000 > 0
+        if self.get_stat(inode)?.mode & S_IFMT == S_IFDIR
         {
             Err(FilesystemError::INodeIsDirectory)
         }
@@ -84,7 +265,7 @@ pub trait Filesystem
     /// Assert is a directory
     fn assert_directory(&mut self, inode: FilesystemIndex) -> FilesystemResult<()>
     {
-        if self.get_stat(inode)?.mode & 0x4000 > 0
+        if self.get_stat(inode)?.mode & S_IFMT == S_IFDIR
         {
             Ok(())
         }
@@ -100,9 +281,11 @@ pub trait Filesystem
         self.assert_not_directory(inode)?;
         self.assert_directory(directory)?;
 
+        self.remove_dir_entry(directory, name)?;
+
+        // The inode itself is only removed once no other names refer to it
         if self.decrement_links(inode)? == 0
         {
-            self.remove_dir_entry(directory, name)?;
             self.remove_inode(inode)?;
         }
 
@@ -0,0 +1,236 @@
+//! Initial RAM Filesystem
+//!
+//! An initramfs is a cpio archive in the newc format which is unpacked into a
+//! tmpfs used as the root filesystem, so the kernel can boot without a disk.
+//! It can be linked into the kernel image with the `builtin-initramfs`
+//! feature, or loaded into memory by the bootloader (QEMU's `-initrd`), in
+//! which case the device tree passed at boot records where it was placed. If
+//! both are present the loaded archive is unpacked over the builtin one.
+
+use crate::*;
+
+use super::fstrait::Filesystem;
+use super::structures::*;
+use super::vfs::FilesystemInterface;
+
+use alloc::collections::BTreeMap;
+
+use libutils::cpio::{CpioArchive, CpioEntry};
+use libutils::paths::OwnedPath;
+
+use mem::PAGE_SIZE;
+
+// Archive linked into the kernel image
+#[cfg(feature = "builtin-initramfs")]
+static BUILTIN_INITRAMFS: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/initramfs.cpio"));
+
+// Address range of the archive loaded by the bootloader
+static mut BOOT_INITRAMFS: Option<(usize, usize)> = None;
+
+/// Get the first page and number of pages covering an address range
+fn covering_pages(start: usize, end: usize) -> (usize, usize)
+{
+    let first = start & !(PAGE_SIZE - 1);
+
+    (first, (end - first + PAGE_SIZE - 1) / PAGE_SIZE)
+}
+
+/// Look for an initramfs loaded by the bootloader in the device tree, and reserve its pages so the allocator leaves
+/// it alone until it has been unpacked
+pub fn init_boot_initramfs(device_tree: usize)
+{
+    let tree = match unsafe { utils::fdt::DeviceTree::from_address(device_tree) }
+    {
+        Some(tree) => tree,
+        None => return
+    };
+
+    let start = tree.get_property("/chosen", "linux,initrd-start").and_then(utils::fdt::read_cells);
+    let end = tree.get_property("/chosen", "linux,initrd-end").and_then(utils::fdt::read_cells);
+
+    if let (Some(start), Some(end)) = (start, end)
+    {
+        if end <= start
+        {
+            return;
+        }
+
+        // The archive is only usable if it is on the kernel heap, which is all that is mapped once paging is enabled
+        let (first, count) = covering_pages(start, end);
+
+        if let Err(e) = mem::kpreserve(first, count)
+        {
+            kwarnln!("Ignoring initramfs at 0x{:x} - 0x{:x}: {:?}", start, end, e);
+            return;
+        }
+
+        kdebugln!(Initialization, "Found initramfs at 0x{:x} - 0x{:x}", start, end);
+
+        unsafe { BOOT_INITRAMFS = Some((start, end)) };
+    }
+}
+
+/// Get every initramfs archive available at boot, in the order they should be unpacked
+pub fn boot_archives() -> Vec<&'static [u8]>
+{
+    let mut archives = Vec::new();
+
+    #[cfg(feature = "builtin-initramfs")]
+    archives.push(BUILTIN_INITRAMFS);
+
+    if let Some((start, end)) = unsafe { BOOT_INITRAMFS }
+    {
+        archives.push(unsafe { core::slice::from_raw_parts(start as *const u8, end - start) });
+    }
+
+    archives
+}
+
+/// Give the memory holding the initramfs loaded by the bootloader back to the allocator, the slices returned by
+/// `boot_archives` must no longer be used
+pub fn release_boot_initramfs()
+{
+    if let Some((start, end)) = unsafe { BOOT_INITRAMFS.take() }
+    {
+        let (first, count) = covering_pages(start, end);
+
+        if let Err(e) = mem::kpfree(first, count)
+        {
+            kwarnln!("Unable to release initramfs memory: {:?}", e);
+        }
+    }
+}
+
+/// Find the directory at a path, creating it and any missing directories above it
+pub fn make_directories(vfs: &mut FilesystemInterface, path: &OwnedPath) -> FilesystemResult<FilesystemIndex>
+{
+    let mut directory = vfs.get_root_index()?;
+
+    for name in path.iter()
+    {
+        directory = match vfs.lookup(directory, name)
+        {
+            Ok(inode) => inode,
+            Err(FilesystemError::FileNotFound(_)) => vfs.create_directory(directory, name.to_string())?,
+            Err(e) => return Err(e)
+        };
+
+        vfs.assert_directory(directory)?;
+    }
+
+    Ok(directory)
+}
+
+/// Create the inode for an archive entry in a directory, returns None for entries which are skipped
+fn create_entry(vfs: &mut FilesystemInterface, directory: FilesystemIndex, name: &str, entry: &CpioEntry, links: &mut BTreeMap<u32, FilesystemIndex>) -> FilesystemResult<Option<FilesystemIndex>>
+{
+    let existing = match vfs.lookup(directory, name)
+    {
+        Ok(inode) => Some(inode),
+        Err(FilesystemError::FileNotFound(_)) => None,
+        Err(e) => return Err(e)
+    };
+
+    let kind = entry.mode as u16 & S_IFMT;
+
+    // Directories are merged with any already there, anything else replaces what was there before
+    if let Some(inode) = existing
+    {
+        if kind == S_IFDIR && vfs.assert_directory(inode).is_ok()
+        {
+            return Ok(Some(inode));
+        }
+
+        vfs.unlink_inode(inode, directory, name.to_string())?;
+    }
+
+    match kind
+    {
+        S_IFDIR => Ok(Some(vfs.create_directory(directory, name.to_string())?)),
+        S_IFREG =>
+        {
+            // Every name for a hard linked file shares an inode number, only the last one carries the data
+            if entry.links > 1
+            {
+                if let Some(inode) = links.get(&entry.inode).copied()
+                {
+                    vfs.create_link(inode, directory, name.to_string())?;
+
+                    if entry.data.len() > 0
+                    {
+                        vfs.write_inode(inode, entry.data)?;
+                    }
+
+                    return Ok(Some(inode));
+                }
+            }
+
+            let inode = vfs.create_file(directory, name.to_string())?;
+            vfs.write_inode(inode, entry.data)?;
+
+            if entry.links > 1
+            {
+                links.insert(entry.inode, inode);
+            }
+
+            Ok(Some(inode))
+        },
+        S_IFLNK =>
+        {
+            let target = core::str::from_utf8(entry.data).map_err(|_| FilesystemError::BadFilesystemFormat)?;
+
+            Ok(Some(vfs.create_symlink(directory, name.to_string(), target)?))
+        },
+        S_IFIFO => Ok(Some(vfs.create_fifo(directory, name.to_string())?)),
+        _ =>
+        {
+            kwarnln!("Skipping initramfs entry `{}` with unsupported mode {:o}", entry.name, entry.mode);
+
+            Ok(None)
+        }
+    }
+}
+
+/// Unpack a cpio archive into the root directory of the filesystem
+pub fn unpack_initramfs(vfs: &mut FilesystemInterface, archive: &[u8]) -> FilesystemResult<()>
+{
+    let mut links = BTreeMap::new();
+
+    for entry in CpioArchive::new(archive)
+    {
+        let entry = entry.map_err(|e|
+            {
+                kerrorln!("Corrupt initramfs archive: {:?}", e);
+                FilesystemError::BadFilesystemFormat
+            })?;
+
+        let path = OwnedPath::new(String::from("/") + entry.name.trim_start_matches("./"));
+
+        if path.iter().next().is_none()
+        {
+            continue;
+        }
+
+        let (parent, name) = path.split_last();
+        let directory = make_directories(vfs, &parent)?;
+
+        kdebugln!(Filesystem, "Unpacking initramfs entry `{}`", path);
+
+        if let Some(inode) = create_entry(vfs, directory, name, &entry, &mut links)?
+        {
+            let mode = if entry.mode as u16 & S_IFMT == S_IFLNK { None } else { Some(entry.mode as u16) };
+
+            vfs.set_attr(inode, SetAttributes
+            {
+                mode,
+                uid: Some(entry.uid as u16),
+                gid: Some(entry.gid as u16),
+                atime: Some(entry.mtime as usize),
+                mtime: Some(entry.mtime as usize),
+                ..Default::default()
+            })?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! Advisory file locks
+//!
+//! Tracks the `flock` and `fcntl` record locks held on every inode, keyed by
+//! the mount and inode so locks taken through different paths or descriptors
+//! to the same file see each other. The two kinds of lock never conflict with
+//! each other, matching Linux. `flock` locks belong to an open file
+//! description and cover the whole file, while record locks belong to a
+//! process and cover a range of bytes. Every release bumps a generation
+//! counter, which blocked processes watch to know when to try again.
+
+use crate::*;
+
+use super::structures::*;
+
+use alloc::collections::BTreeMap;
+
+/// Holder of a lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockOwner
+{
+    /// `flock` locks belong to an open file description, identified by its address
+    Description(usize),
+    /// Record locks belong to a process
+    Process(crate::process::PID)
+}
+
+impl LockOwner
+{
+    /// Check if the owner holds record locks rather than `flock` locks
+    pub fn is_record(&self) -> bool
+    {
+        match self
+        {
+            LockOwner::Description(_) => false,
+            LockOwner::Process(_) => true
+        }
+    }
+}
+
+/// A lock on the bytes from `start` up to but not including `end`, an end of `usize::MAX` extends to the end of the
+/// file however large it grows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileLock
+{
+    pub owner: LockOwner,
+    pub exclusive: bool,
+    pub start: usize,
+    pub end: usize
+}
+
+impl FileLock
+{
+    /// Check if the lock covers any of the bytes from `start` up to `end`
+    fn overlaps(&self, start: usize, end: usize) -> bool
+    {
+        self.start < end && start < self.end
+    }
+
+    /// Check if the lock prevents another from being taken
+    fn conflicts(&self, other: &FileLock) -> bool
+    {
+        self.owner != other.owner && self.owner.is_record() == other.owner.is_record() &&
+            self.overlaps(other.start, other.end) && (self.exclusive || other.exclusive)
+    }
+}
+
+/// Table of the locks held on every inode
+pub struct LockTable
+{
+    locks: BTreeMap<FilesystemIndex, Vec<FileLock>>,
+    generation: usize
+}
+
+impl LockTable
+{
+    /// Create a new, empty lock table
+    pub fn new() -> Self
+    {
+        Self
+        {
+            locks: BTreeMap::new(),
+            generation: 0
+        }
+    }
+
+    /// Get the generation of the table, which changes whenever a lock is released
+    pub fn generation(&self) -> usize
+    {
+        self.generation
+    }
+
+    /// Find a lock held by another owner which would stop `lock` from being taken on an inode
+    pub fn conflict(&self, inode: FilesystemIndex, lock: &FileLock) -> Option<FileLock>
+    {
+        self.locks.get(&inode)?.iter().find(|held| held.conflicts(lock)).copied()
+    }
+
+    /// Take a lock on an inode, replacing any locks the owner holds on the same bytes
+    pub fn lock(&mut self, inode: FilesystemIndex, lock: FileLock) -> FilesystemResult<()>
+    {
+        if self.conflict(inode, &lock).is_some()
+        {
+            return Err(FilesystemError::WouldBlock);
+        }
+
+        // Converting a lock releases the old one first, so downgrading lets waiting processes in
+        self.unlock(inode, lock.owner, lock.start, lock.end);
+        self.locks.entry(inode).or_insert_with(Vec::new).push(lock);
+
+        Ok(())
+    }
+
+    /// Release the locks an owner holds on the bytes of an inode from `start` up to `end`, splitting any lock which
+    /// extends past the range
+    pub fn unlock(&mut self, inode: FilesystemIndex, owner: LockOwner, start: usize, end: usize)
+    {
+        let locks = match self.locks.get_mut(&inode)
+        {
+            Some(locks) => locks,
+            None => return
+        };
+
+        let mut remaining = Vec::with_capacity(locks.len());
+        let mut released = false;
+
+        for lock in locks.drain(..)
+        {
+            if lock.owner != owner || !lock.overlaps(start, end)
+            {
+                remaining.push(lock);
+                continue;
+            }
+
+            if lock.start < start
+            {
+                remaining.push(FileLock { end: start, ..lock });
+            }
+
+            if lock.end > end
+            {
+                remaining.push(FileLock { start: end, ..lock });
+            }
+
+            released = true;
+        }
+
+        if remaining.is_empty()
+        {
+            self.locks.remove(&inode);
+        }
+        else
+        {
+            *locks = remaining;
+        }
+
+        if released
+        {
+            self.generation = self.generation.wrapping_add(1);
+        }
+    }
+
+    /// Release every lock an owner holds on an inode
+    pub fn release_inode(&mut self, inode: FilesystemIndex, owner: LockOwner)
+    {
+        self.unlock(inode, owner, 0, usize::MAX);
+    }
+
+    /// Release every lock an owner holds
+    pub fn release_owner(&mut self, owner: LockOwner)
+    {
+        let inodes = self.locks.iter()
+            .filter(|(_, locks)| locks.iter().any(|lock| lock.owner == owner))
+            .map(|(inode, _)| *inode)
+            .collect::<Vec<_>>();
+
+        for inode in inodes
+        {
+            self.release_inode(inode, owner);
+        }
+    }
+}
+
+/// Lock Table Test
+#[test_case]
+fn lock_table()
+{
+    let file = FilesystemIndex { mount_id: 0, inode: 2 };
+
+    let first = LockOwner::Process(1);
+    let second = LockOwner::Process(2);
+    let description = LockOwner::Description(0x1000);
+
+    let mut table = LockTable::new();
+
+    // Shared locks coexist, exclusive ones only conflict where they overlap
+    table.lock(file, FileLock { owner: first, exclusive: false, start: 0, end: 100 }).unwrap();
+    table.lock(file, FileLock { owner: second, exclusive: false, start: 50, end: 150 }).unwrap();
+    assert!(table.lock(file, FileLock { owner: second, exclusive: true, start: 0, end: 10 }).is_err());
+    table.lock(file, FileLock { owner: second, exclusive: true, start: 100, end: 150 }).unwrap();
+
+    // Record locks and flock locks are independent
+    table.lock(file, FileLock { owner: description, exclusive: true, start: 0, end: usize::MAX }).unwrap();
+
+    // Unlocking the middle of a lock splits it and wakes waiters
+    let generation = table.generation();
+    table.unlock(file, first, 20, 30);
+    assert_ne!(table.generation(), generation);
+    assert!(table.conflict(file, &FileLock { owner: second, exclusive: true, start: 20, end: 30 }).is_none());
+    assert_eq!(table.conflict(file, &FileLock { owner: second, exclusive: true, start: 0, end: 50 }).map(|lock| lock.end), Some(20));
+
+    // Releasing an owner drops all of its locks
+    table.release_owner(first);
+    table.release_owner(description);
+    assert!(table.conflict(file, &FileLock { owner: first, exclusive: true, start: 0, end: 50 }).is_none());
+    assert!(table.conflict(file, &FileLock { owner: first, exclusive: false, start: 0, end: 120 }).is_some());
+}
@@ -6,10 +6,21 @@ use crate::fs::structures::*;
 use super::structures::*;
 
 use alloc::vec;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Number of clean blocks held by the block cache, file data is cached by the page cache so this only needs to
+/// cover metadata
+const BLOCK_CACHE_SIZE: usize = 128;
+
+/// Maximum number of asynchronous block reads the filesystem will have outstanding at once
+const MAX_PENDING_READS: usize = 16;
 
 use crate::process::descriptor::*;
 
-use libutils::paths::PathBuffer;
+use crate::drivers::block::BlockOperation;
+use crate::drivers::virtio::drivers::block::BlockRequestToken;
+
+use libutils::paths::{OwnedPath, PathBuffer};
 
 use super::super::ioctl::*;
 
@@ -20,7 +31,44 @@ enum UpdateTimes
     Create
 }
 
-fn update_time(inode: &mut Minix3Inode, time: UpdateTimes)
+/// Stages of a sync, blocks are written one stage at a time so the disk stays consistent if a sync is interrupted
+///
+/// Allocations reach the bitmaps before anything refers to them, new zones are
+/// filled in before the inodes which point to them, freed inodes are only
+/// cleared once the directory entries naming them are gone, and frees only
+/// reach the bitmaps once nothing on the disk refers to the freed inodes and
+/// zones. An interrupted sync can leak inodes or zones, but never leaves a
+/// directory entry naming a cleared inode or anything in use marked as free.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WriteStage
+{
+    Allocate,
+    NewZones,
+    Inodes,
+    Zones,
+    ReleaseInodes,
+    Release
+}
+
+/// Raw access to the block device for the consistency checker, which runs before anything is cached
+struct CheckerDevice<'a>(&'a mut dyn crate::drivers::block::BlockDevice);
+
+impl libutils::minix3::Minix3Device for CheckerDevice<'_>
+{
+    /// Read `buffer.len()` bytes starting at the given offset, returning false on failure
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> bool
+    {
+        self.0.read_sectors(offset as u64 / 512, buffer.as_mut_ptr(), buffer.len() / 512).is_ok()
+    }
+
+    /// Write the data starting at the given offset, returning false on failure
+    fn write(&mut self, offset: usize, data: &[u8]) -> bool
+    {
+        self.0.write_sectors(offset as u64 / 512, data.as_ptr(), data.len() / 512).is_ok()
+    }
+}
+
+fn update_time(inode: &mut Minix3DiskInode, time: UpdateTimes)
 {
     let this_time = crate::drivers::rtc::driver::RealTimeClockDriver::get_driver().get_unix_timestamp_nano() / 1_000_000_000;
 
@@ -32,68 +80,294 @@ fn update_time(inode: &mut Minix3Inode, time: UpdateTimes)
     }
 }
 
+/// Check that a name fits in the name field of a directory entry
+fn check_entry_name(name: &str) -> FilesystemResult<()>
+{
+    if name.len() > MINIX3_NAME_LENGTH
+    {
+        Err(FilesystemError::NameTooLong)
+    }
+    else
+    {
+        Ok(())
+    }
+}
+
 /// Minix3 Filesystem Driver
 pub struct Minix3Filesystem
 {
-    block_driver: &'static mut crate::drivers::virtio::drivers::block::BlockDriver,
+    device: &'static mut dyn crate::drivers::block::BlockDevice,
     mount_id: Option<usize>,
     vfs: Option<&'static mut crate::fs::vfs::FilesystemInterface>,
-    superblock: Option<Minix3SuperBlock>,
-    cache: Vec<(usize, [u8; 1024])>,
-    rewritten: Vec<(usize, [u8; 1024])>,
-    mount_inodes: Vec<(FilesystemIndex, FilesystemIndex, String)>
+    superblock: Option<Minix3Superblock>,
+    block_size: usize,
+    zone_shift: usize,
+    cache: BTreeMap<usize, Vec<u8>>,
+    cache_order: VecDeque<usize>,
+    pending: BTreeMap<usize, (BlockRequestToken, Vec<u8>)>,
+    rewritten: Vec<(usize, Vec<u8>)>,
+    claimed_zones: BTreeSet<usize>,
+    released_zones: BTreeSet<usize>,
+    released_inodes: BTreeSet<usize>,
+    repair: bool,
+    clean: bool
 }
 
 impl Minix3Filesystem
 {
-    /// Initialize a new Minix3 Filesystem Interface
-    pub fn new(driver_id: usize) -> Self
+    /// Initialize a new Minix3 Filesystem Interface on the block device with the given index
+    pub fn new(device_id: usize) -> Self
     {
         Self
         {
-            block_driver: crate::drivers::virtio::get_block_driver(driver_id).unwrap(),
+            device: crate::drivers::block::get_block_device(device_id).unwrap(),
             mount_id: None,
             vfs: None,
             superblock: None,
-            cache: Vec::new(),
+            block_size: 1024,
+            zone_shift: 0,
+            cache: BTreeMap::new(),
+            cache_order: VecDeque::new(),
+            pending: BTreeMap::new(),
             rewritten: Vec::new(),
-            mount_inodes: Vec::new(),
+            claimed_zones: BTreeSet::new(),
+            released_zones: BTreeSet::new(),
+            released_inodes: BTreeSet::new(),
+            repair: false,
+            clean: false
+        }
+    }
+
+    /// Create a Minix3 filesystem from the options given to mount, `repair` fixes any inconsistencies found
+    pub fn from_options(device_id: usize, options: &str) -> FilesystemResult<Self>
+    {
+        let mut fs = Self::new(device_id);
+
+        for option in options.split(',').filter(|option| option.len() > 0)
+        {
+            match option
+            {
+                "repair" => fs.repair = true,
+                _ => return Err(FilesystemError::InvalidArgument)
+            }
+        }
+
+        Ok(fs)
+    }
+
+    /// Check the consistency of the filesystem, repairing it if the mount asked for repairs
+    fn check(&mut self) -> FilesystemResult<()>
+    {
+        let report = match libutils::minix3::check_minix3(&mut CheckerDevice(&mut *self.device), self.repair)
+        {
+            Ok(report) => report,
+            Err(e) =>
+            {
+                kerrorln!("Unable to check Minix3 filesystem: {:?}", e);
+                return Err(FilesystemError::BadFilesystemFormat);
+            }
+        };
+
+        for problem in &report.problems
+        {
+            kwarnln!("Minix3 filesystem: {:?}", problem);
+        }
+
+        if report.repaired
+        {
+            kwarnln!("Repaired {} problems in Minix3 filesystem", report.problems.len());
+
+            if let Err(e) = self.device.flush()
+            {
+                kerrorln!("Unable to flush block device: {:?}", e);
+            }
+        }
+
+        self.clean = report.is_clean();
+
+        Ok(())
+    }
+
+    /// Write new superblock flags to the disk, waiting for them to reach the disk before returning
+    fn write_flags(&mut self, flags: u16) -> FilesystemResult<()>
+    {
+        let mut superblock = self.superblock.ok_or(FilesystemError::FilesystemUninitialized)?;
+
+        // The superblock always starts at byte 1024, so it is the start of the third sector
+        let mut sector = [0u8; 512];
+        let sector_index = (libutils::minix3::MINIX3_SUPERBLOCK_OFFSET / 512) as u64;
+
+        if let Err(e) = self.device.read_sectors(sector_index, sector.as_mut_ptr(), 1)
+        {
+            kerrorln!("Unable to read Minix3 superblock: {:?}", e);
+            return Err(FilesystemError::IOError);
+        }
+
+        libutils::minix3::put_u16(&mut sector, libutils::minix3::MINIX3_FLAGS_OFFSET, flags);
+
+        if let Err(e) = self.device.write_sectors(sector_index, sector.as_ptr(), 1)
+        {
+            kerrorln!("Unable to write Minix3 superblock: {:?}", e);
+            return Err(FilesystemError::IOError);
+        }
+
+        if let Err(e) = self.device.flush()
+        {
+            kerrorln!("Unable to flush block device: {:?}", e);
+            return Err(FilesystemError::IOError);
+        }
+
+        superblock.flags = flags;
+        self.superblock = Some(superblock);
+
+        Ok(())
+    }
+
+    /// Add a clean block to the block cache, evicting the oldest block if the cache is full
+    fn cache_block(&mut self, index: usize, data: Vec<u8>)
+    {
+        if self.cache.insert(index, data).is_none()
+        {
+            self.cache_order.push_back(index);
+        }
+
+        while self.cache_order.len() > BLOCK_CACHE_SIZE
+        {
+            if let Some(old) = self.cache_order.pop_front()
+            {
+                self.cache.remove(&old);
+            }
+        }
+    }
+
+    /// Check if a block is held in memory
+    fn is_block_loaded(&self, index: usize) -> bool
+    {
+        self.cache.contains_key(&index) || self.rewritten.iter().any(|(idx, _)| *idx == index)
+    }
+
+    /// Wait for an outstanding read of the given block to finish, moving it into the block cache
+    fn settle_pending(&mut self, index: usize)
+    {
+        if let Some((token, buffer)) = self.pending.remove(&index)
+        {
+            // Reads are only left pending on devices with a request queue
+            let succeeded = self.device.request_queue().map(|mut queue| queue.finish(token)).unwrap_or(false);
+
+            if succeeded
+            {
+                self.cache_block(index, buffer);
+            }
+        }
+    }
+
+    /// Move any completed asynchronous reads into the block cache
+    fn collect_pending(&mut self)
+    {
+        let pending: Vec<(usize, BlockRequestToken)> = self.pending.iter().map(|(index, (token, _))| (*index, *token)).collect();
+
+        for (index, token) in pending
+        {
+            if self.device.request_queue().map(|mut queue| queue.is_complete(token)).unwrap_or(true)
+            {
+                self.settle_pending(index);
+            }
+        }
+    }
+
+    /// Start asynchronous reads of blocks into the block cache, returning a token to wait on if any of the blocks
+    /// are not yet loaded
+    fn prefetch_blocks(&mut self, indices: &[usize]) -> Option<BlockRequestToken>
+    {
+        let mut wait = None;
+        let mut buffers = Vec::new();
+
+        for index in indices
+        {
+            if let Some((token, _)) = self.pending.get(index)
+            {
+                wait.get_or_insert(*token);
+                continue;
+            }
+
+            if self.is_block_loaded(*index) || self.pending.len() + buffers.len() >= MAX_PENDING_READS
+            {
+                continue;
+            }
+
+            buffers.push((*index, vec![0u8; self.block_size]));
+        }
+
+        // Devices without a request queue are read synchronously as the blocks are needed
+        let block_size = self.block_size;
+        let mut queue = self.device.request_queue()?;
+
+        buffers.retain(|(index, buffer)|
+        {
+            let ptr = buffer.as_ptr() as *mut u8;
+            queue.queue(BlockOperation::Read, (*index * block_size) as u64, ptr, block_size as u32).is_ok()
+        });
+
+        // Adjacent blocks are merged into a single request by the block layer
+        for ((index, buffer), result) in buffers.into_iter().zip(queue.dispatch())
+        {
+            if let Ok(token) = result
+            {
+                wait.get_or_insert(token);
+                self.pending.insert(index, (token, buffer));
+            }
+        }
+
+        wait
+    }
+
+    /// Load a set of blocks into the block cache, reading any which are missing in as few requests as possible
+    fn load_blocks(&mut self, indices: &[usize])
+    {
+        self.prefetch_blocks(indices);
+
+        for index in indices
+        {
+            self.settle_pending(*index);
         }
     }
 
     /// Read a block as a buffer
-    fn read_block_to_buffer(&mut self, index: usize) -> [u8; 1024]
+    fn read_block_to_buffer(&mut self, index: usize) -> Vec<u8>
     {
+        self.settle_pending(index);
+
         for (idx, data) in &self.rewritten
         {
             if index == *idx
             {
-                return *data;
+                return data.clone();
             }
         }
         
-        for (idx, data) in &self.cache
+        if let Some(data) = self.cache.get(&index)
         {
-            if index == *idx
-            {
-                return *data;
-            }
+            return data.clone();
         }
 
-        let mut buffer = Box::new([0; 1024]);
-
-        let ptr = &mut *buffer as *mut [u8; 1024] as *mut u8;
+        let mut buffer = vec![0; self.block_size];
 
-        self.block_driver.sync_read(ptr, 1024, index as u64 * 1024);
+        if let Err(e) = self.device.read_sectors((index * self.block_sectors()) as u64, buffer.as_mut_ptr(), self.block_sectors())
+        {
+            kerrorln!("Unable to read block {}: {:?}", index, e);
+        }
 
-        self.cache.push((index, *buffer));
+        self.cache_block(index, buffer.clone());
 
-        *buffer
+        buffer
     }
 
     /// Edit the contents of a block
-    fn edit_block(&mut self, index: usize, new_data: [u8; 1024]) -> FilesystemResult<()>
+    fn edit_block(&mut self, index: usize, new_data: Vec<u8>) -> FilesystemResult<()>
     {
+        // Make sure an outstanding read cannot later replace the new contents
+        self.settle_pending(index);
+
         for (idx, data) in &mut self.rewritten
         {
             if index == *idx
@@ -111,6 +385,7 @@ impl Minix3Filesystem
     /// Edit the contents at a specific region in the block
     fn edit_block_region(&mut self, index: usize, start: usize, new_data: &[u8]) -> FilesystemResult<usize>
     {
+        let block_size = self.block_size;
         let mut i = start;
 
         let mut rewritten_index = 0;
@@ -124,7 +399,7 @@ impl Minix3Filesystem
                     data[i] = *v;
                     i += 1;
 
-                    if i == 1024 { break; }
+                    if i == block_size { break; }
                 }
 
                 return Ok(rewritten_index)
@@ -140,7 +415,7 @@ impl Minix3Filesystem
             prev_data[i] = *v;
             i += 1;
 
-            if i == 1024 { break; }
+            if i == block_size { break; }
         }
 
         self.rewritten.push((index, prev_data));
@@ -148,33 +423,73 @@ impl Minix3Filesystem
         Ok(self.rewritten.len() - 1)
     }
 
-    /// Read an inode
-    fn get_inode(&mut self, inode_number: usize) -> FilesystemResult<Minix3Inode>
+    /// Get the number of sectors in a block
+    fn block_sectors(&self) -> usize
     {
-        kdebugln!(Filesystem, "Opening inode {} on fs {:?}", inode_number, self.mount_id);
+        self.block_size / 512
+    }
 
-        if let Some(superblock) = self.superblock
-        {
-            // Conver the inode number to a block index
-            let block_index = (inode_number - 1) / 16 + 2 + superblock.imap_blocks as usize + superblock.zmap_blocks as usize;
+    /// Get the first block of a zone
+    fn zone_block(&self, zone: usize) -> usize
+    {
+        zone << self.zone_shift
+    }
+
+    /// Get the number of blocks in a zone
+    fn zone_blocks(&self) -> usize
+    {
+        1 << self.zone_shift
+    }
 
-            // Read the block into a buffer
-            let mut buffer = self.read_block_to_buffer(block_index);
+    /// Get the number of bytes in a zone
+    fn zone_size(&self) -> usize
+    {
+        self.block_size << self.zone_shift
+    }
+
+    /// Read the zone numbers held by an indirect zone, which are stored in its first block
+    fn read_zone_table(&mut self, zone: usize) -> Vec<u32>
+    {
+        self.read_block_to_buffer(self.zone_block(zone))
+            .chunks(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()
+    }
 
-            // Read the inode out of the buffer
-            let inode = unsafe { (&mut buffer as *mut [u8; 1024] as *mut Minix3Inode).add((inode_number - 1) % 16).read() };
+    /// Get the block holding an inode, along with the index of the inode within the block
+    fn inode_location(&self, inode_number: usize) -> FilesystemResult<(usize, usize)>
+    {
+        let superblock = self.superblock.ok_or(FilesystemError::FilesystemUninitialized)?;
 
-            // The buffer is freed implicitly after the return
-            Ok(inode)
-        }
-        else
+        if inode_number == 0 || inode_number > superblock.ninodes as usize
         {
-            Err(FilesystemError::FilesystemUninitialized)
+            return Err(FilesystemError::BadINode);
         }
+
+        let per_block = self.block_size / core::mem::size_of::<Minix3DiskInode>();
+
+        Ok(((inode_number - 1) / per_block + superblock.inode_table_start(), (inode_number - 1) % per_block))
+    }
+
+    /// Read an inode
+    fn get_inode(&mut self, inode_number: usize) -> FilesystemResult<Minix3DiskInode>
+    {
+        kdebugln!(Filesystem, "Opening inode {} on fs {:?}", inode_number, self.mount_id);
+
+        let (block_index, index) = self.inode_location(inode_number)?;
+
+        // Read the block into a buffer
+        let buffer = self.read_block_to_buffer(block_index);
+
+        // Read the inode out of the buffer
+        let inode = unsafe { (buffer.as_ptr() as *const Minix3DiskInode).add(index).read_unaligned() };
+
+        // The buffer is freed implicitly after the return
+        Ok(inode)
     }
 
     /// Get a mutable buffer into editable memory
-    fn get_mut_buffer(&mut self, block: usize) -> FilesystemResult<&mut [u8; 1024]>
+    fn get_mut_buffer(&mut self, block: usize) -> FilesystemResult<&mut [u8]>
     {
         let mut rewritten_index = 0;
 
@@ -194,130 +509,150 @@ impl Minix3Filesystem
             self.rewritten.push((block, buffer));
         }
 
-        Ok(&mut self.rewritten[rewritten_index].1)
+        Ok(self.rewritten[rewritten_index].1.as_mut_slice())
     }
 
     /// Edit an inode
-    fn get_mut_inode(&mut self, inode_number: usize) -> FilesystemResult<& mut Minix3Inode>
+    fn get_mut_inode(&mut self, inode_number: usize) -> FilesystemResult<& mut Minix3DiskInode>
     {
-        if let Some(superblock) = self.superblock
-        {
-            // Conver the inode number to a block index
-            let block_index = (inode_number - 1) / 16 + 2 + superblock.imap_blocks as usize + superblock.zmap_blocks as usize;
+        let (block_index, index) = self.inode_location(inode_number)?;
 
-            // Get a reference to that memory
-            let buffer_ref = self.get_mut_buffer(block_index)?;
+        // Get a reference to that memory
+        let buffer_ref = self.get_mut_buffer(block_index)?;
 
-            // Get the reference to the specific inode
-            let inode = unsafe { (buffer_ref as *mut [u8; 1024] as *mut Minix3Inode).add((inode_number - 1) % 16).as_mut().unwrap() };
+        // Get the reference to the specific inode
+        let inode = unsafe { (buffer_ref.as_mut_ptr() as *mut Minix3DiskInode).add(index).as_mut().unwrap() };
 
-            Ok(inode)
-        }
-        else
-        {
-            Err(FilesystemError::FilesystemUninitialized)
-        }
+        Ok(inode)
     }
 
-    /// Read from a possibly nested zone
-    fn read_zone(&mut self, zone: usize, level: usize, buffer: *mut u8, index: &mut usize, remaining: &mut usize, offset: &mut usize)
+    /// Read the data from an inode
+    fn read_from_inode(&mut self, inode: Minix3DiskInode) -> Vec<u8>
     {
-        // If no bytes are left to be read, terminate
-        if *remaining == 0
-        {
-            return;
-        }
+        let size = inode.size as usize;
+        let block_size = self.block_size;
+        let mut buffer = vec![0u8; size];
 
-        if level == 0
+        let block_count = (size + block_size - 1) / block_size;
+        let mut block = 0;
+
+        while block < block_count
         {
-            // Read the block to a buffer
-            let data = self.read_block_to_buffer(zone);
+            let end = (block + crate::drivers::virtio::drivers::block::BLOCK_MAX_SEGMENTS).min(block_count);
 
-            // Read byte by byte
-            for v in data.iter()
-            {
-                if *offset > 0
-                {
-                    *offset -= 1;
-                    continue;
-                }
+            // Look up the disk blocks for a run of blocks, then load all of them at once
+            let blocks: Vec<usize> = (block..end).map(|i| self.disk_block(&inode, i)).collect();
+            let allocated: Vec<usize> = blocks.iter().copied().filter(|block| *block != 0).collect();
 
-                unsafe { buffer.add(*index).write(*v) };
+            self.load_blocks(&allocated);
 
-                *index += 1;
-                *remaining -= 1;
+            for (i, disk_block) in blocks.iter().enumerate()
+            {
+                let offset = (block + i) * block_size;
+                let length = (size - offset).min(block_size);
 
-                if *remaining == 0
+                // Unallocated zones read as zeros
+                if *disk_block != 0
                 {
-                    break;
+                    let data = self.read_block_to_buffer(*disk_block);
+                    buffer[offset..offset + length].copy_from_slice(&data[..length]);
                 }
             }
-        }
-        else
-        {
-            // Read the block to a buffer
-            let data = unsafe { core::mem::transmute::<[u8; 1024], [u32; 256]>(self.read_block_to_buffer(zone)) };
 
-            // Read byte by byte
-            for v in data.iter()
-            {
-                // Skip entries which contain zero
-                if *v == 0
-                {
-                    continue;
-                }
+            block = end;
+        }
 
-                // Otherwise, use it as the zone to go to the next level down
-                self.read_zone(*v as usize, level - 1, buffer, index, remaining, offset);
+        buffer
+    }
 
-                // If we are done reading the file, break
-                if *remaining == 0
-                {
-                    break;
-                }
-            }
+    /// Get the disk block holding the given block of an inode, returns zero if the block is not allocated
+    fn disk_block(&mut self, inode: &Minix3DiskInode, block: usize) -> usize
+    {
+        match self.zone_for_index(inode, block >> self.zone_shift)
+        {
+            0 => 0,
+            zone => self.zone_block(zone) + (block & (self.zone_blocks() - 1))
         }
     }
 
-    /// Read the data from an inode
-    fn read_from_inode(&mut self, inode: Minix3Inode) -> Vec<u8>
+    /// Split a page of a file into the parts held by each block, as file offsets and lengths, stopping at the end of
+    /// the file
+    fn page_parts(&self, page: usize, size: usize) -> Vec<(usize, usize)>
     {
-        let mut remaining = inode.size as usize;
-        let mut buffer = vec![0u8; remaining];
-        let mut index = 0;
-        let mut offset = 0;
+        let step = self.block_size.min(mem::PAGE_SIZE);
 
-        for (i, zone) in inode.zones.iter().enumerate()
+        (0..mem::PAGE_SIZE / step)
+            .map(|i| page * mem::PAGE_SIZE + i * step)
+            .filter(|offset| *offset < size)
+            .map(|offset| (offset, (size - offset).min(step)))
+            .collect()
+    }
+
+    /// Get the zone holding the given zone of an inode, returns zero if the zone is not allocated
+    fn zone_for_index(&mut self, inode: &Minix3DiskInode, index: usize) -> usize
+    {
+        if index < 7
         {
-            if *zone == 0 {continue;}
-            
-            self.read_zone(*zone as usize, 
-                i.max(6) - 6, 
-                buffer.as_mut_ptr(), &mut index, &mut remaining, &mut offset);
+            return inode.zones[index] as usize;
         }
 
-        buffer
+        let per_table = self.block_size / 4;
+
+        let mut index = index - 7;
+        let mut per_zone = per_table;
+
+        for level in 1..=3
+        {
+            if index < per_zone
+            {
+                let mut zone = inode.zones[6 + level] as usize;
+
+                // Walk down the indirect zones
+                for depth in (0..level).rev()
+                {
+                    if zone == 0
+                    {
+                        return 0;
+                    }
+
+                    let table = self.read_zone_table(zone);
+                    zone = table[(index / per_table.pow(depth as u32)) % per_table] as usize;
+                }
+
+                return zone;
+            }
+
+            index -= per_zone;
+            per_zone *= per_table;
+        }
+
+        0
     }
 
     /// Add a directory entry at the given inode
     fn add_directory_entry_raw(&mut self, inode: usize, entry: Minix3DirEntry) -> FilesystemResult<()>
     {
+        let per_block = self.block_size / core::mem::size_of::<Minix3DirEntry>();
+
         // Get a mutable reference to the inode
         let inode_ref = self.get_mut_inode(inode)?;
 
         update_time(inode_ref, UpdateTimes::Modify);
 
         // Get the original size
-        let orig_entry_count = inode_ref.size / 64;
+        let orig_entry_count = inode_ref.size as usize / 64;
 
         // Increment the size
         inode_ref.size += 64;
 
-        let zone_index = orig_entry_count / 16;
+        let block = orig_entry_count / per_block;
+        let zone_index = block >> self.zone_shift;
 
         if zone_index < 7
         {
-            let next = if inode_ref.zones[zone_index as usize] == 0
+            let inode_ref = self.get_mut_inode(inode)?;
+
+            let next = if inode_ref.zones[zone_index] == 0
             {
                 let next = self.next_free_zone()?;
                 self.claim_zone(next)?;
@@ -335,15 +670,16 @@ impl Minix3Filesystem
 
             if let Some(next) = next
             {
-                inode_ref.zones[zone_index as usize] = next;
+                inode_ref.zones[zone_index] = next;
             }
 
-            // Get the zone
-            let zone = inode_ref.zones[zone_index as usize];
+            // Get the block within the zone
+            let zone = inode_ref.zones[zone_index] as usize;
+            let disk_block = self.zone_block(zone) + (block & (self.zone_blocks() - 1));
 
-            let buffer = unsafe { core::mem::transmute::<&mut[u8; 1024], &mut[Minix3DirEntry; 16]>(self.get_mut_buffer(zone as usize)?) };
+            let buffer = self.get_mut_buffer(disk_block)?;
 
-            buffer[orig_entry_count as usize % 16] = entry;
+            unsafe { (buffer.as_mut_ptr() as *mut Minix3DirEntry).add(orig_entry_count % per_block).write_unaligned(entry) };
         }
         else
         {
@@ -391,54 +727,222 @@ impl Minix3Filesystem
             }
         }
 
+        if !move_back
+        {
+            return Err(FilesystemError::FileNotFound(name));
+        }
+
         self.write_to_file(inode, unsafe { core::slice::from_raw_parts(buffer.as_ptr(), (original_count as usize - 1) * 64) })?;
 
         Ok(())
     }
 
-    /// Add a directory entry from the inode and name to the given inode
-    fn add_directory_entry(&mut self, dest: usize, inode: usize, name: &str) -> FilesystemResult<()>
+    /// Find the inode a name refers to in the directory at the given inode
+    fn find_directory_entry(&mut self, inode: usize, name: &str) -> FilesystemResult<Option<usize>>
     {
-        let mut ent = Minix3DirEntry
+        let inode_data = self.get_inode(inode)?;
+
+        if inode_data.mode & S_IFMT != S_IFDIR
         {
-            inode: inode as u32,
-            name: [0; 60],
-        };
+            return Err(FilesystemError::INodeIsNotADirectory);
+        }
 
-        for (i, c) in name.chars().enumerate()
+        let data = self.read_from_inode(inode_data);
+        let array = unsafe { core::mem::transmute::<&[u8], &[Minix3DirEntry]>(data.as_slice()) };
+
+        for entry in &array[..inode_data.size as usize / 64]
         {
-            ent.name[i] = c as u8;
+            if entry.to_string() == name
+            {
+                return Ok(Some(entry.inode as usize));
+            }
         }
 
-        self.add_directory_entry_raw(dest, ent)
+        Ok(None)
     }
 
-    /// Get the next available free inode
-    fn next_free_inode(&mut self) -> FilesystemResult<usize>
+    /// Point an existing entry in the directory at the given inode to a different inode
+    fn set_directory_entry(&mut self, inode: usize, name: &str, target: usize) -> FilesystemResult<()>
     {
-        if let Some(superblock) = self.superblock
-        {
-            let mut i = 0;
+        let inode_data = self.get_inode(inode)?;
 
-            let num_blocks = superblock.imap_blocks;
+        let mut buffer = self.read_from_inode(inode_data);
+        let array = unsafe { core::mem::transmute::<&mut [u8], &mut [Minix3DirEntry]>(buffer.as_mut()) };
 
-            for b in 0..num_blocks
+        for entry in &mut array[..inode_data.size as usize / 64]
+        {
+            if entry.to_string() == name
             {
-                let buffer = self.read_block_to_buffer(2 + b as usize);
+                entry.inode = target as u32;
 
-                for v in &buffer
-                {
-                    if *v == 0xFF
-                    {
-                        i += 8;
-                        continue;
-                    }
+                return self.write_to_file(inode, &buffer);
+            }
+        }
+
+        Err(FilesystemError::FileNotFound(name.to_string()))
+    }
+
+    /// Check if the given inode is a directory
+    fn is_directory(&mut self, inode: usize) -> FilesystemResult<bool>
+    {
+        Ok(self.get_inode(inode)?.mode & S_IFMT == S_IFDIR)
+    }
+
+    /// Add `delta` to the link count of an inode, returning the new count
+    fn adjust_links(&mut self, inode: usize, delta: isize) -> FilesystemResult<usize>
+    {
+        let inode_ref = self.get_mut_inode(inode)?;
+
+        inode_ref.nlinks = (inode_ref.nlinks as isize + delta).max(0) as u16;
+        update_time(inode_ref, UpdateTimes::Create);
+
+        Ok(inode_ref.nlinks as usize)
+    }
+
+    /// Move a directory entry to a new name, possibly in another directory, replacing or exchanging with the target
+    fn rename_entry(&mut self, old_dir: usize, old_name: &str, new_dir: usize, new_name: &str, flags: usize) -> FilesystemResult<()>
+    {
+        check_entry_name(new_name)?;
+
+        let source = self.find_directory_entry(old_dir, old_name)?.ok_or_else(|| FilesystemError::FileNotFound(old_name.to_string()))?;
+        let target = self.find_directory_entry(new_dir, new_name)?;
+
+        if target == Some(source)
+        {
+            return Ok(());
+        }
+
+        let source_is_dir = self.is_directory(source)?;
+
+        if flags & RENAME_EXCHANGE > 0
+        {
+            let target = target.ok_or_else(|| FilesystemError::FileNotFound(new_name.to_string()))?;
+            let target_is_dir = self.is_directory(target)?;
+
+            self.set_directory_entry(old_dir, old_name, target)?;
+            self.set_directory_entry(new_dir, new_name, source)?;
+
+            // Each directory's parent entry and the link it adds to its parent follow it
+            if old_dir != new_dir
+            {
+                if source_is_dir
+                {
+                    self.set_directory_entry(source, "..", new_dir)?;
+                    self.adjust_links(old_dir, -1)?;
+                    self.adjust_links(new_dir, 1)?;
+                }
+
+                if target_is_dir
+                {
+                    self.set_directory_entry(target, "..", old_dir)?;
+                    self.adjust_links(new_dir, -1)?;
+                    self.adjust_links(old_dir, 1)?;
+                }
+            }
+
+            update_time(self.get_mut_inode(target)?, UpdateTimes::Create);
+            update_time(self.get_mut_inode(source)?, UpdateTimes::Create);
+
+            return Ok(());
+        }
+
+        if let Some(target) = target
+        {
+            if flags & RENAME_NOREPLACE > 0
+            {
+                return Err(FilesystemError::FileExists);
+            }
+
+            let target_is_dir = self.is_directory(target)?;
+
+            if source_is_dir && !target_is_dir
+            {
+                return Err(FilesystemError::INodeIsNotADirectory);
+            }
+            else if !source_is_dir && target_is_dir
+            {
+                return Err(FilesystemError::INodeIsDirectory);
+            }
+            else if target_is_dir && self.get_inode(target)?.size as usize / 64 > 2
+            {
+                return Err(FilesystemError::DirectoryNotEmpty);
+            }
+
+            self.set_directory_entry(new_dir, new_name, source)?;
+
+            // A replaced directory also takes its parent entry's link away from the new directory
+            if target_is_dir
+            {
+                self.adjust_links(new_dir, -1)?;
+                self.delete_inode(target)?;
+            }
+            else if self.adjust_links(target, -1)? == 0
+            {
+                self.delete_inode(target)?;
+            }
+        }
+        else
+        {
+            self.add_directory_entry(new_dir, source, new_name)?;
+        }
+
+        self.remove_directory_entry(old_dir, old_name.to_string())?;
+
+        if source_is_dir && old_dir != new_dir
+        {
+            self.set_directory_entry(source, "..", new_dir)?;
+            self.adjust_links(old_dir, -1)?;
+            self.adjust_links(new_dir, 1)?;
+        }
+
+        update_time(self.get_mut_inode(source)?, UpdateTimes::Create);
+
+        Ok(())
+    }
+
+    /// Add a directory entry from the inode and name to the given inode
+    fn add_directory_entry(&mut self, dest: usize, inode: usize, name: &str) -> FilesystemResult<()>
+    {
+        check_entry_name(name)?;
+
+        let mut ent = Minix3DirEntry
+        {
+            inode: inode as u32,
+            name: [0; MINIX3_NAME_LENGTH],
+        };
+
+        ent.name[..name.len()].copy_from_slice(name.as_bytes());
+
+        self.add_directory_entry_raw(dest, ent)
+    }
+
+    /// Get the next available free inode
+    fn next_free_inode(&mut self) -> FilesystemResult<usize>
+    {
+        if let Some(superblock) = self.superblock
+        {
+            let mut i = 0;
+
+            let num_blocks = superblock.imap_blocks;
+
+            for b in 0..num_blocks
+            {
+                let buffer = self.read_block_to_buffer(superblock.imap_start() + b as usize);
+
+                for v in &buffer
+                {
+                    if *v == 0xFF
+                    {
+                        i += 8;
+                        continue;
+                    }
 
                     let mut walker = 0x01;
 
                     while walker > 0
                     {
-                        if *v & walker == 0
+                        // Inodes freed since the last sync are not reused until the free reaches the disk
+                        if *v & walker == 0 && !self.released_inodes.contains(&i)
                         {
                             return Ok(i);
                         }
@@ -457,14 +961,26 @@ impl Minix3Filesystem
         }
     }
 
-    /// Claim an inode
-    fn claim_inode(&mut self, mut inode: usize) -> FilesystemResult<()>
+    /// Get the block, byte and bit of the inode bitmap which track an inode
+    fn inode_bit(&self, inode: usize) -> (usize, usize, usize)
+    {
+        (2 + inode / (8 * self.block_size), (inode / 8) % self.block_size, inode % 8)
+    }
+
+    /// Get the block, byte and bit of the zone bitmap which track a zone, bit zero is reserved and bit one tracks
+    /// the first data zone
+    fn zone_bit(&self, zone: usize) -> FilesystemResult<(usize, usize, usize)>
     {
-        inode -= 0;
+        let superblock = self.superblock.ok_or(FilesystemError::FilesystemUninitialized)?;
+        let bit = superblock.zone_bit(zone).ok_or(FilesystemError::UncleanFilesystem)?;
+
+        Ok((superblock.zmap_start() + bit / (8 * self.block_size), (bit / 8) % self.block_size, bit % 8))
+    }
 
-        let block = 2 + inode / (8 * 1024);
-        let byte = (inode / 8) % 1024;
-        let bit = inode % 8;
+    /// Claim an inode
+    fn claim_inode(&mut self, inode: usize) -> FilesystemResult<()>
+    {
+        let (block, byte, bit) = self.inode_bit(inode);
 
         let buffer = self.get_mut_buffer(block)?;
 
@@ -474,18 +990,16 @@ impl Minix3Filesystem
     }
 
     /// Free an inode
-    fn free_inode(&mut self, mut inode: usize) -> FilesystemResult<()>
+    fn free_inode(&mut self, inode: usize) -> FilesystemResult<()>
     {
-        inode -= 0;
-
-        let block = 2 + inode / (8 * 1024);
-        let byte = (inode / 8) % 1024;
-        let bit = inode % 8;
+        let (block, byte, bit) = self.inode_bit(inode);
 
         let buffer = self.get_mut_buffer(block)?;
 
         buffer[byte] &= !(0x01 << bit);
 
+        self.released_inodes.insert(inode);
+
         Ok(())
     }
 
@@ -500,7 +1014,7 @@ impl Minix3Filesystem
 
             for b in 0..num_blocks
             {
-                let buffer = self.read_block_to_buffer(2 + b as usize + superblock.imap_blocks as usize);
+                let buffer = self.read_block_to_buffer(superblock.zmap_start() + b as usize);
 
                 for v in &buffer
                 {
@@ -514,9 +1028,17 @@ impl Minix3Filesystem
 
                     while walker > 0
                     {
-                        if *v & walker == 0 && i as u16 >= superblock.first_data_zone + 500
+                        // Bit zero is reserved, and bit one tracks the first data zone
+                        let zone = i + superblock.first_data_zone as usize - 1;
+
+                        if zone >= superblock.zones as usize
                         {
-                            return Ok(i);
+                            return Err(FilesystemError::OutOfSpace);
+                        }
+
+                        if *v & walker == 0 && i > 0 && !self.released_zones.contains(&zone)
+                        {
+                            return Ok(zone);
                         }
 
                         i += 1;
@@ -536,43 +1058,183 @@ impl Minix3Filesystem
     /// Claim a zone
     fn claim_zone(&mut self, zone: usize) -> FilesystemResult<()>
     {
-        if let Some(superblock) = self.superblock
-        {
-            let block = 2 + superblock.imap_blocks as usize + zone / (8 * 1024);
-            let byte = (zone / 8) % 1024;
-            let bit = zone % 8;
+        let (block, byte, bit) = self.zone_bit(zone)?;
+
+        let buffer = self.get_mut_buffer(block)?;
 
-            let buffer = self.get_mut_buffer(block)?;
+        buffer[byte] |= 0x01 << bit;
 
-            buffer[byte] |= 0x01 << bit;
+        // Nothing on the disk refers to the zone yet, so its contents can be written early
+        self.claimed_zones.insert(zone);
 
-            Ok(())
+        Ok(())
+    }
+
+    /// Free a zone
+    fn free_zone(&mut self, zone: usize) -> FilesystemResult<()>
+    {
+        let (block, byte, bit) = self.zone_bit(zone)?;
+
+        let buffer = self.get_mut_buffer(block)?;
+
+        buffer[byte] &= !(0x01 << bit);
+
+        self.released_zones.insert(zone);
+
+        Ok(())
+    }
+
+    /// Get the stage of a sync in which a block is written
+    fn write_stage(&self, block: usize) -> WriteStage
+    {
+        let superblock = match self.superblock
+        {
+            Some(superblock) => superblock,
+            None => return WriteStage::Zones
+        };
+
+        if block >= superblock.imap_start() && block < superblock.inode_table_start()
+        {
+            WriteStage::Allocate
+        }
+        else if block < self.zone_block(superblock.first_data_zone as usize)
+        {
+            WriteStage::Inodes
+        }
+        else if self.claimed_zones.contains(&(block >> self.zone_shift))
+        {
+            WriteStage::NewZones
         }
         else
         {
-            Err(FilesystemError::FilesystemUninitialized)
+            WriteStage::Zones
         }
     }
 
-    /// Free a zone
-    fn free_zone(&mut self, zone: usize) -> FilesystemResult<()>
+    /// Get the contents of a bitmap block with the inodes and zones freed since the last sync still marked as used
+    fn withhold_frees(&self, block: usize, mut data: Vec<u8>) -> FilesystemResult<Vec<u8>>
     {
-        if let Some(superblock) = self.superblock
+        for inode in &self.released_inodes
         {
-            let block = 2 + superblock.imap_blocks as usize + zone / (8 * 1024);
-            let byte = (zone / 8) % 1024;
-            let bit = zone % 8;
+            let (bit_block, byte, bit) = self.inode_bit(*inode);
 
-            let buffer = self.get_mut_buffer(block)?;
+            if bit_block == block
+            {
+                data[byte] |= 0x01 << bit;
+            }
+        }
 
-            buffer[byte] &= !(0x01 << bit);
+        for zone in &self.released_zones
+        {
+            let (bit_block, byte, bit) = self.zone_bit(*zone)?;
 
-            Ok(())
+            if bit_block == block
+            {
+                data[byte] |= 0x01 << bit;
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Get the contents of an inode table block with the inodes freed since the last sync left as they are on the
+    /// disk
+    fn withhold_released_inodes(&mut self, block: usize, mut data: Vec<u8>) -> FilesystemResult<Vec<u8>>
+    {
+        let inode_size = core::mem::size_of::<Minix3DiskInode>();
+        let mut on_disk = None;
+
+        for inode in self.released_inodes.clone()
+        {
+            let (inode_block, index) = self.inode_location(inode)?;
+
+            if inode_block == block
+            {
+                // The block is no longer in the rewritten list, so this is the copy on the disk
+                let old: &Vec<u8> = on_disk.get_or_insert_with(|| self.read_block_to_buffer(block));
+                let range = index * inode_size..(index + 1) * inode_size;
+
+                data[range.clone()].copy_from_slice(&old[range]);
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Write blocks to the disk, waiting for them to reach the disk before returning
+    fn write_blocks(&mut self, blocks: &[(usize, Vec<u8>)]) -> FilesystemResult<()>
+    {
+        if blocks.is_empty()
+        {
+            return Ok(());
+        }
+
+        let mut failed = false;
+
+        let block_size = self.block_size;
+        let sectors = self.block_sectors();
+
+        if let Some(mut queue) = self.device.request_queue()
+        {
+            for (block, data) in blocks
+            {
+                kdebugln!(Filesystem, "Writing to Block {}", block);
+
+                let ptr = data.as_ptr() as *mut u8;
+
+                if let Err(e) = queue.queue(BlockOperation::Write, (block_size * *block) as u64, ptr, block_size as u32)
+                {
+                    kerrorln!("Unable to write block {}: {:?}", block, e);
+                    failed = true;
+                }
+            }
+
+            // Adjacent blocks are merged into a single request by the block layer, every request is waited on so
+            // none of them still refers to the buffers once this returns
+            for result in queue.dispatch()
+            {
+                match result
+                {
+                    Ok(token) => if !queue.finish(token)
+                    {
+                        kerrorln!("Minix3 block write failed");
+                        failed = true;
+                    },
+                    Err(e) =>
+                    {
+                        kerrorln!("Unable to dispatch Minix3 block write: {:?}", e);
+                        failed = true;
+                    }
+                }
+            }
         }
         else
         {
-            Err(FilesystemError::FilesystemUninitialized)
+            for (block, data) in blocks
+            {
+                kdebugln!(Filesystem, "Writing to Block {}", block);
+
+                if let Err(e) = self.device.write_sectors((*block * sectors) as u64, data.as_ptr(), sectors)
+                {
+                    kerrorln!("Unable to write block {}: {:?}", block, e);
+                    return Err(FilesystemError::IOError);
+                }
+            }
+        }
+
+        if failed
+        {
+            return Err(FilesystemError::IOError);
         }
+
+        // The next stage must not reach the disk before this one
+        if let Err(e) = self.device.flush()
+        {
+            kerrorln!("Unable to flush block device: {:?}", e);
+            return Err(FilesystemError::IOError);
+        }
+
+        Ok(())
     }
 
     /// Recursive zone allocation
@@ -588,13 +1250,15 @@ impl Minix3Filesystem
 
         if level > 0
         {
-            let buffer = self.get_mut_buffer(zone)?;
-            let zones = unsafe { core::mem::transmute::<&mut [u8; 1024], &mut [u32; 256]>(buffer) };
+            let mut table = vec![0u8; self.block_size];
 
-            for z in zones
+            for slot in table.chunks_mut(4)
             {
-                *z = self.recursive_zone_alloc(level - 1, remaining)? as u32;
+                let next = self.recursive_zone_alloc(level - 1, remaining)? as u32;
+                slot.copy_from_slice(&next.to_le_bytes());
             }
+
+            self.edit_block(self.zone_block(zone), table)?;
         }
         else
         {
@@ -605,7 +1269,7 @@ impl Minix3Filesystem
     }
 
     /// Allocate zones
-    fn allocate_zones(&mut self, inode: &mut Minix3Inode, mut count: usize) -> FilesystemResult<()>
+    fn allocate_zones(&mut self, inode: &mut Minix3DiskInode, mut count: usize) -> FilesystemResult<()>
     {
         // TODO: Make this acknowledge any previously allocated zones, right
         // now, it assumes zones are free which means there will be a memory
@@ -635,11 +1299,28 @@ impl Minix3Filesystem
 
         if level == 0
         {
-            for byte in self.get_mut_buffer(zone)?.iter_mut()
+            let block_size = self.block_size;
+            let first = self.zone_block(zone);
+
+            for block in first..first + self.zone_blocks()
             {
-                *byte = data[*index];
+                let length = (data.len() - *index).min(block_size);
+
+                // Blocks which are entirely overwritten do not need to be read first
+                let contents = if length == block_size
+                {
+                    data[*index..*index + length].to_vec()
+                }
+                else
+                {
+                    let mut contents = self.read_block_to_buffer(block);
+                    contents[..length].copy_from_slice(&data[*index..*index + length]);
+                    contents
+                };
+
+                self.edit_block(block, contents)?;
 
-                *index += 1;
+                *index += length;
 
                 if *index >= data.len()
                 {
@@ -649,9 +1330,9 @@ impl Minix3Filesystem
         }
         else
         {
-            for slot in unsafe { core::mem::transmute::<[u8; 1024], [u32; 256]>(self.read_block_to_buffer(zone)) }.iter()
+            for slot in self.read_zone_table(zone)
             {
-                self.recursive_copy_to_zones(*slot as usize, level - 1, data, index)?;
+                self.recursive_copy_to_zones(slot as usize, level - 1, data, index)?;
 
                 if *index >= data.len()
                 {
@@ -664,7 +1345,7 @@ impl Minix3Filesystem
     }
 
     /// Copy data to zones
-    fn copy_to_zones(&mut self, inode: &mut Minix3Inode, data: &[u8]) -> FilesystemResult<()>
+    fn copy_to_zones(&mut self, inode: &mut Minix3DiskInode, data: &[u8]) -> FilesystemResult<()>
     {
         let mut index = 0;
 
@@ -686,73 +1367,336 @@ impl Minix3Filesystem
         Ok(())
     }
 
-    /// Recursive Free Zones
-    fn recursive_free_zones(&mut self, zone: usize, level: usize) -> FilesystemResult<()>
+    /// Get the top level zone pointers of an inode, as the zone, its level of indirection and the index within the
+    /// file of the first zone below it
+    fn zone_roots(&self, inode: &Minix3DiskInode) -> [(usize, usize, usize); 10]
     {
-        if level == 0
-        {
-            self.free_zone(zone)?;
-        }
-        else
-        {
-            let current = self.read_block_to_buffer(zone);
-            let zone_numbers = unsafe { core::mem::transmute::<[u8; 1024], [u32; 256]>(current) };
+        let per_table = self.block_size / 4;
 
-            for zone in &zone_numbers
-            {
-                if *zone == 0
-                {
-                    break;
-                }
+        let mut roots = [(0, 0, 0); 10];
+        let mut first = 0;
 
-                self.recursive_free_zones(*zone as usize, level - 1)?;
-            }
+        for (i, root) in roots.iter_mut().enumerate()
+        {
+            let level = i.max(6) - 6;
+
+            *root = (inode.zones[i] as usize, level, first);
+            first += per_table.pow(level as u32);
         }
 
-        Ok(())
+        roots
     }
 
-    /// Free zones
-    fn free_zones(&mut self, inode: &mut Minix3Inode) -> FilesystemResult<()>
+    /// Allocate a zone filled with zeros, so any part of it which is never written reads as zeros
+    fn new_zone(&mut self) -> FilesystemResult<usize>
     {
-        for (i, zone) in inode.zones.iter_mut().enumerate()
-        {
-            if *zone == 0
-            {
-                break;
-            }
+        let zone = self.next_free_zone()?;
+        self.claim_zone(zone)?;
 
-            self.recursive_free_zones(*zone as usize, i.max(6) - 6)?;
-            *zone = 0;
+        for block in self.zone_block(zone)..self.zone_block(zone) + self.zone_blocks()
+        {
+            self.edit_block(block, vec![0; self.block_size])?;
         }
 
-        Ok(())
+        Ok(zone)
     }
 
-    /// Write data to a file
-    fn write_to_file(&mut self, inode_number: usize, data: &[u8]) -> FilesystemResult<()>
+    /// Get the zone holding the given zone of an inode, allocating it along with any missing indirect zones leading
+    /// to it if it is a hole
+    fn allocate_zone_for_index(&mut self, inode: &mut Minix3DiskInode, index: usize) -> FilesystemResult<usize>
     {
-        let mut inode = self.get_inode(inode_number)?;
+        let per_table = self.block_size / 4;
 
-        // TODO: This is not as efficent as I would like it to be, it currently
-        // will free and then reallocate zones
-        self.free_zones(&mut inode)?;
-        self.allocate_zones(&mut inode, (data.len() + 1023) / 1024)?;
-        self.copy_to_zones(&mut inode, data)?;
+        let (slot, &(_, level, first)) = self.zone_roots(inode).iter().enumerate()
+            .filter(|(_, (_, _, first))| *first <= index)
+            .last()
+            .unwrap();
 
-        inode.size = data.len() as u32;
+        if index - first >= per_table.pow(level as u32)
+        {
+            return Err(FilesystemError::FileTooLarge);
+        }
 
-        update_time(&mut inode, UpdateTimes::Modify);
+        if inode.zones[slot] == 0
+        {
+            inode.zones[slot] = self.new_zone()? as u32;
+        }
 
-        *(self.get_mut_inode(inode_number)?) = inode;
+        let mut zone = inode.zones[slot] as usize;
 
-        Ok(())
-    }
+        // Walk down the indirect zones, filling in any holes on the way
+        for depth in (0..level).rev()
+        {
+            let entry = ((index - first) / per_table.pow(depth as u32)) % per_table;
+            let next = self.read_zone_table(zone)[entry] as usize;
 
-    /// Remove an inode and the blocks associated with it
-    fn delete_inode(&mut self, inode_number: usize) -> FilesystemResult<()>
-    {
-        let mut inode = self.get_inode(inode_number)?;
+            zone = if next == 0
+            {
+                let new = self.new_zone()?;
+                self.edit_block_region(self.zone_block(zone), entry * 4, &(new as u32).to_le_bytes())?;
+
+                new
+            }
+            else
+            {
+                next
+            };
+        }
+
+        Ok(zone)
+    }
+
+    /// Search below a zone pointer for the first zone of the file at or after `from` which is allocated, or which is
+    /// a hole if `allocated` is false
+    fn recursive_find_zone(&mut self, zone: usize, level: usize, first: usize, from: usize, allocated: bool) -> Option<usize>
+    {
+        let per_table = self.block_size / 4;
+        let span = per_table.pow(level as u32);
+
+        if first + span <= from
+        {
+            return None;
+        }
+
+        if zone == 0
+        {
+            return if allocated { None } else { Some(first.max(from)) };
+        }
+
+        if level == 0
+        {
+            return if allocated { Some(first) } else { None };
+        }
+
+        for (i, child) in self.read_zone_table(zone).into_iter().enumerate()
+        {
+            if let Some(index) = self.recursive_find_zone(child as usize, level - 1, first + i * (span / per_table), from, allocated)
+            {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    /// Find the first zone of an inode at or after `from` which is allocated, or which is a hole if `allocated` is
+    /// false
+    fn find_zone(&mut self, inode: &Minix3DiskInode, from: usize, allocated: bool) -> Option<usize>
+    {
+        for &(zone, level, first) in self.zone_roots(inode).iter()
+        {
+            if let Some(index) = self.recursive_find_zone(zone, level, first, from, allocated)
+            {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    /// Free the data zones below a zone pointer whose index within the file is in `start..end`, along with any
+    /// indirect zones left empty, returns true if the zone itself was freed
+    fn recursive_free_range(&mut self, zone: usize, level: usize, first: usize, start: usize, end: usize) -> FilesystemResult<bool>
+    {
+        let per_table = self.block_size / 4;
+        let span = per_table.pow(level as u32);
+
+        if zone == 0 || first + span <= start || first >= end
+        {
+            return Ok(false);
+        }
+
+        if level > 0
+        {
+            let covered = start <= first && first + span <= end;
+            let mut empty = true;
+
+            for (i, child) in self.read_zone_table(zone).into_iter().enumerate()
+            {
+                if self.recursive_free_range(child as usize, level - 1, first + i * (span / per_table), start, end)?
+                {
+                    // A table which is freed entirely does not need its entries cleared
+                    if !covered
+                    {
+                        self.edit_block_region(self.zone_block(zone), i * 4, &[0; 4])?;
+                    }
+                }
+                else if child != 0
+                {
+                    empty = false;
+                }
+            }
+
+            if !empty
+            {
+                return Ok(false);
+            }
+        }
+
+        // Indirect zones are freed along with the zones they point to
+        self.free_zone(zone)?;
+
+        Ok(true)
+    }
+
+    /// Free the zones of an inode whose index within the file is in `start..end`, leaving a hole
+    fn free_zone_range(&mut self, inode: &mut Minix3DiskInode, start: usize, end: usize) -> FilesystemResult<()>
+    {
+        for (slot, &(zone, level, first)) in self.zone_roots(inode).iter().enumerate()
+        {
+            if self.recursive_free_range(zone, level, first, start, end)?
+            {
+                inode.zones[slot] = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Free zones
+    fn free_zones(&mut self, inode: &mut Minix3DiskInode) -> FilesystemResult<()>
+    {
+        self.free_zone_range(inode, 0, usize::MAX)
+    }
+
+    /// Zero the bytes of an inode in `start..end` which are held by allocated blocks
+    fn zero_range(&mut self, inode: &Minix3DiskInode, start: usize, end: usize) -> FilesystemResult<()>
+    {
+        let block_size = self.block_size;
+        let mut position = start;
+
+        while position < end
+        {
+            let length = (end - position).min(block_size - position % block_size);
+            let block = self.disk_block(inode, position / block_size);
+
+            if block != 0
+            {
+                self.edit_block_region(block, position % block_size, &vec![0; length])?;
+            }
+
+            position += length;
+        }
+
+        Ok(())
+    }
+
+    /// Turn the bytes of an inode in `start..end` into a hole, zones entirely inside of the range are freed and the
+    /// rest of the range is zeroed
+    fn punch_hole(&mut self, inode: &mut Minix3DiskInode, start: usize, end: usize) -> FilesystemResult<()>
+    {
+        let zone_size = self.zone_size();
+
+        let first_full = (start + zone_size - 1) / zone_size;
+        let last_full = end / zone_size;
+
+        if first_full < last_full
+        {
+            self.zero_range(inode, start, first_full * zone_size)?;
+            self.free_zone_range(inode, first_full, last_full)?;
+            self.zero_range(inode, last_full * zone_size, end)
+        }
+        else
+        {
+            self.zero_range(inode, start, end)
+        }
+    }
+
+    /// Free the zones of an inode past `size` bytes
+    fn truncate_zones(&mut self, inode: &mut Minix3DiskInode, size: usize) -> FilesystemResult<()>
+    {
+        let zone_size = self.zone_size();
+        let kept = (size + zone_size - 1) / zone_size;
+
+        // The rest of the last zone is zeroed so it does not reappear if the file grows again
+        self.zero_range(inode, size, kept * zone_size)?;
+        self.free_zone_range(inode, kept, usize::MAX)
+    }
+
+    /// Write pages of a file, allocating zones only where data is written so skipped ranges stay holes
+    fn write_pages(&mut self, inode: &mut Minix3DiskInode, size: usize, pages: &[(usize, *const u8)]) -> FilesystemResult<()>
+    {
+        let block_size = self.block_size;
+
+        if size < inode.size as usize
+        {
+            self.truncate_zones(inode, size)?;
+        }
+
+        inode.size = size as u32;
+
+        for (page, ptr) in pages
+        {
+            for (offset, length) in self.page_parts(*page, size)
+            {
+                let data = unsafe { core::slice::from_raw_parts(ptr.add(offset - page * mem::PAGE_SIZE), length) };
+                let mut block = self.disk_block(inode, offset / block_size);
+
+                if block == 0
+                {
+                    // Zeros written into a hole leave the hole in place
+                    if data.iter().all(|byte| *byte == 0)
+                    {
+                        continue;
+                    }
+
+                    let zone = self.allocate_zone_for_index(inode, (offset / block_size) >> self.zone_shift)?;
+                    block = self.zone_block(zone) + (offset / block_size & (self.zone_blocks() - 1));
+                }
+
+                // Blocks which are entirely overwritten do not need to be read first
+                if length == block_size
+                {
+                    self.edit_block(block, data.to_vec())?;
+                }
+                else
+                {
+                    self.edit_block_region(block, offset % block_size, data)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Allocate the zones of an inode holding the bytes in `start..end`
+    fn allocate_range_zones(&mut self, inode: &mut Minix3DiskInode, start: usize, end: usize) -> FilesystemResult<()>
+    {
+        let zone_size = self.zone_size();
+
+        for index in start / zone_size..(end + zone_size - 1) / zone_size
+        {
+            self.allocate_zone_for_index(inode, index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write data to a file
+    fn write_to_file(&mut self, inode_number: usize, data: &[u8]) -> FilesystemResult<()>
+    {
+        let mut inode = self.get_inode(inode_number)?;
+        let zone_size = self.zone_size();
+
+        // TODO: This is not as efficent as I would like it to be, it currently
+        // will free and then reallocate zones
+        self.free_zones(&mut inode)?;
+        self.allocate_zones(&mut inode, (data.len() + zone_size - 1) / zone_size)?;
+        self.copy_to_zones(&mut inode, data)?;
+
+        inode.size = data.len() as u32;
+
+        update_time(&mut inode, UpdateTimes::Modify);
+
+        *(self.get_mut_inode(inode_number)?) = inode;
+
+        Ok(())
+    }
+
+    /// Remove an inode and the blocks associated with it
+    fn delete_inode(&mut self, inode_number: usize) -> FilesystemResult<()>
+    {
+        let mut inode = self.get_inode(inode_number)?;
 
         self.free_zones(&mut inode)?;
         self.free_inode(inode_number)?;
@@ -768,7 +1712,7 @@ impl Minix3Filesystem
 
         let inode = self.get_mut_inode(next_inode)?;
 
-        *inode = Minix3Inode
+        *inode = Minix3DiskInode
         {
             mode,
             nlinks: 1,
@@ -798,6 +1742,10 @@ impl Minix3Filesystem
 
         self.add_directory_entry(dest, inode, &name)?;
 
+        // The new directory is linked from its own `.` entry, and its `..` entry links to the parent
+        self.adjust_links(inode, 1)?;
+        self.adjust_links(dest, 1)?;
+
         Ok(inode)
     }
 }
@@ -810,21 +1758,76 @@ impl Filesystem for Minix3Filesystem
         kdebugln!(Filesystem, "Initializing Minix3 Filesystem");
 
         // Read the super block
-        let mut ptr = Box::new([0u8; 512]);
+        let mut sector = [0u8; 512];
 
-        self.block_driver.sync_read(ptr.as_mut() as *mut [u8; 512] as *mut u8, 512, 1024);
+        if self.device.read_sectors((libutils::minix3::MINIX3_SUPERBLOCK_OFFSET / 512) as u64, sector.as_mut_ptr(), 1).is_err()
+        {
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
 
-        let superblock = unsafe { *(ptr.as_mut() as *mut [u8; 512] as *mut Minix3SuperBlock) };
+        let superblock = Minix3Superblock::from_bytes(&sector);
 
         // Verify the filesystem is a minix3 filesystem
-        if superblock.magic != 0x4d5a
+        if superblock.magic != libutils::minix3::MINIX3_MAGIC
+        {
+            return Err(FilesystemError::BadFilesystemFormat)
+        }
+
+        let block_size = superblock.block_size();
+
+        if block_size < 1024 || !block_size.is_power_of_two() || superblock.log_zone_size > 8
         {
             return Err(FilesystemError::BadFilesystemFormat)
         }
 
         self.superblock = Some(superblock);
+        self.block_size = block_size;
+        self.zone_shift = superblock.log_zone_size as usize;
 
-        Ok(())
+        // A filesystem which was unmounted cleanly is consistent, so only one which was not needs the full check
+        if superblock.flags & libutils::minix3::MINIX3_FLAG_CLEAN != 0
+        {
+            self.clean = true;
+            Ok(())
+        }
+        else
+        {
+            self.check()
+        }
+    }
+
+    /// Clear the clean flag while the filesystem is mounted read write, and set it once everything is synced
+    fn set_writable(&mut self, writable: bool) -> FilesystemResult<()>
+    {
+        let superblock = self.superblock.ok_or(FilesystemError::FilesystemUninitialized)?;
+
+        // A filesystem which is still damaged, or which could not be synced, must be checked at its next mount
+        if !writable && (!self.clean || !self.rewritten.is_empty())
+        {
+            return Ok(());
+        }
+
+        let flags = if writable
+        {
+            superblock.flags & !libutils::minix3::MINIX3_FLAG_CLEAN
+        }
+        else
+        {
+            superblock.flags | libutils::minix3::MINIX3_FLAG_CLEAN
+        };
+
+        if flags == superblock.flags
+        {
+            return Ok(());
+        }
+
+        self.write_flags(flags)
+    }
+
+    /// Check if the filesystem is consistent enough to be mounted read write
+    fn is_clean(&mut self) -> bool
+    {
+        self.clean
     }
 
     /// Sync the filesystem with the current disk
@@ -832,17 +1835,71 @@ impl Filesystem for Minix3Filesystem
     {   
         kdebugln!(Filesystem, "{} Zones Rewritten", self.rewritten.len());
 
-        for (block, data) in &self.rewritten
+        let rewritten = core::mem::take(&mut self.rewritten);
+
+        let mut allocate = Vec::new();
+        let mut new_zones = Vec::new();
+        let mut inodes = Vec::new();
+        let mut zones = Vec::new();
+        let mut release_inodes = Vec::new();
+        let mut release = Vec::new();
+
+        for (block, data) in &rewritten
         {
-            kdebugln!(Filesystem, "Writing to Block {}", block);
+            let (block, data) = (*block, data.clone());
+
+            match self.write_stage(block)
+            {
+                WriteStage::Allocate | WriteStage::Release =>
+                {
+                    // Bitmaps are written twice if they hold frees, first without the frees and then with them
+                    let withheld = self.withhold_frees(block, data.clone())?;
+
+                    if withheld != data
+                    {
+                        release.push((block, data));
+                    }
+
+                    allocate.push((block, withheld));
+                },
+                WriteStage::Inodes | WriteStage::ReleaseInodes =>
+                {
+                    // Inode blocks holding freed inodes are likewise written twice, so a freed inode is only cleared
+                    // once the directory entries naming it have been removed
+                    let withheld = self.withhold_released_inodes(block, data.clone())?;
+
+                    if withheld != data
+                    {
+                        release_inodes.push((block, data));
+                    }
 
-            let ptr = data.as_ptr() as *mut u8;
+                    inodes.push((block, withheld));
+                },
+                WriteStage::NewZones => new_zones.push((block, data)),
+                WriteStage::Zones => zones.push((block, data))
+            }
+        }
 
-            self.block_driver.sync_write(ptr, 1024, 1024 * *block as u64);
+        for stage in [&allocate, &new_zones, &inodes, &zones, &release_inodes, &release]
+        {
+            if let Err(e) = self.write_blocks(stage)
+            {
+                // Nothing after the failed stage reached the disk, so every block stays dirty and the next sync
+                // starts over from the first stage
+                self.rewritten = rewritten;
+                return Err(e);
+            }
         }
 
-        // Clear the rewritten buffer
-        self.rewritten = Vec::new();
+        self.claimed_zones.clear();
+        self.released_zones.clear();
+        self.released_inodes.clear();
+
+        // The written blocks are now clean, so move their final contents into the block cache
+        for (block, data) in rewritten
+        {
+            self.cache_block(block, data);
+        }
 
         Ok(())
     }
@@ -887,7 +1944,7 @@ impl Filesystem for Minix3Filesystem
     }
 
     /// Convert an inode to a path
-    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<PathBuffer>
+    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<OwnedPath>
     {
         if let Some(vfs) = &mut self.vfs
         {
@@ -935,15 +1992,6 @@ impl Filesystem for Minix3Filesystem
                 result.push(DirectoryEntry{ index: FilesystemIndex{ mount_id: inode.mount_id, inode: entry.inode as usize }, name: name, entry_type: DirectoryEntryType::Unknown });
             }
 
-            // Add any mounted filesystems
-            for (place, root, name) in &self.mount_inodes
-            {
-                if *place == inode
-                {
-                    result.push(DirectoryEntry{ index: *root, name: name.clone(), entry_type: DirectoryEntryType::Directory });
-                }
-            }
-
             Ok(result)
         }
         else
@@ -976,7 +2024,7 @@ impl Filesystem for Minix3Filesystem
                 gid: read.gid,
                 special_dev_id: 0,
                 size: read.size as usize,
-                blk_size: 512,
+                blk_size: self.block_size,
                 blocks_alloced: 1, // TODO
                 atime: read.atime as usize,
                 mtime: read.mtime as usize,
@@ -1001,6 +2049,8 @@ impl Filesystem for Minix3Filesystem
     {
         if Some(inode.mount_id) == self.mount_id
         {
+            check_entry_name(&name)?;
+
             let file_inode = self.allocate_file(String::new(), 0o100777)?;
 
             self.add_directory_entry(inode.inode, file_inode, &name)?;
@@ -1025,6 +2075,8 @@ impl Filesystem for Minix3Filesystem
     {
         if Some(inode.mount_id) == self.mount_id
         {
+            check_entry_name(&name)?;
+
             let dir_inode = self.new_directory(inode.inode, name)?;
 
             Ok(FilesystemIndex { mount_id: inode.mount_id, inode: dir_inode } )
@@ -1042,20 +2094,25 @@ impl Filesystem for Minix3Filesystem
         }
     }
 
-    /// Remove an inode at the given index from the given directory
-    fn remove_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<()>
+    /// Create a symbolic link in the directory at the given inode which points to `target`
+    fn create_symlink(&mut self, directory: FilesystemIndex, name: String, target: &str) -> FilesystemResult<FilesystemIndex>
     {
-        if Some(inode.mount_id) == self.mount_id
+        if Some(directory.mount_id) == self.mount_id
         {
-            self.delete_inode(inode.inode)?;
+            // Minix3 has no inline symlinks, the target is always stored in the data zones of the link
+            check_entry_name(&name)?;
 
-            Ok(())
+            let link_inode = self.allocate_file(String::from(target), S_IFLNK | 0o777)?;
+
+            self.add_directory_entry(directory.inode, link_inode, &name)?;
+
+            Ok(FilesystemIndex { mount_id: directory.mount_id, inode: link_inode } )
         }
         else
         {
             if let Some(vfs) = &mut self.vfs
             {
-                vfs.remove_inode(inode)
+                vfs.create_symlink(directory, name, target)
             }
             else
             {
@@ -1064,20 +2121,24 @@ impl Filesystem for Minix3Filesystem
         }
     }
 
-    /// Remove a directory entry from the directory at the given inode
-    fn remove_dir_entry(&mut self, directory_index: FilesystemIndex, name: String) -> FilesystemResult<()>
+    /// Create a named pipe in the directory at the given inode
+    fn create_fifo(&mut self, directory: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
     {
-        if Some(directory_index.mount_id) == self.mount_id
+        if Some(directory.mount_id) == self.mount_id
         {
-            self.remove_directory_entry(directory_index.inode, name)?;
+            check_entry_name(&name)?;
 
-            Ok(())
+            let fifo_inode = self.allocate_file(String::new(), S_IFIFO | 0o644)?;
+
+            self.add_directory_entry(directory.inode, fifo_inode, &name)?;
+
+            Ok(FilesystemIndex { mount_id: directory.mount_id, inode: fifo_inode } )
         }
         else
         {
             if let Some(vfs) = &mut self.vfs
             {
-                vfs.remove_dir_entry(directory_index, name)
+                vfs.create_fifo(directory, name)
             }
             else
             {
@@ -1086,22 +2147,24 @@ impl Filesystem for Minix3Filesystem
         }
     }
 
-    /// Increment the number of links to an inode
-    fn increment_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    /// Create a socket in the directory at the given inode
+    fn create_socket(&mut self, directory: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
     {
-        if Some(inode.mount_id) == self.mount_id
+        if Some(directory.mount_id) == self.mount_id
         {
-            let r = self.get_mut_inode(inode.inode)?;
+            check_entry_name(&name)?;
 
-            r.nlinks += 1;
+            let socket_inode = self.allocate_file(String::new(), S_IFSOCK | 0o755)?;
 
-            Ok(r.nlinks.into())
+            self.add_directory_entry(directory.inode, socket_inode, &name)?;
+
+            Ok(FilesystemIndex { mount_id: directory.mount_id, inode: socket_inode } )
         }
         else
         {
             if let Some(vfs) = &mut self.vfs
             {
-                vfs.increment_links(inode)
+                vfs.create_socket(directory, name)
             }
             else
             {
@@ -1110,22 +2173,232 @@ impl Filesystem for Minix3Filesystem
         }
     }
 
-    /// Decrement the number of links to an inode
-    fn decrement_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    /// Read the target of the symbolic link at the given inode
+    fn read_link(&mut self, inode: FilesystemIndex) -> FilesystemResult<String>
     {
         if Some(inode.mount_id) == self.mount_id
         {
-            let r = self.get_mut_inode(inode.inode)?;
+            let inode_data = self.get_inode(inode.inode)?;
 
-            if r.nlinks > 0
+            if inode_data.mode & S_IFMT != S_IFLNK
             {
-                r.nlinks -= 1;
-                Ok(r.nlinks.into())
+                return Err(FilesystemError::NotASymbolicLink);
             }
-            else
-            {
-                Ok(0)
+
+            let data = self.read_from_inode(inode_data);
+
+            Ok(String::from_utf8_lossy(&data).to_string())
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.read_link(inode)
             }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Add an entry for an existing inode to the directory at the given inode
+    fn create_link(&mut self, inode: FilesystemIndex, directory: FilesystemIndex, name: String) -> FilesystemResult<()>
+    {
+        if Some(directory.mount_id) == self.mount_id
+        {
+            // Hard links to directories would allow loops in the tree
+            if self.get_inode(inode.inode)?.mode & S_IFMT == S_IFDIR
+            {
+                return Err(FilesystemError::PermissionDenied);
+            }
+
+            self.add_directory_entry(directory.inode, inode.inode, &name)?;
+
+            let inode_ref = self.get_mut_inode(inode.inode)?;
+            inode_ref.nlinks += 1;
+            update_time(inode_ref, UpdateTimes::Create);
+
+            Ok(())
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.create_link(inode, directory, name)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Change the metadata of an inode, only the permission bits of a new mode are used
+    fn set_attr(&mut self, inode: FilesystemIndex, attributes: SetAttributes) -> FilesystemResult<()>
+    {
+        if Some(inode.mount_id) == self.mount_id
+        {
+            if let Some(size) = attributes.size
+            {
+                let mut inode_data = self.get_inode(inode.inode)?;
+
+                if inode_data.mode & S_IFMT == S_IFDIR
+                {
+                    return Err(FilesystemError::INodeIsDirectory);
+                }
+
+                if size > u32::MAX as usize
+                {
+                    return Err(FilesystemError::FileTooLarge);
+                }
+
+                // Growing the file leaves a hole, so only shrinking touches the zones
+                if size < inode_data.size as usize
+                {
+                    self.truncate_zones(&mut inode_data, size)?;
+                }
+
+                inode_data.size = size as u32;
+                update_time(&mut inode_data, UpdateTimes::Modify);
+
+                *(self.get_mut_inode(inode.inode)?) = inode_data;
+            }
+
+            let inode_ref = self.get_mut_inode(inode.inode)?;
+
+            if let Some(mode) = attributes.mode
+            {
+                inode_ref.mode = (inode_ref.mode & S_IFMT) | (mode & 0o7777);
+            }
+
+            if let Some(uid) = attributes.uid
+            {
+                inode_ref.uid = uid;
+            }
+
+            if let Some(gid) = attributes.gid
+            {
+                inode_ref.gid = gid;
+            }
+
+            if let Some(atime) = attributes.atime
+            {
+                inode_ref.atime = atime as u32;
+            }
+
+            if let Some(mtime) = attributes.mtime
+            {
+                inode_ref.mtime = mtime as u32;
+            }
+
+            update_time(inode_ref, UpdateTimes::Create);
+
+            Ok(())
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.set_attr(inode, attributes)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Move the entry `old_name` in the directory at `old_directory` to `new_name` in `new_directory`, replacing or
+    /// exchanging with any existing entry depending on the `RENAME_*` flags
+    fn rename(&mut self, old_directory: FilesystemIndex, old_name: String, new_directory: FilesystemIndex, new_name: String, flags: usize) -> FilesystemResult<()>
+    {
+        if Some(old_directory.mount_id) == self.mount_id && Some(new_directory.mount_id) == self.mount_id
+        {
+            self.rename_entry(old_directory.inode, &old_name, new_directory.inode, &new_name, flags)
+        }
+        else if old_directory.mount_id != new_directory.mount_id
+        {
+            Err(FilesystemError::CrossDeviceLink)
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.rename(old_directory, old_name, new_directory, new_name, flags)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Remove an inode at the given index from the given directory
+    fn remove_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<()>
+    {
+        if Some(inode.mount_id) == self.mount_id
+        {
+            self.delete_inode(inode.inode)?;
+
+            Ok(())
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.remove_inode(inode)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Remove a directory entry from the directory at the given inode
+    fn remove_dir_entry(&mut self, directory_index: FilesystemIndex, name: String) -> FilesystemResult<()>
+    {
+        if Some(directory_index.mount_id) == self.mount_id
+        {
+            let removed = self.find_directory_entry(directory_index.inode, &name)?;
+
+            self.remove_directory_entry(directory_index.inode, name)?;
+
+            // Removing a directory also removes the link its `..` entry held on the parent
+            if let Some(removed) = removed
+            {
+                if removed != directory_index.inode && self.is_directory(removed)?
+                {
+                    self.adjust_links(directory_index.inode, -1)?;
+                }
+            }
+
+            Ok(())
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.remove_dir_entry(directory_index, name)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Increment the number of links to an inode
+    fn increment_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if Some(inode.mount_id) == self.mount_id
+        {
+            let r = self.get_mut_inode(inode.inode)?;
+
+            r.nlinks += 1;
+
+            Ok(r.nlinks.into())
         }
         else
         {
@@ -1140,6 +2413,36 @@ impl Filesystem for Minix3Filesystem
         }
     }
 
+    /// Decrement the number of links to an inode
+    fn decrement_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if Some(inode.mount_id) == self.mount_id
+        {
+            let r = self.get_mut_inode(inode.inode)?;
+
+            if r.nlinks > 0
+            {
+                r.nlinks -= 1;
+                Ok(r.nlinks.into())
+            }
+            else
+            {
+                Ok(0)
+            }
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.decrement_links(inode)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
     /// Read the data stored in an inode
     fn read_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<u8>>
     {
@@ -1161,18 +2464,46 @@ impl Filesystem for Minix3Filesystem
         }
     }
 
-    /// Write data to an inode
-    fn write_inode(&mut self, inode: FilesystemIndex, data: &[u8]) -> FilesystemResult<()>
+    /// Read a single page of the data stored in an inode into the buffer, returning the number of bytes read
+    fn read_inode_page(&mut self, inode: FilesystemIndex, page: usize, buffer: *mut u8) -> FilesystemResult<usize>
     {
         if Some(inode.mount_id) == self.mount_id
         {
-            self.write_to_file(inode.inode, data)
+            let inode = self.get_inode(inode.inode)?;
+            let parts = self.page_parts(page, inode.size as usize);
+            let block_size = self.block_size;
+
+            // Read all of the blocks in the page together
+            let blocks: Vec<usize> = parts.iter()
+                .map(|(offset, _)| self.disk_block(&inode, offset / block_size))
+                .filter(|block| *block != 0)
+                .collect();
+
+            self.load_blocks(&blocks);
+
+            let mut read = 0;
+
+            for (offset, length) in parts
+            {
+                let block = self.disk_block(&inode, offset / block_size);
+
+                // Unallocated zones read as zeros, and the buffer is expected to be zeroed
+                if block != 0
+                {
+                    let data = self.read_block_to_buffer(block);
+                    unsafe { core::ptr::copy(data[offset % block_size..].as_ptr(), buffer.add(offset - page * mem::PAGE_SIZE), length) };
+                }
+
+                read += length;
+            }
+
+            Ok(read)
         }
         else
         {
             if let Some(vfs) = &mut self.vfs
             {
-                vfs.write_inode(inode, data)
+                vfs.read_inode_page(inode, page, buffer)
             }
             else
             {
@@ -1181,12 +2512,180 @@ impl Filesystem for Minix3Filesystem
         }
     }
 
-    /// Mount a filesystem at the given inode
-    fn mount_fs_at(&mut self, inode: FilesystemIndex, root: FilesystemIndex, name: String) -> FilesystemResult<()>
+    /// Start reading a page of an inode in the background, returning a token to wait on if the data is not ready
+    fn prefetch_inode_page(&mut self, inode: FilesystemIndex, page: usize) -> FilesystemResult<Option<BlockRequestToken>>
     {
-        self.mount_inodes.push((inode, root, name));
+        if Some(inode.mount_id) == self.mount_id
+        {
+            self.collect_pending();
 
-        Ok(())
+            let inode = self.get_inode(inode.inode)?;
+            let parts = self.page_parts(page, inode.size as usize);
+            let block_size = self.block_size;
+
+            // Indirect zones are read synchronously, only the data blocks are read in the background
+            let blocks: Vec<usize> = parts.iter()
+                .map(|(offset, _)| self.disk_block(&inode, offset / block_size))
+                .filter(|block| *block != 0)
+                .collect();
+
+            let wait = self.prefetch_blocks(&blocks);
+
+            Ok(wait)
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.prefetch_inode_page(inode, page)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Write back pages of the data stored in an inode, resizing the inode to `size` bytes
+    fn write_inode_pages(&mut self, inode: FilesystemIndex, size: usize, pages: &[(usize, *const u8)]) -> FilesystemResult<()>
+    {
+        if Some(inode.mount_id) == self.mount_id
+        {
+            if size > u32::MAX as usize
+            {
+                return Err(FilesystemError::FileTooLarge);
+            }
+
+            let mut inode_data = self.get_inode(inode.inode)?;
+
+            // The inode is stored even if the write fails part way, so it still owns any zones allocated for it
+            let result = self.write_pages(&mut inode_data, size, pages);
+
+            update_time(&mut inode_data, UpdateTimes::Modify);
+            *(self.get_mut_inode(inode.inode)?) = inode_data;
+
+            result
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.write_inode_pages(inode, size, pages)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Find the first offset at or after `offset` which holds data, or which is in a hole if `hole` is set, the end
+    /// of the file counts as a hole
+    fn seek_data(&mut self, inode: FilesystemIndex, offset: usize, hole: bool) -> FilesystemResult<usize>
+    {
+        if Some(inode.mount_id) == self.mount_id
+        {
+            let inode_data = self.get_inode(inode.inode)?;
+            let size = inode_data.size as usize;
+            let zone_size = self.zone_size();
+
+            if offset >= size
+            {
+                return Err(FilesystemError::OffsetPastEnd);
+            }
+
+            match self.find_zone(&inode_data, offset / zone_size, !hole)
+            {
+                Some(index) if hole => Ok((index * zone_size).max(offset).min(size)),
+                None if hole => Ok(size),
+                // Zones allocated past the end of the file do not count as data
+                Some(index) if index * zone_size < size => Ok((index * zone_size).max(offset)),
+                _ => Err(FilesystemError::OffsetPastEnd)
+            }
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.seek_data(inode, offset, hole)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Allocate or deallocate the storage for `length` bytes of an inode starting at `offset` according to the
+    /// `FALLOC_FL_*` flags in `mode`
+    fn allocate_range(&mut self, inode: FilesystemIndex, mode: usize, offset: usize, length: usize) -> FilesystemResult<()>
+    {
+        if Some(inode.mount_id) == self.mount_id
+        {
+            let end = offset.checked_add(length)
+                .filter(|end| *end <= u32::MAX as usize)
+                .ok_or(FilesystemError::FileTooLarge)?;
+
+            let mut inode_data = self.get_inode(inode.inode)?;
+
+            if inode_data.mode & S_IFMT == S_IFDIR
+            {
+                return Err(FilesystemError::INodeIsDirectory);
+            }
+
+            // As with a write, the inode is stored even if the allocation fails part way
+            let result = if mode & FALLOC_FL_PUNCH_HOLE != 0
+            {
+                self.punch_hole(&mut inode_data, offset, end)
+            }
+            else
+            {
+                let result = self.allocate_range_zones(&mut inode_data, offset, end);
+
+                if result.is_ok() && mode & FALLOC_FL_KEEP_SIZE == 0 && end > inode_data.size as usize
+                {
+                    inode_data.size = end as u32;
+                }
+
+                result
+            };
+
+            update_time(&mut inode_data, UpdateTimes::Modify);
+            *(self.get_mut_inode(inode.inode)?) = inode_data;
+
+            result
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.allocate_range(inode, mode, offset, length)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
+    }
+
+    /// Write data to an inode
+    fn write_inode(&mut self, inode: FilesystemIndex, data: &[u8]) -> FilesystemResult<()>
+    {
+        if Some(inode.mount_id) == self.mount_id
+        {
+            self.write_to_file(inode.inode, data)
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.write_inode(inode, data)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
     }
 
     /// Open a filedescriptor for the given inode
@@ -1196,7 +2695,7 @@ impl Filesystem for Minix3Filesystem
         {
             if Some(inode.mount_id) == self.mount_id
             {
-                Ok(Box::new(InodeFileDescriptor::new(vfs, inode, mode).unwrap()))
+                Ok(Box::new(PageCacheDescriptor::new(vfs, inode, mode)?))
             }
             else
             {
@@ -1,11 +1,20 @@
 //! Minix3 File System
 
 // Modules
+pub mod acl;
+pub mod cache;
+pub mod dcache;
 pub mod devfs;
+pub mod ext2;
+pub mod fat32;
 pub mod fstrait;
+pub mod initramfs;
 pub mod ioctl;
+pub mod locks;
 pub mod minix3;
+pub mod mounts;
 pub mod procfs;
-pub mod ramdisk;
 pub mod structures;
-pub mod vfs;
\ No newline at end of file
+pub mod tmpfs;
+pub mod vfs;
+pub mod writeback;
\ No newline at end of file
@@ -0,0 +1,162 @@
+//! Filesystem types and mount flags
+//!
+//! Every kind of filesystem which can be mounted by name registers a
+//! constructor here, which `mount` uses to build a fresh instance of the
+//! filesystem for each mount. The constructor is given the block device named
+//! by the source of the mount, if the type needs one, along with the comma
+//! separated options passed to `mount`.
+
+use crate::*;
+
+use super::fstrait::Filesystem;
+use super::structures::*;
+
+/// Mount the filesystem read only
+pub const MS_RDONLY: usize = 1;
+
+/// Ignore set user and set group id bits on executables
+pub const MS_NOSUID: usize = 2;
+
+/// Do not allow programs to be executed from the filesystem
+pub const MS_NOEXEC: usize = 8;
+
+/// Change the flags of an existing mount
+pub const MS_REMOUNT: usize = 32;
+
+/// Flags which are stored with a mount
+pub const MS_MOUNT_FLAGS: usize = MS_RDONLY | MS_NOSUID | MS_NOEXEC;
+
+/// A kind of filesystem which can be mounted by name
+pub struct FilesystemType
+{
+    pub name: &'static str,
+    pub requires_device: bool,
+    pub create: fn(Option<usize>, &str) -> FilesystemResult<Box<dyn Filesystem>>
+}
+
+/// Description of a mounted filesystem
+#[derive(Debug, Clone)]
+pub struct MountInfo
+{
+    pub source: String,
+    pub fstype: String,
+    pub flags: usize
+}
+
+// Every registered filesystem type
+static mut FILESYSTEM_TYPES: Option<Vec<FilesystemType>> = None;
+
+/// Get the list of registered filesystem types
+fn get_filesystem_types() -> &'static mut Vec<FilesystemType>
+{
+    let types = unsafe { &mut FILESYSTEM_TYPES };
+
+    if types.is_none()
+    {
+        *types = Some(Vec::new());
+    }
+
+    types.as_mut().unwrap()
+}
+
+/// Register a filesystem type so it can be mounted by name
+pub fn register_filesystem_type(fs_type: FilesystemType)
+{
+    kdebugln!(Filesystem, "Registered filesystem type {}", fs_type.name);
+
+    get_filesystem_types().push(fs_type);
+}
+
+/// Get the filesystem type with the given name
+pub fn get_filesystem_type(name: &str) -> Option<&'static FilesystemType>
+{
+    get_filesystem_types().iter().find(|fs_type| fs_type.name == name)
+}
+
+/// Find the block device named by the source of a mount, either as `/dev/<name>` or just `<name>`
+pub fn find_source_device(source: &str) -> FilesystemResult<usize>
+{
+    let name = source.strip_prefix("/dev/").unwrap_or(source);
+
+    for i in 0..crate::drivers::block::block_device_count()
+    {
+        if crate::drivers::block::get_block_device_name(i).as_deref() == Some(name)
+        {
+            return Ok(i);
+        }
+    }
+
+    Err(FilesystemError::FileNotFound(source.to_string()))
+}
+
+/// Format the flags of a mount as they appear in `/proc/mounts`
+pub fn format_mount_options(flags: usize) -> String
+{
+    let mut options = String::from(if flags & MS_RDONLY > 0 { "ro" } else { "rw" });
+
+    if flags & MS_NOSUID > 0
+    {
+        options.push_str(",nosuid");
+    }
+
+    if flags & MS_NOEXEC > 0
+    {
+        options.push_str(",noexec");
+    }
+
+    options
+}
+
+/// Create and initialize a filesystem instance
+fn initialized(mut fs: Box<dyn Filesystem>) -> FilesystemResult<Box<dyn Filesystem>>
+{
+    fs.init()?;
+
+    Ok(fs)
+}
+
+/// Register the filesystem types built into the kernel
+pub fn init_filesystem_types()
+{
+    register_filesystem_type(FilesystemType
+    {
+        name: "minix3",
+        requires_device: true,
+        create: |device, options| initialized(Box::new(super::minix3::Minix3Filesystem::from_options(device.unwrap(), options)?))
+    });
+
+    register_filesystem_type(FilesystemType
+    {
+        name: "ext2",
+        requires_device: true,
+        create: |device, _| initialized(Box::new(super::ext2::Ext2Filesystem::new(device.unwrap())))
+    });
+
+    register_filesystem_type(FilesystemType
+    {
+        name: "vfat",
+        requires_device: true,
+        create: |device, _| initialized(Box::new(super::fat32::Fat32Filesystem::new(device.unwrap())))
+    });
+
+    register_filesystem_type(FilesystemType
+    {
+        name: "devfs",
+        requires_device: false,
+        create: |_, _| initialized(Box::new(super::devfs::DevFilesystem::new()))
+    });
+
+    register_filesystem_type(FilesystemType
+    {
+        name: "proc",
+        requires_device: false,
+        create: |_, _| initialized(Box::new(super::procfs::ProcFilesystem::new()))
+    });
+
+    register_filesystem_type(FilesystemType
+    {
+        name: "tmpfs",
+        requires_device: false,
+        create: |_, options| initialized(Box::new(super::tmpfs::TmpFilesystem::from_options(options)?))
+    });
+}
@@ -3,10 +3,11 @@ use crate::*;
 use super::super::fstrait::*;
 use super::super::structures::*;
 
-use libutils::paths::PathBuffer;
+use libutils::paths::{OwnedPath, PathBuffer};
 
 use crate::process::descriptor::*;
 
+const PROC_INODE_MOUNTS: usize = 2;
 const PROC_INODE_FLAG_PID: usize = 0x10000;
 const PROC_INODE_FLAG_PID_CMDLINE: usize = 0x20000;
 const PROC_INODE_FLAG_PID_STATM: usize = 0x40000;
@@ -83,7 +84,7 @@ impl Filesystem for ProcFilesystem
     }
 
     /// Convert an inode to a path
-    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<PathBuffer>
+    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<OwnedPath>
     {
         if let Some(vfs) = &mut self.vfs
         {
@@ -121,6 +122,12 @@ impl Filesystem for ProcFilesystem
 
                 if inode.inode == 1
                 {
+                    result.push(DirectoryEntry{
+                        index: FilesystemIndex { mount_id: inode.mount_id, inode: PROC_INODE_MOUNTS},
+                        name: String::from("mounts"),
+                        entry_type: DirectoryEntryType::RegularFile,
+                    });
+
                     if let Some(proc_manager) = process::scheduler::get_process_manager()
                     {
                         for key in proc_manager.processes.keys()
@@ -166,7 +173,7 @@ impl Filesystem for ProcFilesystem
 
                 Ok(result)
             }
-            else if inode.inode & !0xFFFF > 0
+            else if inode.inode & !0xFFFF > 0 || inode.inode == PROC_INODE_MOUNTS
             {
                 Err(FilesystemError::INodeIsNotADirectory)
             }
@@ -189,9 +196,41 @@ impl Filesystem for ProcFilesystem
     }
 
     /// Get the directory entry for the given inode
-    fn get_stat(&mut self, _inode: FilesystemIndex) -> FilesystemResult<FileStat>
+    fn get_stat(&mut self, inode: FilesystemIndex) -> FilesystemResult<FileStat>
     {
-        todo!()
+        if Some(inode.mount_id) == self.mount_id
+        {
+            // Files are generated when they are read, so they have no size until then
+            let mode = if inode.inode == 1 || inode.inode & PROC_INODE_FLAG_PID > 0 { S_IFDIR | 0o555 } else { S_IFREG | 0o444 };
+
+            Ok(FileStat
+            {
+                dev_id: inode.mount_id,
+                inode: inode.inode,
+                mode,
+                links: 1,
+                uid: 0,
+                gid: 0,
+                special_dev_id: 0,
+                size: 0,
+                blk_size: 512,
+                blocks_alloced: 0,
+                atime: 0,
+                mtime: 0,
+                ctime: 0
+            })
+        }
+        else
+        {
+            if let Some(vfs) = &mut self.vfs
+            {
+                vfs.get_stat(inode)
+            }
+            else
+            {
+                Err(FilesystemError::FilesystemNotMounted)
+            }
+        }
     }
 
     fn create_file(&mut self, _inode: FilesystemIndex, _name: alloc::string::String) -> FilesystemResult<FilesystemIndex>
@@ -234,7 +273,21 @@ impl Filesystem for ProcFilesystem
         {
             let pid = inode.inode & 0xFFFF;
 
-            if inode.inode & PROC_INODE_FLAG_PID_CMDLINE > 0
+            if inode.inode == PROC_INODE_MOUNTS
+            {
+                let mut result = String::new();
+
+                if let Some(vfs) = &mut self.vfs
+                {
+                    for (path, info) in vfs.mount_entries()?
+                    {
+                        result += &format!("{} {} {} {} 0 0\n", info.source, path, info.fstype, crate::fs::mounts::format_mount_options(info.flags));
+                    }
+                }
+
+                Ok(Vec::from(result.as_bytes()))
+            }
+            else if inode.inode & PROC_INODE_FLAG_PID_CMDLINE > 0
             {
                 if let Some(proc_manager) = process::scheduler::get_process_manager()
                 {
@@ -310,11 +363,6 @@ impl Filesystem for ProcFilesystem
         }
     }
 
-    fn mount_fs_at(&mut self, _inode: FilesystemIndex, _root: FilesystemIndex, _name: alloc::string::String) -> FilesystemResult<()>
-    {
-        todo!()
-    }
-
     /// Open a filedescriptor for the given inode
     fn open_fd(&mut self, inode: FilesystemIndex, mode: usize) -> FilesystemResult<Box<dyn crate::process::descriptor::FileDescriptor>>
     {
@@ -326,7 +374,7 @@ impl Filesystem for ProcFilesystem
                 {
                     Ok(Box::new(InodeFileDescriptor::new(vfs, inode, mode).unwrap()))
                 }
-                else if inode.inode & (PROC_INODE_FLAG_PID_CMDLINE | PROC_INODE_FLAG_PID_STATM) > 0
+                else if inode.inode == PROC_INODE_MOUNTS || inode.inode & (PROC_INODE_FLAG_PID_CMDLINE | PROC_INODE_FLAG_PID_STATM) > 0
                 {
                     Ok(Box::new(InodeFileDescriptor::new(vfs, inode, mode).unwrap()))
                 }
@@ -366,4 +414,11 @@ impl Filesystem for ProcFilesystem
             Err(FilesystemError::FilesystemNotMounted)
         }
     }
+
+    /// Check if the results of lookups can be cached, filesystems whose entries change on their own return false
+    fn cacheable_lookups(&mut self) -> bool
+    {
+        // Entries come and go with the processes on the system
+        false
+    }
 }
\ No newline at end of file
@@ -17,7 +17,27 @@ pub enum FilesystemError
     OutOfSpace,
     PermissionDenied,
     DirectoryNotEmpty,
-    INodeIsDirectory
+    INodeIsDirectory,
+    NotASymbolicLink,
+    TooManySymbolicLinks,
+    FileExists,
+    CrossDeviceLink,
+    InvalidArgument,
+    Busy,
+    ReadOnlyFilesystem,
+    UnknownFilesystemType,
+    UncleanFilesystem,
+    FileTooLarge,
+    OperationNotSupported,
+    OffsetPastEnd,
+    AttributeNotFound,
+    InvalidAttributeName,
+    AttributeTooLarge,
+    WouldBlock,
+    NoReaders,
+    IOError,
+    AccessDenied,
+    NameTooLong
 }
 
 impl FilesystemError
@@ -38,6 +58,26 @@ impl FilesystemError
             FilesystemError::PermissionDenied => errno::EPERM,
             FilesystemError::DirectoryNotEmpty => errno::ENOTEMPTY,
             FilesystemError::INodeIsDirectory => errno::EISDIR,
+            FilesystemError::NotASymbolicLink => errno::EINVAL,
+            FilesystemError::TooManySymbolicLinks => errno::ELOOP,
+            FilesystemError::FileExists => errno::EEXIST,
+            FilesystemError::CrossDeviceLink => errno::EXDEV,
+            FilesystemError::InvalidArgument => errno::EINVAL,
+            FilesystemError::Busy => errno::EBUSY,
+            FilesystemError::ReadOnlyFilesystem => errno::EROFS,
+            FilesystemError::UnknownFilesystemType => errno::ENODEV,
+            FilesystemError::UncleanFilesystem => errno::EUCLEAN,
+            FilesystemError::FileTooLarge => errno::EFBIG,
+            FilesystemError::OperationNotSupported => errno::EOPNOTSUPP,
+            FilesystemError::OffsetPastEnd => errno::ENXIO,
+            FilesystemError::AttributeNotFound => errno::ENODATA,
+            FilesystemError::InvalidAttributeName => errno::ERANGE,
+            FilesystemError::AttributeTooLarge => errno::E2BIG,
+            FilesystemError::WouldBlock => errno::EAGAIN,
+            FilesystemError::NoReaders => errno::ENXIO,
+            FilesystemError::IOError => errno::EIO,
+            FilesystemError::AccessDenied => errno::EACCES,
+            FilesystemError::NameTooLong => errno::ENAMETOOLONG,
         }
     }
 }
@@ -68,6 +108,46 @@ pub enum DirectoryEntryType
     SymbolicLink
 }
 
+/// Mask for the file type bits of a mode
+pub const S_IFMT: u16 = 0o170000;
+
+/// Mode file types
+pub const S_IFSOCK: u16 = 0o140000;
+pub const S_IFLNK: u16 = 0o120000;
+pub const S_IFREG: u16 = 0o100000;
+pub const S_IFBLK: u16 = 0o060000;
+pub const S_IFDIR: u16 = 0o040000;
+pub const S_IFCHR: u16 = 0o020000;
+pub const S_IFIFO: u16 = 0o010000;
+
+/// Mode bits which run a program as the owner or group of the file
+pub const S_ISUID: u16 = 0o4000;
+pub const S_ISGID: u16 = 0o2000;
+
+/// Fail a rename instead of replacing an existing target
+pub const RENAME_NOREPLACE: usize = 1;
+
+/// Atomically swap the source and target of a rename
+pub const RENAME_EXCHANGE: usize = 2;
+
+/// Allocate storage without changing the size of the file
+pub const FALLOC_FL_KEEP_SIZE: usize = 1;
+
+/// Deallocate a range of a file, leaving a hole which reads as zeros
+pub const FALLOC_FL_PUNCH_HOLE: usize = 2;
+
+/// Fail setting an extended attribute which already exists
+pub const XATTR_CREATE: usize = 1;
+
+/// Fail setting an extended attribute which does not exist yet
+pub const XATTR_REPLACE: usize = 2;
+
+/// Maximum length of an extended attribute name
+pub const XATTR_NAME_MAX: usize = 255;
+
+/// Maximum size of an extended attribute value
+pub const XATTR_SIZE_MAX: usize = 65536;
+
 /// Directory Entry
 #[derive(Debug, Clone)]
 pub struct DirectoryEntry
@@ -94,4 +174,16 @@ pub struct FileStat
     pub atime: usize,
     pub mtime: usize,
     pub ctime: usize
-}
\ No newline at end of file
+}
+
+/// Changes to make to the metadata of an inode, fields left as `None` are not changed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SetAttributes
+{
+    pub mode: Option<u16>,
+    pub uid: Option<u16>,
+    pub gid: Option<u16>,
+    pub size: Option<usize>,
+    pub atime: Option<usize>,
+    pub mtime: Option<usize>
+}
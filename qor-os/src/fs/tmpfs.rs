@@ -0,0 +1,1293 @@
+//! In memory filesystem
+//!
+//! Every inode lives on the kernel heap and is lost when the filesystem is
+//! unmounted. The total size of the file data and the number of inodes are
+//! both limited, so a runaway program filling `/tmp` cannot take down the
+//! rest of the kernel. File data is held in whole pages which are lent to the
+//! page cache, so it is never held twice.
+
+use crate::*;
+
+use super::fstrait::Filesystem;
+use super::structures::*;
+
+use alloc::collections::BTreeMap;
+
+use crate::process::descriptor::*;
+
+use libutils::paths::{OwnedPath, PathBuffer};
+
+use super::ioctl::*;
+
+/// Default maximum number of inodes in a tmpfs
+pub const TMPFS_DEFAULT_INODES: usize = 4096;
+
+/// Maximum length of a name in a tmpfs directory
+pub const TMPFS_NAME_MAX: usize = 255;
+
+/// Inode number of the root directory
+const TMPFS_ROOT_INODE: usize = 1;
+
+/// Data of a tmpfs file, pages which have never been written are holes
+struct TmpFile
+{
+    size: usize,
+    pages: BTreeMap<usize, *mut u8>
+}
+
+impl TmpFile
+{
+    /// Create a new, empty file
+    fn new() -> Self
+    {
+        Self { size: 0, pages: BTreeMap::new() }
+    }
+
+    /// Get the page holding the data at page index `index`, allocating it if it is a hole
+    fn page(&mut self, index: usize) -> FilesystemResult<*mut u8>
+    {
+        if let Some(page) = self.pages.get(&index)
+        {
+            return Ok(*page);
+        }
+
+        let page = mem::kpzalloc(1, "tmpfs").map_err(|_| FilesystemError::OutOfSpace)? as *mut u8;
+        self.pages.insert(index, page);
+
+        Ok(page)
+    }
+
+    /// Resize the file, freeing the pages past the new end and zeroing the rest of the new last page
+    fn resize(&mut self, size: usize)
+    {
+        if size < self.size
+        {
+            for page in self.pages.split_off(&((size + mem::PAGE_SIZE - 1) / mem::PAGE_SIZE)).values()
+            {
+                mem::kpfree(*page as usize, 1).unwrap();
+            }
+
+            self.zero(size, (size + mem::PAGE_SIZE - 1) / mem::PAGE_SIZE * mem::PAGE_SIZE);
+        }
+
+        self.size = size;
+    }
+
+    /// Zero the bytes in `start..end` which are not holes
+    fn zero(&mut self, start: usize, end: usize)
+    {
+        let mut position = start;
+
+        while position < end
+        {
+            let length = (mem::PAGE_SIZE - position % mem::PAGE_SIZE).min(end - position);
+
+            if let Some(page) = self.pages.get(&(position / mem::PAGE_SIZE))
+            {
+                unsafe { core::ptr::write_bytes(page.add(position % mem::PAGE_SIZE), 0, length) };
+            }
+
+            position += length;
+        }
+    }
+
+    /// Copy data out of the file starting at `offset`, returning the number of bytes copied
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> usize
+    {
+        let count = buffer.len().min(self.size.saturating_sub(offset));
+        let mut done = 0;
+
+        while done < count
+        {
+            let position = offset + done;
+            let length = (mem::PAGE_SIZE - position % mem::PAGE_SIZE).min(count - done);
+
+            match self.pages.get(&(position / mem::PAGE_SIZE))
+            {
+                Some(page) => unsafe { core::ptr::copy(page.add(position % mem::PAGE_SIZE), buffer[done..].as_mut_ptr(), length) },
+                None => buffer[done..done + length].fill(0)
+            }
+
+            done += length;
+        }
+
+        count
+    }
+
+    /// Copy data into the file starting at `offset`, which must already be large enough to hold it
+    fn write(&mut self, offset: usize, data: &[u8]) -> FilesystemResult<()>
+    {
+        let mut done = 0;
+
+        while done < data.len()
+        {
+            let position = offset + done;
+            let length = (mem::PAGE_SIZE - position % mem::PAGE_SIZE).min(data.len() - done);
+            let page = self.page(position / mem::PAGE_SIZE)?;
+
+            // Pages lent to the page cache are written back onto themselves
+            if unsafe { page.add(position % mem::PAGE_SIZE) } as *const u8 != data[done..].as_ptr()
+            {
+                unsafe { core::ptr::copy(data[done..].as_ptr(), page.add(position % mem::PAGE_SIZE), length) };
+            }
+
+            done += length;
+        }
+
+        Ok(())
+    }
+}
+
+impl core::ops::Drop for TmpFile
+{
+    fn drop(&mut self)
+    {
+        for page in self.pages.values()
+        {
+            mem::kpfree(*page as usize, 1).unwrap();
+        }
+    }
+}
+
+/// Contents of a tmpfs inode
+enum TmpInodeData
+{
+    Directory { entries: BTreeMap<String, usize>, parent: usize },
+    File(TmpFile),
+    Symlink(String),
+    Fifo,
+    Socket
+}
+
+/// A single tmpfs inode
+struct TmpInode
+{
+    data: TmpInodeData,
+    mode: u16,
+    links: u16,
+    uid: u16,
+    gid: u16,
+    atime: usize,
+    mtime: usize,
+    ctime: usize,
+    xattrs: BTreeMap<String, Vec<u8>>
+}
+
+impl TmpInode
+{
+    /// Get the size of the inode as reported by stat
+    fn size(&self) -> usize
+    {
+        match &self.data
+        {
+            TmpInodeData::Directory { entries, .. } => (entries.len() + 2) * 20,
+            TmpInodeData::File(file) => file.size,
+            TmpInodeData::Symlink(target) => target.len(),
+            TmpInodeData::Fifo | TmpInodeData::Socket => 0
+        }
+    }
+
+    /// Get the number of bytes taken by the extended attributes of the inode
+    fn xattr_size(&self) -> usize
+    {
+        self.xattrs.iter().map(|(name, value)| name.len() + value.len()).sum()
+    }
+
+    /// Get the type of directory entry which refers to the inode
+    fn entry_type(&self) -> DirectoryEntryType
+    {
+        match &self.data
+        {
+            TmpInodeData::Directory { .. } => DirectoryEntryType::Directory,
+            TmpInodeData::File(_) => DirectoryEntryType::RegularFile,
+            TmpInodeData::Symlink(_) => DirectoryEntryType::SymbolicLink,
+            TmpInodeData::Fifo => DirectoryEntryType::FirstInFirstOut,
+            TmpInodeData::Socket => DirectoryEntryType::Socket
+        }
+    }
+
+    /// Check if the inode is a directory
+    fn is_directory(&self) -> bool
+    {
+        matches!(self.data, TmpInodeData::Directory { .. })
+    }
+}
+
+/// Get the current time in seconds
+fn now() -> usize
+{
+    (crate::drivers::rtc::driver::RealTimeClockDriver::get_driver().get_unix_timestamp_nano() / 1_000_000_000) as usize
+}
+
+/// Parse a size given in a mount option, allowing a `k`, `m` or `g` suffix
+fn parse_size(value: &str) -> FilesystemResult<usize>
+{
+    let (digits, multiplier) = match value.chars().last().map(|c| c.to_ascii_lowercase())
+    {
+        Some('k') => (&value[..value.len() - 1], 1024),
+        Some('m') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('g') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1)
+    };
+
+    digits.parse::<usize>().map(|size| size * multiplier).map_err(|_| FilesystemError::InvalidArgument)
+}
+
+/// Temporary Filesystem
+pub struct TmpFilesystem
+{
+    mount_id: Option<usize>,
+    vfs: Option<&'static mut crate::fs::vfs::FilesystemInterface>,
+    inodes: BTreeMap<usize, TmpInode>,
+    next_inode: usize,
+    used_bytes: usize,
+    max_bytes: usize,
+    max_inodes: usize
+}
+
+impl TmpFilesystem
+{
+    /// Create a new, empty tmpfs which holds at most `max_bytes` of data in `max_inodes` inodes
+    pub fn new(max_bytes: usize, max_inodes: usize) -> Self
+    {
+        Self
+        {
+            mount_id: None,
+            vfs: None,
+            inodes: BTreeMap::new(),
+            next_inode: TMPFS_ROOT_INODE,
+            used_bytes: 0,
+            max_bytes,
+            max_inodes
+        }
+    }
+
+    /// Create a new tmpfs from the options given to mount, such as `size=4m,nr_inodes=128`
+    pub fn from_options(options: &str) -> FilesystemResult<Self>
+    {
+        // By default a tmpfs may use up to a quarter of the kernel's memory
+        let mut max_bytes = mem::total_kernel_pages() * mem::PAGE_SIZE / 4;
+        let mut max_inodes = TMPFS_DEFAULT_INODES;
+
+        for option in options.split(',').filter(|option| option.len() > 0)
+        {
+            match option.split_once('=')
+            {
+                Some(("size", value)) => max_bytes = parse_size(value)?,
+                Some(("nr_inodes", value)) => max_inodes = parse_size(value)?,
+                _ => return Err(FilesystemError::InvalidArgument)
+            }
+        }
+
+        Ok(Self::new(max_bytes, max_inodes))
+    }
+
+    /// Get the number of bytes of data stored in the filesystem
+    pub fn used_bytes(&self) -> usize
+    {
+        self.used_bytes
+    }
+
+    /// Get the vfs the filesystem is mounted in, used to forward requests for other filesystems
+    fn vfs(&mut self) -> FilesystemResult<&mut crate::fs::vfs::FilesystemInterface>
+    {
+        match &mut self.vfs
+        {
+            Some(vfs) => Ok(vfs),
+            None => Err(FilesystemError::FilesystemNotMounted)
+        }
+    }
+
+    /// Check if an index refers to an inode on this filesystem
+    fn is_local(&self, index: FilesystemIndex) -> bool
+    {
+        Some(index.mount_id) == self.mount_id
+    }
+
+    /// Build the index of an inode on this filesystem
+    fn index(&self, inode: usize) -> FilesystemIndex
+    {
+        FilesystemIndex { mount_id: self.mount_id.unwrap_or(0), inode }
+    }
+
+    /// Get an inode
+    fn get(&self, inode: usize) -> FilesystemResult<&TmpInode>
+    {
+        self.inodes.get(&inode).ok_or(FilesystemError::BadINode)
+    }
+
+    /// Get a mutable reference to an inode
+    fn get_mut(&mut self, inode: usize) -> FilesystemResult<&mut TmpInode>
+    {
+        self.inodes.get_mut(&inode).ok_or(FilesystemError::BadINode)
+    }
+
+    /// Get the entries of a directory
+    fn entries(&self, directory: usize) -> FilesystemResult<&BTreeMap<String, usize>>
+    {
+        match &self.get(directory)?.data
+        {
+            TmpInodeData::Directory { entries, .. } => Ok(entries),
+            _ => Err(FilesystemError::INodeIsNotADirectory)
+        }
+    }
+
+    /// Get a mutable reference to the entries of a directory
+    fn entries_mut(&mut self, directory: usize) -> FilesystemResult<&mut BTreeMap<String, usize>>
+    {
+        match &mut self.get_mut(directory)?.data
+        {
+            TmpInodeData::Directory { entries, .. } => Ok(entries),
+            _ => Err(FilesystemError::INodeIsNotADirectory)
+        }
+    }
+
+    /// Point the parent entry of a directory at a new directory
+    fn set_parent(&mut self, directory: usize, new_parent: usize) -> FilesystemResult<()>
+    {
+        if let TmpInodeData::Directory { parent, .. } = &mut self.get_mut(directory)?.data
+        {
+            *parent = new_parent;
+        }
+
+        Ok(())
+    }
+
+    /// Add or remove links to an inode, returning the new number of links
+    fn adjust_links(&mut self, inode: usize, delta: isize) -> FilesystemResult<usize>
+    {
+        let inode = self.get_mut(inode)?;
+
+        inode.links = (inode.links as isize + delta).max(0) as u16;
+        inode.ctime = now();
+
+        Ok(inode.links as usize)
+    }
+
+    /// Account for a change in the amount of data stored, failing if it would exceed the size limit
+    fn reserve(&mut self, old_size: usize, new_size: usize) -> FilesystemResult<()>
+    {
+        let used = self.used_bytes - old_size + new_size;
+
+        if new_size > old_size && used > self.max_bytes
+        {
+            return Err(FilesystemError::OutOfSpace);
+        }
+
+        self.used_bytes = used;
+
+        Ok(())
+    }
+
+    /// Allocate a new inode with a single link
+    fn allocate(&mut self, data: TmpInodeData, mode: u16) -> FilesystemResult<usize>
+    {
+        if self.inodes.len() >= self.max_inodes
+        {
+            return Err(FilesystemError::OutOfSpace);
+        }
+
+        let size = match &data
+        {
+            TmpInodeData::Symlink(target) => target.len(),
+            _ => 0
+        };
+
+        self.reserve(0, size)?;
+
+        let time = now();
+        let inode = self.next_inode;
+        self.next_inode += 1;
+
+        self.inodes.insert(inode, TmpInode { data, mode, links: 1, uid: 0, gid: 0, atime: time, mtime: time, ctime: time, xattrs: BTreeMap::new() });
+
+        Ok(inode)
+    }
+
+    /// Free an inode along with the data it holds
+    fn release(&mut self, inode: usize) -> FilesystemResult<()>
+    {
+        let removed = self.inodes.remove(&inode).ok_or(FilesystemError::BadINode)?;
+
+        self.used_bytes -= removed.xattr_size();
+
+        match removed.data
+        {
+            TmpInodeData::File(file) => self.used_bytes -= file.size,
+            TmpInodeData::Symlink(target) => self.used_bytes -= target.len(),
+            TmpInodeData::Directory { .. } | TmpInodeData::Fifo | TmpInodeData::Socket => {}
+        }
+
+        Ok(())
+    }
+
+    /// Add an entry for an inode to a directory
+    fn add_entry(&mut self, directory: usize, name: String, inode: usize) -> FilesystemResult<()>
+    {
+        if name.len() > TMPFS_NAME_MAX
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        let entries = self.entries_mut(directory)?;
+
+        if entries.contains_key(&name)
+        {
+            return Err(FilesystemError::FileExists);
+        }
+
+        entries.insert(name, inode);
+
+        let time = now();
+        let directory = self.get_mut(directory)?;
+        directory.mtime = time;
+        directory.ctime = time;
+
+        Ok(())
+    }
+
+    /// Remove an entry from a directory, returning the inode it referred to
+    fn remove_entry(&mut self, directory: usize, name: &str) -> FilesystemResult<usize>
+    {
+        let inode = self.entries_mut(directory)?.remove(name).ok_or_else(|| FilesystemError::FileNotFound(name.to_string()))?;
+
+        let time = now();
+        let directory = self.get_mut(directory)?;
+        directory.mtime = time;
+        directory.ctime = time;
+
+        Ok(inode)
+    }
+
+    /// Create a new inode in a directory
+    fn create_entry(&mut self, directory: usize, name: String, data: TmpInodeData, mode: u16) -> FilesystemResult<usize>
+    {
+        if self.entries(directory)?.contains_key(&name)
+        {
+            return Err(FilesystemError::FileExists);
+        }
+
+        let inode = self.allocate(data, mode)?;
+
+        if let Err(e) = self.add_entry(directory, name, inode)
+        {
+            self.release(inode)?;
+            return Err(e);
+        }
+
+        Ok(inode)
+    }
+
+    /// Resize the data of a file, zero filling any new space
+    fn resize_file(&mut self, inode: usize, size: usize) -> FilesystemResult<()>
+    {
+        let old_size = match &self.get(inode)?.data
+        {
+            TmpInodeData::File(file) => file.size,
+            TmpInodeData::Directory { .. } => return Err(FilesystemError::INodeIsDirectory),
+            TmpInodeData::Symlink(_) | TmpInodeData::Fifo | TmpInodeData::Socket => return Err(FilesystemError::InvalidArgument)
+        };
+
+        self.reserve(old_size, size)?;
+
+        let time = now();
+        let inode = self.get_mut(inode)?;
+
+        if let TmpInodeData::File(file) = &mut inode.data
+        {
+            file.resize(size);
+        }
+
+        inode.mtime = time;
+        inode.ctime = time;
+
+        Ok(())
+    }
+
+    /// Get the data of a file
+    fn file_data(&mut self, inode: usize) -> FilesystemResult<&mut TmpFile>
+    {
+        match &mut self.get_mut(inode)?.data
+        {
+            TmpInodeData::File(file) => Ok(file),
+            TmpInodeData::Directory { .. } => Err(FilesystemError::INodeIsDirectory),
+            TmpInodeData::Symlink(_) | TmpInodeData::Fifo | TmpInodeData::Socket => Err(FilesystemError::InvalidArgument)
+        }
+    }
+
+    /// Move the entry `old_name` in `old_dir` to `new_name` in `new_dir`
+    fn rename_entry(&mut self, old_dir: usize, old_name: &str, new_dir: usize, new_name: &str, flags: usize) -> FilesystemResult<()>
+    {
+        let source = *self.entries(old_dir)?.get(old_name).ok_or_else(|| FilesystemError::FileNotFound(old_name.to_string()))?;
+        let target = self.entries(new_dir)?.get(new_name).copied();
+
+        if target == Some(source)
+        {
+            return Ok(());
+        }
+
+        let source_is_dir = self.get(source)?.is_directory();
+
+        if flags & RENAME_EXCHANGE > 0
+        {
+            let target = target.ok_or_else(|| FilesystemError::FileNotFound(new_name.to_string()))?;
+            let target_is_dir = self.get(target)?.is_directory();
+
+            self.entries_mut(old_dir)?.insert(old_name.to_string(), target);
+            self.entries_mut(new_dir)?.insert(new_name.to_string(), source);
+
+            // Each directory's parent entry and the link it adds to its parent follow it
+            if old_dir != new_dir
+            {
+                if source_is_dir
+                {
+                    self.set_parent(source, new_dir)?;
+                    self.adjust_links(old_dir, -1)?;
+                    self.adjust_links(new_dir, 1)?;
+                }
+
+                if target_is_dir
+                {
+                    self.set_parent(target, old_dir)?;
+                    self.adjust_links(new_dir, -1)?;
+                    self.adjust_links(old_dir, 1)?;
+                }
+            }
+
+            self.get_mut(source)?.ctime = now();
+            self.get_mut(target)?.ctime = now();
+
+            return Ok(());
+        }
+
+        if let Some(target) = target
+        {
+            if flags & RENAME_NOREPLACE > 0
+            {
+                return Err(FilesystemError::FileExists);
+            }
+
+            let target_is_dir = self.get(target)?.is_directory();
+
+            if source_is_dir && !target_is_dir
+            {
+                return Err(FilesystemError::INodeIsNotADirectory);
+            }
+            else if !source_is_dir && target_is_dir
+            {
+                return Err(FilesystemError::INodeIsDirectory);
+            }
+            else if target_is_dir && self.entries(target)?.len() > 0
+            {
+                return Err(FilesystemError::DirectoryNotEmpty);
+            }
+
+            self.entries_mut(new_dir)?.insert(new_name.to_string(), source);
+
+            // A replaced directory also takes its parent entry's link away from the new directory
+            if target_is_dir
+            {
+                self.adjust_links(new_dir, -1)?;
+                self.release(target)?;
+            }
+            else if self.adjust_links(target, -1)? == 0
+            {
+                self.release(target)?;
+            }
+        }
+        else
+        {
+            self.add_entry(new_dir, new_name.to_string(), source)?;
+        }
+
+        self.remove_entry(old_dir, old_name)?;
+
+        if source_is_dir && old_dir != new_dir
+        {
+            self.set_parent(source, new_dir)?;
+            self.adjust_links(old_dir, -1)?;
+            self.adjust_links(new_dir, 1)?;
+        }
+
+        self.get_mut(source)?.ctime = now();
+
+        Ok(())
+    }
+}
+
+impl Filesystem for TmpFilesystem
+{
+    /// Initialize the filesystem on the current disk
+    fn init(&mut self) -> FilesystemResult<()>
+    {
+        if self.inodes.len() == 0
+        {
+            let root = self.allocate(TmpInodeData::Directory { entries: BTreeMap::new(), parent: TMPFS_ROOT_INODE }, S_IFDIR | 0o1777)?;
+            self.get_mut(root)?.links = 2;
+        }
+
+        Ok(())
+    }
+
+    /// Sync the filesystem with the current disk
+    fn sync(&mut self) -> FilesystemResult<()>
+    {
+        // No need to sync this filesystem as it is stored entirely in ram
+        Ok(())
+    }
+
+    /// Set the mount_id of the filesystem
+    fn set_mount_id(&mut self, mount_id: usize, vfs: &'static mut crate::fs::vfs::FilesystemInterface)
+    {
+        self.mount_id = Some(mount_id);
+        self.vfs = Some(vfs);
+    }
+
+    /// Get the index of the root directory of the filesystem
+    fn get_root_index(&mut self) -> FilesystemResult<FilesystemIndex>
+    {
+        if self.mount_id.is_some()
+        {
+            Ok(self.index(TMPFS_ROOT_INODE))
+        }
+        else
+        {
+            Err(FilesystemError::FilesystemUninitialized)
+        }
+    }
+
+    /// Convert a path to an inode
+    fn path_to_inode(&mut self, path: PathBuffer) -> FilesystemResult<FilesystemIndex>
+    {
+        self.vfs()?.path_to_inode(path)
+    }
+
+    /// Convert an inode to a path
+    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<OwnedPath>
+    {
+        self.vfs()?.inode_to_path(inode)
+    }
+
+    /// Get the directory entries for the given inode
+    fn get_dir_entries(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<DirectoryEntry>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.get_dir_entries(inode);
+        }
+
+        let parent = match &self.get(inode.inode)?.data
+        {
+            TmpInodeData::Directory { parent, .. } => *parent,
+            _ => return Err(FilesystemError::INodeIsNotADirectory)
+        };
+
+        let mut result = Vec::new();
+
+        result.push(DirectoryEntry { index: inode, name: String::from("."), entry_type: DirectoryEntryType::Directory });
+        result.push(DirectoryEntry { index: self.index(parent), name: String::from(".."), entry_type: DirectoryEntryType::Directory });
+
+        for (name, child) in self.entries(inode.inode)?
+        {
+            result.push(DirectoryEntry { index: self.index(*child), name: name.clone(), entry_type: self.get(*child)?.entry_type() });
+        }
+
+        Ok(result)
+    }
+
+    /// Find the inode for a name in the directory at the given inode
+    fn lookup(&mut self, directory: FilesystemIndex, name: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.lookup(directory, name);
+        }
+
+        match (&self.get(directory.inode)?.data, name)
+        {
+            (TmpInodeData::Directory { .. }, ".") => Ok(directory),
+            (TmpInodeData::Directory { parent, .. }, "..") => Ok(self.index(*parent)),
+            (TmpInodeData::Directory { entries, .. }, _) => entries.get(name).map(|inode| self.index(*inode)).ok_or_else(|| FilesystemError::FileNotFound(name.to_string())),
+            _ => Err(FilesystemError::INodeIsNotADirectory)
+        }
+    }
+
+    /// Get the directory entry for the given inode
+    fn get_stat(&mut self, inode: FilesystemIndex) -> FilesystemResult<FileStat>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.get_stat(inode);
+        }
+
+        let data = self.get(inode.inode)?;
+        let size = data.size();
+
+        Ok(FileStat
+        {
+            dev_id: inode.mount_id,
+            inode: inode.inode,
+            mode: data.mode,
+            links: data.links,
+            uid: data.uid,
+            gid: data.gid,
+            special_dev_id: 0,
+            size,
+            blk_size: mem::PAGE_SIZE,
+            blocks_alloced: (size + 511) / 512,
+            atime: data.atime,
+            mtime: data.mtime,
+            ctime: data.ctime
+        })
+    }
+
+    /// Create a file in the directory at the given inode
+    fn create_file(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.create_file(inode, name);
+        }
+
+        let file = self.create_entry(inode.inode, name, TmpInodeData::File(TmpFile::new()), S_IFREG | 0o644)?;
+
+        Ok(self.index(file))
+    }
+
+    /// Create a directory in the directory at the given inode
+    fn create_directory(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.create_directory(inode, name);
+        }
+
+        let directory = self.create_entry(inode.inode, name, TmpInodeData::Directory { entries: BTreeMap::new(), parent: inode.inode }, S_IFDIR | 0o755)?;
+
+        // The new directory is linked from its parent and its own loopback entry, and its parent entry links the parent
+        self.get_mut(directory)?.links = 2;
+        self.adjust_links(inode.inode, 1)?;
+
+        Ok(self.index(directory))
+    }
+
+    /// Create a symbolic link in the directory at the given inode which points to `target`
+    fn create_symlink(&mut self, directory: FilesystemIndex, name: String, target: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.create_symlink(directory, name, target);
+        }
+
+        let link = self.create_entry(directory.inode, name, TmpInodeData::Symlink(target.to_string()), S_IFLNK | 0o777)?;
+
+        Ok(self.index(link))
+    }
+
+    /// Create a named pipe in the directory at the given inode
+    fn create_fifo(&mut self, directory: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.create_fifo(directory, name);
+        }
+
+        let fifo = self.create_entry(directory.inode, name, TmpInodeData::Fifo, S_IFIFO | 0o644)?;
+
+        Ok(self.index(fifo))
+    }
+
+    /// Create a socket in the directory at the given inode
+    fn create_socket(&mut self, directory: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.create_socket(directory, name);
+        }
+
+        let socket = self.create_entry(directory.inode, name, TmpInodeData::Socket, S_IFSOCK | 0o755)?;
+
+        Ok(self.index(socket))
+    }
+
+    /// Read the target of the symbolic link at the given inode
+    fn read_link(&mut self, inode: FilesystemIndex) -> FilesystemResult<String>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.read_link(inode);
+        }
+
+        match &self.get(inode.inode)?.data
+        {
+            TmpInodeData::Symlink(target) => Ok(target.clone()),
+            _ => Err(FilesystemError::NotASymbolicLink)
+        }
+    }
+
+    /// Add an entry for an existing inode to the directory at the given inode
+    fn create_link(&mut self, inode: FilesystemIndex, directory: FilesystemIndex, name: String) -> FilesystemResult<()>
+    {
+        if !self.is_local(directory)
+        {
+            return self.vfs()?.create_link(inode, directory, name);
+        }
+
+        if !self.is_local(inode)
+        {
+            return Err(FilesystemError::CrossDeviceLink);
+        }
+
+        if self.get(inode.inode)?.is_directory()
+        {
+            return Err(FilesystemError::PermissionDenied);
+        }
+
+        self.add_entry(directory.inode, name, inode.inode)?;
+        self.adjust_links(inode.inode, 1)?;
+
+        Ok(())
+    }
+
+    /// Change the metadata of an inode, only the permission bits of a new mode are used
+    fn set_attr(&mut self, inode: FilesystemIndex, attributes: SetAttributes) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.set_attr(inode, attributes);
+        }
+
+        if let Some(size) = attributes.size
+        {
+            self.resize_file(inode.inode, size)?;
+        }
+
+        let time = now();
+        let data = self.get_mut(inode.inode)?;
+
+        if let Some(mode) = attributes.mode
+        {
+            data.mode = (data.mode & S_IFMT) | (mode & 0o7777);
+        }
+
+        if let Some(uid) = attributes.uid
+        {
+            data.uid = uid;
+        }
+
+        if let Some(gid) = attributes.gid
+        {
+            data.gid = gid;
+        }
+
+        if let Some(atime) = attributes.atime
+        {
+            data.atime = atime;
+        }
+
+        if let Some(mtime) = attributes.mtime
+        {
+            data.mtime = mtime;
+        }
+
+        data.ctime = time;
+
+        Ok(())
+    }
+
+    /// Move the entry `old_name` in the directory at `old_directory` to `new_name` in `new_directory`, replacing or
+    /// exchanging with any existing entry depending on the `RENAME_*` flags
+    fn rename(&mut self, old_directory: FilesystemIndex, old_name: String, new_directory: FilesystemIndex, new_name: String, flags: usize) -> FilesystemResult<()>
+    {
+        if self.is_local(old_directory) && self.is_local(new_directory)
+        {
+            self.rename_entry(old_directory.inode, &old_name, new_directory.inode, &new_name, flags)
+        }
+        else if old_directory.mount_id != new_directory.mount_id
+        {
+            Err(FilesystemError::CrossDeviceLink)
+        }
+        else
+        {
+            self.vfs()?.rename(old_directory, old_name, new_directory, new_name, flags)
+        }
+    }
+
+    /// Remove an inode at the given index from the given directory
+    fn remove_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.remove_inode(inode);
+        }
+
+        if inode.inode == TMPFS_ROOT_INODE
+        {
+            return Err(FilesystemError::Busy);
+        }
+
+        self.release(inode.inode)
+    }
+
+    /// Remove a directory entry from the directory at the given inode
+    fn remove_dir_entry(&mut self, directory_index: FilesystemIndex, name: String) -> FilesystemResult<()>
+    {
+        if !self.is_local(directory_index)
+        {
+            return self.vfs()?.remove_dir_entry(directory_index, name);
+        }
+
+        let removed = self.remove_entry(directory_index.inode, &name)?;
+
+        // Removing a directory also removes the link its `..` entry held on the parent
+        if self.get(removed)?.is_directory()
+        {
+            self.adjust_links(directory_index.inode, -1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Increment the number of links to an inode
+    fn increment_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.increment_links(inode);
+        }
+
+        self.adjust_links(inode.inode, 1)
+    }
+
+    /// Decrement the number of links to an inode
+    fn decrement_links(&mut self, inode: FilesystemIndex) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.decrement_links(inode);
+        }
+
+        self.adjust_links(inode.inode, -1)
+    }
+
+    /// Read the data stored in an inode
+    fn read_inode(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<u8>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.read_inode(inode);
+        }
+
+        match &self.get(inode.inode)?.data
+        {
+            TmpInodeData::File(file) =>
+            {
+                let mut data = vec![0; file.size];
+                file.read(0, &mut data);
+
+                Ok(data)
+            },
+            TmpInodeData::Symlink(target) => Ok(Vec::from(target.as_bytes())),
+            TmpInodeData::Fifo | TmpInodeData::Socket => Ok(Vec::new()),
+            TmpInodeData::Directory { .. } => Err(FilesystemError::INodeIsDirectory)
+        }
+    }
+
+    /// Write data to an inode
+    fn write_inode(&mut self, inode: FilesystemIndex, data: &[u8]) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.write_inode(inode, data);
+        }
+
+        self.resize_file(inode.inode, data.len())?;
+        self.file_data(inode.inode)?.write(0, data)
+    }
+
+    /// Read a single page of the data stored in an inode into the buffer, returning the number of bytes read
+    fn read_inode_page(&mut self, inode: FilesystemIndex, page: usize, buffer: *mut u8) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.read_inode_page(inode, page, buffer);
+        }
+
+        let buffer = unsafe { core::slice::from_raw_parts_mut(buffer, mem::PAGE_SIZE) };
+
+        Ok(self.file_data(inode.inode)?.read(page * mem::PAGE_SIZE, buffer))
+    }
+
+    /// Write back pages of the data stored in an inode, resizing the inode to `size` bytes
+    fn write_inode_pages(&mut self, inode: FilesystemIndex, size: usize, pages: &[(usize, *const u8)]) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.write_inode_pages(inode, size, pages);
+        }
+
+        self.resize_file(inode.inode, size)?;
+
+        let file = self.file_data(inode.inode)?;
+
+        for (page, ptr) in pages
+        {
+            let start = (page * mem::PAGE_SIZE).min(size);
+            let end = (start + mem::PAGE_SIZE).min(size);
+
+            file.write(start, unsafe { core::slice::from_raw_parts(*ptr, end - start) })?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the page holding a page of a file, which the page cache uses in place of a copy
+    fn inode_page_memory(&mut self, inode: FilesystemIndex, page: usize) -> FilesystemResult<Option<*mut u8>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.inode_page_memory(inode, page);
+        }
+
+        let file = self.file_data(inode.inode)?;
+
+        // Pages past the end of the file are not accounted for, so they are left to the page cache
+        if page * mem::PAGE_SIZE >= file.size
+        {
+            return Ok(None);
+        }
+
+        file.page(page).map(Some)
+    }
+
+    /// Hand a page lent to the page cache over to it, the file gets a new page if it needs one again
+    fn disown_inode_page(&mut self, inode: FilesystemIndex, page: usize)
+    {
+        if !self.is_local(inode)
+        {
+            if let Ok(vfs) = self.vfs()
+            {
+                vfs.disown_inode_page(inode, page);
+            }
+
+            return;
+        }
+
+        if let Ok(file) = self.file_data(inode.inode)
+        {
+            let data = file.pages.remove(&page);
+
+            // The page was mapped with the data at the time, the file keeps a copy of it
+            if let Some(data) = data
+            {
+                match file.page(page)
+                {
+                    Ok(copy) => unsafe { core::ptr::copy(data, copy, mem::PAGE_SIZE) },
+                    Err(e) => kwarnln!("Unable to keep page {} of tmpfs inode {}: {:?}", page, inode.inode, e)
+                }
+            }
+        }
+    }
+
+    /// Make sure a file can grow to `size` bytes without going over the size limit
+    fn reserve_size(&mut self, inode: FilesystemIndex, size: usize) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.reserve_size(inode, size);
+        }
+
+        if size > self.file_data(inode.inode)?.size
+        {
+            self.resize_file(inode.inode, size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Allocate space for `length` bytes of a file starting at `offset`, or punch a hole there. As all of the data is
+    /// in memory, allocating only checks the space is available and extends the file
+    fn allocate_range(&mut self, inode: FilesystemIndex, mode: usize, offset: usize, length: usize) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.allocate_range(inode, mode, offset, length);
+        }
+
+        let end = offset.checked_add(length).ok_or(FilesystemError::FileTooLarge)?;
+        let size = self.file_data(inode.inode)?.size;
+
+        if mode & FALLOC_FL_PUNCH_HOLE != 0
+        {
+            // The pages may be lent to the page cache, so they are zeroed rather than freed
+            self.file_data(inode.inode)?.zero(offset, end.min(size));
+        }
+        else if mode & FALLOC_FL_KEEP_SIZE != 0
+        {
+            if end > size && self.used_bytes + (end - size) > self.max_bytes
+            {
+                return Err(FilesystemError::OutOfSpace);
+            }
+        }
+        else if end > size
+        {
+            self.resize_file(inode.inode, end)?;
+        }
+
+        Ok(())
+    }
+
+    /// Open a filedescriptor for the given inode
+    fn open_fd(&mut self, inode: FilesystemIndex, mode: usize) -> FilesystemResult<Box<dyn crate::process::descriptor::FileDescriptor>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.open_fd(inode, mode);
+        }
+
+        self.get(inode.inode)?;
+
+        Ok(Box::new(PageCacheDescriptor::new(self.vfs()?, inode, mode)?))
+    }
+
+    /// Execute an ioctl command on an inode
+    fn exec_ioctl(&mut self, inode: FilesystemIndex, cmd: IOControlCommand) -> FilesystemResult<usize>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.exec_ioctl(inode, cmd);
+        }
+
+        // Nothing to do here (yet)
+        Ok(usize::MAX)
+    }
+
+    /// Get the value of an extended attribute of an inode
+    fn get_xattr(&mut self, inode: FilesystemIndex, name: &str) -> FilesystemResult<Vec<u8>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.get_xattr(inode, name);
+        }
+
+        self.get(inode.inode)?.xattrs.get(name).cloned().ok_or(FilesystemError::AttributeNotFound)
+    }
+
+    /// Set the value of an extended attribute of an inode, creating the attribute if it does not exist
+    fn set_xattr(&mut self, inode: FilesystemIndex, name: &str, value: &[u8]) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.set_xattr(inode, name, value);
+        }
+
+        // Attributes count towards the size limit along with the file data
+        let old_size = self.get(inode.inode)?.xattrs.get(name).map(|old| name.len() + old.len()).unwrap_or(0);
+        self.reserve(old_size, name.len() + value.len())?;
+
+        let data = self.get_mut(inode.inode)?;
+        data.xattrs.insert(name.to_string(), value.to_vec());
+        data.ctime = now();
+
+        Ok(())
+    }
+
+    /// Get the names of every extended attribute of an inode
+    fn list_xattr(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<String>>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.list_xattr(inode);
+        }
+
+        Ok(self.get(inode.inode)?.xattrs.keys().cloned().collect())
+    }
+
+    /// Remove an extended attribute from an inode
+    fn remove_xattr(&mut self, inode: FilesystemIndex, name: &str) -> FilesystemResult<()>
+    {
+        if !self.is_local(inode)
+        {
+            return self.vfs()?.remove_xattr(inode, name);
+        }
+
+        let data = self.get_mut(inode.inode)?;
+        let value = data.xattrs.remove(name).ok_or(FilesystemError::AttributeNotFound)?;
+        data.ctime = now();
+
+        self.used_bytes -= name.len() + value.len();
+
+        Ok(())
+    }
+}
+
+/// Temporary Filesystem Test
+#[test_case]
+fn tmpfs_files()
+{
+    let mut fs = TmpFilesystem::new(16, 8);
+    fs.mount_id = Some(0);
+    fs.init().unwrap();
+
+    let root = fs.get_root_index().unwrap();
+
+    // Files can be created, written and read back, within the size limit
+    let file = fs.create_file(root, String::from("file")).unwrap();
+    fs.write_inode(file, b"Hello").unwrap();
+    assert_eq!(fs.read_inode(file).unwrap(), b"Hello");
+    assert_eq!(fs.get_stat(file).unwrap().size, 5);
+    assert!(fs.write_inode(file, &[0; 17]).is_err());
+    assert_eq!(fs.used_bytes(), 5);
+
+    // Pages are lent out rather than copied, and growing a file is checked against the size limit up front
+    let page = fs.inode_page_memory(file, 0).unwrap().unwrap();
+    unsafe { page.write(b'J') };
+    assert_eq!(fs.read_inode(file).unwrap(), b"Jello");
+    assert!(fs.reserve_size(file, 17).is_err());
+    fs.reserve_size(file, 8).unwrap();
+    assert_eq!(fs.used_bytes(), 8);
+    fs.set_attr(file, SetAttributes { size: Some(5), ..Default::default() }).unwrap();
+    assert_eq!(fs.read_inode(file).unwrap(), b"Jello");
+
+    // Directories link their parent, and renaming moves that link
+    let dir = fs.create_directory(root, String::from("dir")).unwrap();
+    assert_eq!(fs.get_stat(root).unwrap().links, 3);
+    fs.rename(root, String::from("file"), dir, String::from("moved"), 0).unwrap();
+    assert_eq!(fs.lookup(dir, "moved").unwrap(), file);
+    assert!(fs.lookup(root, "file").is_err());
+
+    // Unlinking the last name frees the data
+    fs.unlink_inode(file, dir, String::from("moved")).unwrap();
+    assert_eq!(fs.used_bytes(), 0);
+
+    // Named pipes hold no data of their own
+    let fifo = fs.create_fifo(root, String::from("fifo")).unwrap();
+    assert_eq!(fs.get_stat(fifo).unwrap().mode & S_IFMT, S_IFIFO);
+    assert!(fs.write_inode(fifo, b"data").is_err());
+    assert!(fs.get_dir_entries(root).unwrap().iter().any(|entry| entry.entry_type == DirectoryEntryType::FirstInFirstOut));
+}
+
+/// Temporary Filesystem Extended Attribute Test
+#[test_case]
+fn tmpfs_xattrs()
+{
+    let mut fs = TmpFilesystem::new(16, 8);
+    fs.mount_id = Some(0);
+    fs.init().unwrap();
+
+    let root = fs.get_root_index().unwrap();
+    let file = fs.create_file(root, String::from("file")).unwrap();
+
+    // Attributes are replaced in place and count towards the size limit
+    fs.set_xattr(file, "user.a", b"one").unwrap();
+    fs.set_xattr(file, "user.a", b"two!").unwrap();
+    assert_eq!(fs.get_xattr(file, "user.a").unwrap(), b"two!");
+    assert_eq!(fs.used_bytes(), 10);
+    assert!(fs.set_xattr(file, "user.b", &[0; 16]).is_err());
+
+    assert_eq!(fs.list_xattr(file).unwrap(), vec![String::from("user.a")]);
+
+    fs.remove_xattr(file, "user.a").unwrap();
+    assert!(fs.get_xattr(file, "user.a").is_err());
+    assert_eq!(fs.used_bytes(), 0);
+}
@@ -3,13 +3,29 @@ use crate::*;
 use super::fstrait::Filesystem;
 use super::structures::*;
 
-use alloc::{collections::BTreeMap, borrow::ToOwned};
-use alloc::format;
+use super::acl::*;
+use super::dcache::{DirectoryCache, DENTRY_CACHE_CAPACITY};
+use super::locks::LockTable;
+use super::mounts::*;
+
+use crate::process::data::Credentials;
+use crate::process::pipe::{Pipe, SharedPipe};
+use crate::process::socket::BoundSocket;
+
+use alloc::collections::BTreeMap;
 
 use libutils::paths::{OwnedPath, PathBuffer};
 
 use super::ioctl::IOControlCommand;
 
+/// Maximum number of symbolic links followed while resolving a single path
+pub const MAX_SYMLINK_DEPTH: usize = 40;
+
+// Must be kept in sync with syscalls.h
+const O_WRONLY: usize = 2;
+const O_APPEND: usize = 4;
+const O_TRUNC: usize =  8;
+
 static VFS_INTERFACE: core::sync::atomic::AtomicPtr<FilesystemInterface> = core::sync::atomic::AtomicPtr::new(0 as *mut FilesystemInterface);
 
 /// Get a reference to the vfs interface
@@ -19,14 +35,62 @@ pub fn get_vfs_reference() -> Option<&'static mut FilesystemInterface>
 
     unsafe { ptr.as_mut() }
 }
+
+/// An entry in the mount table, `parent` is the directory and name the filesystem is mounted under
+struct MountPoint
+{
+    info: MountInfo,
+    parent: Option<(FilesystemIndex, String)>
+}
+
+/// A path given by a process, relative paths start from the directory `start`, or the root if there is none
+#[derive(Debug, Clone)]
+pub struct PathAt
+{
+    pub start: Option<FilesystemIndex>,
+    pub path: OwnedPath
+}
+
+impl From<OwnedPath> for PathAt
+{
+    /// Start the path from the root
+    fn from(path: OwnedPath) -> Self
+    {
+        Self { start: None, path }
+    }
+}
  
 /// Virtual Filesystem Interface
 pub struct FilesystemInterface
 {
     mounts: Vec<Option<Box<dyn Filesystem>>>,
     root: Option<usize>,
-    pub index: BTreeMap<OwnedPath, FilesystemIndex>,
-    indexed: BTreeMap<FilesystemIndex, OwnedPath>
+    dcache: DirectoryCache,
+    mount_table: BTreeMap<usize, MountPoint>,
+    locks: LockTable,
+    fifos: BTreeMap<FilesystemIndex, alloc::sync::Weak<core::cell::RefCell<Pipe>>>,
+    sockets: BTreeMap<FilesystemIndex, BoundSocket>
+}
+
+/// Check an extended attribute name is in a namespace which can be stored, the system namespace only holds the
+/// attributes the kernel understands
+fn check_xattr_name(name: &str) -> FilesystemResult<()>
+{
+    if name.len() == 0 || name.len() > XATTR_NAME_MAX
+    {
+        return Err(FilesystemError::InvalidAttributeName);
+    }
+
+    let namespaced = ["user.", "trusted.", "security."].iter().any(|prefix| name.len() > prefix.len() && name.starts_with(prefix));
+
+    if namespaced || name == XATTR_POSIX_ACL_ACCESS || name == XATTR_POSIX_ACL_DEFAULT
+    {
+        Ok(())
+    }
+    else
+    {
+        Err(FilesystemError::OperationNotSupported)
+    }
 }
 
 impl FilesystemInterface
@@ -43,8 +107,11 @@ impl FilesystemInterface
         {
             mounts: Vec::new(),
             root: None,
-            index: BTreeMap::new(),
-            indexed: BTreeMap::new()
+            dcache: DirectoryCache::new(DENTRY_CACHE_CAPACITY),
+            mount_table: BTreeMap::new(),
+            locks: LockTable::new(),
+            fifos: BTreeMap::new(),
+            sockets: BTreeMap::new()
         });
 
         let reference = Box::leak(singleton);
@@ -54,41 +121,381 @@ impl FilesystemInterface
         unsafe { (reference as *mut FilesystemInterface).as_mut().unwrap() } 
     }
 
-    /// Mount a filesystem to the vfs
-    pub fn mount_fs(&mut self, path: PathBuffer, mut fs: Box<dyn Filesystem>) -> Result<(), FilesystemError>
+    /// Mount a filesystem to the vfs, returning its mount id. The final component of the path does not need to
+    /// exist, and anything which does exist under that name is hidden until the filesystem is unmounted
+    pub fn mount_fs(&mut self, path: PathBuffer, mut fs: Box<dyn Filesystem>, info: MountInfo) -> FilesystemResult<usize>
     {
-        kdebugln!(Filesystem, "Mounting filesystem to index {} at {}", self.mounts.len(), path);
+        kdebugln!(Filesystem, "Mounting {} from {} to index {} at {}", info.fstype, info.source, self.mounts.len(), path);
+
+        let parent = if path.as_str() == "/"
+        {
+            if self.root.is_some()
+            {
+                return Err(FilesystemError::Busy);
+            }
+
+            None
+        }
+        else
+        {
+            if self.root.is_none()
+            {
+                return Err(FilesystemError::MissingRootMount);
+            }
+
+            let (path_start, name) = path.split_last();
+
+            let directory = self.path_to_inode(&path_start)?;
+            self.assert_directory(directory)?;
+
+            if self.mounted_at(directory, name).is_some()
+            {
+                return Err(FilesystemError::Busy);
+            }
+
+            // The mount point must already exist as a directory
+            let mount_point = self.lookup(directory, name)?;
+            self.assert_directory(mount_point)?;
+
+            Some((directory, name.to_string()))
+        };
 
         // Set the mount id
         let id = self.mounts.len();
         fs.set_mount_id(id, unsafe { (self as *mut FilesystemInterface).as_mut().unwrap() });
 
-        let root = fs.get_root_index()?;
-
         // Add the mount
         self.mounts.push(Some(fs));
 
-        // Add the mapping to the mount paths
-        if path.as_str() == "/"
+        match &parent
         {
-            self.root = Some(id);
+            Some((directory, name)) => self.dcache.remove(*directory, name),
+            None => self.root = Some(id)
+        }
 
-            Ok(())
+        self.mount_table.insert(id, MountPoint { info, parent });
+
+        Ok(id)
+    }
+
+    /// Create a filesystem of a registered type and mount it at the given path, `options` are passed to the filesystem
+    pub fn mount(&mut self, path: PathBuffer, source: &str, fstype: &str, flags: usize, options: &str) -> FilesystemResult<usize>
+    {
+        let fs_type = get_filesystem_type(fstype).ok_or(FilesystemError::UnknownFilesystemType)?;
+
+        let device = if fs_type.requires_device
+        {
+            Some(find_source_device(source)?)
         }
         else
         {
-            if self.root.is_none()
+            None
+        };
+
+        let mut fs = (fs_type.create)(device, options)?;
+
+        // A damaged filesystem may still be mounted read only to recover what it holds
+        if flags & MS_RDONLY == 0
+        {
+            if !fs.is_clean()
             {
-                Err(FilesystemError::MissingRootMount)
+                return Err(FilesystemError::UncleanFilesystem);
             }
-            else
+
+            fs.set_writable(true)?;
+        }
+
+        self.mount_fs(path, fs, MountInfo { source: source.to_string(), fstype: fstype.to_string(), flags: flags & MS_MOUNT_FLAGS })
+    }
+
+    /// Change the flags of a mounted filesystem
+    pub fn remount(&mut self, mount_id: usize, flags: usize) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Remounting index {} with flags {:#x}", mount_id, flags);
+
+        if !self.mount_table.contains_key(&mount_id)
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        let writable = self.mount_table[&mount_id].info.flags & MS_RDONLY == 0;
+
+        // Anything still waiting to be written must reach the disk before the mount becomes read only
+        if flags & MS_RDONLY > 0
+        {
+            super::cache::get_page_cache().flush_mount(self, mount_id)?;
+            self.get_fs_mount_error(mount_id)?.sync()?;
+
+            if writable
             {
-                let (path_start, name) = path.split_last();
+                self.get_fs_mount_error(mount_id)?.set_writable(false)?;
+            }
+        }
+        else if !writable
+        {
+            let fs = self.get_fs_mount_error(mount_id)?;
 
-                let inode = self.path_to_inode(&path_start)?;
-                self.mount_fs_at(inode, root, name.to_string())
+            if !fs.is_clean()
+            {
+                return Err(FilesystemError::UncleanFilesystem);
             }
+
+            fs.set_writable(true)?;
+        }
+
+        self.mount_table.get_mut(&mount_id).unwrap().info.flags = flags & MS_MOUNT_FLAGS;
+
+        Ok(())
+    }
+
+    /// Unmount the filesystem with the given mount id, writing back anything it still has cached
+    pub fn unmount(&mut self, mount_id: usize) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Unmounting index {}", mount_id);
+
+        if !self.mount_table.contains_key(&mount_id)
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        // The root cannot be unmounted, nor can anything with another filesystem mounted inside it
+        if self.root == Some(mount_id) || self.mount_table.values().any(|mount| mount.parent.as_ref().map(|(directory, _)| directory.mount_id) == Some(mount_id))
+        {
+            return Err(FilesystemError::Busy);
+        }
+
+        let cache = super::cache::get_page_cache();
+        cache.flush_mount(self, mount_id)?;
+        cache.invalidate_mount(self, mount_id);
+
+        self.get_fs_mount_error(mount_id)?.sync()?;
+
+        if self.mount_table[&mount_id].info.flags & MS_RDONLY == 0
+        {
+            self.get_fs_mount_error(mount_id)?.set_writable(false)?;
+        }
+
+        self.dcache.invalidate_mount(mount_id);
+        self.mount_table.remove(&mount_id);
+        self.mounts[mount_id] = None;
+
+        Ok(())
+    }
+
+    /// Write back everything and mark each filesystem mounted read write as cleanly unmounted, before the system halts
+    pub fn release_all(&mut self) -> FilesystemResult<()>
+    {
+        self.sync()?;
+
+        let writable: Vec<usize> = self.mount_table.iter()
+            .filter(|(_, mount)| mount.info.flags & MS_RDONLY == 0)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for mount_id in writable
+        {
+            self.get_fs_mount_error(mount_id)?.set_writable(false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Make the filesystem mounted at `new_root` the root, moving the old root to the directory `put_old` under it
+    pub fn pivot_root(&mut self, new_root: FilesystemIndex, put_old: FilesystemIndex) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Pivot root to {:?}, old root at {:?}", new_root, put_old);
+
+        let old_root_id = self.root.ok_or(FilesystemError::MissingRootMount)?;
+        let new_root_id = new_root.mount_id;
+
+        // The new root must be the root of a filesystem other than the current root
+        if !self.is_mount_root(new_root)? || new_root_id == old_root_id
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        // The old root must end up somewhere under the new root, in a directory which is not itself mounted over
+        self.assert_directory(put_old)?;
+
+        if put_old == new_root || !self.is_ancestor(new_root, put_old)?
+        {
+            return Err(FilesystemError::InvalidArgument);
         }
+
+        if self.is_mount_root(put_old)?
+        {
+            return Err(FilesystemError::Busy);
+        }
+
+        let (put_old_parent, put_old_name) = self.find_parent(put_old)?;
+
+        let new_root_mount = self.mount_table.get_mut(&new_root_id).ok_or(FilesystemError::InvalidArgument)?;
+
+        if let Some((directory, name)) = new_root_mount.parent.take()
+        {
+            self.dcache.remove(directory, &name);
+        }
+
+        self.dcache.remove(put_old_parent, &put_old_name);
+
+        if let Some(old_root_mount) = self.mount_table.get_mut(&old_root_id)
+        {
+            old_root_mount.parent = Some((put_old_parent, put_old_name));
+        }
+
+        self.root = Some(new_root_id);
+
+        Ok(())
+    }
+
+    /// Get the `MS_*` flags of the filesystem with the given mount id
+    pub fn mount_flags(&self, mount_id: usize) -> usize
+    {
+        self.mount_table.get(&mount_id).map(|mount| mount.info.flags).unwrap_or(0)
+    }
+
+    /// Get the path and description of every mounted filesystem, in the order they were mounted
+    pub fn mount_entries(&mut self) -> FilesystemResult<Vec<(OwnedPath, MountInfo)>>
+    {
+        let ids: Vec<usize> = self.mount_table.keys().copied().collect();
+        let mut result = Vec::new();
+
+        for id in ids
+        {
+            let root = self.get_fs_mount_error(id)?.get_root_index()?;
+            let path = self.inode_to_path(root)?;
+
+            let path = if path.as_str().len() == 0 { OwnedPath::new("/") } else { path };
+
+            result.push((path, self.mount_table.get(&id).unwrap().info.clone()));
+        }
+
+        Ok(result)
+    }
+
+    /// Get the id of the filesystem mounted under a name in a directory
+    fn mounted_at(&self, directory: FilesystemIndex, name: &str) -> Option<usize>
+    {
+        self.mount_table.iter().find(|(_, mount)| match &mount.parent
+            {
+                Some((parent, mount_name)) => *parent == directory && mount_name == name,
+                None => false
+            }).map(|(id, _)| *id)
+    }
+
+    /// Fail if the filesystem with the given mount id is mounted read only
+    fn check_writable(&self, mount_id: usize) -> FilesystemResult<()>
+    {
+        if self.mount_flags(mount_id) & MS_RDONLY > 0
+        {
+            Err(FilesystemError::ReadOnlyFilesystem)
+        }
+        else
+        {
+            Ok(())
+        }
+    }
+
+    /// Set an extended attribute of an inode, failing if it already exists when `XATTR_CREATE` is given or if it does
+    /// not exist when `XATTR_REPLACE` is given
+    pub fn set_xattr_flags(&mut self, inode: FilesystemIndex, name: &str, value: &[u8], flags: usize) -> FilesystemResult<()>
+    {
+        if flags & !(XATTR_CREATE | XATTR_REPLACE) != 0
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        let exists = match self.get_xattr(inode, name)
+        {
+            Ok(_) => true,
+            Err(FilesystemError::AttributeNotFound) => false,
+            Err(e) => return Err(e)
+        };
+
+        if flags & XATTR_CREATE > 0 && exists
+        {
+            Err(FilesystemError::FileExists)
+        }
+        else if flags & XATTR_REPLACE > 0 && !exists
+        {
+            Err(FilesystemError::AttributeNotFound)
+        }
+        else
+        {
+            self.set_xattr(inode, name, value)
+        }
+    }
+
+    /// Check if a user and group may access an inode with all of the permissions in `want`, following the access ACL
+    /// of the inode if it has one
+    pub fn check_access(&mut self, inode: FilesystemIndex, uid: u16, gid: u16, want: u16) -> FilesystemResult<bool>
+    {
+        let stat = self.get_stat(inode)?;
+
+        // The superuser may read and write anything, and execute anything which anyone may execute
+        if uid == 0
+        {
+            return Ok(want & ACL_EXECUTE == 0 || stat.mode & S_IFMT == S_IFDIR || stat.mode & 0o111 != 0);
+        }
+
+        // Without an ACL the permission bits are checked as the ACL they describe
+        let acl = match self.get_xattr(inode, XATTR_POSIX_ACL_ACCESS)
+        {
+            Ok(value) => Acl::from_bytes(&value)?,
+            Err(FilesystemError::AttributeNotFound) | Err(FilesystemError::OperationNotSupported) => acl_from_mode(stat.mode),
+            Err(e) => return Err(e)
+        };
+
+        Ok(acl.permits(stat.uid, stat.gid, uid, gid, want))
+    }
+
+    /// Check if a process may access an inode with all of the permissions in `want`, as its effective user and group
+    pub fn check_permission(&mut self, inode: FilesystemIndex, credentials: &Credentials, want: u16) -> FilesystemResult<()>
+    {
+        if self.check_access(inode, credentials.euid, credentials.egid, want)?
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(FilesystemError::AccessDenied)
+        }
+    }
+
+    /// Get the table of the advisory locks held on every inode
+    pub fn lock_table(&mut self) -> &mut LockTable
+    {
+        &mut self.locks
+    }
+
+    /// Get the pipe connecting the ends of a named pipe, a new one is made when the named pipe has no ends open, so
+    /// data left when the last end closed is discarded
+    pub fn fifo_pipe(&mut self, inode: FilesystemIndex) -> SharedPipe
+    {
+        self.fifos.retain(|_, pipe| pipe.strong_count() > 0);
+
+        if let Some(pipe) = self.fifos.get(&inode).and_then(|pipe| pipe.upgrade())
+        {
+            return pipe;
+        }
+
+        let pipe = Pipe::new();
+        self.fifos.insert(inode, alloc::sync::Arc::downgrade(&pipe));
+
+        pipe
+    }
+
+    /// Map a socket inode to the socket bound to it
+    pub fn bind_socket(&mut self, inode: FilesystemIndex, socket: BoundSocket)
+    {
+        self.sockets.retain(|_, socket| socket.is_open());
+        self.sockets.insert(inode, socket);
+    }
+
+    /// Get the socket bound to a socket inode, if it is still open
+    pub fn bound_socket(&mut self, inode: FilesystemIndex) -> Option<BoundSocket>
+    {
+        self.sockets.get(&inode).filter(|socket| socket.is_open()).cloned()
     }
 
     /// Get the fs mounted at the given index
@@ -150,229 +557,618 @@ impl FilesystemInterface
         }
     }
 
-    /// Index the filesystem from the starting path and starting inode
-    pub fn index_from(&mut self, path: PathBuffer, inode: FilesystemIndex) -> FilesystemResult<()>
+    /// Resolve a path to an inode, following symbolic links along the way, the final component is only followed if
+    /// `follow_last` is set
+    pub fn resolve_path(&mut self, path: PathBuffer, follow_last: bool) -> FilesystemResult<FilesystemIndex>
+    {
+        self.resolve_path_from(None, path, follow_last)
+    }
+
+    /// Resolve a path given by a process to an inode, relative paths are walked from its starting directory
+    pub fn resolve_path_at(&mut self, path: &PathAt, follow_last: bool) -> FilesystemResult<FilesystemIndex>
+    {
+        self.resolve_path_from(path.start, &path.path, follow_last)
+    }
+
+    /// Resolve a path given by a process to the directory holding its final component, along with the name of that
+    /// component
+    pub fn resolve_parent_at(&mut self, path: &PathAt) -> FilesystemResult<(FilesystemIndex, String)>
+    {
+        let mut names: Vec<&str> = path.path.iter().collect();
+        let name = names.pop().ok_or(FilesystemError::InvalidArgument)?.to_string();
+
+        let prefix = if path.path.as_str().starts_with('/') { "/" } else { "" };
+        let directory = self.resolve_path_from(path.start, &OwnedPath::new(format!("{}{}", prefix, names.join("/"))), true)?;
+
+        Ok((directory, name))
+    }
+
+    /// Resolve a path to an inode, relative paths start from the directory `start` or the root if there is none
+    fn resolve_path_from(&mut self, start: Option<FilesystemIndex>, path: PathBuffer, follow_last: bool) -> FilesystemResult<FilesystemIndex>
+    {
+        let root = self.get_root_index()?;
+
+        let mut index = if path.as_str().starts_with('/') { root } else { start.unwrap_or(root) };
+        let mut remaining: Vec<String> = path.iter().map(String::from).collect();
+        remaining.reverse();
+        let mut followed = 0;
+
+        while let Some(name) = remaining.pop()
+        {
+            let next = match self.lookup(index, &name)
+            {
+                Ok(next) => next,
+                Err(FilesystemError::FileNotFound(_)) =>
+                {
+                    kdebugln!(Filesystem, "Map path `{}` to inode -> File Not Found", path);
+                    return Err(FilesystemError::FileNotFound(path.to_string()));
+                },
+                Err(e) => return Err(e)
+            };
+
+            // Entries already known not to be symbolic links do not need to be read
+            if (!remaining.is_empty() || follow_last) && self.dcache.is_symlink(index, &name, next) != Some(false)
+            {
+                let link = self.read_link(next);
+
+                match &link
+                {
+                    Ok(_) => self.dcache.set_symlink(index, &name, next, true),
+                    Err(FilesystemError::NotASymbolicLink) => self.dcache.set_symlink(index, &name, next, false),
+                    Err(_) => {}
+                }
+
+                match link
+                {
+                    Ok(target) =>
+                    {
+                        followed += 1;
+
+                        if followed > MAX_SYMLINK_DEPTH
+                        {
+                            return Err(FilesystemError::TooManySymbolicLinks);
+                        }
+
+                        // The target replaces the link in the path, relative targets start from the link's directory
+                        let target = OwnedPath::new(target);
+                        remaining.extend(target.iter().map(String::from).collect::<Vec<_>>().into_iter().rev());
+
+                        if target.as_str().starts_with('/')
+                        {
+                            index = root;
+                        }
+
+                        continue;
+                    },
+                    Err(FilesystemError::NotASymbolicLink) => {},
+                    Err(e) => return Err(e)
+                }
+            }
+
+            index = next;
+        }
+
+        kdebugln!(Filesystem, "Map path `{}` to inode -> {:?}", path, index);
+
+        Ok(index)
+    }
+
+    /// Check if an inode is the root directory of the filesystem it belongs to
+    fn is_mount_root(&mut self, inode: FilesystemIndex) -> FilesystemResult<bool>
+    {
+        Ok(self.get_fs_mount_error(inode.mount_id)?.get_root_index()? == inode)
+    }
+
+    /// Check if `ancestor` is the directory `inode` or one of the directories above it
+    fn is_ancestor(&mut self, ancestor: FilesystemIndex, inode: FilesystemIndex) -> FilesystemResult<bool>
+    {
+        let root = self.get_root_index()?;
+        let mut current = inode;
+
+        loop
+        {
+            if current == ancestor
+            {
+                return Ok(true);
+            }
+
+            if current == root
+            {
+                return Ok(false);
+            }
+
+            current = self.find_parent(current)?.0;
+        }
+    }
+
+    /// Find the directory an inode lives in along with its name in that directory
+    fn find_parent(&mut self, inode: FilesystemIndex) -> FilesystemResult<(FilesystemIndex, String)>
+    {
+        if let Some(parent) = self.dcache.parent_of(inode)
+        {
+            return Ok(parent);
+        }
+
+        // The root of a mounted filesystem lives wherever it was mounted
+        if self.is_mount_root(inode)?
+        {
+            return self.mount_table.get(&inode.mount_id).and_then(|mount| mount.parent.clone()).ok_or(FilesystemError::BadINode);
+        }
+
+        // Otherwise the inode must be a directory, so its parent can be found through its parent entry
+        let parent = self.lookup(inode, "..")?;
+
+        for entry in self.get_dir_entries(parent)?
+        {
+            if entry.index == inode && entry.name != "." && entry.name != ".."
+            {
+                if self.get_fs_mount_error(parent.mount_id)?.cacheable_lookups()
+                {
+                    self.dcache.insert(parent, &entry.name, Some(inode));
+                }
+
+                return Ok((parent, entry.name));
+            }
+        }
+
+        Err(FilesystemError::BadINode)
+    }
+}
+
+impl Filesystem for FilesystemInterface
+{
+    /// Initialize the filesystem on the current disk
+    fn init(&mut self) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Initialize Virtual Filesystem");
+        // Nothing to do here, the virtual file system doesn't need any initialization
+
+        Ok(())
+    }
+
+    /// Sync the filesystem with the current disk
+    fn sync(&mut self) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Syncing Virtual Filesystem");
+
+        // Write back any dirty pages before syncing the underlying filesystems
+        super::cache::get_page_cache().flush_all(self)?;
+
+        // To sync the entire filesystem just sync all mounted file systems
+        for fs in &mut self.mounts
+        {
+            if let Some(fs) = fs
+            {
+                fs.sync()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the mount_id of the filesystem
+    fn set_mount_id(&mut self, _mount_id: usize, _vfs: &'static mut FilesystemInterface)
+    {
+        panic!("Cannot mount Virtual Filesystem");
+    }
+
+    /// Get the index of the root directory of the filesystem
+    fn get_root_index(&mut self) -> FilesystemResult<FilesystemIndex>
+    {
+        self.get_root_fs_error()?.get_root_index()
+    }
+
+    /// Convert a path to an inode
+    fn path_to_inode(&mut self, path: PathBuffer) -> FilesystemResult<FilesystemIndex>
+    {
+        self.resolve_path(path, true)
+    }
+
+    /// Convert an inode to a path
+    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<OwnedPath>
+    {
+        let root = self.get_root_index()?;
+
+        let mut names = Vec::new();
+        let mut current = inode;
+
+        while current != root
+        {
+            let (parent, name) = self.find_parent(current)?;
+
+            names.push(name);
+            current = parent;
+        }
+
+        let mut path = String::new();
+
+        for name in names.iter().rev()
+        {
+            path.push('/');
+            path.push_str(name);
+        }
+
+        kdebugln!(Filesystem, "Map inode {:?} to path -> `{}`", inode, path);
+
+        Ok(OwnedPath::new(path))
+    }
+
+    /// Find the inode for a name in the directory at the given inode
+    fn lookup(&mut self, directory: FilesystemIndex, name: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        if name == "."
+        {
+            return Ok(directory);
+        }
+
+        // The parent of a mounted filesystem's root is the directory it is mounted in
+        if name == ".." && self.is_mount_root(directory)?
+        {
+            return Ok(match self.mount_table.get(&directory.mount_id).and_then(|mount| mount.parent.as_ref())
+            {
+                Some((parent, _)) => *parent,
+                None => directory
+            });
+        }
+
+        // Mounted filesystems hide whatever is under their name in the directory they are mounted in
+        if let Some(id) = self.mounted_at(directory, name)
+        {
+            return self.get_fs_mount_error(id)?.get_root_index();
+        }
+
+        let fs = self.get_fs_mount_error(directory.mount_id)?;
+
+        if !fs.cacheable_lookups()
+        {
+            return fs.lookup(directory, name);
+        }
+
+        match self.dcache.get(directory, name)
+        {
+            Some(Some(inode)) => return Ok(inode),
+            Some(None) => return Err(FilesystemError::FileNotFound(name.to_string())),
+            None => {}
+        }
+
+        let result = self.get_fs_mount_error(directory.mount_id)?.lookup(directory, name);
+
+        match &result
+        {
+            Ok(inode) => self.dcache.insert(directory, name, Some(*inode)),
+            Err(FilesystemError::FileNotFound(_)) => self.dcache.insert(directory, name, None),
+            Err(_) => {}
+        }
+
+        result
+    }
+
+    /// Get the directory entries in the directory at the given inode
+    fn get_dir_entries(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<DirectoryEntry>>
+    {
+        kdebugln!(Filesystem, "List Directory Entries at {:?}", inode);
+        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        {
+            let mut entries = fs.get_dir_entries(inode)?;
+
+            // Add any filesystems mounted in the directory, replacing the entries they hide
+            let mounted: Vec<(usize, String)> = self.mount_table.iter()
+                .filter_map(|(id, mount)| mount.parent.as_ref().filter(|(parent, _)| *parent == inode).map(|(_, name)| (*id, name.clone())))
+                .collect();
+
+            for (id, name) in mounted
+            {
+                let root = self.get_fs_mount_error(id)?.get_root_index()?;
+                let entry = DirectoryEntry { index: root, name: name.clone(), entry_type: DirectoryEntryType::Directory };
+
+                match entries.iter_mut().find(|existing| existing.name == name)
+                {
+                    Some(existing) => *existing = entry,
+                    None => entries.push(entry)
+                }
+            }
+
+            Ok(entries)
+        }
+        else
+        {
+            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+        }
+    }
+
+    /// Get the directory entry for the given inode
+    fn get_stat(&mut self, inode: FilesystemIndex) -> FilesystemResult<FileStat>
+    {
+        kdebugln!(Filesystem, "Get Directory Entry at {:?}", inode);
+        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        {
+            let mut stat = fs.get_stat(inode)?;
+
+            // The page cache may hold data which has not been written back yet
+            if let Some(size) = super::cache::get_page_cache().cached_size(inode)
+            {
+                stat.size = size;
+            }
+
+            Ok(stat)
+        }
+        else
+        {
+            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+        }
+    }
+
+    /// Create a file in the directory at the given inode
+    fn create_file(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        kdebugln!(Filesystem, "Create file `{}` at {:?}", name, inode);
+
+        self.check_writable(inode.mount_id)?;
+
+        if self.lookup(inode, &name).is_ok()
+        {
+            return Err(FilesystemError::FileExists);
+        }
+
+        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        {
+            let file = fs.create_file(inode, name.clone())?;
+            self.dcache.insert(inode, &name, Some(file));
+
+            Ok(file)
+        }
+        else
+        {
+            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+        }
+    }
+
+    /// Create a directory in the directory at the given inode
+    fn create_directory(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        kdebugln!(Filesystem, "Create directory `{}` at {:?}", name, inode);
+
+        self.check_writable(inode.mount_id)?;
+
+        if self.lookup(inode, &name).is_ok()
+        {
+            return Err(FilesystemError::FileExists);
+        }
+
+        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        {
+            let directory = fs.create_directory(inode, name.clone())?;
+            self.dcache.insert(inode, &name, Some(directory));
+
+            Ok(directory)
+        }
+        else
+        {
+            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+        }
+    }
+
+    /// Create a symbolic link in the directory at the given inode which points to `target`
+    fn create_symlink(&mut self, directory: FilesystemIndex, name: String, target: &str) -> FilesystemResult<FilesystemIndex>
+    {
+        kdebugln!(Filesystem, "Create symlink `{}` -> `{}` at {:?}", name, target, directory);
+
+        self.check_writable(directory.mount_id)?;
+
+        if self.lookup(directory, &name).is_ok()
+        {
+            return Err(FilesystemError::FileExists);
+        }
+
+        let link = self.get_fs_mount_error(directory.mount_id)?.create_symlink(directory, name.clone(), target)?;
+        self.dcache.insert(directory, &name, Some(link));
+
+        Ok(link)
+    }
+
+    /// Create a named pipe in the directory at the given inode
+    fn create_fifo(&mut self, directory: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
     {
-        if self.indexed.contains_key(&inode)
-        {
-            return Ok(());
-        }
+        kdebugln!(Filesystem, "Create fifo `{}` at {:?}", name, directory);
+
+        self.check_writable(directory.mount_id)?;
 
-        // Add the current path to the index
-        if path.as_str().len() > 0
+        if self.lookup(directory, &name).is_ok()
         {
-            self.index.insert(path.clone(), inode);
+            return Err(FilesystemError::FileExists);
         }
-        self.indexed.insert(inode, path.clone());
 
-        // Get the directory entries (if this is a directory)
-        match self.get_dir_entries(inode)
-        {
-            Ok(entries) =>
-            {
-                self.index.insert(OwnedPath::new(path.as_str().to_string() + "/"), inode);
+        let fifo = self.get_fs_mount_error(directory.mount_id)?.create_fifo(directory, name.clone())?;
+        self.dcache.insert(directory, &name, Some(fifo));
 
-                for entry in entries
-                {
-                    self.index_from(&OwnedPath::new(format!("{}/{}", path, entry.name)), entry.index)?;
-                }
+        Ok(fifo)
+    }
+
+    /// Create a socket in the directory at the given inode
+    fn create_socket(&mut self, directory: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
+    {
+        kdebugln!(Filesystem, "Create socket `{}` at {:?}", name, directory);
+
+        self.check_writable(directory.mount_id)?;
 
-                Ok(())
-            },
-            Err(FilesystemError::INodeIsNotADirectory) => Ok(()),
-            Err(e) => Err(e)
+        if self.lookup(directory, &name).is_ok()
+        {
+            return Err(FilesystemError::FileExists);
         }
+
+        let socket = self.get_fs_mount_error(directory.mount_id)?.create_socket(directory, name.clone())?;
+        self.dcache.insert(directory, &name, Some(socket));
+
+        Ok(socket)
     }
 
-    /// Index the full filesystem
-    pub fn index(&mut self) -> FilesystemResult<()>
+    /// Read the target of the symbolic link at the given inode
+    fn read_link(&mut self, inode: FilesystemIndex) -> FilesystemResult<String>
     {
-        // Clear the previous index
-        self.index = BTreeMap::new();
-        self.indexed = BTreeMap::new();
-
-        let root = self.get_root_index()?;
-        self.index_from(&OwnedPath::new(""), root)
+        self.get_fs_mount_error(inode.mount_id)?.read_link(inode)
     }
 
-    /// Invalidate part of the filesystem index
-    pub fn invalidate_index(&mut self, path: PathBuffer) -> FilesystemResult<()>
+    /// Add an entry for an existing inode to the directory at the given inode
+    fn create_link(&mut self, inode: FilesystemIndex, directory: FilesystemIndex, name: String) -> FilesystemResult<()>
     {
-        let mut to_remove = Vec::new();
+        kdebugln!(Filesystem, "Link {:?} as `{}` at {:?}", inode, name, directory);
+
+        self.check_writable(directory.mount_id)?;
 
-        for key in self.index.keys()
+        if inode.mount_id != directory.mount_id
         {
-            if key.as_str().starts_with(path.as_str())
-            {
-                to_remove.push(key.clone());
-            }
+            return Err(FilesystemError::CrossDeviceLink);
         }
-        
-        for key in to_remove
+
+        if self.lookup(directory, &name).is_ok()
         {
-            self.index.remove(&key);
+            return Err(FilesystemError::FileExists);
         }
 
+        self.get_fs_mount_error(directory.mount_id)?.create_link(inode, directory, name.clone())?;
+        self.dcache.insert(directory, &name, Some(inode));
+
         Ok(())
     }
-}
 
-impl Filesystem for FilesystemInterface
-{
-    /// Initialize the filesystem on the current disk
-    fn init(&mut self) -> FilesystemResult<()>
+    /// Change the metadata of an inode, only the permission bits of a new mode are used
+    fn set_attr(&mut self, inode: FilesystemIndex, attributes: SetAttributes) -> FilesystemResult<()>
     {
-        kdebugln!(Filesystem, "Initialize Virtual Filesystem");
-        // Nothing to do here, the virtual file system doesn't need any initialization
+        kdebugln!(Filesystem, "Set attributes of {:?} to {:?}", inode, attributes);
 
-        Ok(())
-    }
+        self.check_writable(inode.mount_id)?;
 
-    /// Sync the filesystem with the current disk
-    fn sync(&mut self) -> FilesystemResult<()>
-    {
-        kdebugln!(Filesystem, "Syncing Virtual Filesystem");
+        // Resizing goes through the page cache so cached pages past the new end are dropped
+        if let Some(size) = attributes.size
+        {
+            self.assert_not_directory(inode)?;
 
-        // To sync the entire filesystem just sync all mounted file systems
-        for fs in &mut self.mounts
+            let cache = super::cache::get_page_cache();
+
+            cache.truncate(self, inode, size)?;
+            cache.flush_inode(self, inode)?;
+        }
+
+        let fs = self.get_fs_mount_error(inode.mount_id)?;
+        fs.set_attr(inode, SetAttributes { size: None, ..attributes })?;
+
+        // The access ACL mirrors the permission bits, so a new mode is copied into it
+        if let Some(mode) = attributes.mode
         {
-            if let Some(fs) = fs
+            if let Ok(value) = fs.get_xattr(inode, XATTR_POSIX_ACL_ACCESS)
             {
-                fs.sync()?;
+                let mut acl = Acl::from_bytes(&value)?;
+                acl.set_mode(mode);
+
+                fs.set_xattr(inode, XATTR_POSIX_ACL_ACCESS, &acl.to_bytes())?;
             }
         }
 
         Ok(())
     }
 
-    /// Set the mount_id of the filesystem
-    fn set_mount_id(&mut self, _mount_id: usize, _vfs: &'static mut FilesystemInterface)
+    /// Move the entry `old_name` in the directory at `old_directory` to `new_name` in `new_directory`, replacing or
+    /// exchanging with any existing entry depending on the `RENAME_*` flags
+    fn rename(&mut self, old_directory: FilesystemIndex, old_name: String, new_directory: FilesystemIndex, new_name: String, flags: usize) -> FilesystemResult<()>
     {
-        panic!("Cannot mount Virtual Filesystem");
-    }
+        kdebugln!(Filesystem, "Rename `{}` in {:?} to `{}` in {:?}", old_name, old_directory, new_name, new_directory);
 
-    /// Get the index of the root directory of the filesystem
-    fn get_root_index(&mut self) -> FilesystemResult<FilesystemIndex>
-    {
-        self.get_root_fs_error()?.get_root_index()
-    }
+        self.check_writable(old_directory.mount_id)?;
 
-    /// Convert a path to an inode
-    fn path_to_inode(&mut self, path: PathBuffer) -> FilesystemResult<FilesystemIndex>
-    {
-        // If we have the path in the index, just use that
-        if let Some(index) = self.index.get(path)
+        if flags & !(RENAME_NOREPLACE | RENAME_EXCHANGE) != 0 || flags == RENAME_NOREPLACE | RENAME_EXCHANGE
         {
-            kdebugln!(Filesystem, "Map path `{}` to inode -> {:?}", path, index);
-            Ok(*index)
+            return Err(FilesystemError::InvalidArgument);
         }
 
-        // Otherwise, we will walk the filesystem
-        else
+        for name in [&old_name, &new_name]
         {
-            let mut index = self.get_root_index()?;
-
-            for name in path.iter()
+            if name.len() == 0 || name == "." || name == ".."
             {
-                let mut found = false;
-                for entry in self.get_dir_entries(index)?
-                {
-                    if entry.name == name
-                    {
-                        found = true;
-                        index = entry.index;
-                        break;
-                    }
-                }
-
-                if !found
-                {
-                    kdebugln!(Filesystem, "Map path `{}` to inode -> File Not Found", path);
-                    return Err(FilesystemError::FileNotFound(path.to_string()));
-                }
+                return Err(FilesystemError::InvalidArgument);
             }
-
-            Ok(index)
         }
-    }
 
-    /// Convert an inode to a path
-    fn inode_to_path(&mut self, inode: FilesystemIndex) -> FilesystemResult<PathBuffer>
-    {
-        if !self.indexed.contains_key(&inode)
-        {
-            self.index()?;
-        }
+        let source = self.lookup(old_directory, &old_name)?;
+        let target = self.lookup(new_directory, &new_name).ok();
 
-        // If we have the inode in the index, just use that
-        if let Some(path) = self.indexed.get(&inode)
+        // Mount points stay where they are, and entries cannot move between filesystems
+        if source.mount_id != old_directory.mount_id || target.map(|t| t.mount_id != new_directory.mount_id).unwrap_or(false)
         {
-            kdebugln!(Filesystem, "Map inode {:?} to path -> `{}`", inode, path);
-            return Ok(path);
+            return Err(FilesystemError::Busy);
         }
-        else
+
+        if old_directory.mount_id != new_directory.mount_id
         {
-            todo!()
+            return Err(FilesystemError::CrossDeviceLink);
         }
-        
-    }
 
-    /// Get the directory entries in the directory at the given inode
-    fn get_dir_entries(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<DirectoryEntry>>
-    {
-        kdebugln!(Filesystem, "List Directory Entries at {:?}", inode);
-        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        if flags & RENAME_EXCHANGE > 0 && target.is_none()
         {
-            fs.get_dir_entries(inode)
+            return Err(FilesystemError::FileNotFound(new_name));
         }
-        else
+
+        // The target exists even when it is the source itself
+        if flags & RENAME_NOREPLACE > 0 && target.is_some()
         {
-            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+            return Err(FilesystemError::FileExists);
         }
-    }
 
-    /// Get the directory entry for the given inode
-    fn get_stat(&mut self, inode: FilesystemIndex) -> FilesystemResult<FileStat>
-    {
-        kdebugln!(Filesystem, "Get Directory Entry at {:?}", inode);
-        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        // Both names already refer to the same inode, so there is nothing to do
+        if target == Some(source)
         {
-            fs.get_stat(inode)
+            return Ok(());
         }
-        else
+
+        // A directory cannot be moved inside of itself
+        if self.is_ancestor(source, new_directory)?
         {
-            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+            return Err(FilesystemError::InvalidArgument);
         }
-    }
-
-    /// Create a file in the directory at the given inode
-    fn create_file(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
-    {
-        kdebugln!(Filesystem, "Create file `{}` at {:?}", name, inode);
 
-        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        if flags & RENAME_EXCHANGE > 0
         {
-            fs.create_file(inode, name)
+            if let Some(target) = target
+            {
+                if self.is_ancestor(target, old_directory)?
+                {
+                    return Err(FilesystemError::InvalidArgument);
+                }
+            }
         }
-        else
+
+        // A replaced file may still have other names, so its data has to reach the filesystem first
+        if let Some(target) = target
         {
-            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+            if flags & RENAME_EXCHANGE == 0
+            {
+                super::cache::get_page_cache().flush_inode(self, target)?;
+            }
         }
-    }
 
-    /// Create a directory in the directory at the given inode
-    fn create_directory(&mut self, inode: FilesystemIndex, name: String) -> FilesystemResult<FilesystemIndex>
-    {
-        kdebugln!(Filesystem, "Create directory `{}` at {:?}", name, inode);
+        self.get_fs_mount_error(old_directory.mount_id)?.rename(old_directory, old_name.clone(), new_directory, new_name.clone(), flags)?;
 
-        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        // Moved directories have a new parent entry
+        self.dcache.remove(source, "..");
+
+        if flags & RENAME_EXCHANGE > 0
         {
-            fs.create_directory(inode, name)
+            self.dcache.remove(target.unwrap(), "..");
+            self.dcache.insert(old_directory, &old_name, target);
         }
         else
         {
-            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+            if let Some(target) = target
+            {
+                super::cache::get_page_cache().invalidate_inode(self, target);
+                self.dcache.invalidate_inode(target);
+            }
+
+            self.dcache.insert(old_directory, &old_name, None);
         }
+
+        self.dcache.insert(new_directory, &new_name, Some(source));
+
+        Ok(())
     }
 
     /// Remove an inode at the given index from the given directory
@@ -380,6 +1176,11 @@ impl Filesystem for FilesystemInterface
     {
         kdebugln!(Filesystem, "Remove inode {:?}", inode);
 
+        self.check_writable(inode.mount_id)?;
+
+        super::cache::get_page_cache().invalidate_inode(self, inode);
+        self.dcache.invalidate_inode(inode);
+
         if let Some(fs) = self.get_fs_mount(inode.mount_id)
         {
             fs.remove_inode(inode)
@@ -395,13 +1196,13 @@ impl Filesystem for FilesystemInterface
     {
         kdebugln!(Filesystem, "Remove directory entry {} in {:?}", name, directory_index);
 
+        self.check_writable(directory_index.mount_id)?;
+
         if let Some(fs) = self.get_fs_mount(directory_index.mount_id)
         {
-            fs.remove_dir_entry(directory_index, name)?;
-
-            let path = self.inode_to_path(directory_index)?.to_owned();
+            fs.remove_dir_entry(directory_index, name.clone())?;
 
-            self.invalidate_index(&path)?;
+            self.dcache.insert(directory_index, &name, None);
 
             Ok(())
         }
@@ -416,6 +1217,8 @@ impl Filesystem for FilesystemInterface
     {
         kdebugln!(Filesystem, "Incrementing links to {:?}", inode);
 
+        self.check_writable(inode.mount_id)?;
+
         if let Some(fs) = self.get_fs_mount(inode.mount_id)
         {
             fs.increment_links(inode)
@@ -431,6 +1234,8 @@ impl Filesystem for FilesystemInterface
     {
         kdebugln!(Filesystem, "Decrement links to {:?}", inode);
 
+        self.check_writable(inode.mount_id)?;
+
         if let Some(fs) = self.get_fs_mount(inode.mount_id)
         {
             fs.decrement_links(inode)
@@ -446,6 +1251,9 @@ impl Filesystem for FilesystemInterface
     {
         kdebugln!(Filesystem, "Read inode {:?}", inode);
 
+        // Make sure the filesystem sees any data still held in the page cache
+        super::cache::get_page_cache().flush_inode(self, inode)?;
+
         if let Some(fs) = self.get_fs_mount(inode.mount_id)
         {
             fs.read_inode(inode)
@@ -461,6 +1269,11 @@ impl Filesystem for FilesystemInterface
     {
         kdebugln!(Filesystem, "Write data to inode {:?}", inode);
 
+        self.check_writable(inode.mount_id)?;
+
+        // Writing the whole inode replaces anything held in the page cache
+        super::cache::get_page_cache().invalidate_inode(self, inode);
+
         if let Some(fs) = self.get_fs_mount(inode.mount_id)
         {
             fs.write_inode(inode, data)
@@ -471,14 +1284,67 @@ impl Filesystem for FilesystemInterface
         }
     }
 
-    /// Mount a filesystem at the given inode
-    fn mount_fs_at(&mut self, inode: FilesystemIndex, root: FilesystemIndex, name: String) -> FilesystemResult<()>
+    /// Read a single page of the data stored in an inode into the buffer, returning the number of bytes read
+    fn read_inode_page(&mut self, inode: FilesystemIndex, page: usize, buffer: *mut u8) -> FilesystemResult<usize>
+    {
+        kdebugln!(Filesystem, "Read page {} of inode {:?}", page, inode);
+
+        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        {
+            fs.read_inode_page(inode, page, buffer)
+        }
+        else
+        {
+            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+        }
+    }
+
+    /// Start reading a page of an inode in the background, returning a token to wait on if the data is not ready
+    fn prefetch_inode_page(&mut self, inode: FilesystemIndex, page: usize) -> FilesystemResult<Option<crate::drivers::virtio::drivers::block::BlockRequestToken>>
+    {
+        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        {
+            fs.prefetch_inode_page(inode, page)
+        }
+        else
+        {
+            Err(FilesystemError::UnableToFindDiskMount(inode.mount_id))
+        }
+    }
+
+    /// Get the memory holding a page of an inode, for filesystems which keep their data in memory
+    fn inode_page_memory(&mut self, inode: FilesystemIndex, page: usize) -> FilesystemResult<Option<*mut u8>>
+    {
+        self.get_fs_mount_error(inode.mount_id)?.inode_page_memory(inode, page)
+    }
+
+    /// Hand a page returned by `inode_page_memory` over to the page cache
+    fn disown_inode_page(&mut self, inode: FilesystemIndex, page: usize)
+    {
+        if let Some(fs) = self.get_fs_mount(inode.mount_id)
+        {
+            fs.disown_inode_page(inode, page);
+        }
+    }
+
+    /// Make sure an inode can grow to `size` bytes before a write through the page cache extends it
+    fn reserve_size(&mut self, inode: FilesystemIndex, size: usize) -> FilesystemResult<()>
+    {
+        self.check_writable(inode.mount_id)?;
+
+        self.get_fs_mount_error(inode.mount_id)?.reserve_size(inode, size)
+    }
+
+    /// Write back pages of the data stored in an inode, resizing the inode to `size` bytes
+    fn write_inode_pages(&mut self, inode: FilesystemIndex, size: usize, pages: &[(usize, *const u8)]) -> FilesystemResult<()>
     {
-        kdebugln!(Filesystem, "Mount fs starting at {:?} at inode {:?}", root, inode);
+        kdebugln!(Filesystem, "Write {} pages to inode {:?}", pages.len(), inode);
+
+        self.check_writable(inode.mount_id)?;
 
         if let Some(fs) = self.get_fs_mount(inode.mount_id)
         {
-            fs.mount_fs_at(inode, root, name)
+            fs.write_inode_pages(inode, size, pages)
         }
         else
         {
@@ -486,11 +1352,192 @@ impl Filesystem for FilesystemInterface
         }
     }
 
+    /// Find the first offset at or after `offset` which holds data, or which is in a hole if `hole` is set, the end
+    /// of the file counts as a hole
+    fn seek_data(&mut self, inode: FilesystemIndex, offset: usize, hole: bool) -> FilesystemResult<usize>
+    {
+        // The filesystem only knows about data which has been written back, pages which have not are data too
+        let cache = super::cache::get_page_cache();
+        let size = cache.get_size(self, inode)?;
+        let dirty = cache.dirty_ranges(inode);
+
+        if offset >= size
+        {
+            return Err(FilesystemError::OffsetPastEnd);
+        }
+
+        let fs = self.get_fs_mount_error(inode.mount_id)?;
+
+        if hole
+        {
+            // Anything past the end of the file on the disk is a hole until it is written back
+            let mut position = offset;
+
+            loop
+            {
+                position = match fs.seek_data(inode, position, true)
+                {
+                    Ok(position) => position,
+                    Err(FilesystemError::OffsetPastEnd) => position,
+                    Err(e) => return Err(e)
+                };
+
+                match dirty.iter().find(|(start, end)| *start <= position && position < *end)
+                {
+                    Some((_, end)) => position = *end,
+                    None => return Ok(position.min(size))
+                }
+            }
+        }
+        else
+        {
+            let on_disk = match fs.seek_data(inode, offset, false)
+            {
+                Ok(position) => position,
+                Err(FilesystemError::OffsetPastEnd) => usize::MAX,
+                Err(e) => return Err(e)
+            };
+
+            let in_cache = dirty.iter().find(|(_, end)| *end > offset).map(|(start, _)| (*start).max(offset)).unwrap_or(usize::MAX);
+            let position = on_disk.min(in_cache);
+
+            if position >= size
+            {
+                Err(FilesystemError::OffsetPastEnd)
+            }
+            else
+            {
+                Ok(position)
+            }
+        }
+    }
+
+    /// Allocate or deallocate the storage for `length` bytes of an inode starting at `offset` according to the
+    /// `FALLOC_FL_*` flags in `mode`
+    fn allocate_range(&mut self, inode: FilesystemIndex, mode: usize, offset: usize, length: usize) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Allocate {} bytes at {} of inode {:?} with mode {}", length, offset, inode, mode);
+
+        self.check_writable(inode.mount_id)?;
+        self.assert_not_directory(inode)?;
+
+        if mode & !(FALLOC_FL_KEEP_SIZE | FALLOC_FL_PUNCH_HOLE) != 0
+        {
+            return Err(FilesystemError::OperationNotSupported);
+        }
+
+        // A hole never changes the size of the file
+        if length == 0 || (mode & FALLOC_FL_PUNCH_HOLE != 0 && mode & FALLOC_FL_KEEP_SIZE == 0)
+        {
+            return Err(FilesystemError::InvalidArgument);
+        }
+
+        // The filesystem works on the data on the disk, so cached pages are written back first, and are then zeroed
+        // where a hole was punched so they match the disk
+        let cache = super::cache::get_page_cache();
+        cache.flush_inode(self, inode)?;
+
+        self.get_fs_mount_error(inode.mount_id)?.allocate_range(inode, mode, offset, length)?;
+
+        if mode & FALLOC_FL_PUNCH_HOLE != 0
+        {
+            cache.zero_range(inode, offset, offset.saturating_add(length));
+        }
+
+        cache.forget_size(inode);
+
+        Ok(())
+    }
+
+    /// Get the value of an extended attribute of an inode
+    fn get_xattr(&mut self, inode: FilesystemIndex, name: &str) -> FilesystemResult<Vec<u8>>
+    {
+        check_xattr_name(name)?;
+
+        self.get_fs_mount_error(inode.mount_id)?.get_xattr(inode, name)
+    }
+
+    /// Set the value of an extended attribute of an inode, creating the attribute if it does not exist, an access ACL
+    /// also sets the permission bits of the mode
+    fn set_xattr(&mut self, inode: FilesystemIndex, name: &str, value: &[u8]) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Set extended attribute `{}` of {:?}", name, inode);
+
+        self.check_writable(inode.mount_id)?;
+        check_xattr_name(name)?;
+
+        if value.len() > XATTR_SIZE_MAX
+        {
+            return Err(FilesystemError::AttributeTooLarge);
+        }
+
+        if name == XATTR_POSIX_ACL_ACCESS || name == XATTR_POSIX_ACL_DEFAULT
+        {
+            let acl = Acl::from_bytes(value)?;
+            let stat = self.get_stat(inode)?;
+
+            let fs = self.get_fs_mount_error(inode.mount_id)?;
+
+            if name == XATTR_POSIX_ACL_DEFAULT
+            {
+                // Only directories have entries created in them
+                if stat.mode & S_IFMT != S_IFDIR
+                {
+                    return Err(FilesystemError::InvalidArgument);
+                }
+            }
+            else
+            {
+                fs.set_attr(inode, SetAttributes { mode: Some((stat.mode & 0o7000) | acl.mode()), ..Default::default() })?;
+
+                // An ACL the mode describes entirely does not need to be stored
+                if acl.is_minimal()
+                {
+                    return match fs.remove_xattr(inode, name)
+                    {
+                        Err(FilesystemError::AttributeNotFound) => Ok(()),
+                        result => result
+                    };
+                }
+            }
+        }
+
+        self.get_fs_mount_error(inode.mount_id)?.set_xattr(inode, name, value)
+    }
+
+    /// Get the names of every extended attribute of an inode
+    fn list_xattr(&mut self, inode: FilesystemIndex) -> FilesystemResult<Vec<String>>
+    {
+        self.get_fs_mount_error(inode.mount_id)?.list_xattr(inode)
+    }
+
+    /// Remove an extended attribute from an inode
+    fn remove_xattr(&mut self, inode: FilesystemIndex, name: &str) -> FilesystemResult<()>
+    {
+        kdebugln!(Filesystem, "Remove extended attribute `{}` of {:?}", name, inode);
+
+        self.check_writable(inode.mount_id)?;
+        check_xattr_name(name)?;
+
+        self.get_fs_mount_error(inode.mount_id)?.remove_xattr(inode, name)
+    }
+
     /// Open a filedescriptor for the given inode
     fn open_fd(&mut self, inode: FilesystemIndex, mode: usize) -> FilesystemResult<Box<dyn crate::process::descriptor::FileDescriptor>>
     {
         kdebugln!(Filesystem, "Open fd at inode {:?}", inode);
 
+        // Devices can still be written to on a read only filesystem, only the files it stores are protected
+        if mode & (O_WRONLY | O_APPEND | O_TRUNC) > 0 && self.check_writable(inode.mount_id).is_err()
+        {
+            let kind = self.get_stat(inode)?.mode & S_IFMT;
+
+            if kind != S_IFCHR && kind != S_IFBLK
+            {
+                return Err(FilesystemError::ReadOnlyFilesystem);
+            }
+        }
+
         if let Some(fs) = self.get_fs_mount(inode.mount_id)
         {
             fs.open_fd(inode, mode)
@@ -0,0 +1,74 @@
+//! Background writeback
+//!
+//! Filesystems hold modified blocks in memory until they are synced, so a
+//! crash between calls to `sync` would lose everything written since the last
+//! one. The timer interrupt calls `writeback_tick`, which only marks a
+//! writeback as due once the writeback interval has passed. The flush itself
+//! happens in `run_pending_writeback`, which the init process asks for through
+//! the sync system call each time it is scheduled, so the disk is never
+//! waited on from the timer interrupt. A due writeback is held back while any
+//! process is parked part way through a read or write, so a flush never lands
+//! in the middle of one.
+
+use crate::*;
+
+use drivers::timer::KernelTime;
+
+use super::fstrait::Filesystem;
+
+/// Seconds between background flushes of modified data
+const WRITEBACK_INTERVAL: usize = 5;
+
+// Time of the next background flush
+static mut NEXT_WRITEBACK: Option<KernelTime> = None;
+
+// Set by the timer once a background flush is due
+static mut WRITEBACK_DUE: bool = false;
+
+/// Mark a background flush as due if the writeback interval has passed since the last one
+pub fn writeback_tick()
+{
+    let now = unsafe { &drivers::TIMER_DRIVER }.time();
+    let next = unsafe { &mut NEXT_WRITEBACK };
+
+    match next
+    {
+        Some(time) if now < *time => return,
+        None =>
+        {
+            *next = Some(now + KernelTime::seconds(WRITEBACK_INTERVAL));
+            return;
+        },
+        _ => {}
+    }
+
+    *next = Some(now + KernelTime::seconds(WRITEBACK_INTERVAL));
+
+    unsafe { WRITEBACK_DUE = true };
+}
+
+/// Sync every mounted filesystem if the timer has marked a background flush as due
+pub fn run_pending_writeback()
+{
+    if !unsafe { WRITEBACK_DUE }
+    {
+        return;
+    }
+
+    if process::scheduler::get_process_manager().map(|manager| manager.has_parked_io()).unwrap_or(false)
+    {
+        return;
+    }
+
+    unsafe { WRITEBACK_DUE = false };
+
+    if let Some(vfs) = super::vfs::get_vfs_reference()
+    {
+        kdebugln!(Filesystem, "Background writeback");
+
+        if let Err(e) = vfs.sync()
+        {
+            kerrorln!("Background writeback failed: {:?}", e);
+        }
+    }
+}
@@ -4,5 +4,13 @@ pub fn kernel_halt()
 {
     kprintln!("System Halt");
 
+    if let Some(vfs) = crate::fs::vfs::get_vfs_reference()
+    {
+        if let Err(e) = vfs.release_all()
+        {
+            kerrorln!("Unable to sync filesystems before halting: {:?}", e);
+        }
+    }
+
     unsafe { crate::drivers::POWER_DRIVER.shutdown() };
 }
\ No newline at end of file
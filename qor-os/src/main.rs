@@ -45,7 +45,7 @@ mod utils;
 /// Kernel Initialize Function (Called immediately after boot)
 #[no_mangle]
 pub extern "C"
-fn kinit()
+fn kinit(device_tree: usize)
 {
     // Initialize the UART driver
     drivers::init_uart_driver();
@@ -54,6 +54,9 @@ fn kinit()
     // Initialize the global kernel page allocator
     mem::init_kernel_page_allocator();
     kdebugln!(Initialization, "Global Kernel Page Allocator Initialized");
+
+    // Keep the allocator away from an initramfs loaded by the bootloader
+    fs::initramfs::init_boot_initramfs(device_tree);
     
     // Run any tests if testing is requested
     #[cfg(test)]
@@ -100,11 +103,9 @@ fn kmain()
     drivers::virtio::init_virtio_interrupts();
     kdebugln!(Initialization, "VirtIO Interrupts Initialized");
 
-    // Check for a block device
-    if drivers::virtio::get_block_driver(0).is_none()
-    {
-        panic!("Cannot boot without block device");
-    }
+    // Register the block devices
+    drivers::block::init_block_devices();
+    kdebugln!(Initialization, "Block Devices Registered");
 
     // Initialize the graphics driver
     if drivers::gpu::init_graphics_driver()
@@ -112,28 +113,69 @@ fn kmain()
         kdebugln!(Initialization, "Graphics Driver Initialized");
     }
 
+    // Register the filesystems which can be mounted
+    fs::mounts::init_filesystem_types();
+
     let mut vfs = fs::vfs::FilesystemInterface::new();
-    let mut disk0 = fs::minix3::Minix3Filesystem::new(0);
-    let mut dev = fs::devfs::DevFilesystem::new();
-    let mut proc = fs::procfs::ProcFilesystem::new();
 
     use fs::fstrait::Filesystem;
     use libutils::paths::OwnedPath;
 
     vfs.init().unwrap();
-    disk0.init().unwrap();
-    dev.init().unwrap();
-    proc.init().unwrap();
 
-    vfs.mount_fs(&OwnedPath::new("/"), Box::new(disk0)).unwrap();
-    vfs.mount_fs(&OwnedPath::new("/dev"), Box::new(dev)).unwrap();
-    vfs.mount_fs(&OwnedPath::new("/proc"), Box::new(proc)).unwrap();
+    // Boot from an initramfs if there is one, leaving init to pivot to the disk, otherwise boot from the disk directly
+    let archives = fs::initramfs::boot_archives();
+
+    if archives.len() > 0
+    {
+        vfs.mount(&OwnedPath::new("/"), "rootfs", "tmpfs", 0, "").unwrap();
+
+        for archive in archives
+        {
+            fs::initramfs::unpack_initramfs(vfs, archive).unwrap();
+        }
+
+        fs::initramfs::release_boot_initramfs();
+        kdebugln!(Initialization, "Unpacked initramfs");
+    }
+    else if drivers::block::get_block_device(0).is_some()
+    {
+        // Nothing can repair the root filesystem once it is mounted, so it is repaired as it is mounted, and one
+        // which cannot be repaired is still mounted read only so whatever it holds can be recovered
+        if let Err(e) = vfs.mount(&OwnedPath::new("/"), "/dev/vda", "minix3", 0, "repair")
+        {
+            kerrorln!("Unable to mount the root filesystem read write: {:?}, mounting it read only", e);
+            vfs.mount(&OwnedPath::new("/"), "/dev/vda", "minix3", fs::mounts::MS_RDONLY, "").unwrap();
+        }
+    }
+    else
+    {
+        panic!("Cannot boot without a block device or an initramfs");
+    }
+
+    // The root filesystem does not have to provide the standard mount points, so any which are missing are made
+    for path in ["/dev", "/proc", "/tmp", "/run"]
+    {
+        if let Err(e) = fs::initramfs::make_directories(vfs, &OwnedPath::new(path))
+        {
+            kerrorln!("Unable to create mount point {}: {:?}", path, e);
+        }
+    }
+
+    vfs.mount(&OwnedPath::new("/dev"), "devfs", "devfs", 0, "").unwrap();
+    vfs.mount(&OwnedPath::new("/proc"), "proc", "proc", 0, "").unwrap();
+
+    // Scratch space which never touches the disk
+    vfs.mount(&OwnedPath::new("/tmp"), "tmpfs", "tmpfs", 0, "").unwrap();
+    vfs.mount(&OwnedPath::new("/run"), "tmpfs", "tmpfs", fs::mounts::MS_NOSUID, "size=1m").unwrap();
 
-    vfs.index().unwrap();
+    // An initramfs provides `/init`, a disk provides `/bin/init`
+    let init_path = if vfs.path_to_inode(&OwnedPath::new("/init")).is_ok() { "/init" } else { "/bin/init" };
 
     let elf_proc = process::loading::load_process(
         &mut vfs, 
-        &OwnedPath::new("/bin/init"), 
+        &OwnedPath::new(init_path).into(), 
+        &process::data::Credentials::default(),
         &mut Vec::new(),
         &mut vec![String::from("PATH=/bin\0")]).unwrap();
     process::scheduler::get_init_process_mut().unwrap().register_child(elf_proc.pid);
@@ -93,6 +93,27 @@ pub fn kpfree(addr: usize, count: usize) -> Result<(), page::KernelPageAllocatio
     }
 }
 
+/// Reserve consecutive pages on the kernel heap which are already in use, so they can later be released with `kpfree`
+pub fn kpreserve(addr: usize, count: usize) -> Result<(), page::KernelPageAllocationError>
+{
+    kdebugln!(MemoryAllocation, "kpreserve(0x{:x}, {})", addr, count);
+
+    // Ensure the global kernel page allocator was initialized
+    if unsafe { GLOBAL_KERNEL_PAGE_ALLOCATOR.is_null() }
+    {
+        panic!("Cannot use kpreserve before the global kernel page allocator is initialized");
+    }
+
+    // Safety: The above ensured it was initialized, and the only method of
+    // initialization is through the proper initializer
+    unsafe
+    {
+        // Panic Safety: This is safe because a null would have been caught
+        // above
+        GLOBAL_KERNEL_PAGE_ALLOCATOR.as_mut().unwrap().reserve_pages(addr, count)
+    }
+}
+
 /// Get the number of allocated pages on the kernel heap
 pub fn allocated_kernel_pages() -> usize
 {
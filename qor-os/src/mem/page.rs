@@ -268,6 +268,49 @@ impl PageMap
         Ok(())
     }
 
+    /// Mark a page as allocated without going through the allocator, used for memory which is already in use
+    pub fn reserve_page(&mut self, addr: usize) -> Result<(), KernelPageAllocationError>
+    {
+        if !self.address_in_map(addr)
+        {
+            // If the address is not in this map, skip to the next page
+            if let Some(next) = unsafe { self.next_page.as_mut() }
+            {
+                next.reserve_page(addr)
+            }
+            else
+            {
+                Err(KernelPageAllocationError::NotInTable(addr))
+            }
+        }
+        else if let Some(page_number) = self.address_to_page(addr)
+        {
+            // Set the proper bit to 1
+            self.data[page_number / 8] |= 128 >> (page_number % 8);
+            Ok(())
+        }
+        else
+        {
+            Err(KernelPageAllocationError::NotAligned(addr))
+        }
+    }
+
+    /// Mark consecutive pages as allocated, if any of them cannot be reserved none of them are
+    pub fn reserve_pages(&mut self, addr: usize, count: usize) -> Result<(), KernelPageAllocationError>
+    {
+        for i in 0..count
+        {
+            if let Err(e) = self.reserve_page(addr + i * PAGE_SIZE)
+            {
+                // Release the pages reserved so far so they are not lost to the allocator
+                self.free_pages(addr, i)?;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Allocate consecutive pages
     pub fn alloc_pages(&mut self, count: usize) -> Result<usize, KernelPageAllocationError>
     {
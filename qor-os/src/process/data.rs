@@ -1,5 +1,4 @@
 use alloc::collections::BTreeMap;
-use libutils::paths::OwnedPath;
 
 use crate::*;
 
@@ -10,6 +9,36 @@ use super::stats::*;
 
 use super::PID;
 
+/// A shared file mapping backed directly by pages in the page cache
+#[derive(Debug, Clone, Copy)]
+pub struct SharedMapping
+{
+    pub inode: crate::fs::structures::FilesystemIndex,
+    pub first_page: usize,
+    pub pages: usize,
+    pub perm: crate::mem::mmu::PageTableEntryFlags
+}
+
+/// End of a named pipe kept while its open waits for the other end to be opened, `opens` is the number of times the
+/// other end had been opened when the wait began
+#[derive(Clone)]
+pub struct PendingFifo
+{
+    pub inode: crate::fs::structures::FilesystemIndex,
+    pub description: alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>,
+    pub opens: usize
+}
+
+/// User and group ids of a process, the effective ids are the ones permissions are checked against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Credentials
+{
+    pub uid: u16,
+    pub gid: u16,
+    pub euid: u16,
+    pub egid: u16
+}
+
 /// Process Data
 pub struct ProcessData
 {
@@ -20,12 +49,16 @@ pub struct ProcessData
     pub children: Vec<PID>,
     pub parent_pid: PID,
     pub process_group_id: PID,
-    pub cwd: OwnedPath,
+    pub credentials: Credentials,
+    pub cwd: Option<fs::structures::FilesystemIndex>, // Working directory, starts at the home directory when first used
     pub cmdline_args: Vec<String>,
     pub mem_stats: MemoryStats,
     pub signal_map: BTreeMap<SignalType, SignalDisposition>,
     pub mmapped_files: BTreeMap<*mut u8, usize>,
-    pub return_code_listener: Option<&'static mut u32>
+    pub shared_mappings: BTreeMap<usize, SharedMapping>,
+    pub return_code_listener: Option<&'static mut u32>,
+    pub readiness_deadline: Option<crate::drivers::timer::KernelTime>,
+    pub pending_fifo: Option<PendingFifo>
 }
 
 impl ProcessData
@@ -44,6 +77,7 @@ impl ProcessData
         signal_map.insert(SignalType::SIGCONT, SignalDisposition::Continue);
         signal_map.insert(SignalType::SIGKILL, SignalDisposition::Terminate);
         signal_map.insert(SignalType::SIGINT, SignalDisposition::Terminate);
+        signal_map.insert(SignalType::SIGPIPE, SignalDisposition::Terminate);
 
         Self
         {
@@ -54,12 +88,16 @@ impl ProcessData
             children: Vec::new(),
             parent_pid: 0,
             process_group_id: pgid,
-            cwd: OwnedPath::new("/home/root/"),
+            credentials: Credentials::default(),
+            cwd: None,
             cmdline_args: Vec::new(),
             mem_stats,
             signal_map,
             mmapped_files: BTreeMap::new(),
-            return_code_listener: None
+            shared_mappings: BTreeMap::new(),
+            return_code_listener: None,
+            readiness_deadline: None,
+            pending_fifo: None
         }
     }
 
@@ -90,7 +128,7 @@ impl ProcessData
     /// Remap a file descriptor
     pub fn remap_file_descriptor(&mut self, index: usize, fd: Box<dyn FileDescriptor>)
     {
-        self.descriptors.insert(index, alloc::sync::Arc::new(core::cell::RefCell::new(fd)));
+        self.descriptors.insert(index, DescriptorEntry::new(alloc::sync::Arc::new(core::cell::RefCell::new(fd)), 0));
     }
 
     /// Register a child process
@@ -5,8 +5,53 @@ use fs::fstrait::Filesystem;
 use fs::structures::FilesystemIndex;
 use alloc::collections::BTreeMap;
 
+/// Close the descriptor when the process executes a new program
+pub const FD_CLOEXEC: usize = 1;
+
+/// Entry in a descriptor table, the open file description and its status flags are shared with every duplicate of
+/// the descriptor, while the descriptor flags belong to this entry alone
+#[derive(Clone)]
+pub struct DescriptorEntry
+{
+    pub description: alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>,
+    status_flags: alloc::sync::Arc<core::sync::atomic::AtomicUsize>,
+    pub flags: usize
+}
+
+impl DescriptorEntry
+{
+    /// Create an entry for a new open file description with the given status flags
+    pub fn new(description: alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>, status_flags: usize) -> Self
+    {
+        Self
+        {
+            description,
+            status_flags: alloc::sync::Arc::new(core::sync::atomic::AtomicUsize::new(status_flags)),
+            flags: 0
+        }
+    }
+
+    /// Create another entry for the same open file description, without the descriptor flags
+    pub fn duplicate(&self) -> Self
+    {
+        Self { flags: 0, ..self.clone() }
+    }
+
+    /// Get the status flags of the open file description
+    pub fn status_flags(&self) -> usize
+    {
+        self.status_flags.load(core::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Replace the status flags of the open file description, which affects every duplicate of the descriptor
+    pub fn set_status_flags(&self, status_flags: usize)
+    {
+        self.status_flags.store(status_flags, core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
 /// Descriptor table type
-pub type DescriptorTable = BTreeMap<usize, alloc::sync::Arc<core::cell::RefCell<Box<dyn super::descriptor::FileDescriptor>>>>;
+pub type DescriptorTable = BTreeMap<usize, DescriptorEntry>;
 
 /// Seek Modes
 #[derive(Debug, Clone, Copy)]
@@ -50,9 +95,47 @@ pub trait FileDescriptor
         true
     }
 
-    fn set_end(&mut self, _: &alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>)
+    /// Check if a write would complete without waiting
+    fn check_writable(&self) -> bool
+    {
+        true
+    }
+
+    /// Check if the other end of the descriptor has gone away, such as the last writer of a pipe closing
+    fn check_hangup(&self) -> bool
     {
+        false
+    }
 
+    /// Get the set of descriptors registered with the descriptor if it is an epoll instance
+    fn epoll_set(&self) -> Option<alloc::sync::Arc<core::cell::RefCell<super::epoll::EpollSet>>>
+    {
+        None
+    }
+
+    /// Get the socket behind the descriptor if it is a socket
+    fn socket(&mut self) -> Option<&mut super::socket::UnixSocket>
+    {
+        None
+    }
+
+    /// Get the pipe behind the descriptor if it is an end of a pipe
+    fn pipe(&mut self) -> Option<&super::pipe::SharedPipe>
+    {
+        None
+    }
+
+    /// Start loading the data for a read of `count` bytes, returning a token to wait on if the read would block on
+    /// a disk
+    fn prefetch(&mut self, _fs: &mut fs::vfs::FilesystemInterface, _count: usize) -> Option<crate::drivers::virtio::drivers::block::BlockRequestToken>
+    {
+        None
+    }
+
+    /// Get the inode backing the descriptor if its data is held in the page cache
+    fn page_cache_inode(&self) -> Option<FilesystemIndex>
+    {
+        None
     }
 }
 
@@ -157,6 +240,11 @@ impl FileDescriptor for InodeFileDescriptor
             return usize::MAX;
         }
 
+        // Writing past the end of the data fills the gap with zeros
+        if self.index > self.data.len()
+        {
+            self.data.resize(self.index, 0);
+        }
 
         for i in 0..count
         {
@@ -227,9 +315,7 @@ impl FileDescriptor for InodeFileDescriptor
             },
             SeekMode::SeekEnd => 
             {
-                self.index = self.data.len() - 1 + offset;
-                self.data.extend_from_slice(&vec![0; offset]);
-
+                self.index = self.data.len() + offset;
                 self.index
             },
         }
@@ -248,6 +334,155 @@ impl core::ops::Drop for InodeFileDescriptor
     }
 }
 
+/// File descriptor which reads and writes through the page cache
+#[derive(Debug, Clone)]
+pub struct PageCacheDescriptor
+{
+    pub inode: FilesystemIndex,
+    index: usize,
+    is_write: bool,
+    is_read: bool,
+    is_append: bool
+}
+
+impl PageCacheDescriptor
+{
+    /// Create a new page cache descriptor
+    pub fn new(fs: &mut fs::vfs::FilesystemInterface, inode: FilesystemIndex, mode: usize) -> fs::structures::FilesystemResult<Self>
+    {
+        let mut temp = Self
+        {
+            inode,
+            index: 0,
+            is_write: mode & (O_WRONLY | O_APPEND) > 0,
+            is_read: mode & O_RDONLY > 0,
+            is_append: mode & O_APPEND > 0
+        };
+
+        let cache = fs::cache::get_page_cache();
+
+        if temp.is_write && mode & O_TRUNC > 0
+        {
+            cache.truncate(fs, inode, 0)?;
+        }
+        else if temp.is_append
+        {
+            temp.index = cache.get_size(fs, inode)?;
+        }
+
+        Ok(temp)
+    }
+}
+
+impl FileDescriptor for PageCacheDescriptor
+{
+    fn close(&mut self, fs: &mut fs::vfs::FilesystemInterface)
+    {
+        if self.is_write
+        {
+            if let Err(e) = fs::cache::get_page_cache().flush_inode(fs, self.inode)
+            {
+                kwarnln!("Unable to write back inode {:?}: {:?}", self.inode, e);
+            }
+        }
+    }
+
+    fn write(&mut self, fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
+    {
+        if !self.is_write
+        {
+            return usize::MAX;
+        }
+
+        let cache = fs::cache::get_page_cache();
+
+        // Appending writes always go to the current end of the file
+        if self.is_append
+        {
+            match cache.get_size(fs, self.inode)
+            {
+                Ok(size) => self.index = size,
+                Err(e) => return e.to_errno()
+            }
+        }
+
+        match cache.write(fs, self.inode, self.index, buffer, count)
+        {
+            Ok(written) =>
+            {
+                self.index += written;
+                written
+            },
+            Err(e) => e.to_errno()
+        }
+    }
+
+    fn read(&mut self, fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
+    {
+        if !self.is_read
+        {
+            return usize::MAX;
+        }
+
+        match fs::cache::get_page_cache().read(fs, self.inode, self.index, buffer, count)
+        {
+            Ok(read) =>
+            {
+                self.index += read;
+                read
+            },
+            Err(e) => e.to_errno()
+        }
+    }
+
+    /// Get the inode of the entry
+    fn get_inode(&mut self) -> Option<FilesystemIndex>
+    {
+        Some(self.inode)
+    }
+
+    /// Seek to the given location in the descriptor
+    fn seek(&mut self, offset: usize, mode: SeekMode) -> usize
+    {
+        match mode
+        {
+            SeekMode::SeekSet => self.index = offset,
+            SeekMode::SeekCurrent => self.index += offset,
+            SeekMode::SeekEnd =>
+            {
+                if let Some(vfs) = fs::vfs::get_vfs_reference()
+                {
+                    if let Ok(size) = fs::cache::get_page_cache().get_size(vfs, self.inode)
+                    {
+                        self.index = size + offset;
+                    }
+                }
+            }
+        }
+
+        self.index
+    }
+
+    /// Start loading the data for a read of `count` bytes, returning a token to wait on if the read would block on
+    /// a disk
+    fn prefetch(&mut self, fs: &mut fs::vfs::FilesystemInterface, count: usize) -> Option<crate::drivers::virtio::drivers::block::BlockRequestToken>
+    {
+        if !self.is_read
+        {
+            return None;
+        }
+
+        // Any error will be reported by the read itself
+        fs::cache::get_page_cache().prefetch(fs, self.inode, self.index, count).unwrap_or(None)
+    }
+
+    /// Get the inode backing the descriptor if its data is held in the page cache
+    fn page_cache_inode(&self) -> Option<FilesystemIndex>
+    {
+        Some(self.inode)
+    }
+}
+
 /// Byte interface wrapper
 pub struct ByteInterfaceDescriptor
 {
@@ -425,4 +660,138 @@ impl FileDescriptor for BufferDescriptor
     {
         self.buffer.get_buffer()
     }
-}
\ No newline at end of file
+}
+/// Size of a sector on a block device
+const SECTOR_SIZE: usize = crate::drivers::virtio::drivers::block::BLOCK_SECTOR_SIZE as usize;
+
+/// Maximum number of sectors moved by a single request of a block device descriptor
+const BLOCK_DESCRIPTOR_MAX_SECTORS: usize = 128;
+
+/// Raw access to a block device as a stream of bytes
+pub struct BlockDeviceDescriptor
+{
+    device: &'static mut dyn crate::drivers::block::BlockDevice,
+    index: usize,
+    inode: FilesystemIndex
+}
+
+impl BlockDeviceDescriptor
+{
+    /// Create a new block device descriptor
+    pub fn new(device: &'static mut dyn crate::drivers::block::BlockDevice, inode: FilesystemIndex) -> Self
+    {
+        Self
+        {
+            device,
+            index: 0,
+            inode
+        }
+    }
+
+    /// Get the size of the device in bytes
+    fn size(&self) -> usize
+    {
+        self.device.sector_count() as usize * SECTOR_SIZE
+    }
+
+    /// Move data between the buffer and the device, partial sectors are read before they are modified
+    fn transfer(&mut self, buffer: *mut u8, count: usize, write: bool) -> usize
+    {
+        let size = self.size();
+
+        if self.index >= size
+        {
+            return if write && count > 0 { errno::ENOSPC } else { 0 };
+        }
+
+        let count = count.min(size - self.index);
+        let mut bounce = alloc::vec![0u8; BLOCK_DESCRIPTOR_MAX_SECTORS * SECTOR_SIZE];
+        let mut done = 0;
+
+        while done < count
+        {
+            let position = self.index + done;
+            let sector = (position / SECTOR_SIZE) as u64;
+            let offset = position % SECTOR_SIZE;
+
+            let length = (count - done).min(bounce.len() - offset);
+            let sectors = (offset + length + SECTOR_SIZE - 1) / SECTOR_SIZE;
+
+            // Only sectors which are not entirely overwritten need to be read first
+            let needs_read = !write || offset != 0 || length % SECTOR_SIZE != 0;
+
+            let mut result = if needs_read { self.device.read_sectors(sector, bounce.as_mut_ptr(), sectors) } else { Ok(()) };
+
+            if result.is_ok()
+            {
+                if write
+                {
+                    unsafe { core::ptr::copy(buffer.add(done), bounce.as_mut_ptr().add(offset), length) };
+                    result = self.device.write_sectors(sector, bounce.as_ptr(), sectors);
+                }
+                else
+                {
+                    unsafe { core::ptr::copy(bounce.as_ptr().add(offset), buffer.add(done), length) };
+                }
+            }
+
+            if let Err(e) = result
+            {
+                kwarnln!("Block device transfer at byte {} failed: {:?}", position, e);
+
+                if done == 0
+                {
+                    return errno::EIO;
+                }
+
+                break;
+            }
+
+            done += length;
+        }
+
+        self.index += done;
+
+        done
+    }
+}
+
+impl FileDescriptor for BlockDeviceDescriptor
+{
+    fn close(&mut self, _fs: &mut fs::vfs::FilesystemInterface)
+    {
+        if let Err(e) = self.device.flush()
+        {
+            kwarnln!("Unable to flush block device: {:?}", e);
+        }
+    }
+
+    fn write(&mut self, _fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
+    {
+        self.transfer(buffer, count, true)
+    }
+
+    fn read(&mut self, _fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
+    {
+        self.transfer(buffer, count, false)
+    }
+
+    /// Get the inode of the entry
+    fn get_inode(&mut self) -> Option<FilesystemIndex>
+    {
+        Some(self.inode)
+    }
+
+    /// Seek to the given location in the descriptor
+    fn seek(&mut self, offset: usize, mode: SeekMode) -> usize
+    {
+        match mode
+        {
+            SeekMode::SeekSet => self.index = offset,
+            SeekMode::SeekCurrent => self.index += offset,
+            SeekMode::SeekEnd => self.index = self.size() + offset
+        }
+
+        self.index
+    }
+}
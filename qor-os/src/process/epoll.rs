@@ -0,0 +1,75 @@
+use crate::*;
+use super::descriptor::*;
+
+use crate::fs::structures::FilesystemIndex;
+
+use alloc::collections::BTreeMap;
+
+/// Events a descriptor registered with an epoll instance is watched for, along with the data reported with them
+#[derive(Clone)]
+pub struct EpollInterest
+{
+    pub events: u32,
+    pub data: u64,
+    pub description: alloc::sync::Weak<core::cell::RefCell<Box<dyn FileDescriptor>>>
+}
+
+/// Descriptor number and address of the open file description a registration was made with, a descriptor number
+/// which is closed and reused for another file does not inherit the registration. The interest holds a weak
+/// reference to the description so its address cannot be reused while the registration exists
+pub type EpollKey = (usize, usize);
+
+/// Descriptors registered with an epoll instance
+pub type EpollSet = BTreeMap<EpollKey, EpollInterest>;
+
+/// Epoll instance
+pub struct EpollDescriptor
+{
+    set: alloc::sync::Arc<core::cell::RefCell<EpollSet>>
+}
+
+impl EpollDescriptor
+{
+    /// Create a new epoll instance with no registered descriptors
+    pub fn new() -> Self
+    {
+        Self
+        {
+            set: alloc::sync::Arc::new(core::cell::RefCell::new(BTreeMap::new()))
+        }
+    }
+}
+
+impl FileDescriptor for EpollDescriptor
+{
+    fn close(&mut self, _fs: &mut fs::vfs::FilesystemInterface)
+    {
+        // Nothing needs to be done but drop the set, which will occur elsewhere
+    }
+
+    fn write(&mut self, _fs: &mut fs::vfs::FilesystemInterface, _buffer: *mut u8, _count: usize) -> usize
+    {
+        errno::EINVAL
+    }
+
+    fn read(&mut self, _fs: &mut fs::vfs::FilesystemInterface, _buffer: *mut u8, _count: usize) -> usize
+    {
+        errno::EINVAL
+    }
+
+    fn get_inode(&mut self) -> Option<FilesystemIndex>
+    {
+        None
+    }
+
+    fn check_available(&self) -> bool
+    {
+        // Nesting epoll instances is not supported, so an instance never reports itself ready
+        false
+    }
+
+    fn epoll_set(&self) -> Option<alloc::sync::Arc<core::cell::RefCell<EpollSet>>>
+    {
+        Some(self.set.clone())
+    }
+}
@@ -1,14 +1,14 @@
-/// init process
+extern "C"
+{
+    fn asm_request_writeback();
+}
+
+/// init process, it also stands in for a kernel task by asking for any background writeback which has come due
+/// through the sync system call, so the flush runs as a system call rather than inside the timer interrupt
 pub fn init_proc()
 {
     loop
     {
-        /*
-        unsafe 
-        {
-            asm!("li a7, 61");
-            asm!("li a0, 0");
-            asm!("ecall");
-        }*/
+        unsafe { asm_request_writeback() };
     }
-}
\ No newline at end of file
+}
@@ -6,7 +6,7 @@ use super::loading;
 use fs::fstrait::Filesystem;
 
 use alloc::vec::Vec;
-use libutils::paths::{PathBuffer, OwnedPath};
+use libutils::paths::OwnedPath;
 
 use super::process::Process;
 
@@ -17,20 +17,64 @@ pub enum ProcessLoadError
     ReadError(fs::structures::FilesystemError),
     NotAnELF,
     NotAnExecutable,
+    PermissionDenied,
     BadFormat(String)
 }
 
+/// Get the credentials a program runs with, set-user-ID and set-group-ID programs take the effective ids from the
+/// owner of the file unless it is on a filesystem mounted nosuid
+fn exec_credentials(interface: &mut fs::vfs::FilesystemInterface, index: fs::structures::FilesystemIndex, credentials: &super::data::Credentials) -> Result<super::data::Credentials, ProcessLoadError>
+{
+    let mut result = *credentials;
+
+    if interface.mount_flags(index.mount_id) & fs::mounts::MS_NOSUID > 0
+    {
+        return Ok(result);
+    }
+
+    let stat = interface.get_stat(index).map_err(|e| ProcessLoadError::ReadError(e))?;
+
+    if stat.mode & fs::structures::S_ISUID > 0
+    {
+        result.euid = stat.uid;
+    }
+
+    if stat.mode & fs::structures::S_ISGID > 0
+    {
+        result.egid = stat.gid;
+    }
 
-pub fn load_process(interface: &mut fs::vfs::FilesystemInterface, path: PathBuffer, args: &mut Vec<String>, envp: &mut Vec<String>) -> Result<Process, ProcessLoadError>
+    Ok(result)
+}
+
+pub fn load_process(interface: &mut fs::vfs::FilesystemInterface, path: &fs::vfs::PathAt, credentials: &super::data::Credentials, args: &mut Vec<String>, envp: &mut Vec<String>) -> Result<Process, ProcessLoadError>
 {
     // Open the file
-    let index = interface.path_to_inode(path).map_err(|e| loading::ProcessLoadError::ReadError(e))?;
+    let index = interface.resolve_path_at(path, true).map_err(|e| loading::ProcessLoadError::ReadError(e))?;
+
+    // Programs cannot be run from filesystems mounted noexec
+    if interface.mount_flags(index.mount_id) & fs::mounts::MS_NOEXEC > 0
+    {
+        return Err(loading::ProcessLoadError::PermissionDenied);
+    }
+
+    // The program must be executable by the process running it
+    match interface.check_permission(index, credentials, fs::acl::ACL_EXECUTE)
+    {
+        Ok(()) => {},
+        Err(fs::structures::FilesystemError::AccessDenied) => return Err(loading::ProcessLoadError::PermissionDenied),
+        Err(e) => return Err(loading::ProcessLoadError::ReadError(e))
+    }
+
     let file_data = interface.read_inode(index).map_err(|e| loading::ProcessLoadError::ReadError(e))?;
 
     // If the file is an ELF file, load that file
     if file_data[0..4] == [0x7F, 'E' as u8, 'L' as u8, 'F' as u8]
     {
-        super::elf::load_elf(file_data, path, args, envp)
+        let mut process = super::elf::load_elf(file_data, &path.path, args, envp)?;
+        process.data.credentials = exec_credentials(interface, index, credentials)?;
+
+        Ok(process)
     }
     else if file_data[0..2] == ['#' as u8, '!' as u8]
     {
@@ -45,9 +89,9 @@ pub fn load_process(interface: &mut fs::vfs::FilesystemInterface, path: PathBuff
             f.push(*c as char);
         }
 
-        args.insert(0, path.to_string());
+        args.insert(0, path.path.to_string());
 
-        load_process(interface, &OwnedPath::new(f), args, envp)
+        load_process(interface, &OwnedPath::new(f).into(), credentials, args, envp)
     }
     else
     {
@@ -2,13 +2,15 @@
 pub mod data;
 pub mod descriptor;
 pub mod elf;
+pub mod epoll;
 pub mod init;
 pub mod loading;
+pub mod pipe;
 pub mod process;
+pub mod readiness;
 pub mod scheduler;
 pub mod stats;
 pub mod signals;
-
-mod pipe;
+pub mod socket;
 
 pub type PID = u16;
\ No newline at end of file
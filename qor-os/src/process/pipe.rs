@@ -1,27 +1,179 @@
 use crate::*;
 use super::descriptor::*;
+use super::readiness::notify_readiness;
 
 use crate::fs::structures::FilesystemIndex;
 
+use alloc::collections::VecDeque;
+
+/// Largest write which is guaranteed not to be interleaved with data from other writers
+pub const PIPE_BUF: usize = 4096;
+
+/// Capacity of a new pipe
+pub const PIPE_DEFAULT_SIZE: usize = 16 * mem::PAGE_SIZE;
+
+/// Largest capacity a pipe can be given
+pub const PIPE_MAX_SIZE: usize = 1024 * 1024;
+
+/// State shared by every end of a pipe
+pub struct Pipe
+{
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    readers: usize,
+    writers: usize,
+    read_opens: usize,
+    write_opens: usize
+}
+
+/// Reference to a pipe shared between its ends
+pub type SharedPipe = alloc::sync::Arc<core::cell::RefCell<Pipe>>;
+
+impl Pipe
+{
+    /// Create a new, empty pipe with no ends open
+    pub fn new() -> SharedPipe
+    {
+        alloc::sync::Arc::new(core::cell::RefCell::new(Self
+        {
+            buffer: VecDeque::new(),
+            capacity: PIPE_DEFAULT_SIZE,
+            readers: 0,
+            writers: 0,
+            read_opens: 0,
+            write_opens: 0
+        }))
+    }
+
+    /// Get the number of read ends open
+    pub fn readers(&self) -> usize
+    {
+        self.readers
+    }
+
+    /// Get the number of write ends open
+    pub fn writers(&self) -> usize
+    {
+        self.writers
+    }
+
+    /// Get the number of read ends ever opened, which changes even if a reader opens and closes again before a
+    /// waiting writer notices
+    pub fn read_opens(&self) -> usize
+    {
+        self.read_opens
+    }
+
+    /// Get the number of write ends ever opened
+    pub fn write_opens(&self) -> usize
+    {
+        self.write_opens
+    }
+
+    /// Get the number of bytes the pipe can hold
+    pub fn capacity(&self) -> usize
+    {
+        self.capacity
+    }
+
+    /// Get the number of bytes which can be written before the pipe is full
+    pub fn room(&self) -> usize
+    {
+        self.capacity.saturating_sub(self.buffer.len())
+    }
+
+    /// Change the number of bytes the pipe can hold, rounded up to a whole number of pages, returns the new capacity
+    pub fn set_capacity(&mut self, size: usize) -> Result<usize, usize>
+    {
+        if size > PIPE_MAX_SIZE
+        {
+            return Err(errno::EPERM);
+        }
+
+        let capacity = ((size + mem::PAGE_SIZE - 1) / mem::PAGE_SIZE).max(1) * mem::PAGE_SIZE;
+
+        // The data already in the pipe must still fit
+        if capacity < self.buffer.len()
+        {
+            return Err(errno::EBUSY);
+        }
+
+        self.capacity = capacity;
+
+        // Writers waiting for room may now have it
+        notify_readiness();
+
+        Ok(capacity)
+    }
+
+    /// Add data to the pipe, returns the number of bytes written or `EAGAIN` if the write has to wait for room.
+    /// Writes of up to `PIPE_BUF` bytes are written whole or not at all, larger writes take whatever fits
+    fn write(&mut self, data: &[u8]) -> usize
+    {
+        if self.readers == 0
+        {
+            return errno::EPIPE;
+        }
+
+        if data.is_empty()
+        {
+            return 0;
+        }
+
+        let room = self.room();
+
+        if room == 0 || (data.len() <= PIPE_BUF && room < data.len())
+        {
+            return errno::EAGAIN;
+        }
+
+        let count = data.len().min(room);
+        self.buffer.extend(data[..count].iter());
+
+        count
+    }
+
+    /// Take data from the front of the pipe, returns the number of bytes read
+    fn read(&mut self, buffer: &mut [u8]) -> usize
+    {
+        let count = buffer.len().min(self.buffer.len());
+
+        for (i, byte) in self.buffer.drain(..count).enumerate()
+        {
+            buffer[i] = byte;
+        }
+
+        count
+    }
+}
+
 /// Write side of a pipe
 pub struct WritePipeDescriptor
 {
-    buffer: alloc::sync::Arc<core::cell::RefCell<utils::ByteRingBuffer>>,
-    read_end: Option<alloc::sync::Weak<core::cell::RefCell<Box<dyn FileDescriptor>>>>
+    pipe: SharedPipe,
+    inode: Option<FilesystemIndex>
 }
 
 impl WritePipeDescriptor
 {
-    fn is_end_closed(&self) -> bool
+    /// Open a new write end of a pipe, named pipes carry the inode they were opened through
+    pub fn new(pipe: SharedPipe, inode: Option<FilesystemIndex>) -> Self
     {
-        if let Some(end) = &self.read_end
-        {
-            end.upgrade().is_none()
-        }
-        else
         {
-            false
+            let mut state = pipe.borrow_mut();
+            state.writers += 1;
+            state.write_opens = state.write_opens.wrapping_add(1);
         }
+
+        // Readers waiting for a writer to open a named pipe can continue
+        notify_readiness();
+
+        Self { pipe, inode }
+    }
+
+    fn is_end_closed(&self) -> bool
+    {
+        self.pipe.borrow().readers == 0
     }
 }
 
@@ -34,17 +186,16 @@ impl FileDescriptor for WritePipeDescriptor
 
     fn write(&mut self, _fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
     {
-        if self.is_end_closed()
-        {
-            return errno::EPIPE;
-        }
+        let data = unsafe { core::slice::from_raw_parts(buffer, count) };
+        let result = self.pipe.borrow_mut().write(data);
 
-        for i in 0..count
+        // Readers waiting for data can continue
+        if (result as isize) > 0
         {
-            self.buffer.borrow_mut().enqueue_byte(unsafe { buffer.add(i).read() });
+            notify_readiness();
         }
 
-        count
+        result
     }
 
     fn read(&mut self, _fs: &mut fs::vfs::FilesystemInterface, _buffer: *mut u8, _count: usize) -> usize
@@ -55,34 +206,54 @@ impl FileDescriptor for WritePipeDescriptor
 
     fn get_inode(&mut self) -> Option<FilesystemIndex>
     {
-        None
+        self.inode
     }
 
-    fn set_end(&mut self, end: &alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>)
+    fn check_writable(&self) -> bool
     {
-        self.read_end = Some(alloc::sync::Arc::<core::cell::RefCell::<Box<dyn FileDescriptor>>>::downgrade(end));
+        let pipe = self.pipe.borrow();
+
+        pipe.readers == 0 || pipe.room() >= PIPE_BUF.min(pipe.capacity)
+    }
+
+    fn check_hangup(&self) -> bool
+    {
+        self.is_end_closed()
+    }
+
+    fn pipe(&mut self) -> Option<&SharedPipe>
+    {
+        Some(&self.pipe)
     }
 }
 
 /// Read side of a pipe
 pub struct ReadPipeDescriptor
 {
-    buffer: alloc::sync::Arc<core::cell::RefCell<utils::ByteRingBuffer>>,
-    write_end: Option<alloc::sync::Weak<core::cell::RefCell<Box<dyn FileDescriptor>>>>
+    pipe: SharedPipe,
+    inode: Option<FilesystemIndex>
 }
 
 impl ReadPipeDescriptor
 {
-    fn is_end_closed(&self) -> bool
+    /// Open a new read end of a pipe, named pipes carry the inode they were opened through
+    pub fn new(pipe: SharedPipe, inode: Option<FilesystemIndex>) -> Self
     {
-        if let Some(end) = &self.write_end
         {
-            end.upgrade().is_none()
-        }
-        else
-        {
-            false
+            let mut state = pipe.borrow_mut();
+            state.readers += 1;
+            state.read_opens = state.read_opens.wrapping_add(1);
         }
+
+        // Writers waiting for a reader to open a named pipe can continue
+        notify_readiness();
+
+        Self { pipe, inode }
+    }
+
+    fn is_end_closed(&self) -> bool
+    {
+        self.pipe.borrow().writers == 0
     }
 }
 
@@ -101,51 +272,161 @@ impl FileDescriptor for ReadPipeDescriptor
 
     fn read(&mut self, _fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
     {
-        for i in 0..count
+        let buffer = unsafe { core::slice::from_raw_parts_mut(buffer, count) };
+        let result = self.pipe.borrow_mut().read(buffer);
+
+        // Writers waiting for room can continue
+        if result > 0
         {
-            if let Some(data) = self.buffer.borrow_mut().dequeue_byte()
-            {
-                unsafe { buffer.add(i).write(data) }
-            }
-            else
-            {
-                return i;
-            }
+            notify_readiness();
         }
 
-        count
+        result
     }
 
     fn get_inode(&mut self) -> Option<FilesystemIndex>
     {
-        None
+        self.inode
     }
 
     fn check_available(&self) -> bool
     {
-        !self.buffer.borrow_mut().is_empty() || self.is_end_closed()
+        !self.pipe.borrow().buffer.is_empty() || self.is_end_closed()
+    }
+
+    fn check_hangup(&self) -> bool
+    {
+        self.is_end_closed()
     }
 
-    fn set_end(&mut self, end: &alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>)
+    fn pipe(&mut self) -> Option<&SharedPipe>
     {
-        self.write_end = Some(alloc::sync::Arc::<core::cell::RefCell::<Box<dyn FileDescriptor>>>::downgrade(end));
+        Some(&self.pipe)
+    }
+}
+
+/// Named pipe opened for both reading and writing, which holds an end of each kind so it never waits for another
+/// process to open the pipe
+pub struct ReadWritePipeDescriptor
+{
+    read: ReadPipeDescriptor,
+    write: WritePipeDescriptor
+}
+
+impl ReadWritePipeDescriptor
+{
+    /// Open both ends of a named pipe
+    pub fn new(pipe: SharedPipe, inode: Option<FilesystemIndex>) -> Self
+    {
+        Self
+        {
+            read: ReadPipeDescriptor::new(pipe.clone(), inode),
+            write: WritePipeDescriptor::new(pipe, inode)
+        }
+    }
+}
+
+impl FileDescriptor for ReadWritePipeDescriptor
+{
+    fn close(&mut self, _fs: &mut fs::vfs::FilesystemInterface)
+    {
+        // Nothing needs to be done but drop both ends, which will occur elsewhere
+    }
+
+    fn write(&mut self, fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
+    {
+        self.write.write(fs, buffer, count)
+    }
+
+    fn read(&mut self, fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
+    {
+        self.read.read(fs, buffer, count)
+    }
+
+    fn get_inode(&mut self) -> Option<FilesystemIndex>
+    {
+        self.read.get_inode()
+    }
+
+    fn check_available(&self) -> bool
+    {
+        self.read.check_available()
+    }
+
+    fn check_writable(&self) -> bool
+    {
+        self.write.check_writable()
+    }
+
+    fn pipe(&mut self) -> Option<&SharedPipe>
+    {
+        self.read.pipe()
+    }
+}
+
+impl core::ops::Drop for WritePipeDescriptor
+{
+    fn drop(&mut self)
+    {
+        self.pipe.borrow_mut().writers -= 1;
+
+        // Readers waiting for data see the end of the pipe
+        notify_readiness();
+    }
+}
+
+impl core::ops::Drop for ReadPipeDescriptor
+{
+    fn drop(&mut self)
+    {
+        self.pipe.borrow_mut().readers -= 1;
+
+        // Writers waiting for room see the pipe break
+        notify_readiness();
     }
 }
 
 /// Create a new pipe pair
 pub fn new_pipe() -> (alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>, alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>)
 {
-    let buffer = utils::ByteRingBuffer::new();
-    let wrapped_buffer = 
-        alloc::sync::Arc::new(
-            core::cell::RefCell::new(
-                buffer));
-    
-    let read = alloc::sync::Arc::new(core::cell::RefCell::new(Box::new(ReadPipeDescriptor { buffer: wrapped_buffer.clone(), write_end: None }) as Box<dyn FileDescriptor>));
-    let write = alloc::sync::Arc::new(core::cell::RefCell::new(Box::new(WritePipeDescriptor { buffer: wrapped_buffer.clone(), read_end: None }) as Box<dyn FileDescriptor>));
-
-    read.borrow_mut().set_end(&write);
-    write.borrow_mut().set_end(&read);
+    let pipe = Pipe::new();
+
+    let read = alloc::sync::Arc::new(core::cell::RefCell::new(Box::new(ReadPipeDescriptor::new(pipe.clone(), None)) as Box<dyn FileDescriptor>));
+    let write = alloc::sync::Arc::new(core::cell::RefCell::new(Box::new(WritePipeDescriptor::new(pipe, None)) as Box<dyn FileDescriptor>));
 
     (read, write)
-}
\ No newline at end of file
+}
+
+/// Pipe Capacity Test
+#[test_case]
+fn pipe_capacity()
+{
+    let pipe = Pipe::new();
+
+    // Writing without a reader breaks the pipe
+    assert_eq!(pipe.borrow_mut().write(&[0x42]), errno::EPIPE);
+
+    let reader = ReadPipeDescriptor::new(pipe.clone(), None);
+    let data = alloc::vec![0x42u8; PIPE_DEFAULT_SIZE];
+
+    // A large write takes whatever fits, leaving the pipe full
+    assert_eq!(pipe.borrow_mut().write(&data[..PIPE_DEFAULT_SIZE - 16]), PIPE_DEFAULT_SIZE - 16);
+    assert_eq!(pipe.borrow_mut().write(&data), 16);
+    assert_eq!(pipe.borrow_mut().write(&data[..1]), errno::EAGAIN);
+
+    // Small writes are written whole or not at all
+    let mut buffer = [0u8; PIPE_BUF];
+    assert_eq!(pipe.borrow_mut().read(&mut buffer[..16]), 16);
+    assert_eq!(pipe.borrow_mut().write(&data[..PIPE_BUF]), errno::EAGAIN);
+
+    assert_eq!(pipe.borrow_mut().read(&mut buffer), PIPE_BUF);
+    assert_eq!(pipe.borrow_mut().write(&data[..PIPE_BUF]), PIPE_BUF);
+
+    // The capacity can only shrink to fit the data already in the pipe
+    assert_eq!(pipe.borrow_mut().set_capacity(1), Err(errno::EBUSY));
+    assert_eq!(pipe.borrow_mut().set_capacity(PIPE_MAX_SIZE + 1), Err(errno::EPERM));
+    assert_eq!(pipe.borrow_mut().set_capacity(PIPE_DEFAULT_SIZE + 1), Ok(PIPE_DEFAULT_SIZE + mem::PAGE_SIZE));
+    assert_eq!(pipe.borrow().room(), mem::PAGE_SIZE + 16);
+
+    drop(reader);
+}
@@ -4,11 +4,11 @@ use crate::fs::fstrait::Filesystem;
 use crate::mem::mmu::PageTableEntryFlags;
 
 use fs::structures::DirectoryEntry;
-use libutils::paths::OwnedPath;
-use libutils::paths::PathBuffer;
+
+use fs::locks::LockOwner;
 
 use super::data::ProcessData;
-use super::descriptor::FileDescriptor;
+use super::descriptor::{DescriptorEntry, FileDescriptor, FD_CLOEXEC};
 use super::stats::MemoryStats;
 
 use mem::mmu::PageTable;
@@ -40,12 +40,25 @@ const O_APPEND: usize = 4;
 const O_TRUNC: usize =  8;
 const O_CREAT: usize =  16;
 const O_EXCL: usize =   32;
+pub const O_CLOEXEC: usize = 64;
+pub const O_NONBLOCK: usize = 128;
+
+/// Flags of an open file description which can be read back with `F_GETFL`
+pub const O_STATUS_FLAGS: usize = O_RDONLY | O_WRONLY | O_APPEND | O_NONBLOCK;
 
 const SEEK_SET: usize = 1;
 const SEEK_CUR: usize = 2;
 const SEEK_END: usize = 4;
+const SEEK_DATA: usize = 8;
+const SEEK_HOLE: usize = 16;
+
+/// Directory processes start in
+const HOME_DIRECTORY: &str = "/home/root";
 
+// Must be kept in sync with syscalls.h
 const MAP_ANON: usize = 1;
+const MAP_SHARED: usize = 2;
+const MAP_PRIVATE: usize = 4;
 
 // Stack locations
 pub const STACK_START: usize = 0x2_0000_0000;
@@ -58,7 +71,14 @@ pub enum WaitMode
     // Pointer to return code
     ForChild,
     ForSignal,
-    ForIO((usize, usize, *mut u8))
+    ForIO((usize, usize, *mut u8)),
+    // Descriptor, buffer, length of the write and the number of bytes written so far
+    ForWrite((usize, *mut u8, usize, usize)),
+    ForBlockDevice(crate::drivers::virtio::drivers::block::BlockRequestToken),
+    // Generation of the lock table when the process started waiting
+    ForLock(usize),
+    // Readiness generation when the process started waiting, and when to give up
+    ForReadiness(usize, Option<KernelTime>)
 }
 
 /// Process State Enumeration
@@ -309,6 +329,10 @@ impl Process
 
         self.state = ProcessState::Zombie;
         self.exit_code = value as u32;
+
+        // Descriptors are closed at exit rather than once the process is reaped, so the locks they hold are released
+        // straight away
+        self.context_cleanup();
     }
 
     /// Initialize the file system
@@ -326,50 +350,120 @@ impl Process
         }
     }
 
-    /// Write descriptor into the next open file descriptor
-    pub fn add_descriptor(&mut self, fd: Box<dyn FileDescriptor>) -> usize
+    /// Get the working directory of the process, starting it at the home directory, or the root if there is none,
+    /// the first time it is needed
+    pub fn get_cwd(&mut self) -> Result<fs::structures::FilesystemIndex, usize>
     {
-        let mut i = 0;
+        if let Some(cwd) = self.data.cwd
+        {
+            return Ok(cwd);
+        }
 
-        while self.data.descriptors.contains_key(&i)
+        self.ensure_fs();
+        let vfs = self.fs_interface.as_mut().unwrap();
+
+        let cwd = match vfs.path_to_inode(&libutils::paths::OwnedPath::new(HOME_DIRECTORY))
         {
-            i += 1;
+            Ok(inode) => inode,
+            Err(_) => vfs.get_root_index().map_err(|e| e.to_errno())?
+        };
+
+        self.data.cwd = Some(cwd);
+
+        Ok(cwd)
+    }
+
+    /// Write descriptor into the next open file descriptor, `mode` gives the status flags of the new description
+    /// and whether the descriptor is closed on exec
+    pub fn add_descriptor(&mut self, fd: Box<dyn FileDescriptor>, mode: usize) -> usize
+    {
+        self.add_wrapped_descriptor(alloc::sync::Arc::new(core::cell::RefCell::new(fd)), mode)
+    }
+
+    // Add an already wrapped descriptor
+    pub fn add_wrapped_descriptor(&mut self, fd: alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>, mode: usize) -> usize
+    {
+        let i = self.lowest_free_descriptor(0);
+
+        let mut entry = DescriptorEntry::new(fd, mode & O_STATUS_FLAGS);
+
+        if mode & O_CLOEXEC > 0
+        {
+            entry.flags |= FD_CLOEXEC;
         }
 
-        self.data.descriptors.insert(i, alloc::sync::Arc::new(core::cell::RefCell::new(fd)));
+        self.data.descriptors.insert(i, entry);
 
         i
     }
 
-    // Add an already wrapped descriptor
-    pub fn add_wrapped_descriptor(&mut self, fd: alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>) -> usize
+    /// Add a descriptor entry passed from another process, such as over a socket, which keeps sharing its open file
+    /// description and status flags
+    pub fn install_descriptor(&mut self, mut entry: DescriptorEntry, cloexec: bool) -> usize
     {
-        let mut i = 0;
+        let i = self.lowest_free_descriptor(0);
+
+        entry.flags = if cloexec { FD_CLOEXEC } else { 0 };
+        self.data.descriptors.insert(i, entry);
+
+        i
+    }
+
+    /// Add a socket as a descriptor open for reading and writing, only `O_CLOEXEC` and `O_NONBLOCK` are used from
+    /// `flags`
+    pub fn add_socket(&mut self, socket: super::socket::UnixSocket, flags: usize) -> usize
+    {
+        self.add_descriptor(Box::new(socket), O_RDONLY | O_WRONLY | (flags & (O_CLOEXEC | O_NONBLOCK)))
+    }
+
+    /// Get the lowest unused file descriptor number which is at least `min`
+    fn lowest_free_descriptor(&self, min: usize) -> usize
+    {
+        let mut i = min;
 
         while self.data.descriptors.contains_key(&i)
         {
             i += 1;
         }
 
-        self.data.descriptors.insert(i, fd);
-
         i
     }
 
+    /// Check if reads from a file descriptor should fail rather than wait for data
+    pub fn is_nonblocking(&self, fd: usize) -> bool
+    {
+        self.data.descriptors.get(&fd).map(|entry| entry.status_flags() & O_NONBLOCK > 0).unwrap_or(false)
+    }
+
     /// Open a file by path
-    pub fn open(&mut self, path: PathBuffer, mode: usize) -> Result<usize, fs::structures::FilesystemError>
+    pub fn open(&mut self, path: &fs::vfs::PathAt, mode: usize) -> Result<usize, fs::structures::FilesystemError>
     {
         self.ensure_fs();
 
+        let credentials = self.data.credentials;
         let vfs = self.fs_interface.as_mut().unwrap();
         let inode = 
-            if let Ok(inode_result) = vfs.path_to_inode(&path)
+            if let Ok(inode_result) = vfs.resolve_path_at(path, true)
             {
                 if (mode & O_EXCL) > 0
                 {
                     return Ok(errno::EEXIST);
                 }
 
+                let mut want = 0;
+
+                if mode & O_RDONLY > 0
+                {
+                    want |= fs::acl::ACL_READ;
+                }
+
+                if mode & (O_WRONLY | O_TRUNC) > 0
+                {
+                    want |= fs::acl::ACL_WRITE;
+                }
+
+                vfs.check_permission(inode_result, &credentials, want)?;
+
                 inode_result
             }
             else
@@ -379,15 +473,96 @@ impl Process
                     return Ok(errno::ENOENT);
                 }
 
-                let (path, name) = path.split_last();
+                let (dest_inode, name) = vfs.resolve_parent_at(path)?;
+                vfs.check_permission(dest_inode, &credentials, fs::acl::ACL_WRITE | fs::acl::ACL_EXECUTE)?;
 
-                let dest_inode = vfs.path_to_inode(&path)?;
-
-                vfs.create_file(dest_inode, name.to_string())?
+                match vfs.create_file(dest_inode, name)
+                {
+                    Ok(inode) => inode,
+                    Err(e) => return Ok(e.to_errno())
+                }
             };
 
+        let kind = vfs.get_stat(inode)?.mode & fs::structures::S_IFMT;
+
+        // Named pipes connect to the pipe shared by every process which opens them, instead of the inode's data
+        if kind == fs::structures::S_IFIFO
+        {
+            let fd = self.open_fifo(inode, mode)?;
+            return Ok(self.add_wrapped_descriptor(fd, mode));
+        }
+
+        // Sockets are reached with connect rather than open
+        if kind == fs::structures::S_IFSOCK
+        {
+            return Ok(errno::ENXIO);
+        }
+
         let fd = vfs.open_fd(inode, mode)?;
-        Ok(self.add_descriptor(fd))
+        Ok(self.add_descriptor(fd, mode))
+    }
+
+    /// Open an end of the named pipe at an inode. Opening a single end waits for the other end to be opened unless
+    /// `O_NONBLOCK` is given, in which case opening the write end fails if no process is reading. Waiting returns
+    /// `WouldBlock`, keeping the end which was opened for the restarted open to pick back up
+    fn open_fifo(&mut self, inode: fs::structures::FilesystemIndex, mode: usize) -> Result<alloc::sync::Arc<core::cell::RefCell<Box<dyn FileDescriptor>>>, fs::structures::FilesystemError>
+    {
+        use super::pipe::*;
+
+        self.ensure_fs();
+        let pipe = self.fs_interface.as_mut().unwrap().fifo_pipe(inode);
+
+        let wrap = |fd: Box<dyn FileDescriptor>| alloc::sync::Arc::new(core::cell::RefCell::new(fd));
+
+        let writing = mode & O_WRONLY > 0;
+
+        // Opening both ends never waits
+        if writing && mode & O_RDONLY > 0
+        {
+            return Ok(wrap(Box::new(ReadWritePipeDescriptor::new(pipe, Some(inode)))));
+        }
+
+        let pending = self.data.pending_fifo.take().filter(|pending| pending.inode == inode);
+
+        let (description, opens) = match pending
+        {
+            Some(pending) => (pending.description, pending.opens),
+            None if writing =>
+            {
+                if mode & O_NONBLOCK > 0 && pipe.borrow().readers() == 0
+                {
+                    return Err(fs::structures::FilesystemError::NoReaders);
+                }
+
+                let opens = pipe.borrow().read_opens();
+                (wrap(Box::new(WritePipeDescriptor::new(pipe.clone(), Some(inode)))), opens)
+            },
+            None =>
+            {
+                let opens = pipe.borrow().write_opens();
+                (wrap(Box::new(ReadPipeDescriptor::new(pipe.clone(), Some(inode)))), opens)
+            }
+        };
+
+        // An open of the other end since the wait began counts even if that end has been closed again
+        let connected = if writing
+        {
+            pipe.borrow().readers() > 0 || pipe.borrow().read_opens() != opens
+        }
+        else
+        {
+            pipe.borrow().writers() > 0 || pipe.borrow().write_opens() != opens || mode & O_NONBLOCK > 0
+        };
+
+        if connected
+        {
+            Ok(description)
+        }
+        else
+        {
+            self.data.pending_fifo = Some(super::data::PendingFifo { inode, description, opens });
+            Err(fs::structures::FilesystemError::WouldBlock)
+        }
     }
 
     /// Read from a file descriptor
@@ -397,7 +572,7 @@ impl Process
 
         if let Some(fd) = self.data.descriptors.get_mut(&fd)
         {
-            fd.borrow_mut().read(self.fs_interface.as_mut().unwrap(), buffer, count)
+            fd.description.borrow_mut().read(self.fs_interface.as_mut().unwrap(), buffer, count)
         }
         else
         {
@@ -405,6 +580,22 @@ impl Process
         }
     }
 
+    /// Start loading the data for a read from a file descriptor, returning a token to wait on if the read would
+    /// block on a disk
+    pub fn prefetch(&mut self, fd: usize, count: usize) -> Option<crate::drivers::virtio::drivers::block::BlockRequestToken>
+    {
+        self.ensure_fs();
+
+        if let Some(fd) = self.data.descriptors.get_mut(&fd)
+        {
+            fd.description.borrow_mut().prefetch(self.fs_interface.as_mut().unwrap(), count)
+        }
+        else
+        {
+            None
+        }
+    }
+
     /// Check for data available on a file descriptor
     pub fn check_available(&mut self, fd: usize) -> bool
     {
@@ -412,7 +603,22 @@ impl Process
 
         if let Some(fd) = self.data.descriptors.get_mut(&fd)
         {
-            fd.borrow_mut().check_available()
+            fd.description.borrow_mut().check_available()
+        }
+        else
+        {
+            false
+        }
+    }
+
+    /// Check if a write to a file descriptor would complete without waiting
+    pub fn check_writable(&mut self, fd: usize) -> bool
+    {
+        self.ensure_fs();
+
+        if let Some(fd) = self.data.descriptors.get_mut(&fd)
+        {
+            fd.description.borrow_mut().check_writable()
         }
         else
         {
@@ -420,14 +626,15 @@ impl Process
         }
     }
 
-    /// Write to a file descriptor
+    /// Write to a file descriptor, a write failing with `EPIPE` leaves raising `SIGPIPE` to the caller, as it is only
+    /// raised if none of the data was written
     pub fn write(&mut self, fd: usize, buffer: *mut u8, count: usize) -> usize
     {
         self.ensure_fs();
 
         if let Some(fd) = self.data.descriptors.get_mut(&fd)
         {
-            fd.borrow_mut().write(self.fs_interface.as_mut().unwrap(), buffer, count)
+            fd.description.borrow_mut().write(self.fs_interface.as_mut().unwrap(), buffer, count)
         }
         else
         {
@@ -435,14 +642,23 @@ impl Process
         }
     }
 
+    /// Deliver `SIGPIPE` to the process after a write to a pipe or socket with no reader
+    pub fn raise_broken_pipe(&mut self)
+    {
+        // If the signal stack is full the write still fails with EPIPE
+        let _ = self.push_signal(POSIXSignal::new(self.pid, self.pid, SignalType::SIGPIPE));
+    }
+
     /// Close a file descriptor
     pub fn close(&mut self, fd_number: usize) -> usize
     {
         self.ensure_fs();
 
+        self.release_descriptor_locks(fd_number);
+
         let v = if let Some(fd) = self.data.descriptors.get_mut(&fd_number)
         {
-            fd.borrow_mut().close(self.fs_interface.as_mut().unwrap());
+            fd.description.borrow_mut().close(self.fs_interface.as_mut().unwrap());
             0
         }
         else
@@ -458,13 +674,85 @@ impl Process
         v
     }
 
-    /// Create a new pipe
-    pub fn pipe(&mut self) -> (usize, usize)
+    /// Get the owner of the `flock` locks taken through a file descriptor, which is shared with every duplicate of
+    /// the descriptor
+    pub fn descriptor_lock_owner(&self, fd: usize) -> Result<LockOwner, usize>
+    {
+        self.data.descriptors.get(&fd).map(|desc| LockOwner::Description(alloc::sync::Arc::as_ptr(&desc.description) as usize)).ok_or(errno::EBADF)
+    }
+
+    /// Release the locks which end when a file descriptor is closed, record locks on the file go with any of the
+    /// process's descriptors, while `flock` locks go with the last descriptor referring to their open file description
+    fn release_descriptor_locks(&mut self, fd: usize)
+    {
+        let (inode, last) = match self.data.descriptors.get(&fd)
+        {
+            Some(desc) => (desc.description.borrow_mut().get_inode(), alloc::sync::Arc::strong_count(&desc.description) == 1),
+            None => return
+        };
+
+        if let Some(inode) = inode
+        {
+            let owner = self.descriptor_lock_owner(fd).unwrap();
+
+            self.ensure_fs();
+            let locks = self.fs_interface.as_mut().unwrap().lock_table();
+
+            locks.release_inode(inode, LockOwner::Process(self.pid));
+
+            if last
+            {
+                locks.release_inode(inode, owner);
+            }
+        }
+    }
+
+    /// Check which of the `POLL*` events in `events` a file descriptor is ready for, hangups are always reported
+    pub fn poll_descriptor(&self, fd: usize, events: usize) -> usize
+    {
+        use super::readiness::*;
+
+        let desc = match self.data.descriptors.get(&fd)
+        {
+            Some(entry) => entry.description.borrow(),
+            None => return POLLNVAL
+        };
+
+        let mut ready = 0;
+
+        if events & POLLIN > 0 && desc.check_available()
+        {
+            ready |= POLLIN;
+        }
+
+        if events & POLLOUT > 0 && desc.check_writable()
+        {
+            ready |= POLLOUT;
+        }
+
+        if desc.check_hangup()
+        {
+            ready |= POLLHUP;
+        }
+
+        ready
+    }
+
+    /// Create a new epoll instance, only `O_CLOEXEC` is used from `mode`
+    pub fn epoll_create(&mut self, mode: usize) -> usize
+    {
+        self.add_descriptor(Box::new(super::epoll::EpollDescriptor::new()), mode & O_CLOEXEC)
+    }
+
+    /// Create a new pipe, only `O_CLOEXEC` and `O_NONBLOCK` are used from `mode`
+    pub fn pipe(&mut self, mode: usize) -> (usize, usize)
     {
         let (read, write) = super::pipe::new_pipe();
 
-        let read = self.add_wrapped_descriptor(read);
-        let write = self.add_wrapped_descriptor(write);
+        let mode = mode & (O_CLOEXEC | O_NONBLOCK);
+
+        let read = self.add_wrapped_descriptor(read, O_RDONLY | mode);
+        let write = self.add_wrapped_descriptor(write, O_WRONLY | mode);
 
         (read, write)
     }
@@ -476,7 +764,7 @@ impl Process
         
         let fd = if let Some(fd) = self.data.descriptors.get(&old)
         {
-            fd.clone()
+            fd.duplicate()
         }
         else
         {
@@ -489,19 +777,17 @@ impl Process
         }
         else
         {
-            let mut i = 0;
-
-            while self.data.descriptors.contains_key(&i)
-            {
-                i += 1;
-            }
-
-            i
+            self.lowest_free_descriptor(0)
         };
 
+        if out != old
+        {
+            self.release_descriptor_locks(out);
+        }
+
         if let Some(v) = self.data.descriptors.get_mut(&out)
         {
-            v.borrow_mut().close(self.fs_interface.as_mut().unwrap());
+            v.description.borrow_mut().close(self.fs_interface.as_mut().unwrap());
         }
 
         self.data.descriptors.insert(out, fd);
@@ -516,11 +802,48 @@ impl Process
         }
     }
 
+    /// Duplicate a file descriptor onto the lowest unused number which is at least `min`, optionally closing the
+    /// duplicate on exec
+    pub fn dup_at_least(&mut self, old: usize, min: usize, cloexec: bool) -> Result<usize, usize>
+    {
+        let mut fd = self.data.descriptors.get(&old).ok_or(errno::EBADF)?.duplicate();
+
+        if cloexec
+        {
+            fd.flags |= FD_CLOEXEC;
+        }
+
+        let out = self.lowest_free_descriptor(min);
+        self.data.descriptors.insert(out, fd);
+
+        Ok(out)
+    }
+
+    /// Close every file descriptor marked to be closed when the process executes a new program
+    pub fn close_on_exec(&mut self)
+    {
+        let marked = self.data.descriptors.iter().filter(|(_, entry)| entry.flags & FD_CLOEXEC > 0).map(|(fd, _)| *fd).collect::<Vec<_>>();
+
+        for fd in marked
+        {
+            self.close(fd);
+        }
+    }
+
     /// Seek to a location in the file descriptor
     pub fn seek(&mut self, fd: usize, offset: usize, mode: usize) -> usize
     {
         use super::descriptor::SeekMode;
 
+        if mode == SEEK_DATA || mode == SEEK_HOLE
+        {
+            return match self.seek_data(fd, offset, mode == SEEK_HOLE)
+            {
+                Ok(position) => self.seek(fd, position, SEEK_SET),
+                Err(e) => e
+            };
+        }
+
         let enum_mode = match mode
         {
             SEEK_CUR => SeekMode::SeekCurrent,
@@ -531,7 +854,7 @@ impl Process
 
         if let Some(fd) = self.data.descriptors.get_mut(&fd)
         {
-            fd.borrow_mut().seek(offset, enum_mode)
+            fd.description.borrow_mut().seek(offset, enum_mode)
         }
         else
         {
@@ -539,6 +862,28 @@ impl Process
         }
     }
 
+    /// Find the first offset at or after `offset` in a file descriptor which holds data, or which is in a hole if
+    /// `hole` is set
+    fn seek_data(&mut self, fd: usize, offset: usize, hole: bool) -> Result<usize, usize>
+    {
+        if self.is_stream_descriptor(fd)?
+        {
+            return Err(errno::ESPIPE);
+        }
+
+        // Other descriptors without an inode have no data to search
+        let inode = match self.get_descriptor_inode(fd)
+        {
+            Err(errno::ENOENT) => Err(errno::EINVAL),
+            result => result
+        }?;
+
+        self.ensure_fs();
+        let vfs = self.fs_interface.as_mut().unwrap();
+
+        vfs.seek_data(inode, offset, hole).map_err(|e| e.to_errno())
+    }
+
     /// Run an ioctl command
     pub fn exec_ioctl(&mut self, fd: usize, cmd: fs::ioctl::IOControlCommand) -> usize
     {
@@ -546,7 +891,7 @@ impl Process
 
         if let Some(fd) = self.data.descriptors.get_mut(&fd)
         {
-            if let Some(inode) = fd.borrow_mut().get_inode()
+            if let Some(inode) = fd.description.borrow_mut().get_inode()
             {
                 if let Ok(val) = self.fs_interface.as_mut().unwrap().exec_ioctl(inode, cmd)
                 {
@@ -598,6 +943,38 @@ impl Process
 
         temp.data.process_group_id = self.data.process_group_id;
 
+        temp.data.credentials = self.data.credentials;
+
+        // Shared mappings must point at the page cache rather than the copies made when duplicating the page table
+        for (addr, mapping) in &self.data.shared_mappings
+        {
+            // The child pins the same pages as the parent, even if they have since been orphaned
+            let pages = self.shared_mapping_pages(*addr, mapping);
+            let cache = fs::cache::get_page_cache();
+            let writable = mapping.perm & PageTableEntryFlags::writable();
+
+            for (i, page) in pages.iter().enumerate()
+            {
+                cache.share_page((mapping.inode, mapping.first_page + i), *page, writable);
+            }
+
+            let table = unsafe { temp.root.as_mut().unwrap() };
+
+            for (i, page) in pages.iter().enumerate()
+            {
+                let vaddr = addr + i * mem::PAGE_SIZE;
+
+                if let Ok(copy) = table.virt_to_phys(vaddr)
+                {
+                    mem::kpfree(copy, 1).unwrap();
+                }
+
+                table.map(vaddr, *page as usize, mapping.perm, 0);
+            }
+
+            temp.data.shared_mappings.insert(*addr, *mapping);
+        }
+
         self.register_child(temp.pid);
 
         temp
@@ -645,9 +1022,20 @@ impl Process
     /// Map a region of memory with the given permissions
     pub fn map(&mut self, length: usize, perm: mem::mmu::PageTableEntryFlags, flags: usize, fd: usize, offset: usize) -> usize
     {
-        if offset != 0
+        if offset % mem::PAGE_SIZE != 0
         {
-            todo!()
+            return errno::EINVAL;
+        }
+
+        // Shared mappings of files held in the page cache map the cached pages directly
+        if flags & MAP_ANON == 0 && flags & MAP_SHARED > 0 && (flags as i64) >= 0
+        {
+            let inode = self.data.descriptors.get(&fd).map(|fd_obj| fd_obj.description.borrow().page_cache_inode()).flatten();
+
+            if let Some(inode) = inode
+            {
+                return self.map_shared(length, perm, inode, offset / mem::PAGE_SIZE);
+            }
         }
 
         // Allocate the memory
@@ -657,7 +1045,7 @@ impl Process
         {
             if let Some(fd_obj) = self.data.descriptors.get_mut(&fd)
             {
-                if let Some(b) = fd_obj.borrow().get_buffer()
+                if let Some(b) = fd_obj.description.borrow().get_buffer()
                 {
                     ptr_op = Some(b);
                 }
@@ -693,13 +1081,30 @@ impl Process
         {
             if let Some(fd_obj) = self.data.descriptors.get_mut(&fd)
             {
+                let cache_inode = fd_obj.description.borrow().page_cache_inode();
+
                 if ptr_op.is_none()
                 {
-                    fd_obj.borrow_mut().seek(offset, process::descriptor::SeekMode::SeekSet);
-                    fd_obj.borrow_mut().read(self.fs_interface.as_mut().unwrap(), ptr as *mut u8, 4096 * length);
+                    if let Some(inode) = cache_inode
+                    {
+                        // Copy out of the page cache without moving the descriptor's offset
+                        if let Err(e) = fs::cache::get_page_cache().read(self.fs_interface.as_mut().unwrap(), inode, offset, ptr as *mut u8, mem::PAGE_SIZE * length)
+                        {
+                            kwarnln!("Unable to read mapped file: {:?}", e);
+                        }
+                    }
+                    else
+                    {
+                        fd_obj.description.borrow_mut().seek(offset, process::descriptor::SeekMode::SeekSet);
+                        fd_obj.description.borrow_mut().read(self.fs_interface.as_mut().unwrap(), ptr as *mut u8, 4096 * length);
+                    }
                 }
                 
-                self.data.mmapped_files.insert(ptr as *mut u8, fd);
+                // Private mappings are never written back to the file
+                if ptr_op.is_some() || flags & MAP_PRIVATE == 0
+                {
+                    self.data.mmapped_files.insert(ptr as *mut u8, fd);
+                }
             }
             else
             {
@@ -711,9 +1116,98 @@ impl Process
         user_addr
     }
 
+    /// Map pages of a file held in the page cache directly into the process
+    fn map_shared(&mut self, length: usize, perm: mem::mmu::PageTableEntryFlags, inode: fs::structures::FilesystemIndex, first_page: usize) -> usize
+    {
+        let mapping = process::data::SharedMapping { inode, first_page, pages: length, perm };
+
+        let pages = match Self::pin_shared_mapping(&mapping)
+        {
+            Ok(pages) => pages,
+            Err(e) => return e.to_errno()
+        };
+
+        let user_addr = self.data.next_heap;
+
+        for page in pages
+        {
+            unsafe { self.root.as_mut().unwrap() }.map(self.data.next_heap, page as usize, perm, 0);
+            self.data.next_heap += mem::PAGE_SIZE;
+        }
+
+        self.data.mem_stats.resident += length;
+        self.data.shared_mappings.insert(user_addr, mapping);
+
+        user_addr
+    }
+
+    /// Pin every page of a shared mapping in the page cache, returning the pages
+    fn pin_shared_mapping(mapping: &process::data::SharedMapping) -> fs::structures::FilesystemResult<Vec<*mut u8>>
+    {
+        let vfs = fs::vfs::get_vfs_reference().ok_or(fs::structures::FilesystemError::FilesystemNotMounted)?;
+        let cache = fs::cache::get_page_cache();
+        let writable = mapping.perm & PageTableEntryFlags::writable();
+
+        let mut pages = Vec::new();
+
+        for i in 0..mapping.pages
+        {
+            match cache.pin_page(vfs, (mapping.inode, mapping.first_page + i), writable)
+            {
+                Ok(page) => pages.push(page),
+                Err(e) =>
+                {
+                    for (j, page) in pages.iter().enumerate()
+                    {
+                        cache.unpin_page((mapping.inode, mapping.first_page + j), *page, writable);
+                    }
+
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(pages)
+    }
+
+    /// Get the page cache pages mapped by a shared mapping at `addr`
+    fn shared_mapping_pages(&self, addr: usize, mapping: &process::data::SharedMapping) -> Vec<*mut u8>
+    {
+        let table = unsafe { self.root.as_ref().unwrap() };
+
+        (0..mapping.pages).filter_map(|i| table.virt_to_phys(addr + i * mem::PAGE_SIZE).ok()).map(|page| page as *mut u8).collect()
+    }
+
+    /// Release the pins held on the pages of a shared mapping
+    fn release_shared_mapping(mapping: &process::data::SharedMapping, pages: &[*mut u8])
+    {
+        let cache = fs::cache::get_page_cache();
+        let writable = mapping.perm & PageTableEntryFlags::writable();
+
+        for (i, page) in pages.iter().enumerate()
+        {
+            cache.unpin_page((mapping.inode, mapping.first_page + i), *page, writable);
+        }
+    }
+
     /// Unmap a region of memory
     pub fn unmap(&mut self, addr: usize, length: usize) -> usize
     {
+        // Shared mappings only need to release their pages back to the page cache
+        if let Some(mapping) = self.data.shared_mappings.remove(&addr)
+        {
+            let pages = self.shared_mapping_pages(addr, &mapping);
+
+            for i in 0..mapping.pages
+            {
+                unsafe { self.root.as_mut().unwrap() }.unmap(addr + i * mem::PAGE_SIZE, 0);
+            }
+
+            Self::release_shared_mapping(&mapping, &pages);
+
+            return 0;
+        }
+
         // Convert the user address to a physical address
         let phys_addr = self.map_mem(addr).unwrap();
 
@@ -727,14 +1221,14 @@ impl Process
             // If the file still exists, update the cache
             if let Some(fd_obj) = self.data.descriptors.get_mut(&fd)
             {
-                if fd_obj.borrow().get_buffer().is_some()
+                if fd_obj.description.borrow().get_buffer().is_some()
                 {
                     should_free = false;
                 }
                 else
                 {
-                    fd_obj.borrow_mut().seek(0, process::descriptor::SeekMode::SeekSet);
-                    fd_obj.borrow_mut().write(self.fs_interface.as_mut().unwrap(), phys_addr as *mut u8, 4096 * length);
+                    fd_obj.description.borrow_mut().seek(0, process::descriptor::SeekMode::SeekSet);
+                    fd_obj.description.borrow_mut().write(self.fs_interface.as_mut().unwrap(), phys_addr as *mut u8, 4096 * length);
                 }
             }
 
@@ -773,24 +1267,39 @@ impl Process
         0
     }
 
-    /// Get directory entries for the given file descriptor
-    pub fn get_dir_entries(&mut self, fd: usize) -> Result<Vec<DirectoryEntry>, usize>
+    /// Get the inode backing the given file descriptor
+    pub fn get_descriptor_inode(&mut self, fd: usize) -> Result<fs::structures::FilesystemIndex, usize>
     {
-        let inode = if let Some(desc) = self.data.descriptors.get_mut(&fd)
+        if let Some(desc) = self.data.descriptors.get_mut(&fd)
         {
-            if let Some(inode) = desc.borrow_mut().get_inode()
+            if let Some(inode) = desc.description.borrow_mut().get_inode()
             {
-                inode
+                Ok(inode)
             }
             else
             {
-                return Err(errno::ENOENT); // File not found
+                Err(errno::ENOENT) // File not found
             }
         }
         else
         {
-            return Err(errno::EBADF); // Bad file descriptor
-        };
+            Err(errno::EBADF) // Bad file descriptor
+        }
+    }
+
+    /// Check if a file descriptor is a pipe, FIFO or socket
+    pub fn is_stream_descriptor(&mut self, fd: usize) -> Result<bool, usize>
+    {
+        let desc = self.data.descriptors.get(&fd).ok_or(errno::EBADF)?;
+        let mut description = desc.description.borrow_mut();
+
+        Ok(description.pipe().is_some() || description.socket().is_some())
+    }
+
+    /// Get directory entries for the given file descriptor
+    pub fn get_dir_entries(&mut self, fd: usize) -> Result<Vec<DirectoryEntry>, usize>
+    {
+        let inode = self.get_descriptor_inode(fd)?;
 
         self.ensure_fs();
 
@@ -798,15 +1307,15 @@ impl Process
     }
 
     /// Unlink a path
-    pub fn unlink(&mut self, path: OwnedPath) -> Result<(), usize>
+    pub fn unlink(&mut self, path: fs::vfs::PathAt) -> Result<(), usize>
     {
         self.ensure_fs();
 
         let vfs = self.fs_interface.as_mut().unwrap();
 
-        // Get the inode of the path involved
+        // Get the inode of the path involved, a symbolic link is removed rather than its target
         let inode = 
-            if let Ok(inode_result) = vfs.path_to_inode(&path)
+            if let Ok(inode_result) = vfs.resolve_path_at(&path, false)
             {
                 inode_result
             }
@@ -815,13 +1324,11 @@ impl Process
                 return Err(errno::ENOENT);
             };
 
-        let (parent_path, name) = path.split_last();
-
         // Get the inode of the parent directory
-        let parent = 
-            if let Ok(inode_result) = vfs.path_to_inode(&parent_path)
+        let (parent, name) = 
+            if let Ok(result) = vfs.resolve_parent_at(&path)
             {
-                inode_result
+                result
             }
             else
             {
@@ -829,7 +1336,7 @@ impl Process
             };
 
         // Unlink the inode
-        if let Err(e) = vfs.unlink_inode(inode, parent, name.to_string())
+        if let Err(e) = vfs.unlink_inode(inode, parent, name)
         {
             return Err(e.to_errno());
         }
@@ -838,15 +1345,15 @@ impl Process
     }
 
     /// Remove a directory
-    pub fn rmdir(&mut self, path: OwnedPath) -> Result<(), usize>
+    pub fn rmdir(&mut self, path: fs::vfs::PathAt) -> Result<(), usize>
     {
         self.ensure_fs();
 
         let vfs = self.fs_interface.as_mut().unwrap();
 
-        // Get the inode of the path involved
+        // Get the inode of the path involved, a symbolic link is removed rather than its target
         let inode = 
-            if let Ok(inode_result) = vfs.path_to_inode(&path)
+            if let Ok(inode_result) = vfs.resolve_path_at(&path, false)
             {
                 inode_result
             }
@@ -855,13 +1362,11 @@ impl Process
                 return Err(errno::ENOENT);
             };
 
-        let (parent_path, name) = path.split_last();
-
         // Get the inode of the parent directory
-        let parent = 
-            if let Ok(inode_result) = vfs.path_to_inode(&parent_path)
+        let (parent, name) = 
+            if let Ok(result) = vfs.resolve_parent_at(&path)
             {
-                inode_result
+                result
             }
             else
             {
@@ -869,7 +1374,7 @@ impl Process
             };
 
         // Remove the directory
-        if let Err(e) = vfs.remove_directory(inode, parent, name.to_string())
+        if let Err(e) = vfs.remove_directory(inode, parent, name)
         {
             return Err(e.to_errno());
         }
@@ -878,20 +1383,107 @@ impl Process
     }
 
     /// Stat a file
-    pub fn stat(&mut self, path: OwnedPath) -> Result<fs::structures::FileStat, usize>
+    pub fn stat(&mut self, path: fs::vfs::PathAt) -> Result<fs::structures::FileStat, usize>
     {
         self.ensure_fs();
         let vfs = self.fs_interface.as_mut().unwrap();
 
         // Convert the path to an inode
-        if let Ok(inode_result) = vfs.path_to_inode(&path)
+        match vfs.resolve_path_at(&path, true)
         {
-            vfs.get_stat(inode_result).map_err(|e| e.to_errno())
+            Ok(inode_result) => vfs.get_stat(inode_result).map_err(|e| e.to_errno()),
+            Err(e) => Err(e.to_errno())
         }
-        else
+    }
+
+    /// Stat the file open as a file descriptor
+    pub fn fstat(&mut self, fd: usize) -> Result<fs::structures::FileStat, usize>
+    {
+        if !self.data.descriptors.contains_key(&fd)
         {
-            Err(errno::ENOENT)
+            return Err(errno::EBADF);
         }
+
+        // Descriptors without an inode are reported as anonymous sockets or FIFOs
+        let inode = match self.get_descriptor_inode(fd)
+        {
+            Ok(inode) => inode,
+            Err(_) => return Ok(fs::structures::FileStat
+            {
+                dev_id: 0,
+                inode: 0,
+                mode: if self.data.descriptors[&fd].description.borrow_mut().socket().is_some() { fs::structures::S_IFSOCK | 0o777 } else { fs::structures::S_IFIFO | 0o600 },
+                links: 1,
+                uid: 0,
+                gid: 0,
+                special_dev_id: 0,
+                size: 0,
+                blk_size: 512,
+                blocks_alloced: 0,
+                atime: 0,
+                mtime: 0,
+                ctime: 0
+            })
+        };
+
+        self.ensure_fs();
+
+        self.fs_interface.as_mut().unwrap().get_stat(inode).map_err(|e| e.to_errno())
+    }
+
+    /// Stat a file without following a symbolic link at the end of the path
+    pub fn lstat(&mut self, path: fs::vfs::PathAt) -> Result<fs::structures::FileStat, usize>
+    {
+        self.ensure_fs();
+        let vfs = self.fs_interface.as_mut().unwrap();
+
+        match vfs.resolve_path_at(&path, false)
+        {
+            Ok(inode_result) => vfs.get_stat(inode_result).map_err(|e| e.to_errno()),
+            Err(e) => Err(e.to_errno())
+        }
+    }
+
+    /// Change the metadata of the file at the given path
+    pub fn set_path_attributes(&mut self, path: fs::vfs::PathAt, follow_last: bool, attributes: fs::structures::SetAttributes) -> Result<(), usize>
+    {
+        self.ensure_fs();
+        let vfs = self.fs_interface.as_mut().unwrap();
+
+        let inode = vfs.resolve_path_at(&path, follow_last).map_err(|e| e.to_errno())?;
+        vfs.set_attr(inode, attributes).map_err(|e| e.to_errno())
+    }
+
+    /// Change the metadata of the file open as a file descriptor
+    pub fn set_descriptor_attributes(&mut self, fd: usize, attributes: fs::structures::SetAttributes) -> Result<(), usize>
+    {
+        let inode = self.get_descriptor_inode(fd)?;
+
+        self.ensure_fs();
+        let vfs = self.fs_interface.as_mut().unwrap();
+
+        vfs.set_attr(inode, attributes).map_err(|e| e.to_errno())
+    }
+
+    /// Allocate or deallocate the storage for part of the file open as a file descriptor
+    pub fn allocate_descriptor_range(&mut self, fd: usize, mode: usize, offset: usize, length: usize) -> Result<(), usize>
+    {
+        if self.is_stream_descriptor(fd)?
+        {
+            return Err(errno::ESPIPE);
+        }
+
+        // Other descriptors without an inode have no storage to allocate
+        let inode = match self.get_descriptor_inode(fd)
+        {
+            Err(errno::ENOENT) => Err(errno::ENODEV),
+            result => result
+        }?;
+
+        self.ensure_fs();
+        let vfs = self.fs_interface.as_mut().unwrap();
+
+        vfs.allocate_range(inode, mode, offset, length).map_err(|e| e.to_errno())
     }
 
     /// Get the total memory held by the process in pages
@@ -1030,12 +1622,16 @@ impl Process
     {
         self.ensure_fs();
 
-        for (_, desc) in &mut self.data.descriptors
+        // Descriptors are closed one at a time so the last reference to a shared description can be recognized
+        for fd in self.data.descriptors.keys().copied().collect::<Vec<_>>()
         {
-            desc.borrow_mut().close(self.fs_interface.as_mut().unwrap());
-        }
+            self.release_descriptor_locks(fd);
 
-        self.data.descriptors.clear();
+            if let Some(desc) = self.data.descriptors.remove(&fd)
+            {
+                desc.description.borrow_mut().close(self.fs_interface.as_mut().unwrap());
+            }
+        }
     }
 }
 
@@ -1053,6 +1649,12 @@ impl core::ops::Drop for Process
             mem::kpfree(true_stack, 1).unwrap();
         }
 
+        // Release any pages shared with the page cache
+        for (addr, mapping) in &self.data.shared_mappings
+        {
+            Self::release_shared_mapping(mapping, &self.shared_mapping_pages(*addr, mapping));
+        }
+
         // Drop the page table
         unsafe { self.root.as_mut() }.unwrap().drop_table();
 
@@ -0,0 +1,33 @@
+//! Descriptor readiness
+//!
+//! Processes waiting in `poll`, `select` or `epoll_wait` sleep until
+//! something happens which could make one of their descriptors ready, such as
+//! data arriving on a pipe or the UART. Sources of such events call
+//! `notify_readiness`, which bumps a generation counter the scheduler compares
+//! against the one each waiting process saw when it went to sleep. A woken
+//! process restarts its syscall and checks its descriptors again.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Must be kept in sync with syscalls.h
+pub const POLLIN: usize = 1;
+pub const POLLPRI: usize = 2;
+pub const POLLOUT: usize = 4;
+pub const POLLERR: usize = 8;
+pub const POLLHUP: usize = 16;
+pub const POLLNVAL: usize = 32;
+
+/// Generation counter bumped whenever a descriptor may have become ready
+static READINESS_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Note that a descriptor may have become ready, waking every process waiting for readiness
+pub fn notify_readiness()
+{
+    READINESS_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Get the current readiness generation
+pub fn readiness_generation() -> usize
+{
+    READINESS_GENERATION.load(Ordering::SeqCst)
+}
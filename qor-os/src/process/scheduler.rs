@@ -2,6 +2,7 @@ use crate::*;
 
 use super::process::Process;
 use super::process::ProcessState;
+use super::process::WaitMode;
 use super::signals::POSIXSignal;
 
 use alloc::collections::BTreeMap;
@@ -97,6 +98,12 @@ impl ProcessManager
         }
     }
 
+    /// Check if any process is parked part way through a read or write
+    pub fn has_parked_io(&self) -> bool
+    {
+        self.processes.values().any(|proc| matches!(proc.state, ProcessState::Waiting(WaitMode::ForBlockDevice(_)) | ProcessState::Waiting(WaitMode::ForWrite(_))))
+    }
+
     /// Schedule the next process
     pub fn schedule_process(&mut self) -> (usize, usize, usize)
     {
@@ -179,6 +186,62 @@ impl ProcessManager
                                         break;
                                     }
                                 }
+                                process::process::WaitMode::ForWrite((fd, buffer, count, written)) =>
+                                {
+                                    // The rest of the data is written as room frees up, the syscall returns once all
+                                    // of it has been written or the write fails
+                                    let result = proc.write(fd, unsafe { buffer.add(written) }, count - written);
+
+                                    if result != errno::EAGAIN
+                                    {
+                                        let failed = (result as isize) < 0;
+                                        let total = if failed { written } else { written + result };
+
+                                        if failed || total == count
+                                        {
+                                            // After a partial write the data written so far is returned rather than
+                                            // the error, and no signal is raised
+                                            if result == errno::EPIPE && written == 0
+                                            {
+                                                proc.raise_broken_pipe();
+                                            }
+
+                                            let length = if failed && written == 0 { result } else { total };
+                                            unsafe { proc.frame.as_mut().unwrap().regs[10] = length; }
+
+                                            break;
+                                        }
+
+                                        proc.state = ProcessState::Waiting(process::process::WaitMode::ForWrite((fd, buffer, count, total)));
+                                    }
+                                },
+                                process::process::WaitMode::ForBlockDevice(token) =>
+                                {
+                                    // Once the request completes the process restarts its syscall
+                                    if drivers::block::get_block_queue(token.device).map(|queue| queue.is_complete(token)).unwrap_or(true)
+                                    {
+                                        break;
+                                    }
+                                },
+                                process::process::WaitMode::ForLock(generation) =>
+                                {
+                                    // Once any lock is released the process restarts its syscall to try again
+                                    if fs::vfs::get_vfs_reference().map(|vfs| vfs.lock_table().generation() != generation).unwrap_or(true)
+                                    {
+                                        break;
+                                    }
+                                },
+                                process::process::WaitMode::ForReadiness(generation, deadline) =>
+                                {
+                                    // The syscall is restarted to check its descriptors again, or to return once the
+                                    // timeout has passed
+                                    let timed_out = deadline.map(|deadline| unsafe { &drivers::TIMER_DRIVER }.time() >= deadline).unwrap_or(false);
+
+                                    if process::readiness::readiness_generation() != generation || timed_out
+                                    {
+                                        break;
+                                    }
+                                },
                                 process::process::WaitMode::ForSignal => {},
                             }
                             
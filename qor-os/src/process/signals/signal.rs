@@ -12,7 +12,8 @@ pub enum SignalType
     SIGSTOP = 19,
     SIGCONT = 18,
     SIGKILL = 9,
-    SIGINT = 2
+    SIGINT = 2,
+    SIGPIPE = 13
 }
 
 impl SignalType
@@ -25,6 +26,7 @@ impl SignalType
             2 => Self::SIGINT,
             5 => Self::SIGTRAP,
             9 => Self::SIGKILL,
+            13 => Self::SIGPIPE,
             15 => Self::SIGTERM,
             18 => Self::SIGCONT,
             19 => Self::SIGSTOP,
@@ -0,0 +1,697 @@
+//! Unix domain sockets
+//!
+//! A connected stream socket holds a pair of channels, one for each
+//! direction, shared with its peer. Connecting to a listening socket queues
+//! the peer's end on the listener until it is accepted. Datagram sockets own
+//! a single inbox, which other sockets send to directly. Sockets bound to a
+//! path are found through the socket inode created by `bind`, which the vfs
+//! maps back to the listener or inbox. Messages can carry open descriptors
+//! along with their data, which is how `SCM_RIGHTS` passes them between
+//! processes.
+
+use crate::*;
+use super::descriptor::*;
+use super::readiness::notify_readiness;
+
+use crate::fs::structures::FilesystemIndex;
+
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use core::cell::RefCell;
+
+// Must be kept in sync with syscalls.h
+pub const AF_UNIX: usize = 1;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+
+/// Number of bytes which can be queued on a socket before sending waits
+pub const SOCKET_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Largest number of connections which can wait to be accepted
+pub const SOMAXCONN: usize = 128;
+
+/// Data queued on a socket, with the descriptors passed along with it and the address of the sender
+pub struct SocketMessage
+{
+    pub data: Vec<u8>,
+    pub rights: Vec<DescriptorEntry>,
+    pub source: Option<String>
+}
+
+/// Messages travelling in one direction, the flags record whether each side of the channel is still open
+pub struct Channel
+{
+    messages: VecDeque<SocketMessage>,
+    bytes: usize,
+    reader_open: bool,
+    writer_open: bool
+}
+
+/// Reference to a channel shared between the sockets at either end
+pub type SharedChannel = Arc<RefCell<Channel>>;
+
+impl Channel
+{
+    /// Create a new, empty channel with both sides open
+    pub fn new() -> SharedChannel
+    {
+        Arc::new(RefCell::new(Self
+        {
+            messages: VecDeque::new(),
+            bytes: 0,
+            reader_open: true,
+            writer_open: true
+        }))
+    }
+
+    /// Get the number of bytes which can be queued before the channel is full
+    fn room(&self) -> usize
+    {
+        SOCKET_BUFFER_SIZE.saturating_sub(self.bytes)
+    }
+
+    /// Add a message to the end of the channel
+    fn push(&mut self, message: SocketMessage)
+    {
+        self.bytes += message.data.len();
+        self.messages.push_back(message);
+
+        notify_readiness();
+    }
+
+    /// Close the reading side of the channel, dropping anything still queued, which also releases any descriptors
+    /// waiting to be received
+    fn close_reader(&mut self)
+    {
+        self.reader_open = false;
+        self.messages.clear();
+        self.bytes = 0;
+    }
+}
+
+/// Connections waiting to be accepted on a listening socket
+pub struct Listener
+{
+    pending: VecDeque<UnixSocket>,
+    backlog: usize,
+    address: Option<String>
+}
+
+/// Socket bound to a socket inode, which connections and datagrams sent to its path are delivered to
+#[derive(Clone)]
+pub enum BoundSocket
+{
+    Listener(Weak<RefCell<Listener>>),
+    Datagram(Weak<RefCell<Channel>>)
+}
+
+impl BoundSocket
+{
+    /// Check if the socket bound to the inode is still open
+    pub fn is_open(&self) -> bool
+    {
+        match self
+        {
+            BoundSocket::Listener(listener) => listener.strong_count() > 0,
+            BoundSocket::Datagram(inbox) => inbox.strong_count() > 0
+        }
+    }
+}
+
+/// Data taken from a socket by a receive
+pub struct Received
+{
+    pub length: usize,
+    pub full_length: usize,
+    pub rights: Vec<DescriptorEntry>,
+    pub source: Option<String>
+}
+
+/// State of a socket
+enum SocketState
+{
+    Unconnected,
+    Listening(Arc<RefCell<Listener>>),
+    Connected { incoming: SharedChannel, outgoing: SharedChannel },
+    Datagram { inbox: SharedChannel, peer: Option<Weak<RefCell<Channel>>> }
+}
+
+/// Unix domain socket
+pub struct UnixSocket
+{
+    kind: usize,
+    address: Option<String>,
+    bound_inode: Option<FilesystemIndex>,
+    peer_address: Option<String>,
+    write_shutdown: bool,
+    state: SocketState
+}
+
+impl UnixSocket
+{
+    /// Create a new, unbound socket of the given `SOCK_*` type
+    pub fn new(kind: usize) -> Self
+    {
+        let state = if kind == SOCK_DGRAM
+        {
+            SocketState::Datagram { inbox: Channel::new(), peer: None }
+        }
+        else
+        {
+            SocketState::Unconnected
+        };
+
+        Self
+        {
+            kind,
+            address: None,
+            bound_inode: None,
+            peer_address: None,
+            write_shutdown: false,
+            state
+        }
+    }
+
+    /// Create a pair of sockets connected to each other
+    pub fn pair(kind: usize) -> (Self, Self)
+    {
+        let mut first = Self::new(kind);
+        let mut second = Self::new(kind);
+
+        if kind == SOCK_DGRAM
+        {
+            let first_inbox = Channel::new();
+            let second_inbox = Channel::new();
+
+            first.state = SocketState::Datagram { inbox: first_inbox.clone(), peer: Some(Arc::downgrade(&second_inbox)) };
+            second.state = SocketState::Datagram { inbox: second_inbox, peer: Some(Arc::downgrade(&first_inbox)) };
+        }
+        else
+        {
+            let forward = Channel::new();
+            let backward = Channel::new();
+
+            first.state = SocketState::Connected { incoming: backward.clone(), outgoing: forward.clone() };
+            second.state = SocketState::Connected { incoming: forward, outgoing: backward };
+        }
+
+        (first, second)
+    }
+
+    /// Get the `SOCK_*` type of the socket
+    pub fn kind(&self) -> usize
+    {
+        self.kind
+    }
+
+    /// Get the path the socket is bound to
+    pub fn address(&self) -> Option<&str>
+    {
+        self.address.as_deref()
+    }
+
+    /// Get the path the peer of the socket is bound to
+    pub fn peer_address(&self) -> Option<&str>
+    {
+        self.peer_address.as_deref()
+    }
+
+    /// Check if the socket has a peer
+    pub fn is_connected(&self) -> bool
+    {
+        match &self.state
+        {
+            SocketState::Connected { .. } => true,
+            SocketState::Datagram { peer, .. } => peer.is_some(),
+            _ => false
+        }
+    }
+
+    /// Bind the socket to the socket inode created for `address`, returning what the inode should be mapped to
+    /// straight away, stream sockets are only mapped once they listen
+    pub fn bind(&mut self, address: String, inode: FilesystemIndex) -> Result<Option<BoundSocket>, usize>
+    {
+        if self.address.is_some()
+        {
+            return Err(errno::EINVAL);
+        }
+
+        self.address = Some(address);
+        self.bound_inode = Some(inode);
+
+        match &self.state
+        {
+            SocketState::Datagram { inbox, .. } => Ok(Some(BoundSocket::Datagram(Arc::downgrade(inbox)))),
+            _ => Ok(None)
+        }
+    }
+
+    /// Start accepting connections, returning the inode to map to the new listener if the socket was not already
+    /// listening
+    pub fn listen(&mut self, backlog: usize) -> Result<Option<(FilesystemIndex, BoundSocket)>, usize>
+    {
+        if self.kind != SOCK_STREAM
+        {
+            return Err(errno::EOPNOTSUPP);
+        }
+
+        let backlog = backlog.clamp(1, SOMAXCONN);
+
+        match &self.state
+        {
+            SocketState::Listening(listener) =>
+            {
+                listener.borrow_mut().backlog = backlog;
+                Ok(None)
+            },
+            SocketState::Connected { .. } => Err(errno::EINVAL),
+            _ =>
+            {
+                // Only sockets bound to a path can be connected to
+                let inode = self.bound_inode.ok_or(errno::EINVAL)?;
+
+                let listener = Arc::new(RefCell::new(Listener { pending: VecDeque::new(), backlog, address: self.address.clone() }));
+                let bound = BoundSocket::Listener(Arc::downgrade(&listener));
+
+                self.state = SocketState::Listening(listener);
+
+                Ok(Some((inode, bound)))
+            }
+        }
+    }
+
+    /// Connect to the socket bound to an inode, a stream connection waits with `EAGAIN` while the listener's backlog
+    /// is full
+    pub fn connect(&mut self, target: BoundSocket) -> Result<(), usize>
+    {
+        match (&mut self.state, target)
+        {
+            (SocketState::Unconnected, BoundSocket::Listener(listener)) =>
+            {
+                let listener = listener.upgrade().ok_or(errno::ECONNREFUSED)?;
+                let mut listener = listener.borrow_mut();
+
+                if listener.pending.len() >= listener.backlog
+                {
+                    return Err(errno::EAGAIN);
+                }
+
+                let forward = Channel::new();
+                let backward = Channel::new();
+
+                let mut server = Self::new(SOCK_STREAM);
+                server.address = listener.address.clone();
+                server.peer_address = self.address.clone();
+                server.state = SocketState::Connected { incoming: forward.clone(), outgoing: backward.clone() };
+
+                listener.pending.push_back(server);
+
+                self.peer_address = listener.address.clone();
+                self.state = SocketState::Connected { incoming: backward, outgoing: forward };
+
+                notify_readiness();
+
+                Ok(())
+            },
+            (SocketState::Datagram { peer, .. }, BoundSocket::Datagram(inbox)) =>
+            {
+                if inbox.strong_count() == 0
+                {
+                    return Err(errno::ECONNREFUSED);
+                }
+
+                *peer = Some(inbox);
+
+                Ok(())
+            },
+            (SocketState::Connected { .. }, _) => Err(errno::EISCONN),
+            (SocketState::Listening(_), _) => Err(errno::EINVAL),
+            _ => Err(errno::EPROTOTYPE)
+        }
+    }
+
+    /// Record the path of the socket a datagram socket was connected to
+    pub fn set_peer_address(&mut self, address: String)
+    {
+        self.peer_address = Some(address);
+    }
+
+    /// Take the next connection waiting on a listening socket, or `EAGAIN` if there is none yet
+    pub fn accept(&mut self) -> Result<UnixSocket, usize>
+    {
+        match &self.state
+        {
+            SocketState::Listening(listener) =>
+            {
+                let socket = listener.borrow_mut().pending.pop_front().ok_or(errno::EAGAIN)?;
+
+                // Connections waiting for room in the backlog can try again
+                notify_readiness();
+
+                Ok(socket)
+            },
+            _ => Err(errno::EINVAL)
+        }
+    }
+
+    /// Send data along with descriptors, to `target` for an unconnected datagram socket. Stream sockets may send only
+    /// part of the data, while a datagram is sent whole or not at all. Returns `EAGAIN` if the send has to wait for
+    /// room
+    pub fn send(&mut self, data: &[u8], rights: Vec<DescriptorEntry>, target: Option<SharedChannel>) -> Result<usize, usize>
+    {
+        if self.write_shutdown
+        {
+            return Err(errno::EPIPE);
+        }
+
+        match &self.state
+        {
+            SocketState::Connected { outgoing, .. } =>
+            {
+                if target.is_some()
+                {
+                    return Err(errno::EISCONN);
+                }
+
+                let mut outgoing = outgoing.borrow_mut();
+
+                if !outgoing.reader_open
+                {
+                    return Err(errno::EPIPE);
+                }
+
+                let length = data.len().min(outgoing.room());
+
+                if length == 0 && data.len() > 0
+                {
+                    return Err(errno::EAGAIN);
+                }
+
+                outgoing.push(SocketMessage { data: data[..length].to_vec(), rights, source: None });
+
+                Ok(length)
+            },
+            SocketState::Datagram { peer, .. } =>
+            {
+                let inbox = match (target, peer)
+                {
+                    (Some(inbox), _) => inbox,
+                    (None, Some(peer)) => peer.upgrade().ok_or(errno::ECONNREFUSED)?,
+                    (None, None) => return Err(errno::EDESTADDRREQ)
+                };
+
+                if data.len() > SOCKET_BUFFER_SIZE
+                {
+                    return Err(errno::EMSGSIZE);
+                }
+
+                let mut inbox = inbox.borrow_mut();
+
+                if !inbox.reader_open
+                {
+                    return Err(errno::ECONNREFUSED);
+                }
+
+                if data.len() > inbox.room()
+                {
+                    return Err(errno::EAGAIN);
+                }
+
+                inbox.push(SocketMessage { data: data.to_vec(), rights, source: self.address.clone() });
+
+                Ok(data.len())
+            },
+            _ => Err(errno::ENOTCONN)
+        }
+    }
+
+    /// Receive data into a buffer, leaving it queued if `peek` is set. A stream socket reads across messages but
+    /// stops before one carrying descriptors, so they arrive with the start of their data, while a datagram socket
+    /// takes a single message and discards what does not fit. Returns `EAGAIN` if the receive has to wait for data
+    pub fn recv(&mut self, buffer: &mut [u8], peek: bool) -> Result<Received, usize>
+    {
+        let mut received = Received { length: 0, full_length: 0, rights: Vec::new(), source: None };
+
+        match &self.state
+        {
+            SocketState::Connected { incoming, .. } =>
+            {
+                let mut incoming = incoming.borrow_mut();
+
+                // Nothing more will arrive once the reading side is shut down or the peer stops writing
+                if incoming.messages.is_empty()
+                {
+                    return if incoming.reader_open && incoming.writer_open { Err(errno::EAGAIN) } else { Ok(received) };
+                }
+
+                let mut index = 0;
+
+                while received.length < buffer.len() && index < incoming.messages.len()
+                {
+                    let message = &mut incoming.messages[index];
+
+                    if received.length > 0 && message.rights.len() > 0
+                    {
+                        break;
+                    }
+
+                    let count = message.data.len().min(buffer.len() - received.length);
+                    buffer[received.length..received.length + count].copy_from_slice(&message.data[..count]);
+                    received.length += count;
+
+                    if peek
+                    {
+                        index += 1;
+                        continue;
+                    }
+
+                    received.rights.append(&mut message.rights);
+                    message.data.drain(..count);
+
+                    if message.data.is_empty()
+                    {
+                        incoming.messages.pop_front();
+                    }
+                }
+
+                received.full_length = received.length;
+
+                if !peek
+                {
+                    incoming.bytes -= received.length;
+
+                    // Writers waiting for room can continue
+                    notify_readiness();
+                }
+
+                Ok(received)
+            },
+            SocketState::Datagram { inbox, .. } =>
+            {
+                let mut inbox = inbox.borrow_mut();
+
+                let message = match inbox.messages.front()
+                {
+                    Some(message) => message,
+                    None => return if inbox.reader_open { Err(errno::EAGAIN) } else { Ok(received) }
+                };
+
+                received.full_length = message.data.len();
+                received.length = message.data.len().min(buffer.len());
+                received.source = message.source.clone();
+                buffer[..received.length].copy_from_slice(&message.data[..received.length]);
+
+                if !peek
+                {
+                    let message = inbox.messages.pop_front().unwrap();
+                    inbox.bytes -= message.data.len();
+                    received.rights = message.rights;
+
+                    notify_readiness();
+                }
+
+                Ok(received)
+            },
+            SocketState::Listening(_) => Err(errno::EINVAL),
+            SocketState::Unconnected => Err(errno::ENOTCONN)
+        }
+    }
+
+    /// Shut down the reading or writing sides of a socket
+    pub fn shutdown(&mut self, read: bool, write: bool) -> Result<(), usize>
+    {
+        if !self.is_connected()
+        {
+            return Err(errno::ENOTCONN);
+        }
+
+        match &self.state
+        {
+            SocketState::Connected { incoming, outgoing } =>
+            {
+                if read
+                {
+                    incoming.borrow_mut().close_reader();
+                }
+
+                if write
+                {
+                    outgoing.borrow_mut().writer_open = false;
+                }
+            },
+            SocketState::Datagram { inbox, .. } =>
+            {
+                if read
+                {
+                    inbox.borrow_mut().close_reader();
+                }
+            },
+            _ => {}
+        }
+
+        self.write_shutdown |= write;
+
+        notify_readiness();
+
+        Ok(())
+    }
+}
+
+impl FileDescriptor for UnixSocket
+{
+    fn close(&mut self, _fs: &mut fs::vfs::FilesystemInterface)
+    {
+        // Nothing needs to be done but drop the socket, which will occur elsewhere
+    }
+
+    fn write(&mut self, _fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
+    {
+        let data = unsafe { core::slice::from_raw_parts(buffer, count) };
+
+        match self.send(data, Vec::new(), None)
+        {
+            Ok(length) => length,
+            Err(e) => e
+        }
+    }
+
+    fn read(&mut self, _fs: &mut fs::vfs::FilesystemInterface, buffer: *mut u8, count: usize) -> usize
+    {
+        let buffer = unsafe { core::slice::from_raw_parts_mut(buffer, count) };
+
+        match self.recv(buffer, false)
+        {
+            Ok(received) => received.length,
+            Err(e) => e
+        }
+    }
+
+    fn get_inode(&mut self) -> Option<FilesystemIndex>
+    {
+        None
+    }
+
+    fn check_available(&self) -> bool
+    {
+        match &self.state
+        {
+            SocketState::Listening(listener) => !listener.borrow().pending.is_empty(),
+            SocketState::Connected { incoming, .. } =>
+            {
+                let incoming = incoming.borrow();
+                !incoming.messages.is_empty() || !incoming.reader_open || !incoming.writer_open
+            },
+            SocketState::Datagram { inbox, .. } =>
+            {
+                let inbox = inbox.borrow();
+                !inbox.messages.is_empty() || !inbox.reader_open
+            },
+            // Reading fails straight away
+            SocketState::Unconnected => true
+        }
+    }
+
+    fn check_writable(&self) -> bool
+    {
+        match &self.state
+        {
+            SocketState::Listening(_) => false,
+            SocketState::Connected { outgoing, .. } =>
+            {
+                let outgoing = outgoing.borrow();
+                outgoing.room() > 0 || !outgoing.reader_open
+            },
+            SocketState::Datagram { peer, .. } =>
+            {
+                peer.as_ref().and_then(|peer| peer.upgrade()).map(|peer| peer.borrow().room() > 0).unwrap_or(true)
+            },
+            // Writing fails straight away
+            SocketState::Unconnected => true
+        }
+    }
+
+    fn check_hangup(&self) -> bool
+    {
+        match &self.state
+        {
+            SocketState::Connected { incoming, outgoing } => !incoming.borrow().writer_open && !outgoing.borrow().reader_open,
+            _ => false
+        }
+    }
+
+    fn socket(&mut self) -> Option<&mut UnixSocket>
+    {
+        Some(self)
+    }
+}
+
+impl core::ops::Drop for UnixSocket
+{
+    fn drop(&mut self)
+    {
+        match &self.state
+        {
+            SocketState::Connected { incoming, outgoing } =>
+            {
+                incoming.borrow_mut().close_reader();
+                outgoing.borrow_mut().writer_open = false;
+            },
+            SocketState::Datagram { inbox, .. } => inbox.borrow_mut().close_reader(),
+            _ => {}
+        }
+
+        // Peers waiting on the socket see it close
+        notify_readiness();
+    }
+}
+
+/// Unix Socket Test
+#[test_case]
+fn unix_sockets()
+{
+    // Stream data arrives in order, and a closed peer reads as the end of the stream
+    let (mut first, mut second) = UnixSocket::pair(SOCK_STREAM);
+    let mut buffer = [0u8; 8];
+
+    assert_eq!(first.send(b"hello", Vec::new(), None), Ok(5));
+    assert_eq!(first.send(b" world", Vec::new(), None), Ok(6));
+    assert_eq!(second.recv(&mut buffer, false).map(|received| received.length), Ok(8));
+    assert_eq!(&buffer, b"hello wo");
+    assert_eq!(second.recv(&mut buffer, false).map(|received| received.length), Ok(3));
+    assert!(second.recv(&mut buffer, false).is_err());
+
+    drop(first);
+    assert_eq!(second.recv(&mut buffer, false).map(|received| received.length), Ok(0));
+    assert_eq!(second.send(b"gone", Vec::new(), None), Err(errno::EPIPE));
+
+    // Datagrams keep their boundaries and are truncated to the buffer
+    let (mut first, mut second) = UnixSocket::pair(SOCK_DGRAM);
+
+    first.send(b"0123456789", Vec::new(), None).unwrap();
+    first.send(b"ab", Vec::new(), None).unwrap();
+
+    let received = second.recv(&mut buffer, false).unwrap();
+    assert_eq!((received.length, received.full_length), (8, 10));
+    assert_eq!(second.recv(&mut buffer, true).map(|received| received.length), Ok(2));
+    assert_eq!(second.recv(&mut buffer, false).map(|received| received.length), Ok(2));
+}
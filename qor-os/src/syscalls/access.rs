@@ -0,0 +1,45 @@
+use crate::*;
+
+use super::utils::{AT_EACCESS, AT_SYMLINK_NOFOLLOW};
+
+/// Check for read permission
+const R_OK: usize = 4;
+
+/// Check for write permission
+const W_OK: usize = 2;
+
+/// Check for execute permission
+const X_OK: usize = 1;
+
+/// access Syscall
+pub fn syscall_access(proc: &mut super::Process, path_ptr: usize, mode: usize) -> Result<usize, usize>
+{
+    syscall_faccessat(proc, super::utils::AT_FDCWD, path_ptr, mode, 0)
+}
+
+/// faccessat Syscall
+pub fn syscall_faccessat(proc: &mut super::Process, dirfd: usize, path_ptr: usize, mode: usize, flags: usize) -> Result<usize, usize>
+{
+    if mode & !(R_OK | W_OK | X_OK) != 0 || flags & !(AT_EACCESS | AT_SYMLINK_NOFOLLOW) != 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let expanded_path = super::utils::userspace_string_to_path_at(proc, dirfd, path_ptr)?;
+
+    // The check is made as the real user and group unless the effective ones are asked for
+    let credentials = proc.data.credentials;
+    let (uid, gid) = if flags & AT_EACCESS > 0 { (credentials.euid, credentials.egid) } else { (credentials.uid, credentials.gid) };
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    let inode = vfs.resolve_path_at(&expanded_path, flags & AT_SYMLINK_NOFOLLOW == 0).map_err(|e| e.to_errno())?;
+
+    if !vfs.check_access(inode, uid, gid, mode as u16).map_err(|e| e.to_errno())?
+    {
+        return Err(errno::EACCES);
+    }
+
+    Ok(0)
+}
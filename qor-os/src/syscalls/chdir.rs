@@ -1,7 +1,6 @@
 use crate::*;
 
 use fs::fstrait::Filesystem;
-use libutils::paths::OwnedPath;
 
 /// chdir Syscall
 pub fn syscall_chdir(proc: &mut super::Process, path_ptr: usize) -> Result<usize, usize>
@@ -9,34 +8,13 @@ pub fn syscall_chdir(proc: &mut super::Process, path_ptr: usize) -> Result<usize
     let path = super::utils::userspace_string_to_path(proc, path_ptr)?;
     
     proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
 
-    if let Ok(inode) = proc.fs_interface.as_mut().unwrap().path_to_inode(&path)
-    {
-        if let Ok(path) = proc.fs_interface.as_mut().unwrap().inode_to_path(inode)
-        {
-            if path.as_str().len() == 0
-            {
-                proc.data.cwd = OwnedPath::new("/");
-            }
-            else
-            {
-                proc.data.cwd = path.clone();
-            }
+    // The working directory is held as the inode itself, so it follows the directory wherever it is moved
+    let inode = vfs.resolve_path_at(&path, true).map_err(|e| e.to_errno())?;
+    vfs.assert_directory(inode).map_err(|e| e.to_errno())?;
 
-            if !proc.data.cwd.as_str().ends_with("/")
-            {
-                proc.data.cwd.as_mut_str().push('/');
-            }
+    proc.data.cwd = Some(inode);
 
-            Ok(0)
-        }
-        else
-        {
-            Err(errno::EIO)
-        }
-    }
-    else
-    {
-        Err(errno::ENOENT) // No entry - Directory not found
-    }
+    Ok(0)
 }
\ No newline at end of file
@@ -0,0 +1,21 @@
+use crate::*;
+
+use fs::structures::SetAttributes;
+
+/// chmod Syscall
+pub fn syscall_chmod(proc: &mut super::Process, path_ptr: usize, mode: usize) -> Result<usize, usize>
+{
+    let path = super::utils::userspace_string_to_path(proc, path_ptr)?;
+
+    proc.set_path_attributes(path, true, SetAttributes { mode: Some((mode & 0o7777) as u16), ..Default::default() })?;
+
+    Ok(0)
+}
+
+/// fchmod Syscall
+pub fn syscall_fchmod(proc: &mut super::Process, fd: usize, mode: usize) -> Result<usize, usize>
+{
+    proc.set_descriptor_attributes(fd, SetAttributes { mode: Some((mode & 0o7777) as u16), ..Default::default() })?;
+
+    Ok(0)
+}
@@ -0,0 +1,29 @@
+use crate::*;
+
+use fs::structures::SetAttributes;
+
+/// Build the attributes for a change of owner, an id of -1 leaves that id unchanged
+fn owner_attributes(owner: usize, group: usize) -> SetAttributes
+{
+    let convert = |id: usize| if id as u32 == u32::MAX { None } else { Some(id as u16) };
+
+    SetAttributes { uid: convert(owner), gid: convert(group), ..Default::default() }
+}
+
+/// chown Syscall
+pub fn syscall_chown(proc: &mut super::Process, path_ptr: usize, owner: usize, group: usize) -> Result<usize, usize>
+{
+    let path = super::utils::userspace_string_to_path(proc, path_ptr)?;
+
+    proc.set_path_attributes(path, true, owner_attributes(owner, group))?;
+
+    Ok(0)
+}
+
+/// fchown Syscall
+pub fn syscall_fchown(proc: &mut super::Process, fd: usize, owner: usize, group: usize) -> Result<usize, usize>
+{
+    proc.set_descriptor_attributes(fd, owner_attributes(owner, group))?;
+
+    Ok(0)
+}
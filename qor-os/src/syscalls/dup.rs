@@ -14,4 +14,26 @@ pub fn syscall_dup2(proc: &mut super::Process, old_fd: usize, new_fd: usize)-> u
     kdebugln!(Syscalls, "Duplicating FD {} to {} on Process PID {}", old_fd, new_fd, proc.pid);
 
     proc.dup(old_fd, Some(new_fd))
-}
\ No newline at end of file
+}
+/// dup3 Syscall
+pub fn syscall_dup3(proc: &mut super::Process, old_fd: usize, new_fd: usize, flags: usize) -> Result<usize, usize>
+{
+    kdebugln!(Syscalls, "Duplicating FD {} to {} on Process PID {}", old_fd, new_fd, proc.pid);
+
+    if flags & !process::process::O_CLOEXEC != 0 || old_fd == new_fd
+    {
+        return Err(errno::EINVAL);
+    }
+
+    if proc.dup(old_fd, Some(new_fd)) == usize::MAX
+    {
+        return Err(errno::EBADF);
+    }
+
+    if flags & process::process::O_CLOEXEC > 0
+    {
+        proc.data.descriptors.get_mut(&new_fd).unwrap().flags |= process::descriptor::FD_CLOEXEC;
+    }
+
+    Ok(new_fd)
+}
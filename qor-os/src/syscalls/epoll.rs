@@ -0,0 +1,185 @@
+use crate::*;
+
+use drivers::timer::KernelTime;
+use process::epoll::{EpollInterest, EpollKey, EpollSet};
+use process::process::O_CLOEXEC;
+use process::readiness::*;
+
+use alloc::sync::Arc;
+use core::cell::RefCell;
+
+// Must be kept in sync with syscalls.h
+const EPOLL_CTL_ADD: usize = 1;
+const EPOLL_CTL_DEL: usize = 2;
+const EPOLL_CTL_MOD: usize = 3;
+
+const EPOLLONESHOT: u32 = 1 << 30;
+const EPOLLET: u32 = 1 << 31;
+
+/// Events which are reported whether or not they were asked for
+const EPOLL_ALWAYS: u32 = (POLLERR | POLLHUP) as u32;
+
+/// Event passed to `epoll_ctl` and returned by `epoll_wait`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct EpollEvent
+{
+    events: u32,
+    data: u64
+}
+
+/// Get the set of an epoll instance open as a file descriptor
+fn epoll_set(proc: &mut super::Process, epfd: usize) -> Result<Arc<RefCell<EpollSet>>, usize>
+{
+    let entry = proc.data.descriptors.get(&epfd).ok_or(errno::EBADF)?;
+    let set = entry.description.borrow().epoll_set();
+
+    set.ok_or(errno::EINVAL)
+}
+
+/// Get the key a descriptor is registered under, which is only valid while the descriptor refers to the same open
+/// file description
+fn epoll_key(proc: &super::Process, fd: usize) -> Option<EpollKey>
+{
+    proc.data.descriptors.get(&fd).map(|entry| (fd, Arc::as_ptr(&entry.description) as usize))
+}
+
+/// epoll_create Syscall
+pub fn syscall_epoll_create(proc: &mut super::Process, size: usize) -> Result<usize, usize>
+{
+    // The size is only a hint, but must still be positive
+    if (size as i32) <= 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    Ok(proc.epoll_create(0))
+}
+
+/// epoll_create1 Syscall
+pub fn syscall_epoll_create1(proc: &mut super::Process, flags: usize) -> Result<usize, usize>
+{
+    if flags & !O_CLOEXEC != 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    Ok(proc.epoll_create(flags))
+}
+
+/// epoll_ctl Syscall
+pub fn syscall_epoll_ctl(proc: &mut super::Process, epfd: usize, op: usize, fd: usize, event_ptr: usize) -> Result<usize, usize>
+{
+    let set = epoll_set(proc, epfd)?;
+    let key = epoll_key(proc, fd).ok_or(errno::EBADF)?;
+
+    if fd == epfd
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let event = if op == EPOLL_CTL_DEL
+    {
+        None
+    }
+    else
+    {
+        let event = unsafe { (proc.map_mem(event_ptr).map_err(|_| errno::EFAULT)? as *const EpollEvent).read() };
+
+        // Only level triggered notification is supported
+        if event.events & EPOLLET > 0
+        {
+            return Err(errno::EINVAL);
+        }
+
+        let description = Arc::downgrade(&proc.data.descriptors[&fd].description);
+
+        Some(EpollInterest { events: event.events, data: event.data, description })
+    };
+
+    let mut set = set.borrow_mut();
+
+    match (op, event)
+    {
+        (EPOLL_CTL_ADD, Some(interest)) =>
+        {
+            if set.contains_key(&key)
+            {
+                return Err(errno::EEXIST);
+            }
+
+            set.insert(key, interest);
+        },
+        (EPOLL_CTL_MOD, Some(interest)) =>
+        {
+            *set.get_mut(&key).ok_or(errno::ENOENT)? = interest;
+        },
+        (EPOLL_CTL_DEL, None) =>
+        {
+            set.remove(&key).ok_or(errno::ENOENT)?;
+        },
+        _ => return Err(errno::EINVAL)
+    }
+
+    Ok(0)
+}
+
+/// epoll_wait Syscall
+pub fn syscall_epoll_wait(proc: &mut super::Process, epfd: usize, events_ptr: usize, max_events: usize, timeout: usize) -> Result<usize, usize>
+{
+    if (max_events as i32) <= 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let set = epoll_set(proc, epfd)?;
+    let events = proc.map_mem(events_ptr).map_err(|_| errno::EFAULT)? as *mut EpollEvent;
+
+    let mut ready = Vec::new();
+
+    {
+        let mut set = set.borrow_mut();
+
+        // Descriptors which have been closed, or now refer to another file, are dropped from the set
+        set.retain(|key, _| epoll_key(proc, key.0) == Some(*key));
+
+        for ((fd, _), interest) in set.iter_mut()
+        {
+            if ready.len() >= max_events as i32 as usize
+            {
+                break;
+            }
+
+            // Oneshot descriptors which have already been reported wait to be rearmed
+            if interest.events & !EPOLLONESHOT == 0
+            {
+                continue;
+            }
+
+            let wanted = interest.events & !EPOLLONESHOT | EPOLL_ALWAYS;
+            let found = proc.poll_descriptor(*fd, wanted as usize) as u32 & wanted;
+
+            if found != 0
+            {
+                ready.push(EpollEvent { events: found, data: interest.data });
+
+                if interest.events & EPOLLONESHOT > 0
+                {
+                    interest.events = EPOLLONESHOT;
+                }
+            }
+        }
+    }
+
+    // A negative timeout waits forever
+    let timeout = if (timeout as i32) < 0 { None } else { Some(KernelTime::milliseconds(timeout as i32 as usize)) };
+
+    super::utils::wait_for_readiness(proc, !ready.is_empty(), timeout);
+
+    for (i, event) in ready.iter().enumerate()
+    {
+        unsafe { events.add(i).write(*event) };
+    }
+
+    Ok(ready.len())
+}
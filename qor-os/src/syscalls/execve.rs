@@ -1,8 +1,5 @@
 use crate::*;
 
-use alloc::format;
-use libutils::paths::OwnedPath;
-
 /// Execve Syscall
 pub fn syscall_execve(proc: &mut super::Process, path_ptr: usize, argv_ptr: usize, envp_ptr: usize) -> usize
 {
@@ -91,30 +88,34 @@ pub fn syscall_execve(proc: &mut super::Process, path_ptr: usize, argv_ptr: usiz
         i += 1;
     }
 
-    if !path.starts_with("/")
+    // Relative paths start from the working directory
+    let path = match super::utils::path_at(proc, super::utils::AT_FDCWD, path)
     {
-        path = format!("{}{}", proc.data.cwd, path);
-    }
+        Ok(path) => path,
+        Err(e) => return e
+    };
      
     // Create a process from an elf file
-    if let Ok(mut new_proc) = process::loading::load_process(proc.fs_interface.as_mut().unwrap(), &OwnedPath::new(path), &mut argv_vals, &mut envp_vals)
-    // if true
+    match process::loading::load_process(proc.fs_interface.as_mut().unwrap(), &path, &proc.data.credentials, &mut argv_vals, &mut envp_vals)
     {
-        new_proc.data.descriptors = proc.data.descriptors.clone();
+        Ok(mut new_proc) =>
+        {
+            // The descriptors move to the new program, except those marked to be closed on exec
+            proc.close_on_exec();
+            new_proc.data.descriptors = core::mem::take(&mut proc.data.descriptors);
 
-        new_proc.data.cwd = proc.data.cwd.clone();
+            new_proc.data.cwd = proc.data.cwd.clone();
 
-        new_proc.data.process_group_id = proc.data.process_group_id;
+            new_proc.data.process_group_id = proc.data.process_group_id;
 
-        new_proc.set_arguments(&argv_vals, &envp_vals);
+            new_proc.set_arguments(&argv_vals, &envp_vals);
 
-        process::scheduler::replace_process(proc.pid, new_proc);
-        
-        let schedule = process::scheduler::schedule_next();
-        process::scheduler::schedule_jump(schedule);
-    }
-    else
-    {
-        errno::ENOENT // File not found
+            process::scheduler::replace_process(proc.pid, new_proc);
+            
+            let schedule = process::scheduler::schedule_next();
+            process::scheduler::schedule_jump(schedule);
+        },
+        Err(process::loading::ProcessLoadError::PermissionDenied) => errno::EACCES, // Permission denied
+        Err(_) => errno::ENOENT // File not found
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,14 @@
+use crate::*;
+
+/// fallocate Syscall
+pub fn syscall_fallocate(proc: &mut super::Process, fd: usize, mode: usize, offset: usize, length: usize) -> Result<usize, usize>
+{
+    if (offset as isize) < 0 || (length as isize) <= 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    proc.allocate_descriptor_range(fd, mode, offset, length)?;
+
+    Ok(0)
+}
@@ -0,0 +1,212 @@
+use crate::*;
+
+use fs::locks::{FileLock, LockOwner};
+use fs::structures::FilesystemError;
+
+use process::descriptor::FD_CLOEXEC;
+use process::process::O_NONBLOCK;
+
+// Must be kept in sync with syscalls.h
+const F_DUPFD: usize = 0;
+const F_GETFD: usize = 1;
+const F_SETFD: usize = 2;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
+const F_GETLK: usize = 5;
+const F_SETLK: usize = 6;
+const F_SETLKW: usize = 7;
+const F_DUPFD_CLOEXEC: usize = 1030;
+const F_SETPIPE_SZ: usize = 1031;
+const F_GETPIPE_SZ: usize = 1032;
+
+const F_RDLCK: i16 = 0;
+const F_WRLCK: i16 = 1;
+const F_UNLCK: i16 = 2;
+
+const SEEK_SET: i16 = 1;
+const SEEK_CUR: i16 = 2;
+const SEEK_END: i16 = 4;
+
+/// Lock description passed to the record locking commands
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Flock
+{
+    lock_type: i16,
+    whence: i16,
+    start: isize,
+    length: isize,
+    pid: i32
+}
+
+/// Convert the range of a lock description into the range of bytes it covers, a length of zero reaches the end of
+/// the file however large it grows
+fn lock_range(proc: &mut super::Process, fd: usize, flock: &Flock) -> Result<(usize, usize), usize>
+{
+    let base = match flock.whence
+    {
+        SEEK_SET => 0,
+        SEEK_CUR =>
+        {
+            let position = proc.seek(fd, 0, SEEK_CUR as usize);
+
+            if (position as isize) < 0
+            {
+                return Err(position);
+            }
+
+            position as isize
+        },
+        SEEK_END => proc.fstat(fd)?.size as isize,
+        _ => return Err(errno::EINVAL)
+    };
+
+    let start = base.checked_add(flock.start).ok_or(errno::EINVAL)?;
+
+    // A negative length covers the bytes before the start
+    let (start, end) = if flock.length < 0
+    {
+        (start.checked_add(flock.length).ok_or(errno::EINVAL)?, start)
+    }
+    else if flock.length == 0
+    {
+        (start, isize::MAX)
+    }
+    else
+    {
+        (start, start.checked_add(flock.length).ok_or(errno::EINVAL)?)
+    };
+
+    if start < 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let end = if end == isize::MAX { usize::MAX } else { end as usize };
+
+    Ok((start as usize, end))
+}
+
+/// Get, take or release a record lock
+fn record_lock(proc: &mut super::Process, fd: usize, cmd: usize, flock_ptr: usize) -> Result<usize, usize>
+{
+    let flock_ptr = proc.map_mem(flock_ptr).map_err(|_| errno::EFAULT)? as *mut Flock;
+    let mut flock = unsafe { flock_ptr.read() };
+
+    // Descriptors without an inode, such as pipes, have nothing to lock
+    let inode = match proc.get_descriptor_inode(fd)
+    {
+        Err(errno::ENOENT) => return Err(errno::EINVAL),
+        result => result?
+    };
+
+    let (start, end) = lock_range(proc, fd, &flock)?;
+    let owner = LockOwner::Process(proc.pid);
+
+    let exclusive = match flock.lock_type
+    {
+        F_RDLCK => false,
+        F_WRLCK => true,
+        F_UNLCK if cmd != F_GETLK =>
+        {
+            proc.ensure_fs();
+            proc.fs_interface.as_mut().unwrap().lock_table().unlock(inode, owner, start, end);
+
+            return Ok(0);
+        },
+        _ => return Err(errno::EINVAL)
+    };
+
+    let lock = FileLock { owner, exclusive, start, end };
+
+    proc.ensure_fs();
+    let locks = proc.fs_interface.as_mut().unwrap().lock_table();
+
+    if cmd == F_GETLK
+    {
+        // Report the first lock in the way, or that the lock could be taken
+        match locks.conflict(inode, &lock)
+        {
+            Some(held) =>
+            {
+                flock.lock_type = if held.exclusive { F_WRLCK } else { F_RDLCK };
+                flock.whence = SEEK_SET;
+                flock.start = held.start as isize;
+                flock.length = if held.end == usize::MAX { 0 } else { (held.end - held.start) as isize };
+                flock.pid = match held.owner
+                {
+                    LockOwner::Process(pid) => pid as i32,
+                    LockOwner::Description(_) => -1
+                };
+            },
+            None => flock.lock_type = F_UNLCK
+        }
+
+        unsafe { flock_ptr.write(flock) };
+
+        return Ok(0);
+    }
+
+    match locks.lock(inode, lock)
+    {
+        Ok(()) => Ok(0),
+        Err(FilesystemError::WouldBlock) if cmd == F_SETLKW => super::utils::wait_for_lock(proc),
+        Err(e) => Err(e.to_errno())
+    }
+}
+
+/// Get or change the capacity of a pipe
+fn pipe_size(proc: &mut super::Process, fd: usize, cmd: usize, size: usize) -> Result<usize, usize>
+{
+    let description = proc.data.descriptors.get(&fd).ok_or(errno::EBADF)?.description.clone();
+    let mut description = description.borrow_mut();
+    let mut pipe = description.pipe().ok_or(errno::EBADF)?.borrow_mut();
+
+    if cmd == F_SETPIPE_SZ
+    {
+        pipe.set_capacity(size)
+    }
+    else
+    {
+        Ok(pipe.capacity())
+    }
+}
+
+/// fcntl Syscall
+pub fn syscall_fcntl(proc: &mut super::Process, fd: usize, cmd: usize, arg: usize) -> Result<usize, usize>
+{
+    if cmd == F_DUPFD || cmd == F_DUPFD_CLOEXEC
+    {
+        return proc.dup_at_least(fd, arg, cmd == F_DUPFD_CLOEXEC);
+    }
+
+    if cmd == F_GETLK || cmd == F_SETLK || cmd == F_SETLKW
+    {
+        return record_lock(proc, fd, cmd, arg);
+    }
+
+    if cmd == F_SETPIPE_SZ || cmd == F_GETPIPE_SZ
+    {
+        return pipe_size(proc, fd, cmd, arg);
+    }
+
+    let entry = proc.data.descriptors.get_mut(&fd).ok_or(errno::EBADF)?;
+
+    match cmd
+    {
+        F_GETFD => Ok(entry.flags),
+        F_SETFD =>
+        {
+            entry.flags = arg & FD_CLOEXEC;
+            Ok(0)
+        },
+        F_GETFL => Ok(entry.status_flags()),
+        F_SETFL =>
+        {
+            // The access mode and O_APPEND are fixed when the file is opened
+            entry.set_status_flags((entry.status_flags() & !O_NONBLOCK) | (arg & O_NONBLOCK));
+            Ok(0)
+        },
+        _ => Err(errno::EINVAL)
+    }
+}
@@ -0,0 +1,48 @@
+use crate::*;
+
+use fs::locks::FileLock;
+use fs::structures::FilesystemError;
+
+// Must be kept in sync with syscalls.h
+const LOCK_SH: usize = 1;
+const LOCK_EX: usize = 2;
+const LOCK_NB: usize = 4;
+const LOCK_UN: usize = 8;
+
+/// flock Syscall
+pub fn syscall_flock(proc: &mut super::Process, fd: usize, operation: usize) -> Result<usize, usize>
+{
+    // Descriptors without an inode, such as pipes, have nothing to lock
+    let inode = match proc.get_descriptor_inode(fd)
+    {
+        Err(errno::ENOENT) => return Err(errno::EINVAL),
+        result => result?
+    };
+
+    let owner = proc.descriptor_lock_owner(fd)?;
+
+    let exclusive = match operation & !LOCK_NB
+    {
+        LOCK_SH => false,
+        LOCK_EX => true,
+        LOCK_UN =>
+        {
+            proc.ensure_fs();
+            proc.fs_interface.as_mut().unwrap().lock_table().release_inode(inode, owner);
+
+            return Ok(0);
+        },
+        _ => return Err(errno::EINVAL)
+    };
+
+    proc.ensure_fs();
+    let result = proc.fs_interface.as_mut().unwrap().lock_table().lock(inode, FileLock { owner, exclusive, start: 0, end: usize::MAX });
+
+    match result
+    {
+        Ok(()) => Ok(0),
+        Err(FilesystemError::WouldBlock) if operation & LOCK_NB == 0 => super::utils::wait_for_lock(proc),
+        Err(FilesystemError::WouldBlock) => Err(errno::EWOULDBLOCK),
+        Err(e) => Err(e.to_errno())
+    }
+}
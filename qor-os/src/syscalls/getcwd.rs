@@ -1,10 +1,32 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+
 /// Getcwd Syscall
 pub fn syscall_getcwd(proc: &mut super::Process, buffer_ptr: usize, size: usize) -> usize
 {
     let buffer = proc.map_mem(buffer_ptr).unwrap() as *mut u8;
 
+    // The path is found from the working directory inode each time, as the directory may have been moved
+    let cwd = match proc.get_cwd()
+    {
+        Ok(cwd) => cwd,
+        Err(e) => return e
+    };
+
+    let mut path = match proc.fs_interface.as_mut().unwrap().inode_to_path(cwd)
+    {
+        Ok(path) => String::from(path.as_str()),
+        Err(e) => return e.to_errno()
+    };
+
+    if !path.ends_with('/')
+    {
+        path.push('/');
+    }
+
     let mut i = 0;
-    for c in proc.data.cwd.as_str().as_bytes()
+    for c in path.as_bytes()
     {
         if i == size
         {
@@ -11,6 +11,7 @@ pub fn syscall_kill(proc: &mut super::Process, pid: usize, signal: usize) -> usi
     {
         2 => SignalType::SIGINT,
         9 => SignalType::SIGKILL,
+        13 => SignalType::SIGPIPE,
         15 => SignalType::SIGTERM,
         _ => { kwarnln!("Unknown signal {}", signal); return errno::EINVAL }
     };
@@ -0,0 +1,21 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+
+/// link Syscall
+pub fn syscall_link(proc: &mut super::Process, old_path_ptr: usize, new_path_ptr: usize) -> Result<usize, usize>
+{
+    let old_path = super::utils::userspace_string_to_path(proc, old_path_ptr)?;
+    let new_path = super::utils::userspace_string_to_path(proc, new_path_ptr)?;
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    // Like Linux, a symbolic link given as the existing path is linked itself rather than its target
+    let inode = vfs.resolve_path_at(&old_path, false).map_err(|e| e.to_errno())?;
+    let (dest_inode, name) = vfs.resolve_parent_at(&new_path).map_err(|e| e.to_errno())?;
+
+    vfs.create_link(inode, dest_inode, name).map_err(|e| e.to_errno())?;
+
+    Ok(0)
+}
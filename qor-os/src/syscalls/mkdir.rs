@@ -3,20 +3,21 @@ use crate::*;
 use fs::fstrait::Filesystem;
 
 /// mkdir Syscall
-pub fn syscall_mkdir(proc: &mut super::Process, path_ptr: usize, _mode: usize) -> Result<usize, usize>
+pub fn syscall_mkdir(proc: &mut super::Process, path_ptr: usize, mode: usize) -> Result<usize, usize>
 {
-    let expanded = super::utils::userspace_string_to_path(proc, path_ptr)?;
-    let (dest_path, name) = expanded.split_last();
+    syscall_mkdirat(proc, super::utils::AT_FDCWD, path_ptr, mode)
+}
 
-    let vfs = crate::fs::vfs::get_vfs_reference().unwrap();
+/// mkdirat Syscall
+pub fn syscall_mkdirat(proc: &mut super::Process, dirfd: usize, path_ptr: usize, _mode: usize) -> Result<usize, usize>
+{
+    let expanded = super::utils::userspace_string_to_path_at(proc, dirfd, path_ptr)?;
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
 
-    if let Ok(dest_inode) = vfs.path_to_inode(&dest_path)
-    {
-        if let Ok(_) = vfs.create_directory(dest_inode, name.to_string())
-        {
-            return Ok(0);
-        }
-    }
+    let (dest_inode, name) = vfs.resolve_parent_at(&expanded).map_err(|e| e.to_errno())?;
+    vfs.create_directory(dest_inode, name).map_err(|e| e.to_errno())?;
 
-    Err(usize::MAX)
-}
\ No newline at end of file
+    Ok(0)
+}
@@ -0,0 +1,50 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+use fs::structures::{S_IFBLK, S_IFCHR, S_IFIFO, S_IFMT, S_IFREG, S_IFSOCK};
+
+/// mknod Syscall
+pub fn syscall_mknod(proc: &mut super::Process, path_ptr: usize, mode: usize, dev: usize) -> Result<usize, usize>
+{
+    syscall_mknodat(proc, super::utils::AT_FDCWD, path_ptr, mode, dev)
+}
+
+/// mknodat Syscall
+pub fn syscall_mknodat(proc: &mut super::Process, dirfd: usize, path_ptr: usize, mode: usize, _dev: usize) -> Result<usize, usize>
+{
+    let kind = mode as u16 & S_IFMT;
+
+    // Device nodes are only made by devfs
+    if kind == S_IFCHR || kind == S_IFBLK
+    {
+        return Err(errno::EPERM);
+    }
+
+    if kind != 0 && kind != S_IFREG && kind != S_IFIFO && kind != S_IFSOCK
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let expanded = super::utils::userspace_string_to_path_at(proc, dirfd, path_ptr)?;
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    let (dest_inode, name) = vfs.resolve_parent_at(&expanded).map_err(|e| e.to_errno())?;
+
+    if kind == S_IFIFO
+    {
+        vfs.create_fifo(dest_inode, name).map_err(|e| e.to_errno())?;
+    }
+    else if kind == S_IFSOCK
+    {
+        // The socket inode is not bound to any socket until one binds to a new path
+        vfs.create_socket(dest_inode, name).map_err(|e| e.to_errno())?;
+    }
+    else
+    {
+        vfs.create_file(dest_inode, name).map_err(|e| e.to_errno())?;
+    }
+
+    Ok(0)
+}
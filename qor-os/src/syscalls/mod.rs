@@ -5,36 +5,54 @@ use crate::*;
 use process::process::Process;
 
 // Modules
+mod access;
 mod chdir;
+mod chmod;
+mod chown;
 mod close;
 mod dup;
+mod epoll;
 mod execve;
 mod exit;
+mod fallocate;
+mod fcntl;
+mod flock;
 mod fork;
 mod getcwd;
 mod getdents;
 mod getpid;
 mod ioctl;
 mod kill;
+mod link;
 mod lseek;
 mod mkdir;
+mod mknod;
 mod mmap;
+mod mount;
 mod munmap;
 mod nanosleep;
 mod open;
 mod pause;
 mod pipe;
+mod poll;
 mod read;
+mod readlink;
 mod reboot;
+mod rename;
 mod rmdir;
 mod setpgid;
 mod sigaction;
 mod sigreturn;
+mod socket;
 mod stat;
+mod symlink;
 mod sync;
+mod truncate;
 mod unlink;
+mod utimensat;
 mod wait;
 mod write;
+mod xattr;
 
 pub mod utils;
 
@@ -77,6 +95,21 @@ pub fn handle_syscall(proc: &mut Process, num: usize, arg0: usize, arg1: usize,
         {
             flatten_syscall_result(stat::syscall_stat(proc, arg0, arg1))
         },
+        // fstat Syscall
+        5 =>
+        {
+            flatten_syscall_result(stat::syscall_fstat(proc, arg0, arg1))
+        },
+        // lstat Syscall
+        6 =>
+        {
+            flatten_syscall_result(stat::syscall_lstat(proc, arg0, arg1))
+        },
+        // poll Syscall
+        7 =>
+        {
+            flatten_syscall_result(poll::syscall_poll(proc, arg0, arg1, arg2))
+        },
         // lseek Syscall
         8 =>
         {
@@ -109,11 +142,21 @@ pub fn handle_syscall(proc: &mut Process, num: usize, arg0: usize, arg1: usize,
         {
             ioctl::syscall_ioctl(proc, arg0, arg1, arg2)
         },
+        // access Syscall
+        21 =>
+        {
+            flatten_syscall_result(access::syscall_access(proc, arg0, arg1))
+        },
         // pipe Syscall
         22 =>
         {
             pipe::syscall_pipe(proc, arg0)
         },
+        // select Syscall
+        23 =>
+        {
+            flatten_syscall_result(poll::syscall_select(proc, arg0, arg1, arg2, arg3, arg4))
+        },
         // dup Syscall
         32 =>
         {
@@ -139,6 +182,71 @@ pub fn handle_syscall(proc: &mut Process, num: usize, arg0: usize, arg1: usize,
         {
             getpid::syscall_getpid(proc)
         },
+        // socket Syscall
+        41 =>
+        {
+            flatten_syscall_result(socket::syscall_socket(proc, arg0, arg1, arg2))
+        },
+        // connect Syscall
+        42 =>
+        {
+            flatten_syscall_result(socket::syscall_connect(proc, arg0, arg1, arg2))
+        },
+        // accept Syscall
+        43 =>
+        {
+            flatten_syscall_result(socket::syscall_accept(proc, arg0, arg1, arg2))
+        },
+        // sendto Syscall
+        44 =>
+        {
+            flatten_syscall_result(socket::syscall_sendto(proc, arg0, arg1, arg2, arg3, arg4, arg5))
+        },
+        // recvfrom Syscall
+        45 =>
+        {
+            flatten_syscall_result(socket::syscall_recvfrom(proc, arg0, arg1, arg2, arg3, arg4, arg5))
+        },
+        // sendmsg Syscall
+        46 =>
+        {
+            flatten_syscall_result(socket::syscall_sendmsg(proc, arg0, arg1, arg2))
+        },
+        // recvmsg Syscall
+        47 =>
+        {
+            flatten_syscall_result(socket::syscall_recvmsg(proc, arg0, arg1, arg2))
+        },
+        // shutdown Syscall
+        48 =>
+        {
+            flatten_syscall_result(socket::syscall_shutdown(proc, arg0, arg1))
+        },
+        // bind Syscall
+        49 =>
+        {
+            flatten_syscall_result(socket::syscall_bind(proc, arg0, arg1, arg2))
+        },
+        // listen Syscall
+        50 =>
+        {
+            flatten_syscall_result(socket::syscall_listen(proc, arg0, arg1))
+        },
+        // getsockname Syscall
+        51 =>
+        {
+            flatten_syscall_result(socket::syscall_getsockname(proc, arg0, arg1, arg2))
+        },
+        // getpeername Syscall
+        52 =>
+        {
+            flatten_syscall_result(socket::syscall_getpeername(proc, arg0, arg1, arg2))
+        },
+        // socketpair Syscall
+        53 =>
+        {
+            flatten_syscall_result(socket::syscall_socketpair(proc, arg0, arg1, arg2, arg3))
+        },
         // Fork Syscall
         57 =>
         {
@@ -167,6 +275,26 @@ pub fn handle_syscall(proc: &mut Process, num: usize, arg0: usize, arg1: usize,
             kill::syscall_kill(proc, arg0, arg1);
             0
         },
+        // fcntl Syscall
+        72 =>
+        {
+            flatten_syscall_result(fcntl::syscall_fcntl(proc, arg0, arg1, arg2))
+        },
+        // flock Syscall
+        73 =>
+        {
+            flatten_syscall_result(flock::syscall_flock(proc, arg0, arg1))
+        },
+        // Truncate Syscall
+        76 =>
+        {
+            flatten_syscall_result(truncate::syscall_truncate(proc, arg0, arg1))
+        },
+        // ftruncate Syscall
+        77 =>
+        {
+            flatten_syscall_result(truncate::syscall_ftruncate(proc, arg0, arg1))
+        },
         // Getdents Syscall
         78 =>
         {
@@ -182,6 +310,11 @@ pub fn handle_syscall(proc: &mut Process, num: usize, arg0: usize, arg1: usize,
         {
             flatten_syscall_result(chdir::syscall_chdir(proc, arg0))
         },
+        // Rename Syscall
+        82 =>
+        {
+            flatten_syscall_result(rename::syscall_rename(proc, arg0, arg1))
+        },
         // Mkdir Syscall
         83 =>
         {
@@ -192,26 +325,226 @@ pub fn handle_syscall(proc: &mut Process, num: usize, arg0: usize, arg1: usize,
         {
             flatten_syscall_result(rmdir::syscall_rmdir(proc, arg0))
         },
+        // Link Syscall
+        86 =>
+        {
+            flatten_syscall_result(link::syscall_link(proc, arg0, arg1))
+        },
         // Unlink Syscall
         87 =>
         {
             flatten_syscall_result(unlink::syscall_unlink(proc, arg0))
         },
+        // Symlink Syscall
+        88 =>
+        {
+            flatten_syscall_result(symlink::syscall_symlink(proc, arg0, arg1))
+        },
+        // Readlink Syscall
+        89 =>
+        {
+            flatten_syscall_result(readlink::syscall_readlink(proc, arg0, arg1, arg2))
+        },
+        // Chmod Syscall
+        90 =>
+        {
+            flatten_syscall_result(chmod::syscall_chmod(proc, arg0, arg1))
+        },
+        // fchmod Syscall
+        91 =>
+        {
+            flatten_syscall_result(chmod::syscall_fchmod(proc, arg0, arg1))
+        },
+        // Chown Syscall
+        92 =>
+        {
+            flatten_syscall_result(chown::syscall_chown(proc, arg0, arg1, arg2))
+        },
+        // fchown Syscall
+        93 =>
+        {
+            flatten_syscall_result(chown::syscall_fchown(proc, arg0, arg1, arg2))
+        },
         // setpgid Syscall
         109 =>
         {
             setpgid::syscall_setpgid(proc, arg0, arg1)
         },
+        // mknod Syscall
+        133 =>
+        {
+            flatten_syscall_result(mknod::syscall_mknod(proc, arg0, arg1, arg2))
+        },
+        // pivot_root Syscall
+        155 =>
+        {
+            flatten_syscall_result(mount::syscall_pivot_root(proc, arg0, arg1))
+        },
         // Sync Syscall
         162 =>
         {
             sync::syscall_sync(proc)
         },
+        // Mount Syscall
+        165 =>
+        {
+            flatten_syscall_result(mount::syscall_mount(proc, arg0, arg1, arg2, arg3, arg4))
+        },
+        // umount2 Syscall
+        166 =>
+        {
+            flatten_syscall_result(mount::syscall_umount2(proc, arg0, arg1))
+        },
         // Reboot Syscall
         169 =>
         {
             reboot::syscall_reboot(proc, arg0, arg1, arg2, arg3)
         },
+        // setxattr Syscall
+        188 =>
+        {
+            flatten_syscall_result(xattr::syscall_setxattr(proc, arg0, arg1, arg2, arg3, arg4))
+        },
+        // lsetxattr Syscall
+        189 =>
+        {
+            flatten_syscall_result(xattr::syscall_lsetxattr(proc, arg0, arg1, arg2, arg3, arg4))
+        },
+        // fsetxattr Syscall
+        190 =>
+        {
+            flatten_syscall_result(xattr::syscall_fsetxattr(proc, arg0, arg1, arg2, arg3, arg4))
+        },
+        // getxattr Syscall
+        191 =>
+        {
+            flatten_syscall_result(xattr::syscall_getxattr(proc, arg0, arg1, arg2, arg3))
+        },
+        // lgetxattr Syscall
+        192 =>
+        {
+            flatten_syscall_result(xattr::syscall_lgetxattr(proc, arg0, arg1, arg2, arg3))
+        },
+        // fgetxattr Syscall
+        193 =>
+        {
+            flatten_syscall_result(xattr::syscall_fgetxattr(proc, arg0, arg1, arg2, arg3))
+        },
+        // listxattr Syscall
+        194 =>
+        {
+            flatten_syscall_result(xattr::syscall_listxattr(proc, arg0, arg1, arg2))
+        },
+        // llistxattr Syscall
+        195 =>
+        {
+            flatten_syscall_result(xattr::syscall_llistxattr(proc, arg0, arg1, arg2))
+        },
+        // flistxattr Syscall
+        196 =>
+        {
+            flatten_syscall_result(xattr::syscall_flistxattr(proc, arg0, arg1, arg2))
+        },
+        // removexattr Syscall
+        197 =>
+        {
+            flatten_syscall_result(xattr::syscall_removexattr(proc, arg0, arg1))
+        },
+        // lremovexattr Syscall
+        198 =>
+        {
+            flatten_syscall_result(xattr::syscall_lremovexattr(proc, arg0, arg1))
+        },
+        // fremovexattr Syscall
+        199 =>
+        {
+            flatten_syscall_result(xattr::syscall_fremovexattr(proc, arg0, arg1))
+        },
+        // epoll_create Syscall
+        213 =>
+        {
+            flatten_syscall_result(epoll::syscall_epoll_create(proc, arg0))
+        },
+        // epoll_wait Syscall
+        232 =>
+        {
+            flatten_syscall_result(epoll::syscall_epoll_wait(proc, arg0, arg1, arg2, arg3))
+        },
+        // epoll_ctl Syscall
+        233 =>
+        {
+            flatten_syscall_result(epoll::syscall_epoll_ctl(proc, arg0, arg1, arg2, arg3))
+        },
+        // openat Syscall
+        257 =>
+        {
+            flatten_syscall_result(open::syscall_openat(proc, arg0, arg1, arg2, arg3))
+        },
+        // mkdirat Syscall
+        258 =>
+        {
+            flatten_syscall_result(mkdir::syscall_mkdirat(proc, arg0, arg1, arg2))
+        },
+        // mknodat Syscall
+        259 =>
+        {
+            flatten_syscall_result(mknod::syscall_mknodat(proc, arg0, arg1, arg2, arg3))
+        },
+        // newfstatat Syscall
+        262 =>
+        {
+            flatten_syscall_result(stat::syscall_newfstatat(proc, arg0, arg1, arg2, arg3))
+        },
+        // unlinkat Syscall
+        263 =>
+        {
+            flatten_syscall_result(unlink::syscall_unlinkat(proc, arg0, arg1, arg2))
+        },
+        // readlinkat Syscall
+        267 =>
+        {
+            flatten_syscall_result(readlink::syscall_readlinkat(proc, arg0, arg1, arg2, arg3))
+        },
+        // faccessat Syscall
+        269 =>
+        {
+            flatten_syscall_result(access::syscall_faccessat(proc, arg0, arg1, arg2, arg3))
+        },
+        // utimensat Syscall
+        280 =>
+        {
+            flatten_syscall_result(utimensat::syscall_utimensat(proc, arg0, arg1, arg2, arg3))
+        },
+        // fallocate Syscall
+        285 =>
+        {
+            flatten_syscall_result(fallocate::syscall_fallocate(proc, arg0, arg1, arg2, arg3))
+        },
+        // accept4 Syscall
+        288 =>
+        {
+            flatten_syscall_result(socket::syscall_accept4(proc, arg0, arg1, arg2, arg3))
+        },
+        // epoll_create1 Syscall
+        291 =>
+        {
+            flatten_syscall_result(epoll::syscall_epoll_create1(proc, arg0))
+        },
+        // dup3 Syscall
+        292 =>
+        {
+            flatten_syscall_result(dup::syscall_dup3(proc, arg0, arg1, arg2))
+        },
+        // pipe2 Syscall
+        293 =>
+        {
+            flatten_syscall_result(pipe::syscall_pipe2(proc, arg0, arg1))
+        },
+        // Renameat2 Syscall
+        316 =>
+        {
+            flatten_syscall_result(rename::syscall_renameat2(proc, arg0, arg1, arg2, arg3, arg4))
+        },
         default =>
         {
             kwarnln!("Syscall from PID {}", proc.pid);
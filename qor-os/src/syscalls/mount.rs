@@ -0,0 +1,138 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+use fs::mounts::{MS_MOUNT_FLAGS, MS_REMOUNT};
+
+/// Unmount a filesystem even if it is in use
+const MNT_FORCE: usize = 1;
+
+/// Check if any process has a file open or its working directory on a filesystem
+fn mount_in_use(mount_id: usize) -> bool
+{
+    if let Some(proc_manager) = process::scheduler::get_process_manager()
+    {
+        for proc in proc_manager.processes.values()
+        {
+            for desc in proc.data.descriptors.values()
+            {
+                if desc.description.borrow_mut().get_inode().map(|inode| inode.mount_id) == Some(mount_id)
+                {
+                    return true;
+                }
+            }
+
+            if proc.data.cwd.map(|inode| inode.mount_id) == Some(mount_id)
+            {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+/// Get the mount id of the filesystem whose root is at the given path
+fn mount_root_at(vfs: &mut fs::vfs::FilesystemInterface, path: &fs::vfs::PathAt) -> Result<usize, usize>
+{
+    let inode = vfs.resolve_path_at(path, true).map_err(|e| e.to_errno())?;
+    let root = vfs.get_fs_mount_error(inode.mount_id).map_err(|e| e.to_errno())?.get_root_index().map_err(|e| e.to_errno())?;
+
+    if root != inode
+    {
+        return Err(errno::EINVAL);
+    }
+
+    Ok(inode.mount_id)
+}
+
+/// mount Syscall
+pub fn syscall_mount(proc: &mut super::Process, source_ptr: usize, target_ptr: usize, fstype_ptr: usize, flags: usize, data_ptr: usize) -> Result<usize, usize>
+{
+    if flags & !(MS_MOUNT_FLAGS | MS_REMOUNT) != 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let target = super::utils::userspace_string_to_path(proc, target_ptr)?;
+
+    // Changing the flags of an existing mount ignores the source and filesystem type
+    if flags & MS_REMOUNT > 0
+    {
+        proc.ensure_fs();
+        let vfs = proc.fs_interface.as_mut().unwrap();
+
+        let mount_id = mount_root_at(vfs, &target)?;
+        vfs.remount(mount_id, flags).map_err(|e| e.to_errno())?;
+
+        return Ok(0);
+    }
+
+    let fstype = super::utils::userspace_string(proc, fstype_ptr)?;
+
+    // Filesystems without a device may leave the source empty, in which case they are listed under their type
+    let source = if source_ptr == 0 { String::new() } else { super::utils::userspace_string(proc, source_ptr)? };
+    let source = if source.len() == 0 { fstype.clone() } else { source };
+
+    let options = if data_ptr == 0 { String::new() } else { super::utils::userspace_string(proc, data_ptr)? };
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    // The target must be an existing directory, which is mounted over under its canonical path
+    let inode = vfs.resolve_path_at(&target, true).map_err(|e| e.to_errno())?;
+    vfs.assert_directory(inode).map_err(|e| e.to_errno())?;
+
+    let target = vfs.inode_to_path(inode).map_err(|e| e.to_errno())?;
+
+    if target.as_str().len() == 0
+    {
+        return Err(errno::EBUSY);
+    }
+
+    vfs.mount(&target, &source, &fstype, flags, &options).map_err(|e| e.to_errno())?;
+
+    Ok(0)
+}
+
+/// umount2 Syscall
+pub fn syscall_umount2(proc: &mut super::Process, target_ptr: usize, flags: usize) -> Result<usize, usize>
+{
+    if flags & !MNT_FORCE != 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let target = super::utils::userspace_string_to_path(proc, target_ptr)?;
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    let mount_id = mount_root_at(vfs, &target)?;
+
+    if flags & MNT_FORCE == 0 && mount_in_use(mount_id)
+    {
+        return Err(errno::EBUSY);
+    }
+
+    vfs.unmount(mount_id).map_err(|e| e.to_errno())?;
+
+    Ok(0)
+}
+
+/// pivot_root Syscall
+pub fn syscall_pivot_root(proc: &mut super::Process, new_root_ptr: usize, put_old_ptr: usize) -> Result<usize, usize>
+{
+    let new_root = super::utils::userspace_string_to_path(proc, new_root_ptr)?;
+    let put_old = super::utils::userspace_string_to_path(proc, put_old_ptr)?;
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    let new_root = vfs.resolve_path_at(&new_root, true).map_err(|e| e.to_errno())?;
+    let put_old = vfs.resolve_path_at(&put_old, true).map_err(|e| e.to_errno())?;
+
+    // Working directories are held as inodes, so they keep pointing at the same directories under their new paths
+    vfs.pivot_root(new_root, put_old).map_err(|e| e.to_errno())?;
+
+    Ok(0)
+}
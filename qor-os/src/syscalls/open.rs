@@ -1,9 +1,22 @@
 use crate::*;
 
+use fs::structures::FilesystemError;
+
 /// Open Syscall
-pub fn syscall_open(proc: &mut super::Process, path_ptr: usize, flags: usize, _create_mode: usize) -> Result<usize, usize>
+pub fn syscall_open(proc: &mut super::Process, path_ptr: usize, flags: usize, create_mode: usize) -> Result<usize, usize>
+{
+    syscall_openat(proc, super::utils::AT_FDCWD, path_ptr, flags, create_mode)
+}
+
+/// openat Syscall
+pub fn syscall_openat(proc: &mut super::Process, dirfd: usize, path_ptr: usize, flags: usize, _create_mode: usize) -> Result<usize, usize>
 {
-    let expanded_path = super::utils::userspace_string_to_path(proc, path_ptr)?;
+    let expanded_path = super::utils::userspace_string_to_path_at(proc, dirfd, path_ptr)?;
 
-    proc.open(&expanded_path, flags).map_err( |_| errno::ENOENT )
-}
\ No newline at end of file
+    match proc.open(&expanded_path, flags)
+    {
+        Ok(fd) => Ok(fd),
+        Err(FilesystemError::WouldBlock) => super::utils::wait_for_event(proc),
+        Err(e) => Err(e.to_errno())
+    }
+}
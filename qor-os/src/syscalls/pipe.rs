@@ -1,9 +1,13 @@
+use crate::*;
+
+use process::process::{O_CLOEXEC, O_NONBLOCK};
+
 /// Pipe Syscall
 pub fn syscall_pipe(proc: &mut super::Process, fds: usize) -> usize
 {
     let buffer = proc.map_mem(fds).unwrap() as *mut u32;
 
-    let (read, write) = proc.pipe();
+    let (read, write) = proc.pipe(0);
     
     unsafe
     {
@@ -12,4 +16,25 @@ pub fn syscall_pipe(proc: &mut super::Process, fds: usize) -> usize
     }
 
     0
-}
\ No newline at end of file
+}
+
+/// pipe2 Syscall
+pub fn syscall_pipe2(proc: &mut super::Process, fds: usize, flags: usize) -> Result<usize, usize>
+{
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let buffer = proc.map_mem(fds).map_err(|_| errno::EFAULT)? as *mut u32;
+
+    let (read, write) = proc.pipe(flags);
+
+    unsafe
+    {
+        buffer.add(0).write(read as u32);
+        buffer.add(1).write(write as u32);
+    }
+
+    Ok(0)
+}
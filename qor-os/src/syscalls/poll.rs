@@ -0,0 +1,186 @@
+use crate::*;
+
+use drivers::timer::KernelTime;
+use process::readiness::*;
+
+/// Largest number of descriptors which can be checked at once
+const MAX_POLL_DESCRIPTORS: usize = 1024;
+
+/// Number of descriptors in the bitmaps passed to `select`
+const FD_SETSIZE: usize = 1024;
+
+/// Number of bits in a word of a `select` bitmap
+const FD_BITS: usize = usize::BITS as usize;
+
+/// Descriptor and events passed to `poll`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct PollFd
+{
+    fd: i32,
+    events: i16,
+    revents: i16
+}
+
+/// Timeout passed to `select`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct TimeVal
+{
+    seconds: isize,
+    microseconds: isize
+}
+
+/// poll Syscall
+pub fn syscall_poll(proc: &mut super::Process, fds_ptr: usize, count: usize, timeout: usize) -> Result<usize, usize>
+{
+    if count > MAX_POLL_DESCRIPTORS
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let fds = if count == 0
+    {
+        &mut []
+    }
+    else
+    {
+        let ptr = proc.map_mem(fds_ptr).map_err(|_| errno::EFAULT)? as *mut PollFd;
+        unsafe { core::slice::from_raw_parts_mut(ptr, count) }
+    };
+
+    let mut ready = 0;
+
+    for poll_fd in fds.iter_mut()
+    {
+        // Negative descriptors are skipped, which lets an entry be disabled without removing it
+        poll_fd.revents = if poll_fd.fd < 0
+        {
+            0
+        }
+        else
+        {
+            let wanted = poll_fd.events as u16 as usize | POLLERR | POLLHUP | POLLNVAL;
+            (proc.poll_descriptor(poll_fd.fd as usize, poll_fd.events as u16 as usize) & wanted) as i16
+        };
+
+        if poll_fd.revents != 0
+        {
+            ready += 1;
+        }
+    }
+
+    // A negative timeout waits forever
+    let timeout = if (timeout as i32) < 0 { None } else { Some(KernelTime::milliseconds(timeout as i32 as usize)) };
+
+    super::utils::wait_for_readiness(proc, ready > 0, timeout);
+
+    Ok(ready)
+}
+
+/// Copy the bitmap of a `select` descriptor set covering `count` descriptors out of userspace, a null pointer is an
+/// empty set
+fn read_fd_set(proc: &mut super::Process, set_ptr: usize, count: usize) -> Result<Option<*mut usize>, usize>
+{
+    if set_ptr == 0
+    {
+        return Ok(None);
+    }
+
+    let ptr = proc.map_mem(set_ptr).map_err(|_| errno::EFAULT)? as *mut usize;
+
+    // Reading the bitmap here catches bad pointers before any descriptor is checked
+    for word in 0..(count + FD_BITS - 1) / FD_BITS
+    {
+        unsafe { ptr.add(word).read() };
+    }
+
+    Ok(Some(ptr))
+}
+
+/// Check if a descriptor is in a `select` descriptor set
+fn fd_isset(set: Option<*mut usize>, fd: usize) -> bool
+{
+    set.map(|ptr| unsafe { ptr.add(fd / FD_BITS).read() } & (1 << (fd % FD_BITS)) > 0).unwrap_or(false)
+}
+
+/// Add or remove a descriptor from a `select` descriptor set
+fn fd_assign(set: Option<*mut usize>, fd: usize, value: bool)
+{
+    if let Some(ptr) = set
+    {
+        let word = unsafe { ptr.add(fd / FD_BITS).read() };
+        let bit = 1 << (fd % FD_BITS);
+
+        unsafe { ptr.add(fd / FD_BITS).write(if value { word | bit } else { word & !bit }) };
+    }
+}
+
+/// select Syscall
+pub fn syscall_select(proc: &mut super::Process, count: usize, read_ptr: usize, write_ptr: usize, except_ptr: usize, timeout_ptr: usize) -> Result<usize, usize>
+{
+    if count > FD_SETSIZE
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let read_set = read_fd_set(proc, read_ptr, count)?;
+    let write_set = read_fd_set(proc, write_ptr, count)?;
+    let except_set = read_fd_set(proc, except_ptr, count)?;
+
+    // A null timeout waits forever
+    let timeout = if timeout_ptr == 0
+    {
+        None
+    }
+    else
+    {
+        let time = unsafe { (proc.map_mem(timeout_ptr).map_err(|_| errno::EFAULT)? as *const TimeVal).read() };
+
+        if time.seconds < 0 || time.microseconds < 0 || time.microseconds >= 1_000_000
+        {
+            return Err(errno::EINVAL);
+        }
+
+        Some(KernelTime::microseconds(time.seconds as usize * 1_000_000 + time.microseconds as usize))
+    };
+
+    // Check every descriptor before changing the sets, so a bad descriptor leaves them untouched
+    let mut results = Vec::new();
+
+    for fd in 0..count
+    {
+        let readable = fd_isset(read_set, fd);
+        let writable = fd_isset(write_set, fd);
+        let except = fd_isset(except_set, fd);
+
+        if !(readable || writable || except)
+        {
+            continue;
+        }
+
+        let wanted = if readable { POLLIN } else { 0 } | if writable { POLLOUT } else { 0 } | if except { POLLPRI } else { 0 };
+        let events = proc.poll_descriptor(fd, wanted);
+
+        if events & POLLNVAL > 0
+        {
+            return Err(errno::EBADF);
+        }
+
+        // A hangup counts as readable, since a read would return straight away
+        results.push((fd, readable && events & (POLLIN | POLLHUP) > 0, writable && events & POLLOUT > 0, except && events & POLLPRI > 0));
+    }
+
+    let ready = results.iter().map(|&(_, r, w, e)| r as usize + w as usize + e as usize).sum::<usize>();
+
+    super::utils::wait_for_readiness(proc, ready > 0, timeout);
+
+    for (fd, readable, writable, except) in results
+    {
+        fd_assign(read_set, fd, readable);
+        fd_assign(write_set, fd, writable);
+        fd_assign(except_set, fd, except);
+    }
+
+    Ok(ready)
+}
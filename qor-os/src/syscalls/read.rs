@@ -11,10 +11,24 @@ pub fn syscall_read(proc: &mut super::Process, fd: usize, buffer: usize, count:
         return errno::EBADFD;
     }
 
+    // If the data has to come from a disk, wait for it without holding up the other processes, the syscall is
+    // restarted once the request completes
+    if let Some(token) = proc.prefetch(fd, count)
+    {
+        proc.state = ProcessState::Waiting(WaitMode::ForBlockDevice(token));
+
+        let schedule = process::scheduler::schedule_next();
+        process::scheduler::schedule_jump(schedule);
+    }
+
     if proc.check_available(fd)
     {
         proc.read(fd, ptr, count)
     }
+    else if proc.is_nonblocking(fd)
+    {
+        errno::EAGAIN
+    }
     else
     {
         proc.state = ProcessState::Waiting(WaitMode::ForIO((fd, count, ptr)));
@@ -0,0 +1,35 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+
+/// readlink Syscall
+pub fn syscall_readlink(proc: &mut super::Process, path_ptr: usize, buffer_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    syscall_readlinkat(proc, super::utils::AT_FDCWD, path_ptr, buffer_ptr, size)
+}
+
+/// readlinkat Syscall
+pub fn syscall_readlinkat(proc: &mut super::Process, dirfd: usize, path_ptr: usize, buffer_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    let path = super::utils::userspace_string_to_path_at(proc, dirfd, path_ptr)?;
+
+    if size == 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    let inode = vfs.resolve_path_at(&path, false).map_err(|e| e.to_errno())?;
+    let target = vfs.read_link(inode).map_err(|e| e.to_errno())?;
+
+    let buffer = proc.map_mem(buffer_ptr).map_err(|_| errno::EFAULT)? as *mut u8;
+
+    // The target is truncated to fit the buffer and is not null terminated
+    let length = target.len().min(size);
+
+    unsafe { core::ptr::copy(target.as_ptr(), buffer, length) };
+
+    Ok(length)
+}
@@ -0,0 +1,37 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+
+use fs::vfs::PathAt;
+
+/// Move the entry at one path to another
+fn rename_paths(proc: &mut super::Process, old_path: PathAt, new_path: PathAt, flags: usize) -> Result<usize, usize>
+{
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    let (old_directory, old_name) = vfs.resolve_parent_at(&old_path).map_err(|e| e.to_errno())?;
+    let (new_directory, new_name) = vfs.resolve_parent_at(&new_path).map_err(|e| e.to_errno())?;
+
+    vfs.rename(old_directory, old_name, new_directory, new_name, flags).map_err(|e| e.to_errno())?;
+
+    Ok(0)
+}
+
+/// rename Syscall
+pub fn syscall_rename(proc: &mut super::Process, old_path_ptr: usize, new_path_ptr: usize) -> Result<usize, usize>
+{
+    let old_path = super::utils::userspace_string_to_path(proc, old_path_ptr)?;
+    let new_path = super::utils::userspace_string_to_path(proc, new_path_ptr)?;
+
+    rename_paths(proc, old_path, new_path, 0)
+}
+
+/// renameat2 Syscall
+pub fn syscall_renameat2(proc: &mut super::Process, old_dirfd: usize, old_path_ptr: usize, new_dirfd: usize, new_path_ptr: usize, flags: usize) -> Result<usize, usize>
+{
+    let old_path = super::utils::userspace_string_to_path_at(proc, old_dirfd, old_path_ptr)?;
+    let new_path = super::utils::userspace_string_to_path_at(proc, new_dirfd, new_path_ptr)?;
+
+    rename_paths(proc, old_path, new_path, flags)
+}
@@ -0,0 +1,659 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+use fs::structures::{FilesystemError, S_IFMT, S_IFSOCK};
+
+use process::descriptor::DescriptorEntry;
+use process::process::{O_CLOEXEC, O_NONBLOCK};
+use process::socket::*;
+
+// Must be kept in sync with syscalls.h
+const SOCK_TYPE_MASK: usize = 0xf;
+
+const MSG_PEEK: usize = 0x2;
+const MSG_CTRUNC: usize = 0x8;
+const MSG_TRUNC: usize = 0x20;
+const MSG_DONTWAIT: usize = 0x40;
+const MSG_NOSIGNAL: usize = 0x4000;
+const MSG_CMSG_CLOEXEC: usize = 0x40000000;
+
+const SHUT_RD: usize = 0;
+const SHUT_WR: usize = 1;
+const SHUT_RDWR: usize = 2;
+
+const SOL_SOCKET: i32 = 1;
+const SCM_RIGHTS: i32 = 1;
+
+/// Largest number of descriptors which can be passed in a single message
+const SCM_MAX_FD: usize = 253;
+
+/// Length of the path in a socket address
+const UNIX_PATH_MAX: usize = 108;
+
+/// Unix socket address
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SocketAddress
+{
+    family: u16,
+    path: [u8; UNIX_PATH_MAX]
+}
+
+/// Buffer in a scatter or gather list
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct IoVec
+{
+    base: usize,
+    length: usize
+}
+
+/// Message passed to `sendmsg` and `recvmsg`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct MessageHeader
+{
+    name: usize,
+    name_length: u32,
+    iov: usize,
+    iov_length: usize,
+    control: usize,
+    control_length: usize,
+    flags: i32
+}
+
+/// Header of a control message, which is followed by its data
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ControlHeader
+{
+    length: usize,
+    level: i32,
+    kind: i32
+}
+
+/// Size of a control message header, which its data follows
+const CONTROL_HEADER_SIZE: usize = core::mem::size_of::<ControlHeader>();
+
+/// Round the length of a control message up to the alignment of the next one
+fn control_align(length: usize) -> usize
+{
+    (length + core::mem::size_of::<usize>() - 1) & !(core::mem::size_of::<usize>() - 1)
+}
+
+/// Run an operation on the socket open as a file descriptor
+fn with_socket<T>(proc: &mut super::Process, fd: usize, f: impl FnOnce(&mut super::Process, &mut UnixSocket) -> Result<T, usize>) -> Result<T, usize>
+{
+    let description = proc.data.descriptors.get(&fd).ok_or(errno::EBADF)?.description.clone();
+    let mut description = description.borrow_mut();
+    let socket = description.socket().ok_or(errno::ENOTSOCK)?;
+
+    f(proc, socket)
+}
+
+/// Wait and restart the syscall if a socket operation has to wait, unless the socket or the call does not block.
+/// The wait happens here, once the socket is no longer borrowed
+fn wait_if_blocked(proc: &mut super::Process, fd: usize, dont_wait: bool, result: Result<usize, usize>) -> Result<usize, usize>
+{
+    match result
+    {
+        Err(errno::EAGAIN) if !dont_wait && !proc.is_nonblocking(fd) => super::utils::wait_for_event(proc),
+        result => result
+    }
+}
+
+/// Copy the path of a socket address out of userspace
+fn read_address(proc: &mut super::Process, address_ptr: usize, length: usize) -> Result<String, usize>
+{
+    if length <= 2 || length > core::mem::size_of::<SocketAddress>()
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let ptr = proc.map_mem(address_ptr).map_err(|_| errno::EFAULT)? as *const u8;
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, length) };
+
+    if u16::from_ne_bytes([bytes[0], bytes[1]]) as usize != AF_UNIX
+    {
+        return Err(errno::EAFNOSUPPORT);
+    }
+
+    // The path ends at the first null byte or the end of the address, abstract addresses are not supported
+    let path = &bytes[2..];
+    let path = &path[..path.iter().position(|c| *c == 0).unwrap_or(path.len())];
+
+    if path.is_empty()
+    {
+        return Err(errno::EINVAL);
+    }
+
+    Ok(String::from_utf8_lossy(path).to_string())
+}
+
+/// Copy a socket address into a userspace buffer of `capacity` bytes, truncating it if it does not fit, returns the
+/// full length of the address. Unbound sockets have an address holding only the family
+fn write_address(proc: &mut super::Process, address_ptr: usize, capacity: usize, address: Option<&str>) -> Result<usize, usize>
+{
+    let mut bytes = (AF_UNIX as u16).to_ne_bytes().to_vec();
+
+    if let Some(path) = address
+    {
+        bytes.extend_from_slice(path.as_bytes());
+        bytes.push(0);
+    }
+
+    let count = bytes.len().min(capacity);
+
+    if count > 0
+    {
+        let ptr = proc.map_mem(address_ptr).map_err(|_| errno::EFAULT)? as *mut u8;
+        unsafe { core::ptr::copy(bytes.as_ptr(), ptr, count) };
+    }
+
+    Ok(bytes.len())
+}
+
+/// Copy a socket address to userspace where the length of the buffer is passed and returned through `length_ptr`,
+/// a null address skips the copy
+fn write_address_length(proc: &mut super::Process, address_ptr: usize, length_ptr: usize, address: Option<&str>) -> Result<(), usize>
+{
+    if address_ptr == 0
+    {
+        return Ok(());
+    }
+
+    let length_ptr = proc.map_mem(length_ptr).map_err(|_| errno::EFAULT)? as *mut u32;
+    let capacity = unsafe { length_ptr.read() } as usize;
+
+    let length = write_address(proc, address_ptr, capacity, address)?;
+    unsafe { length_ptr.write(length as u32) };
+
+    Ok(())
+}
+
+/// Find the socket bound to the socket inode at a path
+fn lookup_socket(proc: &mut super::Process, address: &str) -> Result<BoundSocket, usize>
+{
+    let path = super::utils::path_at(proc, super::utils::AT_FDCWD, address.to_string())?;
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    let inode = vfs.resolve_path_at(&path, true).map_err(|e| e.to_errno())?;
+
+    if vfs.get_stat(inode).map_err(|e| e.to_errno())?.mode & S_IFMT != S_IFSOCK
+    {
+        return Err(errno::ECONNREFUSED);
+    }
+
+    vfs.bound_socket(inode).ok_or(errno::ECONNREFUSED)
+}
+
+/// Find the inbox of the datagram socket bound to a path
+fn lookup_inbox(proc: &mut super::Process, address: &str) -> Result<SharedChannel, usize>
+{
+    match lookup_socket(proc, address)?
+    {
+        BoundSocket::Datagram(inbox) => inbox.upgrade().ok_or(errno::ECONNREFUSED),
+        BoundSocket::Listener(_) => Err(errno::EPROTOTYPE)
+    }
+}
+
+/// Check the type of a new socket, returning the `SOCK_*` type and the flags given with it
+fn socket_type(domain: usize, kind: usize, protocol: usize) -> Result<(usize, usize), usize>
+{
+    if domain != AF_UNIX
+    {
+        return Err(errno::EAFNOSUPPORT);
+    }
+
+    let flags = kind & !SOCK_TYPE_MASK;
+    let kind = kind & SOCK_TYPE_MASK;
+
+    if kind != SOCK_STREAM && kind != SOCK_DGRAM
+    {
+        return Err(errno::ESOCKTNOSUPPORT);
+    }
+
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    if protocol != 0
+    {
+        return Err(errno::EPROTONOSUPPORT);
+    }
+
+    Ok((kind, flags))
+}
+
+/// socket Syscall
+pub fn syscall_socket(proc: &mut super::Process, domain: usize, kind: usize, protocol: usize) -> Result<usize, usize>
+{
+    let (kind, flags) = socket_type(domain, kind, protocol)?;
+
+    Ok(proc.add_socket(UnixSocket::new(kind), flags))
+}
+
+/// socketpair Syscall
+pub fn syscall_socketpair(proc: &mut super::Process, domain: usize, kind: usize, protocol: usize, fds_ptr: usize) -> Result<usize, usize>
+{
+    let (kind, flags) = socket_type(domain, kind, protocol)?;
+    let fds = proc.map_mem(fds_ptr).map_err(|_| errno::EFAULT)? as *mut i32;
+
+    let (first, second) = UnixSocket::pair(kind);
+
+    let first = proc.add_socket(first, flags);
+    let second = proc.add_socket(second, flags);
+
+    unsafe
+    {
+        fds.write(first as i32);
+        fds.add(1).write(second as i32);
+    }
+
+    Ok(0)
+}
+
+/// bind Syscall
+pub fn syscall_bind(proc: &mut super::Process, fd: usize, address_ptr: usize, length: usize) -> Result<usize, usize>
+{
+    let address = read_address(proc, address_ptr, length)?;
+
+    with_socket(proc, fd, |proc, socket|
+    {
+        if socket.address().is_some()
+        {
+            return Err(errno::EINVAL);
+        }
+
+        let path = super::utils::path_at(proc, super::utils::AT_FDCWD, address.clone())?;
+
+        proc.ensure_fs();
+        let vfs = proc.fs_interface.as_mut().unwrap();
+
+        // Binding creates the socket inode, so a path which already exists is in use even if no socket is bound
+        let (directory, name) = vfs.resolve_parent_at(&path).map_err(|e| e.to_errno())?;
+        let inode = match vfs.create_socket(directory, name)
+        {
+            Err(FilesystemError::FileExists) => return Err(errno::EADDRINUSE),
+            result => result.map_err(|e| e.to_errno())?
+        };
+
+        if let Some(bound) = socket.bind(address, inode)?
+        {
+            vfs.bind_socket(inode, bound);
+        }
+
+        Ok(0)
+    })
+}
+
+/// listen Syscall
+pub fn syscall_listen(proc: &mut super::Process, fd: usize, backlog: usize) -> Result<usize, usize>
+{
+    // A negative backlog asks for the largest one
+    let backlog = if (backlog as i32) < 0 { SOMAXCONN } else { backlog as i32 as usize };
+
+    with_socket(proc, fd, |proc, socket|
+    {
+        if let Some((inode, bound)) = socket.listen(backlog)?
+        {
+            proc.ensure_fs();
+            proc.fs_interface.as_mut().unwrap().bind_socket(inode, bound);
+        }
+
+        Ok(0)
+    })
+}
+
+/// accept Syscall
+pub fn syscall_accept(proc: &mut super::Process, fd: usize, address_ptr: usize, length_ptr: usize) -> Result<usize, usize>
+{
+    syscall_accept4(proc, fd, address_ptr, length_ptr, 0)
+}
+
+/// accept4 Syscall
+pub fn syscall_accept4(proc: &mut super::Process, fd: usize, address_ptr: usize, length_ptr: usize, flags: usize) -> Result<usize, usize>
+{
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let result = with_socket(proc, fd, |proc, socket|
+    {
+        let connection = socket.accept()?;
+
+        // The address of the peer is written before the connection becomes a descriptor, so a bad pointer loses it
+        write_address_length(proc, address_ptr, length_ptr, connection.peer_address())?;
+
+        Ok(proc.add_socket(connection, flags))
+    });
+
+    wait_if_blocked(proc, fd, false, result)
+}
+
+/// connect Syscall
+pub fn syscall_connect(proc: &mut super::Process, fd: usize, address_ptr: usize, length: usize) -> Result<usize, usize>
+{
+    let address = read_address(proc, address_ptr, length)?;
+    let target = lookup_socket(proc, &address)?;
+
+    let result = with_socket(proc, fd, |_, socket|
+    {
+        socket.connect(target)?;
+
+        if socket.kind() == SOCK_DGRAM
+        {
+            socket.set_peer_address(address);
+        }
+
+        Ok(0)
+    });
+
+    wait_if_blocked(proc, fd, false, result)
+}
+
+/// Gather the descriptors passed in the control messages of `sendmsg`
+fn gather_rights(proc: &mut super::Process, control_ptr: usize, control_length: usize) -> Result<Vec<DescriptorEntry>, usize>
+{
+    let mut rights = Vec::new();
+
+    if control_length == 0
+    {
+        return Ok(rights);
+    }
+
+    let control = proc.map_mem(control_ptr).map_err(|_| errno::EFAULT)? as *const u8;
+    let mut offset = 0;
+
+    while offset + CONTROL_HEADER_SIZE <= control_length
+    {
+        let header = unsafe { (control.add(offset) as *const ControlHeader).read() };
+
+        if header.length < CONTROL_HEADER_SIZE || offset + header.length > control_length
+        {
+            return Err(errno::EINVAL);
+        }
+
+        if header.level != SOL_SOCKET || header.kind != SCM_RIGHTS
+        {
+            return Err(errno::EINVAL);
+        }
+
+        let count = (header.length - CONTROL_HEADER_SIZE) / core::mem::size_of::<i32>();
+        let fds = unsafe { control.add(offset + CONTROL_HEADER_SIZE) } as *const i32;
+
+        for i in 0..count
+        {
+            let fd = unsafe { fds.add(i).read() } as usize;
+            rights.push(proc.data.descriptors.get(&fd).ok_or(errno::EBADF)?.duplicate());
+        }
+
+        offset += control_align(header.length);
+    }
+
+    if rights.len() > SCM_MAX_FD
+    {
+        return Err(errno::EINVAL);
+    }
+
+    Ok(rights)
+}
+
+/// Send a message on a socket, to `address` if one is given
+fn send_message(proc: &mut super::Process, fd: usize, data: &[u8], rights: Vec<DescriptorEntry>, flags: usize, address: Option<String>) -> Result<usize, usize>
+{
+    if flags & !(MSG_DONTWAIT | MSG_NOSIGNAL) != 0
+    {
+        return Err(errno::EOPNOTSUPP);
+    }
+
+    let result = with_socket(proc, fd, |proc, socket|
+    {
+        let target = match &address
+        {
+            Some(address) if socket.kind() == SOCK_DGRAM => Some(lookup_inbox(proc, address)?),
+            Some(_) if socket.is_connected() => return Err(errno::EISCONN),
+            Some(_) => return Err(errno::EOPNOTSUPP),
+            None => None
+        };
+
+        socket.send(data, rights, target)
+    });
+
+    if result == Err(errno::EPIPE) && flags & MSG_NOSIGNAL == 0
+    {
+        proc.raise_broken_pipe();
+    }
+
+    wait_if_blocked(proc, fd, flags & MSG_DONTWAIT > 0, result)
+}
+
+/// Receive a message from a socket, installing any descriptors passed with it as new descriptors of the process
+fn receive_message(proc: &mut super::Process, fd: usize, buffer: &mut [u8], flags: usize) -> Result<(Received, Option<String>), usize>
+{
+    if flags & !(MSG_DONTWAIT | MSG_PEEK | MSG_TRUNC | MSG_CMSG_CLOEXEC) != 0
+    {
+        return Err(errno::EOPNOTSUPP);
+    }
+
+    let result = with_socket(proc, fd, |_, socket|
+    {
+        let received = socket.recv(buffer, flags & MSG_PEEK > 0)?;
+
+        // Stream sockets report the address of their peer, datagrams that of their sender
+        let source = if socket.kind() == SOCK_STREAM { socket.peer_address().map(|address| address.to_string()) } else { received.source.clone() };
+
+        Ok((received, source))
+    });
+
+    match result
+    {
+        Err(errno::EAGAIN) if flags & MSG_DONTWAIT == 0 && !proc.is_nonblocking(fd) => super::utils::wait_for_event(proc),
+        result => result
+    }
+}
+
+/// sendto Syscall
+pub fn syscall_sendto(proc: &mut super::Process, fd: usize, buffer_ptr: usize, length: usize, flags: usize, address_ptr: usize, address_length: usize) -> Result<usize, usize>
+{
+    let address = if address_ptr == 0 { None } else { Some(read_address(proc, address_ptr, address_length)?) };
+
+    let data = if length == 0
+    {
+        &[]
+    }
+    else
+    {
+        let ptr = proc.map_mem(buffer_ptr).map_err(|_| errno::EFAULT)? as *const u8;
+        unsafe { core::slice::from_raw_parts(ptr, length) }
+    };
+
+    send_message(proc, fd, data, Vec::new(), flags, address)
+}
+
+/// recvfrom Syscall
+pub fn syscall_recvfrom(proc: &mut super::Process, fd: usize, buffer_ptr: usize, length: usize, flags: usize, address_ptr: usize, length_ptr: usize) -> Result<usize, usize>
+{
+    let buffer = if length == 0
+    {
+        &mut []
+    }
+    else
+    {
+        let ptr = proc.map_mem(buffer_ptr).map_err(|_| errno::EFAULT)? as *mut u8;
+        unsafe { core::slice::from_raw_parts_mut(ptr, length) }
+    };
+
+    let (received, source) = receive_message(proc, fd, buffer, flags)?;
+
+    write_address_length(proc, address_ptr, length_ptr, source.as_deref())?;
+
+    Ok(if flags & MSG_TRUNC > 0 { received.full_length } else { received.length })
+}
+
+/// Read the list of buffers of a message
+fn read_iovecs(proc: &mut super::Process, header: &MessageHeader) -> Result<Vec<IoVec>, usize>
+{
+    if header.iov_length > 1024
+    {
+        return Err(errno::EMSGSIZE);
+    }
+
+    if header.iov_length == 0
+    {
+        return Ok(Vec::new());
+    }
+
+    let ptr = proc.map_mem(header.iov).map_err(|_| errno::EFAULT)? as *const IoVec;
+
+    Ok(unsafe { core::slice::from_raw_parts(ptr, header.iov_length) }.to_vec())
+}
+
+/// sendmsg Syscall
+pub fn syscall_sendmsg(proc: &mut super::Process, fd: usize, message_ptr: usize, flags: usize) -> Result<usize, usize>
+{
+    let header = unsafe { (proc.map_mem(message_ptr).map_err(|_| errno::EFAULT)? as *const MessageHeader).read() };
+
+    let address = if header.name == 0 { None } else { Some(read_address(proc, header.name, header.name_length as usize)?) };
+
+    // The buffers are gathered into a single message
+    let mut data = Vec::new();
+
+    for iovec in read_iovecs(proc, &header)?.iter().filter(|iovec| iovec.length > 0)
+    {
+        let ptr = proc.map_mem(iovec.base).map_err(|_| errno::EFAULT)? as *const u8;
+        data.extend_from_slice(unsafe { core::slice::from_raw_parts(ptr, iovec.length) });
+    }
+
+    let rights = gather_rights(proc, header.control, header.control_length)?;
+
+    send_message(proc, fd, &data, rights, flags, address)
+}
+
+/// Install the descriptors received with a message and describe them in the control buffer of a `recvmsg`, returns
+/// the length of control data written and whether any descriptors did not fit
+fn deliver_rights(proc: &mut super::Process, rights: Vec<DescriptorEntry>, control_ptr: usize, control_length: usize, cloexec: bool) -> Result<(usize, bool), usize>
+{
+    if rights.is_empty()
+    {
+        return Ok((0, false));
+    }
+
+    // Descriptors which do not fit are closed
+    let fit = control_length.saturating_sub(CONTROL_HEADER_SIZE) / core::mem::size_of::<i32>();
+    let truncated = fit < rights.len();
+
+    if fit == 0
+    {
+        return Ok((0, truncated));
+    }
+
+    let control = proc.map_mem(control_ptr).map_err(|_| errno::EFAULT)? as *mut u8;
+    let fds = unsafe { control.add(CONTROL_HEADER_SIZE) } as *mut i32;
+
+    let mut count = 0;
+
+    for entry in rights.into_iter().take(fit)
+    {
+        let fd = proc.install_descriptor(entry, cloexec);
+        unsafe { fds.add(count).write(fd as i32) };
+
+        count += 1;
+    }
+
+    let length = CONTROL_HEADER_SIZE + count * core::mem::size_of::<i32>();
+
+    unsafe { (control as *mut ControlHeader).write(ControlHeader { length, level: SOL_SOCKET, kind: SCM_RIGHTS }) };
+
+    Ok((control_align(length).min(control_length), truncated))
+}
+
+/// recvmsg Syscall
+pub fn syscall_recvmsg(proc: &mut super::Process, fd: usize, message_ptr: usize, flags: usize) -> Result<usize, usize>
+{
+    let header_ptr = proc.map_mem(message_ptr).map_err(|_| errno::EFAULT)? as *mut MessageHeader;
+    let mut header = unsafe { header_ptr.read() };
+
+    let iovecs = read_iovecs(proc, &header)?;
+
+    // The message is received into one buffer and then scattered
+    let mut buffer = alloc::vec![0u8; iovecs.iter().map(|iovec| iovec.length).sum()];
+
+    let (received, source) = receive_message(proc, fd, &mut buffer, flags)?;
+
+    let mut offset = 0;
+
+    for iovec in iovecs.iter().filter(|iovec| iovec.length > 0)
+    {
+        if offset >= received.length
+        {
+            break;
+        }
+
+        let count = iovec.length.min(received.length - offset);
+        let ptr = proc.map_mem(iovec.base).map_err(|_| errno::EFAULT)? as *mut u8;
+
+        unsafe { core::ptr::copy(buffer[offset..].as_ptr(), ptr, count) };
+        offset += count;
+    }
+
+    if header.name != 0
+    {
+        header.name_length = write_address(proc, header.name, header.name_length as usize, source.as_deref())? as u32;
+    }
+
+    let (control_length, control_truncated) = deliver_rights(proc, received.rights, header.control, header.control_length, flags & MSG_CMSG_CLOEXEC > 0)?;
+
+    header.control_length = control_length;
+    header.flags = (if received.full_length > received.length { MSG_TRUNC } else { 0 } | if control_truncated { MSG_CTRUNC } else { 0 }) as i32;
+
+    unsafe { header_ptr.write(header) };
+
+    Ok(if flags & MSG_TRUNC > 0 { received.full_length } else { received.length })
+}
+
+/// shutdown Syscall
+pub fn syscall_shutdown(proc: &mut super::Process, fd: usize, how: usize) -> Result<usize, usize>
+{
+    let (read, write) = match how
+    {
+        SHUT_RD => (true, false),
+        SHUT_WR => (false, true),
+        SHUT_RDWR => (true, true),
+        _ => return Err(errno::EINVAL)
+    };
+
+    with_socket(proc, fd, |_, socket| socket.shutdown(read, write).map(|_| 0))
+}
+
+/// getsockname Syscall
+pub fn syscall_getsockname(proc: &mut super::Process, fd: usize, address_ptr: usize, length_ptr: usize) -> Result<usize, usize>
+{
+    let address = with_socket(proc, fd, |_, socket| Ok(socket.address().map(|address| address.to_string())))?;
+
+    write_address_length(proc, address_ptr, length_ptr, address.as_deref())?;
+
+    Ok(0)
+}
+
+/// getpeername Syscall
+pub fn syscall_getpeername(proc: &mut super::Process, fd: usize, address_ptr: usize, length_ptr: usize) -> Result<usize, usize>
+{
+    let address = with_socket(proc, fd, |_, socket|
+    {
+        if !socket.is_connected()
+        {
+            return Err(errno::ENOTCONN);
+        }
+
+        Ok(socket.peer_address().map(|address| address.to_string()))
+    })?;
+
+    write_address_length(proc, address_ptr, length_ptr, address.as_deref())?;
+
+    Ok(0)
+}
@@ -1,3 +1,5 @@
+use super::utils::{AT_EMPTY_PATH, AT_FDCWD, AT_SYMLINK_NOFOLLOW};
+
 /// Stat structure
 #[repr(C)]
 pub struct OutputStatStruct
@@ -17,14 +19,10 @@ pub struct OutputStatStruct
     pub ctime: usize
 }
 
-/// Stat Syscall
-pub fn syscall_stat(proc: &mut super::Process, path_ptr: usize, buffer_ptr: usize) -> Result<usize, usize>
+/// Copy stat data out to a userspace buffer
+fn write_stat_buffer(proc: &mut super::Process, buffer_ptr: usize, stat_data: crate::fs::structures::FileStat) -> Result<usize, usize>
 {
-    let buffer_ptr = proc.map_mem(buffer_ptr).unwrap() as *mut OutputStatStruct;
-    
-    let expanded_path = super::utils::userspace_string_to_path(proc, path_ptr)?;
-
-    let stat_data = proc.stat(expanded_path)?;
+    let buffer_ptr = proc.map_mem(buffer_ptr).map_err(|_| crate::errno::EFAULT)? as *mut OutputStatStruct;
 
     unsafe
     {
@@ -46,4 +44,62 @@ pub fn syscall_stat(proc: &mut super::Process, path_ptr: usize, buffer_ptr: usiz
     }
 
     Ok(0)
-}
\ No newline at end of file
+}
+
+/// Stat Syscall
+pub fn syscall_stat(proc: &mut super::Process, path_ptr: usize, buffer_ptr: usize) -> Result<usize, usize>
+{
+    syscall_newfstatat(proc, AT_FDCWD, path_ptr, buffer_ptr, 0)
+}
+
+/// fstat Syscall
+pub fn syscall_fstat(proc: &mut super::Process, fd: usize, buffer_ptr: usize) -> Result<usize, usize>
+{
+    let stat_data = proc.fstat(fd)?;
+
+    write_stat_buffer(proc, buffer_ptr, stat_data)
+}
+
+/// lstat Syscall
+pub fn syscall_lstat(proc: &mut super::Process, path_ptr: usize, buffer_ptr: usize) -> Result<usize, usize>
+{
+    syscall_newfstatat(proc, AT_FDCWD, path_ptr, buffer_ptr, AT_SYMLINK_NOFOLLOW)
+}
+
+/// newfstatat Syscall
+pub fn syscall_newfstatat(proc: &mut super::Process, dirfd: usize, path_ptr: usize, buffer_ptr: usize, flags: usize) -> Result<usize, usize>
+{
+    let path = super::utils::userspace_string(proc, path_ptr)?;
+
+    // An empty path refers to the descriptor itself
+    if path.len() == 0
+    {
+        if flags & AT_EMPTY_PATH == 0
+        {
+            return Err(crate::errno::ENOENT);
+        }
+
+        // With no descriptor, the empty path is the working directory
+        if dirfd == AT_FDCWD
+        {
+            let cwd = super::utils::path_at(proc, AT_FDCWD, ".".into())?;
+            let stat_data = proc.stat(cwd)?;
+            return write_stat_buffer(proc, buffer_ptr, stat_data);
+        }
+
+        return syscall_fstat(proc, dirfd, buffer_ptr);
+    }
+
+    let expanded_path = super::utils::path_at(proc, dirfd, path)?;
+
+    let stat_data = if flags & AT_SYMLINK_NOFOLLOW > 0
+    {
+        proc.lstat(expanded_path)?
+    }
+    else
+    {
+        proc.stat(expanded_path)?
+    };
+
+    write_stat_buffer(proc, buffer_ptr, stat_data)
+}
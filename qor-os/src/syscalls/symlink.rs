@@ -0,0 +1,25 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+
+/// symlink Syscall
+pub fn syscall_symlink(proc: &mut super::Process, target_ptr: usize, link_path_ptr: usize) -> Result<usize, usize>
+{
+    // The target is stored exactly as given, it is only resolved when the link is followed
+    let target = super::utils::userspace_string(proc, target_ptr)?;
+    let link_path = super::utils::userspace_string_to_path(proc, link_path_ptr)?;
+
+    if target.len() == 0
+    {
+        return Err(errno::ENOENT);
+    }
+
+    proc.ensure_fs();
+    let vfs = proc.fs_interface.as_mut().unwrap();
+
+    let (dest_inode, name) = vfs.resolve_parent_at(&link_path).map_err(|e| e.to_errno())?;
+
+    vfs.create_symlink(dest_inode, name, &target).map_err(|e| e.to_errno())?;
+
+    Ok(0)
+}
@@ -3,6 +3,13 @@ use crate::*;
 /// sync Syscall
 pub fn syscall_sync(proc: &mut super::Process) -> usize
 {
+    // The init process only asks for the background writeback the timer has marked as due
+    if proc.pid == 0
+    {
+        fs::writeback::run_pending_writeback();
+        return 0;
+    }
+
     kdebugln!(Syscalls, "PID {} requests fs sync", proc.pid);
 
     use fs::fstrait::Filesystem;
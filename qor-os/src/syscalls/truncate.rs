@@ -0,0 +1,37 @@
+use crate::*;
+
+use fs::structures::SetAttributes;
+
+/// truncate Syscall
+pub fn syscall_truncate(proc: &mut super::Process, path_ptr: usize, length: usize) -> Result<usize, usize>
+{
+    if (length as isize) < 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let path = super::utils::userspace_string_to_path(proc, path_ptr)?;
+
+    proc.set_path_attributes(path, true, SetAttributes { size: Some(length), ..Default::default() })?;
+
+    Ok(0)
+}
+
+/// ftruncate Syscall
+pub fn syscall_ftruncate(proc: &mut super::Process, fd: usize, length: usize) -> Result<usize, usize>
+{
+    if (length as isize) < 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    // Descriptors without an inode, such as pipes, cannot be resized
+    let result = proc.set_descriptor_attributes(fd, SetAttributes { size: Some(length), ..Default::default() });
+
+    match result
+    {
+        Err(errno::ENOENT) => Err(errno::EINVAL),
+        Err(e) => Err(e),
+        Ok(()) => Ok(0)
+    }
+}
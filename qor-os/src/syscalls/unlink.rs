@@ -1,9 +1,29 @@
+use super::utils::AT_REMOVEDIR;
+
 /// unlink Syscall
 pub fn syscall_unlink(proc: &mut super::Process, path_ptr: usize) -> Result<usize, usize>
 {
-    let expanded_path = super::utils::userspace_string_to_path(proc, path_ptr)?;
+    syscall_unlinkat(proc, super::utils::AT_FDCWD, path_ptr, 0)
+}
+
+/// unlinkat Syscall
+pub fn syscall_unlinkat(proc: &mut super::Process, dirfd: usize, path_ptr: usize, flags: usize) -> Result<usize, usize>
+{
+    if flags & !AT_REMOVEDIR != 0
+    {
+        return Err(crate::errno::EINVAL);
+    }
+
+    let expanded_path = super::utils::userspace_string_to_path_at(proc, dirfd, path_ptr)?;
 
-    proc.unlink(expanded_path)?;
+    if flags & AT_REMOVEDIR > 0
+    {
+        proc.rmdir(expanded_path)?;
+    }
+    else
+    {
+        proc.unlink(expanded_path)?;
+    }
 
     Ok(0)
-}
\ No newline at end of file
+}
@@ -2,11 +2,15 @@ use crate::*;
 use super::Process;
 use libutils::paths::OwnedPath;
 
+use fs::vfs::PathAt;
+
+use fs::fstrait::Filesystem;
+
 // Constants for error handling with long paths
 pub const MAX_PATH_LENGTH: usize = 128;
 
-/// Convert a userspace string into a canonicalized path
-pub fn userspace_string_to_path(proc: &mut Process, userspace_ptr: usize) -> Result<OwnedPath, usize>
+/// Copy a null terminated string out of userspace
+pub fn userspace_string(proc: &mut Process, userspace_ptr: usize) -> Result<String, usize>
 {
     let path_ptr = proc.map_mem(userspace_ptr).map_err( |_| errno::EFAULT )? as *mut u8;
     let mut path = String::new();
@@ -29,8 +33,107 @@ pub fn userspace_string_to_path(proc: &mut Process, userspace_ptr: usize) -> Res
         i += 1;
     }
 
-    let mut expanded_path = OwnedPath::new(path);
-    expanded_path.canonicalize(&proc.data.cwd);
+    Ok(path)
+}
+
+/// Convert a userspace string into a path, relative paths start from the current working directory
+pub fn userspace_string_to_path(proc: &mut Process, userspace_ptr: usize) -> Result<PathAt, usize>
+{
+    let path = userspace_string(proc, userspace_ptr)?;
+
+    path_at(proc, AT_FDCWD, path)
+}
+
+/// Special directory descriptor which refers to the current working directory
+pub const AT_FDCWD: usize = (-100 as isize) as usize;
+
+/// Do not follow a symbolic link at the end of the path
+pub const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+
+/// Remove a directory rather than a file in `unlinkat`
+pub const AT_REMOVEDIR: usize = 0x200;
+
+/// Check access using the effective ids in `faccessat`
+pub const AT_EACCESS: usize = 0x200;
+
+/// Operate on the descriptor itself when the path is empty
+pub const AT_EMPTY_PATH: usize = 0x1000;
+
+/// Resolve a path against the directory open as `dirfd`, or the current working directory for `AT_FDCWD`. The walk
+/// starts from the directory itself, so it does not matter where the directory has since been moved
+pub fn path_at(proc: &mut Process, dirfd: usize, path: String) -> Result<PathAt, usize>
+{
+    if path.starts_with('/')
+    {
+        return Ok(OwnedPath::new(path).into());
+    }
+
+    if dirfd == AT_FDCWD
+    {
+        return Ok(PathAt { start: Some(proc.get_cwd()?), path: OwnedPath::new(path) });
+    }
+
+    let inode = proc.get_descriptor_inode(dirfd)?;
+
+    proc.ensure_fs();
+    proc.fs_interface.as_mut().unwrap().assert_directory(inode).map_err(|e| e.to_errno())?;
+
+    Ok(PathAt { start: Some(inode), path: OwnedPath::new(path) })
+}
+
+/// Convert a userspace string into a path, relative paths start from the directory open as `dirfd`
+pub fn userspace_string_to_path_at(proc: &mut Process, dirfd: usize, userspace_ptr: usize) -> Result<PathAt, usize>
+{
+    let path = userspace_string(proc, userspace_ptr)?;
+
+    if path.len() == 0
+    {
+        return Err(errno::ENOENT);
+    }
+
+    path_at(proc, dirfd, path)
+}
+
+/// Block the process until a lock is released, after which the syscall is restarted to try to take its lock again
+pub fn wait_for_lock(proc: &mut Process) -> !
+{
+    proc.ensure_fs();
+    let generation = proc.fs_interface.as_mut().unwrap().lock_table().generation();
+
+    proc.state = process::process::ProcessState::Waiting(process::process::WaitMode::ForLock(generation));
+
+    let schedule = process::scheduler::schedule_next();
+    process::scheduler::schedule_jump(schedule);
+}
+
+/// Block the process until a descriptor may have changed state, such as the other end of a named pipe being opened
+/// or data arriving on a socket, after which the syscall is restarted to try again
+pub fn wait_for_event(proc: &mut Process) -> !
+{
+    proc.state = process::process::ProcessState::Waiting(process::process::WaitMode::ForReadiness(process::readiness::readiness_generation(), None));
+
+    let schedule = process::scheduler::schedule_next();
+    process::scheduler::schedule_jump(schedule);
+}
+
+/// Finish checking a set of descriptors, blocking the process if none of them are `ready` until one may have
+/// become ready, after which the syscall is restarted to check them again. Returns once a descriptor is ready or the
+/// timeout has passed, with a timeout of `None` waiting forever
+pub fn wait_for_readiness(proc: &mut Process, ready: bool, timeout: Option<crate::drivers::timer::KernelTime>)
+{
+    let now = unsafe { &drivers::TIMER_DRIVER }.time();
+
+    // A restarted syscall keeps the deadline it started with
+    let deadline = proc.data.readiness_deadline.or(timeout.map(|timeout| now + timeout));
+
+    if !ready && !deadline.map(|deadline| now >= deadline).unwrap_or(false)
+    {
+        proc.data.readiness_deadline = deadline;
+        proc.state = process::process::ProcessState::Waiting(process::process::WaitMode::ForReadiness(process::readiness::readiness_generation(), deadline));
+
+        let schedule = process::scheduler::schedule_next();
+        process::scheduler::schedule_jump(schedule);
+    }
 
-    Ok(expanded_path)
-}
\ No newline at end of file
+    proc.data.readiness_deadline = None;
+}
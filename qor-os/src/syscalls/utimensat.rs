@@ -0,0 +1,70 @@
+use crate::*;
+
+use fs::structures::SetAttributes;
+
+use super::utils::AT_SYMLINK_NOFOLLOW;
+
+/// Set the time to the current time
+const UTIME_NOW: usize = (1 << 30) - 1;
+
+/// Leave the time unchanged
+const UTIME_OMIT: usize = (1 << 30) - 2;
+
+/// Time structure passed to `utimensat`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct TimeSpec
+{
+    seconds: usize,
+    nanoseconds: usize
+}
+
+/// Convert a requested time into the value to store, `None` if the time should not change
+fn convert_time(time: TimeSpec, now: usize) -> Result<Option<usize>, usize>
+{
+    match time.nanoseconds
+    {
+        UTIME_NOW => Ok(Some(now)),
+        UTIME_OMIT => Ok(None),
+        nanoseconds if nanoseconds >= 1_000_000_000 => Err(errno::EINVAL),
+        _ => Ok(Some(time.seconds))
+    }
+}
+
+/// utimensat Syscall
+pub fn syscall_utimensat(proc: &mut super::Process, dirfd: usize, path_ptr: usize, times_ptr: usize, flags: usize) -> Result<usize, usize>
+{
+    if flags & !AT_SYMLINK_NOFOLLOW != 0
+    {
+        return Err(errno::EINVAL);
+    }
+
+    let now = (crate::drivers::rtc::driver::RealTimeClockDriver::get_driver().get_unix_timestamp_nano() / 1_000_000_000) as usize;
+
+    // A null pointer for the times sets both to the current time
+    let attributes = if times_ptr == 0
+    {
+        SetAttributes { atime: Some(now), mtime: Some(now), ..Default::default() }
+    }
+    else
+    {
+        let times_ptr = proc.map_mem(times_ptr).map_err(|_| errno::EFAULT)? as *const TimeSpec;
+        let (access, modify) = unsafe { (times_ptr.read(), times_ptr.add(1).read()) };
+
+        SetAttributes { atime: convert_time(access, now)?, mtime: convert_time(modify, now)?, ..Default::default() }
+    };
+
+    // A null path refers to the descriptor itself
+    if path_ptr == 0
+    {
+        proc.set_descriptor_attributes(dirfd, attributes)?;
+    }
+    else
+    {
+        let path = super::utils::userspace_string_to_path_at(proc, dirfd, path_ptr)?;
+
+        proc.set_path_attributes(path, flags & AT_SYMLINK_NOFOLLOW == 0, attributes)?;
+    }
+
+    Ok(0)
+}
@@ -1,7 +1,41 @@
+use crate::{errno, process::process::{ProcessState, WaitMode}};
+use crate::process;
+
 /// Write Syscall
 pub fn syscall_write(proc: &mut super::Process, fd: usize, buffer: usize, count: usize) -> usize
 {
     let ptr = proc.map_mem(buffer).unwrap() as *mut u8;
 
-    proc.write(fd, ptr, count)
-}
\ No newline at end of file
+    let result = proc.write(fd, ptr, count);
+
+    if result == errno::EPIPE
+    {
+        proc.raise_broken_pipe();
+    }
+
+    if proc.is_nonblocking(fd)
+    {
+        return result;
+    }
+
+    // If a pipe or socket is too full to take all of the data, wait for room for the rest without holding up the
+    // other processes, the syscall returns once all of it has been written
+    let written = if result == errno::EAGAIN
+    {
+        0
+    }
+    else if (result as isize) >= 0 && result < count && !proc.check_writable(fd)
+    {
+        result
+    }
+    else
+    {
+        return result;
+    };
+
+    proc.state = ProcessState::Waiting(WaitMode::ForWrite((fd, ptr, count, written)));
+    proc.program_counter += 4;
+
+    let schedule = process::scheduler::schedule_next();
+    process::scheduler::schedule_jump(schedule);
+}
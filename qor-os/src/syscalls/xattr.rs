@@ -0,0 +1,219 @@
+use crate::*;
+
+use fs::fstrait::Filesystem;
+use fs::structures::{FilesystemIndex, XATTR_NAME_MAX, XATTR_SIZE_MAX};
+
+/// Copy the null terminated name of an extended attribute out of userspace
+fn userspace_name(proc: &mut super::Process, name_ptr: usize) -> Result<String, usize>
+{
+    let ptr = proc.map_mem(name_ptr).map_err(|_| errno::EFAULT)? as *const u8;
+    let mut name = String::new();
+
+    loop
+    {
+        let v = unsafe { ptr.add(name.len()).read() } as char;
+
+        if v == '\x00'
+        {
+            break;
+        }
+
+        if name.len() >= XATTR_NAME_MAX
+        {
+            return Err(errno::ERANGE);
+        }
+
+        name.push(v);
+    }
+
+    Ok(name)
+}
+
+/// Find the inode at a path, following a symbolic link at the end of the path if `follow` is set
+fn path_inode(proc: &mut super::Process, path_ptr: usize, follow: bool) -> Result<FilesystemIndex, usize>
+{
+    let path = super::utils::userspace_string_to_path(proc, path_ptr)?;
+
+    proc.ensure_fs();
+    proc.fs_interface.as_mut().unwrap().resolve_path_at(&path, follow).map_err(|e| e.to_errno())
+}
+
+/// Find the inode open as a file descriptor, descriptors without one, such as pipes, have no attributes
+fn descriptor_inode(proc: &mut super::Process, fd: usize) -> Result<FilesystemIndex, usize>
+{
+    match proc.get_descriptor_inode(fd)
+    {
+        Err(errno::ENOENT) => Err(errno::EOPNOTSUPP),
+        result => result
+    }
+}
+
+/// Copy data into a userspace buffer of `size` bytes, a size of zero only reports the length of the data
+fn copy_out(proc: &mut super::Process, data: &[u8], buffer_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    if size == 0
+    {
+        return Ok(data.len());
+    }
+
+    if data.len() > size
+    {
+        return Err(errno::ERANGE);
+    }
+
+    let buffer = proc.map_mem(buffer_ptr).map_err(|_| errno::EFAULT)? as *mut u8;
+
+    unsafe { core::ptr::copy(data.as_ptr(), buffer, data.len()) };
+
+    Ok(data.len())
+}
+
+/// Set an extended attribute of an inode
+fn set_inode_xattr(proc: &mut super::Process, inode: FilesystemIndex, name_ptr: usize, value_ptr: usize, size: usize, flags: usize) -> Result<usize, usize>
+{
+    let name = userspace_name(proc, name_ptr)?;
+
+    if size > XATTR_SIZE_MAX
+    {
+        return Err(errno::E2BIG);
+    }
+
+    let value = if size == 0
+    {
+        Vec::new()
+    }
+    else
+    {
+        let ptr = proc.map_mem(value_ptr).map_err(|_| errno::EFAULT)? as *const u8;
+
+        unsafe { core::slice::from_raw_parts(ptr, size) }.to_vec()
+    };
+
+    proc.ensure_fs();
+    proc.fs_interface.as_mut().unwrap().set_xattr_flags(inode, &name, &value, flags).map_err(|e| e.to_errno())?;
+
+    Ok(0)
+}
+
+/// Get an extended attribute of an inode
+fn get_inode_xattr(proc: &mut super::Process, inode: FilesystemIndex, name_ptr: usize, value_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    let name = userspace_name(proc, name_ptr)?;
+
+    proc.ensure_fs();
+    let value = proc.fs_interface.as_mut().unwrap().get_xattr(inode, &name).map_err(|e| e.to_errno())?;
+
+    copy_out(proc, &value, value_ptr, size)
+}
+
+/// List the extended attributes of an inode as a sequence of null terminated names
+fn list_inode_xattr(proc: &mut super::Process, inode: FilesystemIndex, list_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    proc.ensure_fs();
+    let names = proc.fs_interface.as_mut().unwrap().list_xattr(inode).map_err(|e| e.to_errno())?;
+
+    let mut list = Vec::new();
+
+    for name in names
+    {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+    }
+
+    copy_out(proc, &list, list_ptr, size)
+}
+
+/// Remove an extended attribute from an inode
+fn remove_inode_xattr(proc: &mut super::Process, inode: FilesystemIndex, name_ptr: usize) -> Result<usize, usize>
+{
+    let name = userspace_name(proc, name_ptr)?;
+
+    proc.ensure_fs();
+    proc.fs_interface.as_mut().unwrap().remove_xattr(inode, &name).map_err(|e| e.to_errno())?;
+
+    Ok(0)
+}
+
+/// setxattr Syscall
+pub fn syscall_setxattr(proc: &mut super::Process, path_ptr: usize, name_ptr: usize, value_ptr: usize, size: usize, flags: usize) -> Result<usize, usize>
+{
+    let inode = path_inode(proc, path_ptr, true)?;
+    set_inode_xattr(proc, inode, name_ptr, value_ptr, size, flags)
+}
+
+/// lsetxattr Syscall
+pub fn syscall_lsetxattr(proc: &mut super::Process, path_ptr: usize, name_ptr: usize, value_ptr: usize, size: usize, flags: usize) -> Result<usize, usize>
+{
+    let inode = path_inode(proc, path_ptr, false)?;
+    set_inode_xattr(proc, inode, name_ptr, value_ptr, size, flags)
+}
+
+/// fsetxattr Syscall
+pub fn syscall_fsetxattr(proc: &mut super::Process, fd: usize, name_ptr: usize, value_ptr: usize, size: usize, flags: usize) -> Result<usize, usize>
+{
+    let inode = descriptor_inode(proc, fd)?;
+    set_inode_xattr(proc, inode, name_ptr, value_ptr, size, flags)
+}
+
+/// getxattr Syscall
+pub fn syscall_getxattr(proc: &mut super::Process, path_ptr: usize, name_ptr: usize, value_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    let inode = path_inode(proc, path_ptr, true)?;
+    get_inode_xattr(proc, inode, name_ptr, value_ptr, size)
+}
+
+/// lgetxattr Syscall
+pub fn syscall_lgetxattr(proc: &mut super::Process, path_ptr: usize, name_ptr: usize, value_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    let inode = path_inode(proc, path_ptr, false)?;
+    get_inode_xattr(proc, inode, name_ptr, value_ptr, size)
+}
+
+/// fgetxattr Syscall
+pub fn syscall_fgetxattr(proc: &mut super::Process, fd: usize, name_ptr: usize, value_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    let inode = descriptor_inode(proc, fd)?;
+    get_inode_xattr(proc, inode, name_ptr, value_ptr, size)
+}
+
+/// listxattr Syscall
+pub fn syscall_listxattr(proc: &mut super::Process, path_ptr: usize, list_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    let inode = path_inode(proc, path_ptr, true)?;
+    list_inode_xattr(proc, inode, list_ptr, size)
+}
+
+/// llistxattr Syscall
+pub fn syscall_llistxattr(proc: &mut super::Process, path_ptr: usize, list_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    let inode = path_inode(proc, path_ptr, false)?;
+    list_inode_xattr(proc, inode, list_ptr, size)
+}
+
+/// flistxattr Syscall
+pub fn syscall_flistxattr(proc: &mut super::Process, fd: usize, list_ptr: usize, size: usize) -> Result<usize, usize>
+{
+    let inode = descriptor_inode(proc, fd)?;
+    list_inode_xattr(proc, inode, list_ptr, size)
+}
+
+/// removexattr Syscall
+pub fn syscall_removexattr(proc: &mut super::Process, path_ptr: usize, name_ptr: usize) -> Result<usize, usize>
+{
+    let inode = path_inode(proc, path_ptr, true)?;
+    remove_inode_xattr(proc, inode, name_ptr)
+}
+
+/// lremovexattr Syscall
+pub fn syscall_lremovexattr(proc: &mut super::Process, path_ptr: usize, name_ptr: usize) -> Result<usize, usize>
+{
+    let inode = path_inode(proc, path_ptr, false)?;
+    remove_inode_xattr(proc, inode, name_ptr)
+}
+
+/// fremovexattr Syscall
+pub fn syscall_fremovexattr(proc: &mut super::Process, fd: usize, name_ptr: usize) -> Result<usize, usize>
+{
+    let inode = descriptor_inode(proc, fd)?;
+    remove_inode_xattr(proc, inode, name_ptr)
+}
@@ -67,6 +67,7 @@ pub fn interrupt_handler(interrupt_context: InterruptContext) -> usize
         },
         InterruptType::MachineTimerInterrupt =>
         {
+            fs::writeback::writeback_tick();
             switch_process();
         },
         default =>
@@ -0,0 +1,166 @@
+//! Flattened Device Tree Reading
+//!
+//! Only enough of the format is understood to read properties from a node
+//! given by its path, which is all the kernel needs to find what the
+//! bootloader describes in `/chosen`.
+
+// Magic number at the start of every device tree
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+// Structure block tokens
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Read a big endian word from memory
+///
+/// Safety: The address must be valid and aligned to four bytes
+unsafe fn read_be32(addr: usize) -> u32
+{
+    u32::from_be((addr as *const u32).read_volatile())
+}
+
+/// Round an offset up to the next multiple of four
+fn align4(offset: usize) -> usize
+{
+    (offset + 3) & !3
+}
+
+/// Get the length of the null terminated string at an address
+///
+/// Safety: The address must point to a null terminated string
+unsafe fn string_length(addr: usize) -> usize
+{
+    let mut length = 0;
+
+    while (addr as *const u8).add(length).read_volatile() != 0
+    {
+        length += 1;
+    }
+
+    length
+}
+
+/// Read a property value made of one or two cells as a number
+pub fn read_cells(value: &[u8]) -> Option<usize>
+{
+    match value.len()
+    {
+        4 => Some(u32::from_be_bytes([value[0], value[1], value[2], value[3]]) as usize),
+        8 => Some(u64::from_be_bytes([value[0], value[1], value[2], value[3], value[4], value[5], value[6], value[7]]) as usize),
+        _ => None
+    }
+}
+
+/// Flattened device tree passed to the kernel by the bootloader
+pub struct DeviceTree
+{
+    base: usize
+}
+
+impl DeviceTree
+{
+    /// Wrap the device tree at the given address, returns None if there is no device tree there
+    ///
+    /// Safety: The address must be zero or point to readable memory
+    pub unsafe fn from_address(base: usize) -> Option<Self>
+    {
+        if base == 0 || base & 3 != 0 || read_be32(base) != FDT_MAGIC
+        {
+            None
+        }
+        else
+        {
+            Some(Self { base })
+        }
+    }
+
+    /// Get the total size of the device tree in bytes
+    pub fn total_size(&self) -> usize
+    {
+        unsafe { read_be32(self.base + 0x04) as usize }
+    }
+
+    /// Get the value of a property of the node at the given path, unit addresses must be included in the path
+    pub fn get_property(&self, node_path: &str, name: &str) -> Option<&'static [u8]>
+    {
+        let structure = self.base + unsafe { read_be32(self.base + 0x08) } as usize;
+        let strings = self.base + unsafe { read_be32(self.base + 0x0c) } as usize;
+        let end = self.base + self.total_size();
+
+        let target_depth = node_path.split('/').filter(|s| !s.is_empty()).count();
+
+        // The root node is at depth zero, and `matched` counts how many of the enclosing nodes are on the path
+        let mut depth = 0usize;
+        let mut matched = 0usize;
+        let mut offset = structure;
+        let mut started = false;
+
+        while offset < end
+        {
+            let token = unsafe { read_be32(offset) };
+            offset += 4;
+
+            match token
+            {
+                FDT_BEGIN_NODE =>
+                {
+                    let length = unsafe { string_length(offset) };
+                    let node_name = unsafe { core::str::from_utf8(core::slice::from_raw_parts(offset as *const u8, length)).ok()? };
+                    offset = align4(offset + length + 1);
+
+                    if started
+                    {
+                        depth += 1;
+
+                        if matched == depth - 1 && node_path.split('/').filter(|s| !s.is_empty()).nth(depth - 1) == Some(node_name)
+                        {
+                            matched = depth;
+                        }
+                    }
+
+                    started = true;
+                },
+                FDT_END_NODE =>
+                {
+                    if depth == 0
+                    {
+                        return None;
+                    }
+
+                    if matched == depth
+                    {
+                        matched -= 1;
+                    }
+
+                    depth -= 1;
+                },
+                FDT_PROP =>
+                {
+                    let length = unsafe { read_be32(offset) } as usize;
+                    let name_offset = unsafe { read_be32(offset + 4) } as usize;
+                    let value = offset + 8;
+                    offset = align4(value + length);
+
+                    if depth == target_depth && matched == depth
+                    {
+                        let prop_name = strings + name_offset;
+                        let prop_length = unsafe { string_length(prop_name) };
+
+                        if unsafe { core::slice::from_raw_parts(prop_name as *const u8, prop_length) } == name.as_bytes()
+                        {
+                            return Some(unsafe { core::slice::from_raw_parts(value as *const u8, length) });
+                        }
+                    }
+                },
+                FDT_NOP => {},
+                FDT_END => return None,
+                _ => return None
+            }
+        }
+
+        None
+    }
+}
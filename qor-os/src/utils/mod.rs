@@ -10,6 +10,8 @@ pub use ringbuffer::*;
 pub mod blocking;
 pub use blocking::*;
 
+pub mod fdt;
+
 use crate::*;
 
 /// Seperate a path into a path and the last item (path, name)